@@ -1,6 +1,23 @@
 mod workspace;
 
-use workspace::{delete_book_file, load_workspace_snapshot, save_workspace_snapshot};
+use tauri::RunEvent;
+use workspace::{
+    acquire_workspace_lock, append_journal_entry, cancel_load, create_book,
+    create_workspace_from_template, delete_book, delete_book_file, delete_cols, delete_rows,
+    diagnose_workspace, diff_workspaces, discard_journal, duplicate_workspace, enqueue_save,
+    evaluate_book_formulas, export_book_to_csv, export_book_to_markdown, export_bundle,
+    export_cell_range, export_workspace_to_sqlite, export_workspace_zip, flush_save_queue,
+    import_bundle, import_csv_as_book, import_csv_directory, import_workspace_zip, insert_cols,
+    insert_rows, inspect_book_types, invalidate_cache, issue_load_id, list_backups, list_trash,
+    load_single_book, load_with_patches, load_workspace_metadata, load_workspace_snapshot,
+    load_workspace_snapshot_cached, load_workspace_snapshot_with_progress, merge_books,
+    normalize_workspace, parse_clipboard_table, prune_orphan_books, recover_from_journal,
+    release_held_locks, release_workspace_lock, relocate_workspace, rename_book, reorder_books,
+    replace_in_workspace, restore_backup, restore_from_trash, save_workspace_snapshot,
+    scan_workspace_index, search_all_workspaces, search_workspace, set_workspace_readonly,
+    transform_book_cells, trim_empty_book, unwatch_workspace, validate_workspace_against_schema,
+    watch_workspace, workspace_stats, WatcherState,
+};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -13,12 +30,78 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(WatcherState::default())
         .invoke_handler(tauri::generate_handler![
             greet,
             load_workspace_snapshot,
+            load_workspace_metadata,
+            load_single_book,
             save_workspace_snapshot,
-            delete_book_file
+            delete_book_file,
+            list_backups,
+            restore_backup,
+            watch_workspace,
+            unwatch_workspace,
+            export_book_to_csv,
+            import_csv_as_book,
+            create_book,
+            delete_book,
+            list_trash,
+            restore_from_trash,
+            rename_book,
+            reorder_books,
+            duplicate_workspace,
+            diff_workspaces,
+            load_workspace_snapshot_with_progress,
+            issue_load_id,
+            cancel_load,
+            search_workspace,
+            replace_in_workspace,
+            export_bundle,
+            import_bundle,
+            relocate_workspace,
+            workspace_stats,
+            set_workspace_readonly,
+            export_book_to_markdown,
+            acquire_workspace_lock,
+            release_workspace_lock,
+            append_journal_entry,
+            recover_from_journal,
+            discard_journal,
+            export_workspace_to_sqlite,
+            import_csv_directory,
+            enqueue_save,
+            flush_save_queue,
+            diagnose_workspace,
+            prune_orphan_books,
+            load_workspace_snapshot_cached,
+            invalidate_cache,
+            export_cell_range,
+            transform_book_cells,
+            validate_workspace_against_schema,
+            merge_books,
+            export_workspace_zip,
+            import_workspace_zip,
+            insert_rows,
+            delete_rows,
+            insert_cols,
+            delete_cols,
+            create_workspace_from_template,
+            evaluate_book_formulas,
+            load_with_patches,
+            trim_empty_book,
+            scan_workspace_index,
+            inspect_book_types,
+            search_all_workspaces,
+            normalize_workspace,
+            parse_clipboard_table
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|_, event| {
+            if let RunEvent::Exit = event {
+                flush_save_queue();
+                release_held_locks();
+            }
+        });
 }
@@ -0,0 +1,289 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_compressed, parse_json_bytes, write_atomic};
+use super::paths::{normalize_lexically, workspace_dir_of};
+use super::WORKSPACE_FILE_NAME;
+use chrono::{NaiveDateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const BACKUP_DIR: &str = ".sheet-up-backups";
+const BACKUP_EXTENSION: &str = "bak";
+const BACKUP_ID_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupOptions {
+    pub enabled: bool,
+    /// How many backups to keep per file; older ones are deleted.
+    pub generations: usize,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            generations: 5,
+        }
+    }
+}
+
+/// Backups of `<workspace>/books/a.json` live in
+/// `<workspace>/.sheet-up-backups/books/a.json/<timestamp>.bak`.
+pub fn backup_dir_for(workspace_dir: &Path, file_path: &Path) -> PathBuf {
+    let relative = normalize_lexically(workspace_dir)
+        .zip(normalize_lexically(file_path))
+        .and_then(|(dir, path)| path.strip_prefix(dir).ok().map(Path::to_path_buf))
+        .or_else(|| file_path.file_name().map(PathBuf::from))
+        .unwrap_or_default();
+    workspace_dir.join(BACKUP_DIR).join(relative)
+}
+
+/// Sort key for `<timestamp>[-<counter>]` ids: the timestamp sorts
+/// lexically, the collision counter numerically.
+fn backup_sort_key(path: &Path) -> (String, u32) {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match stem.split_once('-') {
+        Some((timestamp, counter)) => (timestamp.to_string(), counter.parse().unwrap_or(0)),
+        None => (stem, 0),
+    }
+}
+
+/// A timestamp id that sorts after every existing backup, even when several
+/// are taken within the same millisecond.
+fn new_backup_id(existing: &[PathBuf]) -> String {
+    let timestamp = Utc::now().format(BACKUP_ID_FORMAT).to_string();
+    match existing.last().map(|path| backup_sort_key(path)) {
+        Some((latest, counter)) if latest >= timestamp => format!("{}-{}", latest, counter + 1),
+        _ => timestamp,
+    }
+}
+
+/// Backup file paths for `file_path`, oldest first.
+pub fn existing_backups(workspace_dir: &Path, file_path: &Path) -> WorkspaceResult<Vec<PathBuf>> {
+    let dir = backup_dir_for(workspace_dir, file_path);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(WorkspaceError::io("read", &dir, err)),
+    };
+    let mut backups: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == BACKUP_EXTENSION)
+        })
+        .collect();
+    backups.sort_by_key(|path| backup_sort_key(path));
+    Ok(backups)
+}
+
+/// Copies the current content of `file_path` into the backup directory and
+/// prunes old generations. Returns `None` when there is nothing to back up.
+pub fn create_backup(
+    workspace_dir: &Path,
+    file_path: &Path,
+    generations: usize,
+) -> WorkspaceResult<Option<PathBuf>> {
+    if !file_path.is_file() {
+        return Ok(None);
+    }
+
+    let dir = backup_dir_for(workspace_dir, file_path);
+    fs::create_dir_all(&dir).map_err(|err| WorkspaceError::io("create", &dir, err))?;
+    let existing = existing_backups(workspace_dir, file_path)?;
+    let backup_path = dir.join(format!("{}.{}", new_backup_id(&existing), BACKUP_EXTENSION));
+    fs::copy(file_path, &backup_path).map_err(|err| WorkspaceError::io("copy", file_path, err))?;
+
+    let backups = existing_backups(workspace_dir, file_path)?;
+    let excess = backups.len().saturating_sub(generations.max(1));
+    for old in &backups[..excess] {
+        fs::remove_file(old).map_err(|err| WorkspaceError::io("delete", old, err))?;
+    }
+
+    Ok(Some(backup_path))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub id: String,
+    pub path: String,
+    /// ISO8601 time parsed from the id, when it has the expected format.
+    pub created_at: Option<String>,
+    pub size_bytes: u64,
+    /// Whether the backup still parses as JSON.
+    pub valid: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+    pub restored_id: String,
+    /// Backup of the content that was replaced, so the restore can be undone.
+    pub previous_backup_id: Option<String>,
+}
+
+/// Backups are stored relative to the workspace, so find the nearest
+/// ancestor holding a `workspace.json`.
+/// Encrypted backups cannot be checked without the passphrase and are
+/// assumed intact.
+fn is_restorable(contents: &[u8], file_path: &Path, backup_path: &Path) -> bool {
+    !matches!(
+        parse_json_bytes(contents, is_compressed(file_path), None, backup_path),
+        Err(err) if !matches!(err, WorkspaceError::PassphraseRequired { .. })
+    )
+}
+
+pub(super) fn find_workspace_dir(file_path: &Path) -> PathBuf {
+    file_path
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join(WORKSPACE_FILE_NAME).is_file())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| workspace_dir_of(file_path))
+}
+
+pub(super) fn backup_id_of(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn created_at_of(id: &str) -> Option<String> {
+    let timestamp = id.split_once('-').map_or(id, |(timestamp, _)| timestamp);
+    NaiveDateTime::parse_from_str(timestamp, BACKUP_ID_FORMAT)
+        .ok()
+        .map(|time| time.and_utc().to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+#[tauri::command]
+pub fn list_backups(path: String) -> WorkspaceResult<Vec<BackupInfo>> {
+    let file_path = PathBuf::from(path);
+    let workspace_dir = find_workspace_dir(&file_path);
+
+    let mut backups = Vec::new();
+    for backup_path in existing_backups(&workspace_dir, &file_path)?
+        .into_iter()
+        .rev()
+    {
+        let size_bytes = fs::metadata(&backup_path)
+            .map_err(|err| WorkspaceError::io("stat", &backup_path, err))?
+            .len();
+        let id = backup_id_of(&backup_path);
+        backups.push(BackupInfo {
+            created_at: created_at_of(&id),
+            id,
+            path: backup_path.to_string_lossy().into_owned(),
+            size_bytes,
+            valid: fs::read(&backup_path)
+                .is_ok_and(|bytes| is_restorable(&bytes, &file_path, &backup_path)),
+        });
+    }
+    Ok(backups)
+}
+
+#[tauri::command]
+pub fn restore_backup(path: String, backup_id: String) -> WorkspaceResult<RestoreResult> {
+    let file_path = PathBuf::from(path);
+    let workspace_dir = find_workspace_dir(&file_path);
+    let not_found = || WorkspaceError::BackupNotFound {
+        path: file_path.display().to_string(),
+        backup_id: backup_id.clone(),
+    };
+
+    // Ids are plain file stems; anything path-like cannot name a backup.
+    if backup_id.is_empty() || backup_id.contains(['/', '\\', '.']) {
+        return Err(not_found());
+    }
+    let backup_path = backup_dir_for(&workspace_dir, &file_path)
+        .join(format!("{}.{}", backup_id, BACKUP_EXTENSION));
+    if !backup_path.is_file() {
+        return Err(not_found());
+    }
+
+    // Read first: backing up the current file may prune the one we restore.
+    let contents =
+        fs::read(&backup_path).map_err(|err| WorkspaceError::io("read", &backup_path, err))?;
+    if !is_restorable(&contents, &file_path, &backup_path) {
+        parse_json_bytes(&contents, is_compressed(&file_path), None, &backup_path)?;
+    }
+
+    let previous_backup_id = create_backup(&workspace_dir, &file_path, usize::MAX)?
+        .map(|previous| backup_id_of(&previous));
+    write_atomic(&file_path, |file| file.write_all(&contents))
+        .map_err(|err| WorkspaceError::io("write", &file_path, err))?;
+
+    Ok(RestoreResult {
+        restored_id: backup_id,
+        previous_backup_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_backup_keeps_requested_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("books/a.json");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+
+        for round in 0..4 {
+            fs::write(&file, format!("{{\"round\":{}}}", round)).unwrap();
+            create_backup(dir.path(), &file, 2).unwrap().unwrap();
+        }
+
+        let backups = existing_backups(dir.path(), &file).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert!(backups[0].starts_with(dir.path().join(".sheet-up-backups/books/a.json")));
+        assert_eq!(fs::read_to_string(&backups[1]).unwrap(), "{\"round\":3}");
+    }
+
+    #[test]
+    fn create_backup_skips_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("books/new.json");
+        assert!(create_backup(dir.path(), &file, 3).unwrap().is_none());
+    }
+
+    #[test]
+    fn restore_backup_swaps_content_and_keeps_undo_point() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(WORKSPACE_FILE_NAME), "{}").unwrap();
+        let file = dir.path().join("books/a.json");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "{\"v\":1}").unwrap();
+        create_backup(dir.path(), &file, 5).unwrap();
+        fs::write(&file, "{\"v\":").unwrap();
+
+        let path = file.to_string_lossy().into_owned();
+        let backups = list_backups(path.clone()).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].valid);
+        assert!(backups[0].created_at.is_some());
+
+        let result = restore_backup(path.clone(), backups[0].id.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{\"v\":1}");
+        assert!(result.previous_backup_id.is_some());
+
+        let backups = list_backups(path.clone()).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert!(!backups[0].valid);
+
+        assert!(matches!(
+            restore_backup(path.clone(), "missing".into()),
+            Err(WorkspaceError::BackupNotFound { .. })
+        ));
+        assert!(matches!(
+            restore_backup(path, "../a".into()),
+            Err(WorkspaceError::BackupNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,721 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_compressed, read_workspace_json, FileEncoding};
+use super::paths::{normalize_lexically, resolve_data_path, workspace_dir_of};
+use super::readonly::ensure_writable;
+use super::schema::validate_workspace;
+use super::trash::{move_to_trash, take_from_trash};
+use super::{book_file_path, rename_tracked, watcher, write_tracked};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BOOKS_DIR: &str = "books";
+pub(super) const DEFAULT_SHEET_NAME: &str = "シート1";
+pub const DEFAULT_ROWS: usize = 100;
+pub const DEFAULT_COLS: usize = 26;
+const FALLBACK_STEM: &str = "book";
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewBook {
+    pub book_id: String,
+    pub sheet_id: String,
+    pub data_path: String,
+    pub file_path: String,
+}
+
+/// Turns a book name into a file stem that is valid on every platform:
+/// separators, control and Windows-forbidden characters are dropped,
+/// trailing dots and spaces trimmed, and reserved device names (`CON`,
+/// `NUL`, ...) suffixed with `_`.
+pub fn sanitize_file_stem(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| {
+            !c.is_control() && !matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*')
+        })
+        .collect();
+    let stem = cleaned.trim().trim_end_matches(['.', ' ']);
+    if stem.is_empty() {
+        return FALLBACK_STEM.to_string();
+    }
+    // Windows treats `NUL.txt` like `NUL`, so compare the part before the
+    // first dot.
+    let device = stem.split('.').next().unwrap_or(stem).trim_end();
+    if WINDOWS_RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(device))
+    {
+        return format!("{}_", stem);
+    }
+    stem.to_string()
+}
+
+/// Splits `a.json` / `a.json.gz` into stem and extension; other names keep
+/// their last extension.
+pub(super) fn split_book_file_name(file_name: &str) -> (&str, &str) {
+    let extension = if is_compressed(Path::new(file_name)) {
+        "json.gz"
+    } else {
+        file_name
+            .rsplit_once('.')
+            .map_or("json", |(_, extension)| extension)
+    };
+    let stem = file_name
+        .strip_suffix(extension)
+        .and_then(|stem| stem.strip_suffix('.'))
+        .unwrap_or(file_name);
+    (stem, extension)
+}
+
+/// The workspace entry's `name`, which the frontend's bookFactory derives
+/// from the file name it would pick.
+pub(super) fn entry_name(name: &str) -> String {
+    format!("{}.json", name.replace('/', "／"))
+}
+
+pub(super) fn now_rfc3339() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// `books/<stem>.<extension>`, adding ` (2)`, ` (3)` ... until the path is
+/// neither on disk nor referenced by the workspace.
+pub(super) fn unique_data_path(
+    workspace_dir: &Path,
+    workspace: &Value,
+    name: &str,
+    extension: &str,
+) -> String {
+    let taken: Vec<String> = workspace["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|book| book["dataPath"].as_str())
+        .map(|data_path| data_path.replace('\\', "/").to_lowercase())
+        .collect();
+    let stem = sanitize_file_stem(name);
+    (1..)
+        .map(|attempt| match attempt {
+            1 => format!("{}/{}.{}", BOOKS_DIR, stem, extension),
+            _ => format!("{}/{} ({}).{}", BOOKS_DIR, stem, attempt, extension),
+        })
+        .find(|data_path| {
+            // Case-insensitive so the result is also unique on Windows and macOS.
+            !taken.contains(&data_path.to_lowercase()) && !workspace_dir.join(data_path).exists()
+        })
+        .expect("some suffix is always free")
+}
+
+/// What a freshly created book file is made of.
+pub(super) struct BookSkeleton<'a> {
+    pub schema_version: &'a str,
+    pub book_id: &'a str,
+    pub name: &'a str,
+    pub sheet_id: &'a str,
+    pub grid_size: (usize, usize),
+    pub now: &'a str,
+}
+
+impl BookSkeleton<'_> {
+    /// Book file with one sheet holding `rows`.
+    pub fn build(&self, rows: Map<String, Value>) -> Value {
+        json!({
+            "schemaVersion": self.schema_version,
+            "book": {
+                "id": self.book_id,
+                "name": self.name,
+                "createdAt": self.now,
+                "updatedAt": self.now,
+                "properties": { "defaultFormat": "plain", "locked": false }
+            },
+            "sheets": [{
+                "id": self.sheet_id,
+                "name": DEFAULT_SHEET_NAME,
+                "gridSize": { "rows": self.grid_size.0, "cols": self.grid_size.1 },
+                "settings": {},
+                "rows": rows
+            }]
+        })
+    }
+}
+
+/// Writes a new single-sheet book with the given cells and appends its entry
+/// to `workspace.json`. The book file is removed again if the workspace
+/// cannot be saved.
+pub fn add_book(
+    workspace_path: &Path,
+    name: &str,
+    grid_size: (usize, usize),
+    rows: Map<String, Value>,
+) -> WorkspaceResult<NewBook> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(WorkspaceError::InvalidOption {
+            name: "name",
+            message: "book name must not be empty".into(),
+        });
+    }
+    let mut workspace = read_workspace_json(workspace_path)?;
+    ensure_writable(workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+    let workspace_dir = workspace_dir_of(workspace_path);
+    let data_path = unique_data_path(&workspace_dir, &workspace, name, "json");
+    let book_path = workspace_dir.join(&data_path);
+
+    let now = now_rfc3339();
+    let book_id = format!("book-{}", uuid::Uuid::new_v4());
+    let sheet_id = format!("sheet-{}", uuid::Uuid::new_v4());
+    let book = BookSkeleton {
+        schema_version: workspace["schemaVersion"].as_str().unwrap_or("1.0.0"),
+        book_id: &book_id,
+        name,
+        sheet_id: &sheet_id,
+        grid_size,
+        now: &now,
+    }
+    .build(rows);
+
+    let books = workspace["books"]
+        .as_array_mut()
+        .expect("validated workspace has a books array");
+    // Same shape as the frontend's bookFactory.
+    books.push(json!({
+        "id": book_id,
+        "name": entry_name(name),
+        "folderId": null,
+        "order": books.len(),
+        "dataPath": data_path,
+        "activeSheetId": sheet_id,
+        "createdAt": now,
+        "updatedAt": now
+    }));
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert("updatedAt".into(), Value::String(now));
+    }
+
+    write_tracked(&book_path, &book, FileEncoding::default())?;
+    if let Err(err) = write_tracked(workspace_path, &workspace, FileEncoding::default()) {
+        let _ = fs::remove_file(&book_path);
+        return Err(err);
+    }
+
+    Ok(NewBook {
+        book_id,
+        sheet_id,
+        data_path,
+        file_path: book_path.to_string_lossy().into_owned(),
+    })
+}
+
+/// Replaces the contents of the book at `index` with a single sheet holding
+/// `rows`, keeping its id, entry and file. Its `activeSheetId` moves to the
+/// new sheet.
+pub(super) fn overwrite_book(
+    workspace_path: &Path,
+    index: usize,
+    name: &str,
+    grid_size: (usize, usize),
+    rows: Map<String, Value>,
+) -> WorkspaceResult<NewBook> {
+    let mut workspace = read_workspace_json(workspace_path)?;
+    ensure_writable(workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+    let workspace_dir = workspace_dir_of(workspace_path);
+    let book_ref = &workspace["books"][index];
+    let book_path = book_file_path(&workspace_dir, book_ref, index)?;
+    let book_id = book_ref["id"].as_str().unwrap_or_default().to_string();
+    let data_path = book_ref["dataPath"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+
+    let now = now_rfc3339();
+    let sheet_id = format!("sheet-{}", uuid::Uuid::new_v4());
+    let book = BookSkeleton {
+        schema_version: workspace["schemaVersion"].as_str().unwrap_or("1.0.0"),
+        book_id: &book_id,
+        name: name.trim(),
+        sheet_id: &sheet_id,
+        grid_size,
+        now: &now,
+    }
+    .build(rows);
+    if let Some(entry) = workspace["books"][index].as_object_mut() {
+        entry.insert("activeSheetId".into(), Value::String(sheet_id.clone()));
+        entry.insert("updatedAt".into(), Value::String(now.clone()));
+    }
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert("updatedAt".into(), Value::String(now));
+    }
+
+    write_tracked(&book_path, &book, FileEncoding::default())?;
+    write_tracked(workspace_path, &workspace, FileEncoding::default())?;
+    Ok(NewBook {
+        book_id,
+        sheet_id,
+        data_path,
+        file_path: book_path.to_string_lossy().into_owned(),
+    })
+}
+
+/// Creates an empty book under `books/` and registers it in
+/// `workspace.json`. The frontend should reload the workspace afterwards.
+#[tauri::command]
+pub fn create_book(workspace_path: String, name: String) -> WorkspaceResult<NewBook> {
+    add_book(
+        &PathBuf::from(workspace_path),
+        &name,
+        (DEFAULT_ROWS, DEFAULT_COLS),
+        Map::new(),
+    )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteBookResult {
+    /// Pass to `restore_from_trash` to undo; `None` for permanent deletes.
+    pub trash_id: Option<String>,
+    /// `false` when the file was left alone because another book still
+    /// references it, its `dataPath` is unusable, or it did not exist.
+    pub file_removed: bool,
+}
+
+fn resolved_file(workspace_dir: &Path, book_ref: &Value, index: usize) -> Option<PathBuf> {
+    let path = book_file_path(workspace_dir, book_ref, index).ok()?;
+    normalize_lexically(&path)
+}
+
+/// Removes the book's entry from `workspace.json`, then deletes its file or,
+/// with `to_trash`, moves it to `.sheet-up-trash/`. Later books in the same
+/// folder move up one `order` slot.
+#[tauri::command]
+pub fn delete_book(
+    workspace_path: String,
+    book_id: String,
+    to_trash: bool,
+) -> WorkspaceResult<DeleteBookResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let mut workspace = read_workspace_json(&workspace_path)?;
+    ensure_writable(&workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+
+    let books = workspace["books"]
+        .as_array_mut()
+        .expect("validated workspace has a books array");
+    let index = books
+        .iter()
+        .position(|book| book["id"] == book_id.as_str())
+        .ok_or_else(|| WorkspaceError::BookNotFound {
+            path: workspace_path.display().to_string(),
+            book_id: book_id.clone(),
+        })?;
+    let book_ref = books.remove(index);
+    let file = resolved_file(&workspace_dir, &book_ref, index).filter(|file| {
+        let shared = books
+            .iter()
+            .enumerate()
+            .any(|(other, book)| resolved_file(&workspace_dir, book, other).as_ref() == Some(file));
+        !shared && file.is_file()
+    });
+
+    let removed_order = book_ref["order"].as_u64();
+    for book in books.iter_mut() {
+        let order = book["order"].as_u64();
+        if book["folderId"] == book_ref["folderId"] && order > removed_order {
+            book["order"] = json!(order.unwrap_or_default() - 1);
+        }
+    }
+    let now = now_rfc3339();
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert("updatedAt".into(), Value::String(now));
+    }
+    if let Some(recent) = workspace["workspace"]["settings"]["recentBookIds"].as_array_mut() {
+        recent.retain(|id| id != book_id.as_str());
+    }
+
+    let trash_id = if to_trash {
+        Some(move_to_trash(&workspace_dir, &book_ref, file.as_deref())?)
+    } else {
+        None
+    };
+    if let Err(err) = write_tracked(&workspace_path, &workspace, FileEncoding::default()) {
+        if let (Some(trash_id), Some(file)) = (&trash_id, &file) {
+            let _ = take_from_trash(&workspace_dir, trash_id, file);
+        }
+        return Err(err);
+    }
+    if let (false, Some(file)) = (to_trash, &file) {
+        watcher::self_remove(file);
+        fs::remove_file(file).map_err(|err| WorkspaceError::io("delete", file, err))?;
+    }
+
+    Ok(DeleteBookResult {
+        trash_id,
+        file_removed: file.is_some(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameBookResult {
+    pub data_path: String,
+    pub file_path: String,
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    // Case-insensitive so a case-only rename is not seen as a collision on
+    // Windows and macOS.
+    let key =
+        |path: &Path| normalize_lexically(path).map(|path| path.to_string_lossy().to_lowercase());
+    key(a) == key(b)
+}
+
+/// Renames the book's entry and its file together. The file keeps its
+/// directory and extension; when the new file name is taken nothing is
+/// changed.
+#[tauri::command]
+pub fn rename_book(
+    workspace_path: String,
+    book_id: String,
+    new_name: String,
+) -> WorkspaceResult<RenameBookResult> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err(WorkspaceError::InvalidOption {
+            name: "newName",
+            message: "book name must not be empty".into(),
+        });
+    }
+    let workspace_path = PathBuf::from(workspace_path);
+    let mut workspace = read_workspace_json(&workspace_path)?;
+    ensure_writable(&workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+
+    let books = workspace["books"]
+        .as_array_mut()
+        .expect("validated workspace has a books array");
+    let index = books
+        .iter()
+        .position(|book| book["id"] == book_id.as_str())
+        .ok_or_else(|| WorkspaceError::BookNotFound {
+            path: workspace_path.display().to_string(),
+            book_id: book_id.clone(),
+        })?;
+    let source = book_file_path(&workspace_dir, &books[index], index)?;
+    let old_data_path = books[index]["dataPath"]
+        .as_str()
+        .unwrap_or_default()
+        .replace('\\', "/");
+    let (parent, file_name) = old_data_path
+        .rsplit_once('/')
+        .unwrap_or(("", &old_data_path));
+    let (_, extension) = split_book_file_name(file_name);
+    let file_name = format!("{}.{}", sanitize_file_stem(new_name), extension);
+    let data_path = match parent {
+        "" => file_name,
+        parent => format!("{}/{}", parent, file_name),
+    };
+    let target = resolve_data_path(&workspace_dir, &data_path, index)?;
+
+    if !same_file(&source, &target) {
+        let referenced = books.iter().enumerate().any(|(other, book)| {
+            book_file_path(&workspace_dir, book, other).is_ok_and(|path| same_file(&path, &target))
+        });
+        if referenced || target.exists() {
+            return Err(WorkspaceError::AlreadyExists {
+                path: target.display().to_string(),
+            });
+        }
+    }
+
+    let now = now_rfc3339();
+    let book_ref = &mut books[index];
+    book_ref["name"] = json!(entry_name(new_name));
+    book_ref["dataPath"] = json!(data_path);
+    book_ref["updatedAt"] = json!(now);
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert("updatedAt".into(), Value::String(now));
+    }
+
+    let moved = source != target;
+    if moved {
+        rename_tracked(&source, &target)?;
+    }
+    if let Err(err) = write_tracked(&workspace_path, &workspace, FileEncoding::default()) {
+        if moved {
+            let _ = rename_tracked(&target, &source);
+        }
+        return Err(err);
+    }
+
+    Ok(RenameBookResult {
+        data_path,
+        file_path: target.to_string_lossy().into_owned(),
+    })
+}
+
+/// Rearranges `books` to follow `ordered_book_ids`, which must name every
+/// book exactly once, and renumbers `order` within each folder to match.
+/// Book files are not touched.
+#[tauri::command]
+pub fn reorder_books(workspace_path: String, ordered_book_ids: Vec<String>) -> WorkspaceResult<()> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let mut workspace = read_workspace_json(&workspace_path)?;
+    ensure_writable(&workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+
+    let books = workspace["books"]
+        .as_array_mut()
+        .expect("validated workspace has a books array");
+    let count = books.len();
+    let mut by_id: HashMap<String, Value> = books
+        .drain(..)
+        .map(|book| (book["id"].as_str().unwrap_or_default().to_string(), book))
+        .collect();
+    if by_id.len() != count {
+        return Err(WorkspaceError::invalid_schema(
+            "workspace.json lists the same book id more than once",
+        ));
+    }
+    let mut seen = HashSet::new();
+    let (mut duplicated, mut unknown) = (Vec::new(), Vec::new());
+    for id in &ordered_book_ids {
+        if !seen.insert(id.as_str()) {
+            if !duplicated.contains(id) {
+                duplicated.push(id.clone());
+            }
+        } else if !by_id.contains_key(id) {
+            unknown.push(id.clone());
+        }
+    }
+    let mut missing: Vec<String> = by_id
+        .keys()
+        .filter(|id| !seen.contains(id.as_str()))
+        .cloned()
+        .collect();
+    if !(missing.is_empty() && duplicated.is_empty() && unknown.is_empty()) {
+        missing.sort();
+        return Err(WorkspaceError::InvalidBookOrder {
+            path: workspace_path.display().to_string(),
+            missing,
+            duplicated,
+            unknown,
+        });
+    }
+
+    let mut next_order: HashMap<String, usize> = HashMap::new();
+    for id in &ordered_book_ids {
+        let mut book = by_id.remove(id).expect("every id was checked above");
+        let slot = next_order.entry(book["folderId"].to_string()).or_default();
+        book["order"] = json!(*slot);
+        *slot += 1;
+        books.push(book);
+    }
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert("updatedAt".into(), Value::String(now_rfc3339()));
+    }
+    write_tracked(&workspace_path, &workspace, FileEncoding::default())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+    use crate::workspace::schema::validate_book;
+
+    #[test]
+    fn file_stems_are_portable() {
+        for (name, stem) in [
+            ("売上 2024", "売上 2024"),
+            ("a/b\\c", "abc"),
+            ("what?*<>|\"", "what"),
+            ("tab\there\n", "tabhere"),
+            ("trailing...  ", "trailing"),
+            ("CON", "CON_"),
+            ("nul.backup", "nul.backup_"),
+            ("Console", "Console"),
+            ("///", "book"),
+        ] {
+            assert_eq!(sanitize_file_stem(name), stem, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn create_book_writes_file_and_registers_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "workspace": {}, "books": [] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let path = workspace_path.to_string_lossy().into_owned();
+
+        let first = create_book(path.clone(), "Plan".into()).unwrap();
+        let second = create_book(path.clone(), " plan ".into()).unwrap();
+        assert_eq!(first.data_path, "books/Plan.json");
+        assert_eq!(second.data_path, "books/plan (2).json");
+
+        let book = read_json_file(Path::new(&first.file_path)).unwrap();
+        validate_book(&book).unwrap();
+        assert_eq!(book["sheets"][0]["id"], json!(first.sheet_id));
+
+        let workspace = read_json_file(&workspace_path).unwrap();
+        let ids: Vec<&str> = workspace["books"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|book| book["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec![first.book_id.as_str(), second.book_id.as_str()]);
+
+        assert!(matches!(
+            create_book(path, "  ".into()),
+            Err(WorkspaceError::InvalidOption { .. })
+        ));
+    }
+
+    #[test]
+    fn delete_book_keeps_files_other_books_still_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let workspace = json!({
+            "workspace": { "settings": { "recentBookIds": ["book-2", "book-1"] } },
+            "books": [
+                { "id": "book-1", "name": "1", "folderId": null, "order": 0, "dataPath": "books/a.json" },
+                { "id": "book-2", "name": "2", "folderId": null, "order": 1, "dataPath": "books/./a.json" },
+                { "id": "book-3", "name": "3", "folderId": null, "order": 2, "dataPath": "books/c.json" }
+            ]
+        });
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+        for name in ["a", "c"] {
+            let book = dir.path().join(format!("books/{}.json", name));
+            write_json_file(&book, &json!({}), FileEncoding::default()).unwrap();
+        }
+        let path = workspace_path.to_string_lossy().into_owned();
+
+        let shared = delete_book(path.clone(), "book-1".into(), false).unwrap();
+        assert!(!shared.file_removed);
+        assert!(dir.path().join("books/a.json").exists());
+
+        let removed = delete_book(path.clone(), "book-3".into(), false).unwrap();
+        assert!(removed.file_removed && removed.trash_id.is_none());
+        assert!(!dir.path().join("books/c.json").exists());
+
+        let workspace = read_json_file(&workspace_path).unwrap();
+        assert_eq!(
+            workspace["books"],
+            json!([
+                { "id": "book-2", "name": "2", "folderId": null, "order": 0, "dataPath": "books/./a.json" }
+            ])
+        );
+        assert_eq!(
+            workspace["workspace"]["settings"]["recentBookIds"],
+            json!(["book-2"])
+        );
+        assert!(matches!(
+            delete_book(path, "book-1".into(), true),
+            Err(WorkspaceError::BookNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn rename_book_moves_file_and_keeps_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let workspace = json!({
+            "workspace": {},
+            "books": [
+                { "id": "book-1", "name": "old.json", "dataPath": "books/old.json.gz" },
+                { "id": "book-2", "name": "taken.json", "dataPath": "books/taken.json.gz" }
+            ]
+        });
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+        for name in ["old", "taken"] {
+            let book = dir.path().join(format!("books/{}.json.gz", name));
+            write_json_file(&book, &json!({ "name": name }), FileEncoding::default()).unwrap();
+        }
+        let path = workspace_path.to_string_lossy().into_owned();
+
+        assert!(matches!(
+            rename_book(path.clone(), "book-1".into(), "Taken".into()),
+            Err(WorkspaceError::AlreadyExists { .. })
+        ));
+        assert!(dir.path().join("books/old.json.gz").exists());
+        assert_eq!(read_json_file(&workspace_path).unwrap(), workspace);
+
+        let renamed = rename_book(path.clone(), "book-1".into(), "新/名前".into()).unwrap();
+        assert_eq!(renamed.data_path, "books/新名前.json.gz");
+        assert!(!dir.path().join("books/old.json.gz").exists());
+        assert_eq!(
+            read_json_file(Path::new(&renamed.file_path)).unwrap(),
+            json!({ "name": "old" })
+        );
+        let workspace = read_json_file(&workspace_path).unwrap();
+        assert_eq!(workspace["books"][0]["name"], "新／名前.json");
+        assert_eq!(workspace["books"][0]["dataPath"], "books/新名前.json.gz");
+
+        assert!(matches!(
+            rename_book(path, "book-9".into(), "x".into()),
+            Err(WorkspaceError::BookNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn reorder_books_rejects_incomplete_orders() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let books: Vec<Value> = ["a", "b", "c"]
+            .iter()
+            .map(|id| {
+                json!({ "id": id, "name": id, "folderId": null, "order": 0,
+                        "dataPath": format!("books/{}.json", id) })
+            })
+            .collect();
+        let workspace = json!({ "workspace": {}, "books": books });
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+        let path = workspace_path.to_string_lossy().into_owned();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        match reorder_books(path.clone(), ids(&["c", "x", "c"])) {
+            Err(WorkspaceError::InvalidBookOrder {
+                missing,
+                duplicated,
+                unknown,
+                ..
+            }) => {
+                assert_eq!(missing, ids(&["a", "b"]));
+                assert_eq!(duplicated, ids(&["c"]));
+                assert_eq!(unknown, ids(&["x"]));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(read_json_file(&workspace_path).unwrap(), workspace);
+
+        reorder_books(path, ids(&["c", "a", "b"])).unwrap();
+        let workspace = read_json_file(&workspace_path).unwrap();
+        let order: Vec<(&str, u64)> = workspace["books"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|book| {
+                (
+                    book["id"].as_str().unwrap(),
+                    book["order"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(order, vec![("c", 0), ("a", 1), ("b", 2)]);
+    }
+}
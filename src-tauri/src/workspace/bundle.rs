@@ -0,0 +1,295 @@
+use super::duplicate::{move_into_place, staging_dir_for};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_compressed, read_json_file, write_json_file, FileEncoding, SizeLimits};
+use super::manifest::{self, MANIFEST_FILE_NAME};
+use super::paths::{resolve_data_path, workspace_dir_of};
+use super::schema::{validate_book, validate_workspace};
+use super::{book_file_path, load_book, load_workspace_file, write_tracked, WORKSPACE_FILE_NAME};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format of the bundle file; bumped when its layout changes.
+pub(super) const BUNDLE_VERSION: u64 = 1;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportBundleResult {
+    pub output_path: String,
+    pub books: usize,
+    pub compressed: bool,
+}
+
+/// Writes `workspace.json` and every book into one JSON file shaped
+/// `{ version, workspace, books: [{ dataPath, data }] }`. An `output_path`
+/// ending in `.json.gz` (e.g. `.bundle.json.gz`) is gzip-compressed. Every
+/// book is validated first and the export stops at the first one that is
+/// unreadable, invalid, or fails its manifest checksum. Encrypted books need
+/// their passphrase and are stored decrypted.
+#[tauri::command]
+pub fn export_bundle(
+    workspace_path: String,
+    output_path: String,
+    passphrase: Option<String>,
+) -> WorkspaceResult<ExportBundleResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let output_path = PathBuf::from(output_path);
+    let workspace = load_workspace_file(&workspace_path, SizeLimits::default().workspace)?.data;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+
+    let mut books: Vec<Value> = Vec::new();
+    let mut book_paths: Vec<String> = Vec::new();
+    for (index, book_ref) in workspace["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let data_path = book_ref["dataPath"].as_str().unwrap_or_default();
+        if books.iter().any(|book| book["dataPath"] == data_path) {
+            continue;
+        }
+        let book = book_file_path(&workspace_dir, book_ref, index)
+            .and_then(|path| load_book(&path, passphrase.as_deref(), SizeLimits::default().book))
+            .map_err(|err| WorkspaceError::BookLoad {
+                index,
+                source: Box::new(err),
+            })?;
+        book_paths.push(book.file_path);
+        books.push(json!({ "dataPath": data_path, "data": book.data }));
+    }
+    if let Some(mismatch) =
+        manifest::verify_books(&workspace_dir, book_paths.iter().map(String::as_str))
+            .into_iter()
+            .next()
+    {
+        return Err(mismatch);
+    }
+
+    let count = books.len();
+    let bundle = json!({
+        "version": BUNDLE_VERSION,
+        "workspace": workspace,
+        "books": books,
+    });
+    write_tracked(&output_path, &bundle, FileEncoding::default())?;
+    Ok(ExportBundleResult {
+        output_path: output_path.to_string_lossy().into_owned(),
+        books: count,
+        compressed: is_compressed(&output_path),
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportBundleResult {
+    pub workspace_path: String,
+    pub books: usize,
+    /// Non-fatal problems, e.g. a checksum manifest that could not be updated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Book files of a bundle relative to the destination, with their data.
+/// `dataPath`s that would land outside the destination, or on the
+/// workspace file itself, are rejected before anything is written.
+fn bundle_books(bundle: &Value) -> WorkspaceResult<Vec<(PathBuf, &Value)>> {
+    let mut books = Vec::new();
+    for (index, book) in bundle["books"].as_array().into_iter().flatten().enumerate() {
+        let data_path = book["dataPath"].as_str().ok_or_else(|| {
+            WorkspaceError::invalid_schema(format!(
+                "books[{}].dataPath is missing or invalid",
+                index
+            ))
+        })?;
+        let relative = resolve_data_path(Path::new(""), data_path, index)?;
+        let reserved = [WORKSPACE_FILE_NAME, MANIFEST_FILE_NAME]
+            .iter()
+            .any(|name| relative.to_string_lossy().eq_ignore_ascii_case(name));
+        if relative.as_os_str().is_empty() || reserved {
+            return Err(WorkspaceError::invalid_schema(format!(
+                "books[{}].dataPath {} is not a book file",
+                index, data_path
+            )));
+        }
+        validate_book(&book["data"])?;
+        books.push((relative, &book["data"]));
+    }
+    Ok(books)
+}
+
+/// Unpacks a bundle from `export_bundle` into `dest_dir` as a regular
+/// workspace. Existing files are an `alreadyExists` error unless
+/// `overwrite` is set. Files are assembled in a staging directory and moved
+/// in once complete; with `overwrite`, files replaced before a failure are
+/// not restored.
+#[tauri::command]
+pub fn import_bundle(
+    bundle_path: String,
+    dest_dir: String,
+    overwrite: Option<bool>,
+) -> WorkspaceResult<ImportBundleResult> {
+    let bundle_path = PathBuf::from(bundle_path);
+    let dest_dir = PathBuf::from(dest_dir);
+    let bundle = read_json_file(&bundle_path)?;
+    if bundle["version"].as_u64() != Some(BUNDLE_VERSION) {
+        return Err(WorkspaceError::UnsupportedBundleVersion {
+            path: bundle_path.display().to_string(),
+            version: bundle["version"].to_string(),
+        });
+    }
+    let workspace = &bundle["workspace"];
+    validate_workspace(workspace)?;
+    let books = bundle_books(&bundle)?;
+
+    let workspace_file = Path::new(WORKSPACE_FILE_NAME);
+    let mut files: Vec<PathBuf> = books.iter().map(|(path, _)| path.clone()).collect();
+    files.push(workspace_file.to_path_buf());
+    if !overwrite.unwrap_or(false) {
+        if let Some(existing) = files
+            .iter()
+            .map(|relative| dest_dir.join(relative))
+            .find(|target| target.exists())
+        {
+            return Err(WorkspaceError::AlreadyExists {
+                path: existing.display().to_string(),
+            });
+        }
+    }
+
+    let staging = staging_dir_for(&dest_dir);
+    let staged = (|| {
+        for (relative, data) in &books {
+            write_json_file(&staging.join(relative), data, FileEncoding::default())?;
+        }
+        write_json_file(
+            &staging.join(workspace_file),
+            workspace,
+            FileEncoding::default(),
+        )?;
+        move_into_place(&staging, &dest_dir, &files)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    staged?;
+
+    // Overwritten books would otherwise fail an existing manifest's checks.
+    let mut warnings = Vec::new();
+    if dest_dir.join(MANIFEST_FILE_NAME).is_file() {
+        let book_paths: Vec<PathBuf> = books
+            .iter()
+            .map(|(relative, _)| dest_dir.join(relative))
+            .collect();
+        if let Err(err) = manifest::update_manifest(&dest_dir, &book_paths, &book_paths) {
+            warnings.push(format!("Checksum manifest not updated: {}", err));
+        }
+    }
+
+    Ok(ImportBundleResult {
+        workspace_path: dest_dir.join(workspace_file).to_string_lossy().into_owned(),
+        books: books.len(),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::load_workspace_snapshot;
+
+    fn write_workspace(dir: &Path) -> String {
+        let workspace_path = dir.join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": [
+                { "id": "book-1", "name": "One", "dataPath": "books/one.json" },
+                { "id": "book-2", "name": "Two", "dataPath": "books/two.json.gz" }
+            ] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        for (id, file) in [("book-1", "one.json"), ("book-2", "two.json.gz")] {
+            let book = json!({
+                "schemaVersion": "1.0.0",
+                "book": { "id": id, "name": id },
+                "sheets": []
+            });
+            write_json_file(
+                &dir.join("books").join(file),
+                &book,
+                FileEncoding::default(),
+            )
+            .unwrap();
+        }
+        workspace_path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn bundles_every_book_and_stops_at_broken_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path());
+        let output = dir.path().join("out.bundle.json.gz");
+
+        let result =
+            export_bundle(path.clone(), output.to_string_lossy().into_owned(), None).unwrap();
+        assert_eq!(result.books, 2);
+        assert!(result.compressed);
+        let bundle = read_json_file(&output).unwrap();
+        assert_eq!(bundle["version"], BUNDLE_VERSION);
+        assert_eq!(bundle["workspace"]["books"][0]["id"], "book-1");
+        assert_eq!(bundle["books"][1]["dataPath"], "books/two.json.gz");
+        assert_eq!(bundle["books"][1]["data"]["book"]["id"], "book-2");
+
+        std::fs::write(dir.path().join("books/one.json"), "{ broken").unwrap();
+        let output = dir.path().join("again.bundle.json");
+        assert!(matches!(
+            export_bundle(path, output.to_string_lossy().into_owned(), None),
+            Err(WorkspaceError::BookLoad { index: 0, .. })
+        ));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn imports_what_was_exported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path());
+        let bundle_path = dir.path().join("out.bundle.json.gz");
+        export_bundle(path, bundle_path.to_string_lossy().into_owned(), None).unwrap();
+        let bundle = bundle_path.to_string_lossy().into_owned();
+        let dest = dir.path().join("restored");
+        let dest_dir = dest.to_string_lossy().into_owned();
+
+        let result = import_bundle(bundle.clone(), dest_dir.clone(), None).unwrap();
+        assert_eq!(result.books, 2);
+        let snapshot = load_workspace_snapshot(result.workspace_path, None).unwrap();
+        assert_eq!(snapshot.books.len(), 2);
+        assert!(snapshot.failed.is_empty());
+
+        assert!(matches!(
+            import_bundle(bundle.clone(), dest_dir.clone(), None),
+            Err(WorkspaceError::AlreadyExists { .. })
+        ));
+        import_bundle(bundle, dest_dir.clone(), Some(true)).unwrap();
+
+        let crafted = dir.path().join("crafted.bundle.json");
+        let mut value = read_json_file(&bundle_path).unwrap();
+        value["books"][0]["dataPath"] = json!("../../escaped.json");
+        write_json_file(&crafted, &value, FileEncoding::default()).unwrap();
+        assert!(matches!(
+            import_bundle(
+                crafted.to_string_lossy().into_owned(),
+                dest_dir.clone(),
+                Some(true)
+            ),
+            Err(WorkspaceError::PathOutsideWorkspace { .. })
+        ));
+        assert!(!dir.path().join("escaped.json").exists());
+
+        value["version"] = json!(99);
+        write_json_file(&crafted, &value, FileEncoding::default()).unwrap();
+        assert!(matches!(
+            import_bundle(crafted.to_string_lossy().into_owned(), dest_dir, Some(true)),
+            Err(WorkspaceError::UnsupportedBundleVersion { .. })
+        ));
+    }
+}
@@ -0,0 +1,360 @@
+//! Keeps what `load_workspace_snapshot_cached` read, so loading the same
+//! workspace again only reads the files whose modification time or size
+//! changed since. A file is dropped when a save writes it or the watcher
+//! sees it change, a workspace when `invalidate_cache` is called for it or,
+//! least recently loaded first, when the cache outgrows its memory limit.
+
+use super::error::WorkspaceResult;
+use super::paths::{canonicalize_lenient, normalize_lexically};
+use super::{load_snapshot, FilePayload, LoadOptions, WorkspaceSnapshotPayload};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// Upper bound for the estimated size of everything cached.
+pub const MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(|| Mutex::new(Cache::new(MEMORY_LIMIT)));
+
+fn cache() -> MutexGuard<'static, Cache> {
+    CACHE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// What a file looked like when it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+    })
+}
+
+/// Paths naming the same file through links or `..` share one key.
+fn key(path: &Path) -> PathBuf {
+    canonicalize_lenient(path)
+        .or_else(|| normalize_lexically(path))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Counts the bytes written to it.
+struct ByteCount(usize);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Size of `payload` as compact JSON, which tracks the memory it takes.
+fn estimate(payload: &FilePayload) -> usize {
+    let mut count = ByteCount(payload.file_path.len());
+    let _ = serde_json::to_writer(&mut count, &payload.data);
+    count.0
+}
+
+struct CachedFile {
+    stamp: Stamp,
+    payload: FilePayload,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct Entry {
+    /// `workspace.json` and the book files, by key.
+    files: HashMap<PathBuf, CachedFile>,
+    bytes: usize,
+    last_used: u64,
+}
+
+struct Cache {
+    workspaces: HashMap<PathBuf, Entry>,
+    bytes: usize,
+    limit: usize,
+    clock: u64,
+}
+
+impl Cache {
+    fn new(limit: usize) -> Self {
+        Self {
+            workspaces: HashMap::new(),
+            bytes: 0,
+            limit,
+            clock: 0,
+        }
+    }
+
+    fn entry(&mut self, workspace: &Path) -> &mut Entry {
+        self.clock += 1;
+        let entry = self.workspaces.entry(workspace.to_path_buf()).or_default();
+        entry.last_used = self.clock;
+        entry
+    }
+
+    fn get(&mut self, workspace: &Path, file: &Path, stamp: Stamp) -> Option<FilePayload> {
+        let cached = self.entry(workspace).files.get(file)?;
+        (cached.stamp == stamp).then(|| cached.payload.clone())
+    }
+
+    fn remove_file(&mut self, workspace: &Path, file: &Path) {
+        if let Some(entry) = self.workspaces.get_mut(workspace) {
+            if let Some(cached) = entry.files.remove(file) {
+                entry.bytes -= cached.bytes;
+                self.bytes -= cached.bytes;
+            }
+        }
+    }
+
+    fn remove_workspace(&mut self, workspace: &Path) {
+        if let Some(entry) = self.workspaces.remove(workspace) {
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    /// Caches `payload`, evicting the least recently loaded other
+    /// workspaces while over the limit. A file that does not fit even then
+    /// is not kept.
+    fn insert(&mut self, workspace: &Path, file: PathBuf, stamp: Stamp, payload: FilePayload) {
+        self.remove_file(workspace, &file);
+        let bytes = estimate(&payload);
+        let entry = self.entry(workspace);
+        entry.bytes += bytes;
+        entry.files.insert(
+            file.clone(),
+            CachedFile {
+                stamp,
+                payload,
+                bytes,
+            },
+        );
+        self.bytes += bytes;
+
+        while self.bytes > self.limit {
+            let oldest = self
+                .workspaces
+                .iter()
+                .filter(|(key, _)| key.as_path() != workspace)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.remove_workspace(&oldest),
+                None => {
+                    self.remove_file(workspace, &file);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Drops the files of `workspace` not in `keep`, e.g. books no longer
+    /// listed in its `workspace.json`.
+    fn retain(&mut self, workspace: &Path, keep: &HashSet<PathBuf>) {
+        let Some(entry) = self.workspaces.get_mut(workspace) else {
+            return;
+        };
+        let mut dropped = 0;
+        entry.files.retain(|file, cached| {
+            let kept = keep.contains(file);
+            if !kept {
+                dropped += cached.bytes;
+            }
+            kept
+        });
+        entry.bytes -= dropped;
+        self.bytes -= dropped;
+    }
+
+    fn forget(&mut self, file: &Path) {
+        let owners: Vec<PathBuf> = self
+            .workspaces
+            .iter()
+            .filter(|(_, entry)| entry.files.contains_key(file))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for owner in owners {
+            self.remove_file(&owner, file);
+        }
+    }
+}
+
+/// Returns `file` of `workspace_path` from the cache when it has not
+/// changed since it was cached, or reads it with `read` and caches the
+/// result. Failed reads are not cached.
+pub(super) fn load(
+    workspace_path: &Path,
+    file: &Path,
+    read: impl FnOnce() -> WorkspaceResult<FilePayload>,
+) -> WorkspaceResult<FilePayload> {
+    let workspace = key(workspace_path);
+    let file_key = key(file);
+    // Taken before reading, so a change during the read shows next time.
+    let Some(before) = stamp(file) else {
+        cache().remove_file(&workspace, &file_key);
+        return read();
+    };
+    if let Some(mut payload) = cache().get(&workspace, &file_key, before) {
+        // Cached under another spelling of the same path.
+        payload.file_path = file.to_string_lossy().into_owned();
+        return Ok(payload);
+    }
+    let payload = read()?;
+    cache().insert(&workspace, file_key, before, payload.clone());
+    Ok(payload)
+}
+
+/// Drops the cached files of `workspace_path` other than `files`.
+pub(super) fn retain<'a>(workspace_path: &Path, files: impl IntoIterator<Item = &'a str>) {
+    let keep = files.into_iter().map(|file| key(Path::new(file))).collect();
+    cache().retain(&key(workspace_path), &keep);
+}
+
+/// Drops `files` from every cached workspace.
+pub(super) fn forget<P: AsRef<Path>>(files: impl IntoIterator<Item = P>) {
+    let keys: Vec<PathBuf> = files.into_iter().map(|file| key(file.as_ref())).collect();
+    let mut cache = cache();
+    for file in keys {
+        cache.forget(&file);
+    }
+}
+
+/// Same result as `load_workspace_snapshot` without options, but files
+/// unchanged since the previous cached load are not read again.
+#[tauri::command(async)]
+pub fn load_workspace_snapshot_cached(path: String) -> WorkspaceResult<WorkspaceSnapshotPayload> {
+    let options = LoadOptions {
+        cached: true,
+        ..LoadOptions::default()
+    };
+    load_snapshot(Path::new(&path), options, |_| {}, |_| {})
+}
+
+/// Forgets everything cached for the workspace at `path`.
+#[tauri::command]
+pub fn invalidate_cache(path: String) {
+    cache().remove_workspace(&key(Path::new(&path)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use crate::workspace::load_workspace_snapshot;
+    use serde_json::json;
+
+    fn payload(name: &str, text: &str) -> FilePayload {
+        FilePayload {
+            file_path: name.to_string(),
+            data: json!(text),
+            modified: None,
+            hash: None,
+            text_style: None,
+            size_bytes: None,
+            data_path: None,
+            resolved_path: None,
+        }
+    }
+
+    fn write_book(path: &Path, name: &str) {
+        let book = json!({ "book": { "id": "a", "name": name }, "sheets": [] });
+        write_json_file(path, &book, FileEncoding::default()).unwrap();
+    }
+
+    #[test]
+    fn cached_loads_match_uncached_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = dir.path().join("books/a.json");
+        write_book(&book, "First");
+        let path = dir.path().join("workspace.json");
+        write_json_file(
+            &path,
+            &json!({
+                "schemaVersion": "1.0.0",
+                "books": [{ "id": "a", "name": "A", "dataPath": "books/a.json" }]
+            }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let uncached =
+            || serde_json::to_value(load_workspace_snapshot(path.clone(), None).unwrap());
+        let cached = || serde_json::to_value(load_workspace_snapshot_cached(path.clone()).unwrap());
+
+        let first = cached().unwrap();
+        assert_eq!(first, uncached().unwrap());
+        assert_eq!(cached().unwrap(), first);
+
+        write_book(&book, "Second, longer");
+        let changed = cached().unwrap();
+        assert_eq!(
+            changed["books"][0]["data"]["book"]["name"],
+            "Second, longer"
+        );
+        assert_eq!(changed, uncached().unwrap());
+
+        invalidate_cache(path.clone());
+        assert_eq!(cached().unwrap(), changed);
+    }
+
+    #[test]
+    fn serves_unchanged_files_and_rereads_changed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("workspace.json");
+        let file = dir.path().join("a.json");
+        fs::write(&file, "1").unwrap();
+        let reads = std::cell::Cell::new(0);
+        let read = || {
+            reads.set(reads.get() + 1);
+            Ok(payload("a", &fs::read_to_string(&file).unwrap()))
+        };
+
+        load(&workspace, &file, read).unwrap();
+        assert_eq!(load(&workspace, &file, read).unwrap().data, "1");
+        assert_eq!(reads.get(), 1);
+
+        fs::write(&file, "22").unwrap();
+        assert_eq!(load(&workspace, &file, read).unwrap().data, "22");
+        forget([&file]);
+        load(&workspace, &file, read).unwrap();
+        assert_eq!(reads.get(), 3);
+    }
+
+    #[test]
+    fn evicts_least_recently_loaded_workspaces() {
+        let stamp = Stamp {
+            modified: None,
+            len: 0,
+        };
+        let text = "x".repeat(40);
+        let size = estimate(&payload("a", &text));
+        let mut cache = Cache::new(size * 2);
+        let [first, second, third] = [Path::new("/1"), Path::new("/2"), Path::new("/3")];
+
+        cache.insert(first, "a".into(), stamp, payload("a", &text));
+        cache.insert(second, "a".into(), stamp, payload("a", &text));
+        assert!(cache.get(first, Path::new("a"), stamp).is_some());
+        cache.insert(third, "a".into(), stamp, payload("a", &text));
+        assert!(cache.workspaces.contains_key(first));
+        assert!(!cache.workspaces.contains_key(second));
+        assert_eq!(cache.bytes, size * 2);
+
+        // A single workspace past the limit keeps what fits.
+        cache.insert(third, "b".into(), stamp, payload("a", &text));
+        cache.insert(third, "c".into(), stamp, payload("a", &text));
+        assert!(cache.bytes <= size * 2);
+        assert!(cache.get(third, Path::new("c"), stamp).is_none());
+        assert!(cache.get(third, Path::new("b"), stamp).is_some());
+    }
+}
@@ -0,0 +1,67 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Cancellation flags of loads that may still be running, keyed by load id.
+static LOADS: LazyLock<Mutex<HashMap<String, Arc<AtomicBool>>>> = LazyLock::new(Default::default);
+
+fn loads() -> std::sync::MutexGuard<'static, HashMap<String, Arc<AtomicBool>>> {
+    LOADS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The flag of one load; unregisters the load id when dropped.
+pub(super) struct LoadToken {
+    load_id: String,
+    flag: Arc<AtomicBool>,
+}
+
+impl LoadToken {
+    /// Claims a load id from `issue_load_id`. Each id is good for one load.
+    pub fn claim(load_id: &str) -> WorkspaceResult<Self> {
+        let flag = loads()
+            .get(load_id)
+            .cloned()
+            .ok_or_else(|| WorkspaceError::InvalidOption {
+                name: "loadId",
+                message: format!("{} was not issued or has already been used", load_id),
+            })?;
+        Ok(Self {
+            load_id: load_id.to_string(),
+            flag,
+        })
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for LoadToken {
+    fn drop(&mut self) {
+        loads().remove(&self.load_id);
+    }
+}
+
+/// Issues an id to pass as `loadId` to a load so it can be stopped with
+/// `cancel_load` while it runs.
+#[tauri::command]
+pub fn issue_load_id() -> String {
+    let load_id = uuid::Uuid::new_v4().to_string();
+    loads().insert(load_id.clone(), Arc::default());
+    load_id
+}
+
+/// Asks the load using `load_id` to stop; it then fails with `cancelled`.
+/// Returns false when no such load is pending, e.g. because it already
+/// finished.
+#[tauri::command]
+pub fn cancel_load(load_id: String) -> bool {
+    match loads().get(&load_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,498 @@
+//! `inspect_book_types`: a data quality report of which kinds of values each
+//! column of a book holds, pointing at the cells that do not fit the rest
+//! of their column, e.g. text typed into a column of amounts. The book is
+//! read once through a streaming parser, as `workspace_stats` does, and is
+//! never written.
+
+use super::cells::column_index;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::intern;
+use super::io::read_json_file_as;
+use serde::de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InspectTypesOptions {
+    /// The sheet to inspect; every sheet when omitted.
+    pub sheet_id: Option<String>,
+    /// Share of the non-empty cells of a column (0 exclusive to 1) its most
+    /// common kind needs for the column to count as that kind rather than
+    /// `mixed`. 0.95 when omitted.
+    pub threshold: f64,
+    /// Decrypts an encrypted book.
+    pub passphrase: Option<String>,
+}
+
+impl Default for InspectTypesOptions {
+    fn default() -> Self {
+        Self {
+            sheet_id: None,
+            threshold: 0.95,
+            passphrase: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CellKind {
+    Number,
+    String,
+    Boolean,
+    /// No value, `null` or `""`.
+    Empty,
+}
+
+const VALUE_KINDS: [CellKind; 3] = [CellKind::Number, CellKind::String, CellKind::Boolean];
+/// Cells listed in `anomalies` per column; the rest are only counted, so
+/// that memory stays bounded however many cells are off.
+const MAX_ANOMALIES: usize = 100;
+
+impl CellKind {
+    /// Kind of a cell by its value. Values that are neither numbers nor
+    /// booleans, such as error objects, count as strings.
+    fn of(cell: &Value) -> Self {
+        if cell.get(intern::REF_KEY).is_some() {
+            return Self::String;
+        }
+        match cell.get("value") {
+            None | Some(Value::Null) => Self::Empty,
+            Some(Value::String(text)) if text.is_empty() => Self::Empty,
+            Some(Value::Number(_)) => Self::Number,
+            Some(Value::Bool(_)) => Self::Boolean,
+            Some(_) => Self::String,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KindCounts {
+    pub number: u64,
+    pub string: u64,
+    pub boolean: u64,
+    pub empty: u64,
+}
+
+impl KindCounts {
+    fn of(&self, kind: CellKind) -> u64 {
+        match kind {
+            CellKind::Number => self.number,
+            CellKind::String => self.string,
+            CellKind::Boolean => self.boolean,
+            CellKind::Empty => self.empty,
+        }
+    }
+
+    fn add(&mut self, kind: CellKind) {
+        match kind {
+            CellKind::Number => self.number += 1,
+            CellKind::String => self.string += 1,
+            CellKind::Boolean => self.boolean += 1,
+            CellKind::Empty => self.empty += 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnTypes {
+    pub column: String,
+    pub counts: KindCounts,
+    /// The most common kind among the non-empty cells; `None` for a column
+    /// of empty cells.
+    pub kind: Option<CellKind>,
+    /// Whether `kind` falls short of the threshold.
+    pub mixed: bool,
+    /// Addresses of non-empty cells of another kind than `kind`, at most
+    /// 100 of them.
+    pub anomalies: Vec<String>,
+    /// All such cells, including those not listed.
+    pub anomaly_count: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetTypes {
+    pub sheet_id: String,
+    /// In column order.
+    pub columns: Vec<ColumnTypes>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeReport {
+    pub sheets: Vec<SheetTypes>,
+    /// Columns with `mixed` set or any anomalies, across all sheets.
+    pub flagged_columns: usize,
+}
+
+/// What the pass keeps of a column: counts, and the first cells of each
+/// kind, since which kind is the odd one out is only known at the end.
+/// Keeping [`MAX_ANOMALIES`] of each is enough to list that many of the
+/// other kinds together.
+#[derive(Default)]
+struct ColumnTally {
+    counts: KindCounts,
+    cells: [Vec<String>; 3],
+}
+
+#[derive(Default)]
+struct SheetTally {
+    id: String,
+    /// Keyed by column index first so that the columns come out in order.
+    columns: BTreeMap<(u32, String), ColumnTally>,
+}
+
+/// Seeds for the levels of `{ sheets: [{ id, rows: { "1": { "A": cell } } }] }`
+/// the pass descends into; everything else is skipped unparsed.
+struct BookSeed<'a>(&'a mut Vec<SheetTally>);
+
+struct SheetsSeed<'a>(&'a mut Vec<SheetTally>);
+
+struct SheetSeed;
+
+struct RowsSeed<'a>(&'a mut SheetTally);
+
+struct RowSeed<'a> {
+    row: &'a str,
+    sheet: &'a mut SheetTally,
+}
+
+impl<'de> DeserializeSeed<'de> for BookSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BookSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a book object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "sheets" {
+                map.next_value_seed(SheetsSeed(&mut *self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for SheetsSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SheetsSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of sheets")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(sheet) = seq.next_element_seed(SheetSeed)? {
+            self.0.push(sheet);
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for SheetSeed {
+    type Value = SheetTally;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<SheetTally, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SheetSeed {
+    type Value = SheetTally;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sheet object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SheetTally, A::Error> {
+        let mut sheet = SheetTally::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "id" => sheet.id = map.next_value::<Option<String>>()?.unwrap_or_default(),
+                "rows" => map.next_value_seed(RowsSeed(&mut sheet))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(sheet)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for RowsSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RowsSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an object of rows")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(row) = map.next_key::<String>()? {
+            map.next_value_seed(RowSeed {
+                row: &row,
+                sheet: &mut *self.0,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for RowSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RowSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an object of cells")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(column) = map.next_key::<String>()? {
+            let kind = CellKind::of(&map.next_value::<Value>()?);
+            let index = column_index(&column).unwrap_or(u32::MAX);
+            let address = format!("{}{}", column, self.row);
+            let tally = self.sheet.columns.entry((index, column)).or_default();
+            tally.counts.add(kind);
+            if let Some(slot) = VALUE_KINDS.iter().position(|value| *value == kind) {
+                if tally.cells[slot].len() < MAX_ANOMALIES {
+                    tally.cells[slot].push(address);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The book's sheets as tallied by one streaming pass.
+struct BookTally(Vec<SheetTally>);
+
+impl<'de> Deserialize<'de> for BookTally {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut sheets = Vec::new();
+        BookSeed(&mut sheets).deserialize(deserializer)?;
+        Ok(Self(sheets))
+    }
+}
+
+fn column_types(column: String, tally: ColumnTally, threshold: f64) -> ColumnTypes {
+    let counts = tally.counts;
+    let non_empty: u64 = VALUE_KINDS.iter().map(|kind| counts.of(*kind)).sum();
+    // The first of the kinds tied for most common, in `VALUE_KINDS` order.
+    let kind = VALUE_KINDS
+        .iter()
+        .copied()
+        .filter(|kind| counts.of(*kind) > 0)
+        .rev()
+        .max_by_key(|kind| counts.of(*kind));
+    let Some(kind) = kind else {
+        return ColumnTypes {
+            column,
+            counts,
+            kind: None,
+            mixed: false,
+            anomalies: Vec::new(),
+            anomaly_count: 0,
+        };
+    };
+    let mixed = (counts.of(kind) as f64) < threshold * non_empty as f64;
+    let mut anomalies: Vec<String> = VALUE_KINDS
+        .iter()
+        .zip(tally.cells)
+        .filter(|(other, _)| **other != kind)
+        .flat_map(|(_, cells)| cells)
+        .collect();
+    anomalies.truncate(MAX_ANOMALIES);
+    ColumnTypes {
+        column,
+        counts,
+        kind: Some(kind),
+        mixed,
+        anomalies,
+        anomaly_count: non_empty - counts.of(kind),
+    }
+}
+
+/// Counts the kinds of value (number, string, boolean, empty) in every
+/// column of the book at `book_file_path` and lists the cells that differ
+/// from their column's most common kind. Read-only; the book is parsed once
+/// and never held in memory as a whole.
+#[tauri::command(async)]
+pub fn inspect_book_types(
+    book_file_path: String,
+    options: Option<InspectTypesOptions>,
+) -> WorkspaceResult<TypeReport> {
+    let options = options.unwrap_or_default();
+    if !(options.threshold > 0.0 && options.threshold <= 1.0) {
+        return Err(WorkspaceError::InvalidOption {
+            name: "threshold",
+            message: format!("must be above 0 and at most 1, got {}", options.threshold),
+        });
+    }
+    let path = Path::new(&book_file_path);
+    let BookTally(sheets) = read_json_file_as(path, options.passphrase.as_deref())?;
+    let sheets: Vec<SheetTypes> = sheets
+        .into_iter()
+        .filter(|sheet| options.sheet_id.as_ref().is_none_or(|id| *id == sheet.id))
+        .map(|sheet| SheetTypes {
+            sheet_id: sheet.id,
+            columns: sheet
+                .columns
+                .into_iter()
+                .map(|((_, column), tally)| column_types(column, tally, options.threshold))
+                .collect(),
+        })
+        .collect();
+    if let (true, Some(sheet_id)) = (sheets.is_empty(), options.sheet_id) {
+        return Err(WorkspaceError::SheetNotFound {
+            path: book_file_path,
+            sheet_id,
+        });
+    }
+    let flagged_columns = sheets
+        .iter()
+        .flat_map(|sheet| &sheet.columns)
+        .filter(|column| column.mixed || column.anomaly_count > 0)
+        .count();
+    Ok(TypeReport {
+        sheets,
+        flagged_columns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+
+    #[test]
+    fn reports_kinds_per_column_and_the_cells_out_of_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let mut rows = serde_json::Map::new();
+        for row in 1..=40 {
+            let amount = match row {
+                7 => json!("n/a"),
+                30 => json!(true),
+                _ => json!(row * 100),
+            };
+            let code = if row % 2 == 0 { json!(row) } else { json!("x") };
+            rows.insert(
+                row.to_string(),
+                json!({
+                    "AA": { "value": amount },
+                    "B": { "value": code },
+                    "C": { "value": "", "style": { "bold": true } }
+                }),
+            );
+        }
+        let book = json!({
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [
+                { "id": "sheet-1", "rows": rows },
+                { "id": "sheet-2", "rows": { "1": { "A": { "value": 1 } } } }
+            ]
+        });
+        write_json_file(&path, &book, FileEncoding::default()).unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let report = inspect_book_types(path.clone(), None).unwrap();
+        assert_eq!(report.sheets.len(), 2);
+        assert_eq!(report.flagged_columns, 2);
+        let columns = &report.sheets[0].columns;
+        let names: Vec<&str> = columns
+            .iter()
+            .map(|column| column.column.as_str())
+            .collect();
+        assert_eq!(names, ["B", "C", "AA"]);
+
+        let amounts = &columns[2];
+        assert_eq!(amounts.kind, Some(CellKind::Number));
+        assert!(!amounts.mixed);
+        assert_eq!(amounts.anomalies, ["AA7", "AA30"]);
+        assert_eq!(amounts.anomaly_count, 2);
+        assert_eq!(
+            (
+                amounts.counts.number,
+                amounts.counts.string,
+                amounts.counts.boolean
+            ),
+            (38, 1, 1)
+        );
+        // Tied kinds go to the number; the column is mixed.
+        assert_eq!(columns[0].kind, Some(CellKind::Number));
+        assert!(columns[0].mixed);
+        assert_eq!(columns[0].anomaly_count, 20);
+        assert_eq!(columns[0].anomalies[0], "B1");
+        assert_eq!(columns[1].kind, None);
+        assert_eq!(columns[1].counts.empty, 40);
+
+        let strict = InspectTypesOptions {
+            sheet_id: Some("sheet-1".into()),
+            threshold: 1.0,
+            ..Default::default()
+        };
+        let report = inspect_book_types(path.clone(), Some(strict)).unwrap();
+        assert_eq!(report.sheets.len(), 1);
+        assert!(report.sheets[0].columns[2].mixed);
+
+        let invalid = InspectTypesOptions {
+            threshold: 0.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            inspect_book_types(path.clone(), Some(invalid)),
+            Err(WorkspaceError::InvalidOption {
+                name: "threshold",
+                ..
+            })
+        ));
+        let missing = InspectTypesOptions {
+            sheet_id: Some("missing".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            inspect_book_types(path, Some(missing)),
+            Err(WorkspaceError::SheetNotFound { .. })
+        ));
+    }
+}
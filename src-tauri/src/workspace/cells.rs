@@ -0,0 +1,83 @@
+//! Cell addressing used by book files: sheets store `rows` keyed by 1-based
+//! row numbers (`"1"`), each row keyed by column letters (`"A"`, `"AA"`).
+
+/// `"A"` -> 1, `"Z"` -> 26, `"AA"` -> 27. Only uppercase letters are valid,
+/// and labels past `u32` (from `"MWLQKWV"` on) are not.
+pub fn column_index(label: &str) -> Option<u32> {
+    if label.is_empty() || label.len() > 7 {
+        return None;
+    }
+    label.bytes().try_fold(0u32, |acc, byte| {
+        if !byte.is_ascii_uppercase() {
+            return None;
+        }
+        acc.checked_mul(26)?.checked_add(u32::from(byte - b'A') + 1)
+    })
+}
+
+/// Inverse of [`column_index`]: 1 -> `"A"`, 27 -> `"AA"`.
+pub fn column_label(mut index: u32) -> String {
+    let mut label = Vec::new();
+    while index > 0 {
+        index -= 1;
+        label.push(b'A' + (index % 26) as u8);
+        index /= 26;
+    }
+    label.reverse();
+    String::from_utf8(label).expect("column labels are ASCII")
+}
+
+/// Parses a 1-based row key. Leading zeros and `"0"` are rejected so every
+/// row has exactly one spelling.
+pub fn row_index(key: &str) -> Option<u32> {
+    if key.starts_with('0') || !key.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    key.parse().ok()
+}
+
+/// Parses an address like `"B12"` into its 1-based `(row, column)`,
+/// ignoring letter case.
+pub fn parse_address(address: &str) -> Option<(u32, u32)> {
+    let address = address.trim().to_ascii_uppercase();
+    let split = address.find(|c: char| c.is_ascii_digit())?;
+    let (column, row) = address.split_at(split);
+    Some((row_index(row)?, column_index(column)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_labels_map_to_indices() {
+        for (label, index) in [("A", 1), ("Z", 26), ("AA", 27), ("AZ", 52), ("ZZ", 702)] {
+            assert_eq!(column_index(label), Some(index));
+            assert_eq!(column_label(index), label);
+        }
+        assert_eq!(column_index("a"), None);
+        assert_eq!(column_index("A1"), None);
+        // Seven letters may run past `u32`; those are invalid, not wrapped.
+        assert_eq!(column_index("MWLQKWU"), Some(u32::MAX));
+        assert_eq!(column_index("MWLQKWV"), None);
+        assert_eq!(column_index("ZZZZZZZ"), None);
+    }
+
+    #[test]
+    fn row_keys_are_canonical_numbers() {
+        assert_eq!(row_index("1"), Some(1));
+        assert_eq!(row_index("120"), Some(120));
+        assert_eq!(row_index("0"), None);
+        assert_eq!(row_index("01"), None);
+        assert_eq!(row_index("r1"), None);
+    }
+
+    #[test]
+    fn addresses_split_into_row_and_column() {
+        assert_eq!(parse_address("A1"), Some((1, 1)));
+        assert_eq!(parse_address(" ab12 "), Some((12, 28)));
+        for invalid in ["", "A", "12", "A0", "1A", "A1B"] {
+            assert_eq!(parse_address(invalid), None, "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! `parse_clipboard_table`: turns text pasted from Excel, Google Sheets or a
+//! CSV file into a grid of cells. Fields are quoted the way spreadsheets
+//! copy them: a field starting with `"` runs to the next lone `"` and may
+//! hold delimiters and line breaks, with `""` standing for a quote. Unlike
+//! CSV import, blank lines are kept as empty rows so that pasted cells stay
+//! where they were copied from.
+
+use super::csv_import::parse_number;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipboardDelimiter {
+    /// Tab when the text holds one outside quotes, as spreadsheets copy;
+    /// comma when every line has the same number of them; otherwise the
+    /// text is a single column.
+    #[default]
+    Auto,
+    Tab,
+    Comma,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClipboardTableOptions {
+    pub delimiter: ClipboardDelimiter,
+    /// Turn fields that are JSON-style numbers into numbers; when off
+    /// every cell is a string.
+    pub infer_types: bool,
+}
+
+impl Default for ClipboardTableOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ClipboardDelimiter::default(),
+            infer_types: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardTable {
+    pub rows: usize,
+    pub cols: usize,
+    pub delimiter: char,
+    /// `rows` rows of `cols` values each; short rows are padded with `""`.
+    pub cells: Vec<Vec<Value>>,
+}
+
+/// Where the last run of an odd number of quotes in `text` starts. Inside a
+/// quoted field, pairs of quotes are escaped quotes and a lone one ends the
+/// field, so the field ends at the first odd run after its opening quote.
+fn last_odd_quote_run(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut last = None;
+    let mut index = 0;
+    while index < bytes.len() {
+        let start = index;
+        while index < bytes.len() && bytes[index] == b'"' {
+            index += 1;
+        }
+        if (index - start) % 2 == 1 {
+            last = Some(start);
+        }
+        index = index.max(start + 1);
+    }
+    last
+}
+
+/// Splits `text` into records of fields. A quote that is never closed is
+/// taken literally where it occurs, as spreadsheets copy a cell like `"abc`
+/// unquoted.
+fn split_records(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let last_odd_run = last_odd_quote_run(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut field_start = true;
+    let mut quoted = false;
+    let mut chars = text.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.next_if(|(_, next)| *next == '"').is_some() => field.push('"'),
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field_start => {
+                // The opening quote's own run holds an odd number of quotes
+                // after it when the run is even; otherwise a later odd run
+                // has to close the field.
+                let run = text[offset..]
+                    .bytes()
+                    .take_while(|byte| *byte == b'"')
+                    .count();
+                quoted = run % 2 == 0 || last_odd_run.is_some_and(|start| start > offset);
+                if !quoted {
+                    field.push(c);
+                }
+            }
+            _ if c == delimiter => {
+                record.push(std::mem::take(&mut field));
+                field_start = true;
+                continue;
+            }
+            '\r' | '\n' => {
+                if c == '\r' {
+                    chars.next_if(|(_, next)| *next == '\n');
+                }
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                field_start = true;
+                continue;
+            }
+            _ => field.push(c),
+        }
+        field_start = false;
+    }
+    record.push(field);
+    records.push(record);
+    records
+}
+
+/// How many times `delimiter` separates fields on each line of `text`,
+/// skipping blank lines.
+fn delimiters_per_line(text: &str, delimiter: char) -> Vec<usize> {
+    split_records(text, delimiter)
+        .iter()
+        .filter(|record| record.len() > 1 || record.first().is_some_and(|field| !field.is_empty()))
+        .map(|record| record.len() - 1)
+        .collect()
+}
+
+/// How much of the text the delimiter is detected from; a large paste is
+/// judged by its first lines.
+const DETECT_PREFIX_BYTES: usize = 64 * 1024;
+
+/// The first lines of `text`, cut after the last line break within
+/// [`DETECT_PREFIX_BYTES`] so no line is cut short.
+fn detection_prefix(text: &str) -> &str {
+    if text.len() <= DETECT_PREFIX_BYTES {
+        return text;
+    }
+    let mut end = DETECT_PREFIX_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let prefix = &text[..end];
+    prefix
+        .rfind('\n')
+        .map_or(prefix, |line_end| &prefix[..line_end])
+}
+
+fn detect_delimiter(text: &str) -> char {
+    let text = detection_prefix(text);
+    if delimiters_per_line(text, '\t')
+        .iter()
+        .any(|count| *count > 0)
+    {
+        return '\t';
+    }
+    let commas = delimiters_per_line(text, ',');
+    match commas.first() {
+        Some(&first)
+            if first > 0 && commas.len() > 1 && commas.iter().all(|count| *count == first) =>
+        {
+            ','
+        }
+        _ => '\t',
+    }
+}
+
+/// Parses pasted table text into cells. One line break at the end of the
+/// text, which spreadsheets add after the last row, does not make a row.
+#[tauri::command]
+pub fn parse_clipboard_table(
+    text: String,
+    options: Option<ClipboardTableOptions>,
+) -> ClipboardTable {
+    let options = options.unwrap_or_default();
+    let text = text
+        .strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix(['\n', '\r']))
+        .unwrap_or(&text);
+    let delimiter = match options.delimiter {
+        ClipboardDelimiter::Auto => detect_delimiter(text),
+        ClipboardDelimiter::Tab => '\t',
+        ClipboardDelimiter::Comma => ',',
+    };
+    if text.is_empty() {
+        return ClipboardTable {
+            rows: 0,
+            cols: 0,
+            delimiter,
+            cells: Vec::new(),
+        };
+    }
+    let records = split_records(text, delimiter);
+    let cols = records.iter().map(Vec::len).max().unwrap_or(0);
+    let cells: Vec<Vec<Value>> = records
+        .into_iter()
+        .map(|record| {
+            let mut row: Vec<Value> = record
+                .into_iter()
+                .map(
+                    |field| match options.infer_types.then(|| parse_number(&field)).flatten() {
+                        Some(number) => Value::Number(number),
+                        None => Value::String(field),
+                    },
+                )
+                .collect();
+            row.resize(cols, Value::String(String::new()));
+            row
+        })
+        .collect();
+    ClipboardTable {
+        rows: cells.len(),
+        cols,
+        delimiter,
+        cells,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(text: &str) -> ClipboardTable {
+        parse_clipboard_table(text.into(), None)
+    }
+
+    #[test]
+    fn reads_what_spreadsheets_copy() {
+        // Quoted line breaks and tabs, escaped quotes, a blank row, a ragged
+        // row and the trailing line break Excel adds.
+        let table = parse("name\tnote\tqty\r\n\"a\tb\"\t\"line 1\nline \"\"2\"\"\"\t007\r\n\r\nx\t\t-1.50\r\nlast\r\n");
+        assert_eq!((table.rows, table.cols, table.delimiter), (5, 3, '\t'));
+        let expected: Value = serde_json::from_str(
+            r#"[
+                ["name", "note", "qty"],
+                ["a\tb", "line 1\nline \"2\"", "007"],
+                ["", "", ""],
+                ["x", "", -1.50],
+                ["last", "", ""]
+            ]"#,
+        )
+        .unwrap();
+        // Numbers keep their digits as written.
+        assert_eq!(json!(table.cells), expected);
+    }
+
+    #[test]
+    fn detects_commas_and_keeps_stray_quotes() {
+        let table = parse("a,\"b,c\"\n1,2\n");
+        assert_eq!((table.delimiter, table.cols), (',', 2));
+        assert_eq!(table.cells[0][1], "b,c");
+        assert_eq!(table.cells[1][0], 1);
+
+        // A single line of prose is one cell, not split at its comma.
+        let table = parse("Hello, world");
+        assert_eq!((table.rows, table.cols), (1, 1));
+        // An unclosed quote is part of the text.
+        let table = parse("\"12 inch\tscreen\nnext");
+        assert_eq!(
+            json!(table.cells),
+            json!([["\"12 inch", "screen"], ["next", ""]])
+        );
+
+        // Only the quote left unclosed is literal; the one before it still
+        // opens a field.
+        let table = parse("x\t\"a\t\"b\t\"c");
+        assert_eq!(json!(table.cells), json!([["x", "a\tb", "\"c"]]));
+        let mut long = "\"a\"\t".repeat(50_000);
+        long.push_str("\"z");
+        let table = parse(&long);
+        assert_eq!((table.rows, table.cols), (1, 50_001));
+        assert_eq!(table.cells[0][0], "a");
+        assert_eq!(table.cells[0][50_000], "\"z");
+
+        // Long pastes are judged by their first lines.
+        let long = "1,2\n".repeat(40_000);
+        assert_eq!(detect_delimiter(&long), ',');
+        assert!(detection_prefix(&long).len() <= DETECT_PREFIX_BYTES);
+
+        let options = ClipboardTableOptions {
+            delimiter: ClipboardDelimiter::Comma,
+            infer_types: false,
+        };
+        let table = parse_clipboard_table("1,2".into(), Some(options));
+        assert_eq!(json!(table.cells), json!([["1", "2"]]));
+        assert_eq!(parse("").rows, 0);
+    }
+}
@@ -0,0 +1,163 @@
+//! Checks, before anything is written, that the books of a snapshot are the
+//! ones its `workspace.json` lists. A frontend bug that drops or adds a book
+//! on one side only would otherwise be saved as is.
+
+use super::error::WorkspaceError;
+use super::paths::{normalize_lexically, BookRoots};
+use super::FilePayload;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// How a save treats a snapshot whose books and `workspace.json` disagree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConsistencyCheck {
+    /// Refuse the save with `inconsistentSnapshot`.
+    #[default]
+    Strict,
+    /// Save anyway and report the mismatch in `warnings`.
+    Warn,
+}
+
+fn key(path: &Path, case_insensitive: bool) -> Option<String> {
+    let key = normalize_lexically(path)?.to_string_lossy().into_owned();
+    Some(if case_insensitive {
+        key.to_lowercase()
+    } else {
+        key
+    })
+}
+
+/// Compares the book payloads with the `books` of the workspace payload.
+/// A book payload no entry points at is unreferenced. An entry without a
+/// payload is only missing when its file does not exist either, since books
+/// that failed to load are legitimately left out of a snapshot.
+pub fn check_snapshot(
+    roots: &BookRoots,
+    workspace: &FilePayload,
+    books: &[FilePayload],
+    case_insensitive: bool,
+) -> Option<WorkspaceError> {
+    let payloads: HashSet<String> = books
+        .iter()
+        .filter_map(|book| key(Path::new(&book.file_path), case_insensitive))
+        .collect();
+
+    let mut referenced = HashSet::new();
+    let mut missing = Vec::new();
+    let book_refs = workspace.data["books"].as_array().into_iter().flatten();
+    for (index, book_ref) in book_refs.enumerate() {
+        // Entries without a usable `dataPath` are reported by the schema
+        // and path checks.
+        let Some(data_path) = book_ref["dataPath"].as_str() else {
+            continue;
+        };
+        let Ok(path) = roots.resolve(data_path, index) else {
+            continue;
+        };
+        let Some(path_key) = key(&path, case_insensitive) else {
+            continue;
+        };
+        if !payloads.contains(&path_key) && !path.exists() {
+            missing.push(data_path.to_string());
+        }
+        referenced.insert(path_key);
+    }
+    let unreferenced: Vec<String> = books
+        .iter()
+        .filter(|book| {
+            key(Path::new(&book.file_path), case_insensitive)
+                .is_some_and(|path_key| !referenced.contains(&path_key))
+        })
+        .map(|book| book.file_path.clone())
+        .collect();
+
+    if unreferenced.is_empty() && missing.is_empty() {
+        return None;
+    }
+    Some(WorkspaceError::InconsistentSnapshot {
+        path: workspace.file_path.clone(),
+        unreferenced,
+        missing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::fs;
+
+    fn payload(file_path: &Path, data: Value) -> FilePayload {
+        FilePayload {
+            file_path: file_path.to_string_lossy().into_owned(),
+            data,
+            modified: None,
+            hash: None,
+            text_style: None,
+            size_bytes: None,
+            data_path: None,
+            resolved_path: None,
+        }
+    }
+
+    fn roots(dir: &Path) -> BookRoots {
+        BookRoots::new(dir, &Value::Null)
+    }
+
+    fn workspace_listing(dir: &Path, data_paths: &[&str]) -> FilePayload {
+        let books: Vec<Value> = data_paths
+            .iter()
+            .map(|data_path| json!({ "dataPath": data_path }))
+            .collect();
+        payload(&dir.join("workspace.json"), json!({ "books": books }))
+    }
+
+    #[test]
+    fn matching_books_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace_listing(dir.path(), &["books/a.json", "./books/../books/b.json"]);
+        let books = [
+            payload(&dir.path().join("books/a.json"), json!({})),
+            payload(&dir.path().join("books/b.json"), json!({})),
+        ];
+        assert!(check_snapshot(&roots(dir.path()), &workspace, &books, false).is_none());
+    }
+
+    #[test]
+    fn reports_unreferenced_and_missing_books() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("books")).unwrap();
+        fs::write(dir.path().join("books/failed.json"), "{").unwrap();
+        let workspace = workspace_listing(
+            dir.path(),
+            &["books/a.json", "books/failed.json", "books/gone.json"],
+        );
+        let stray = dir.path().join("books/stray.json");
+        let books = [
+            payload(&dir.path().join("books/a.json"), json!({})),
+            payload(&stray, json!({})),
+        ];
+        match check_snapshot(&roots(dir.path()), &workspace, &books, false) {
+            Some(WorkspaceError::InconsistentSnapshot {
+                unreferenced,
+                missing,
+                ..
+            }) => {
+                assert_eq!(unreferenced, [stray.to_string_lossy()]);
+                assert_eq!(missing, ["books/gone.json"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn letter_case_only_matters_when_paths_are_case_sensitive() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace_listing(dir.path(), &["books/A.json"]);
+        let books = [payload(&dir.path().join("books/a.json"), json!({}))];
+        assert!(check_snapshot(&roots(dir.path()), &workspace, &books, false).is_some());
+        assert!(check_snapshot(&roots(dir.path()), &workspace, &books, true).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Passphrase encryption for book files. An encrypted file is
+//! `MAGIC | salt | nonce | AES-256-GCM ciphertext`, where the key is derived
+//! from the passphrase and salt with Argon2id and the header is
+//! authenticated as associated data.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"SUPENC01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], path: &Path) -> WorkspaceResult<Key<Aes256Gcm>> {
+    let mut key = Key::<Aes256Gcm>::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| WorkspaceError::Encryption {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        })?;
+    Ok(key)
+}
+
+pub fn encrypt(plain: &[u8], passphrase: &str, path: &Path) -> WorkspaceResult<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let mut output = Vec::with_capacity(HEADER_LEN + plain.len() + 16);
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt, path)?);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plain,
+                aad: &output,
+            },
+        )
+        .map_err(|err| WorkspaceError::Encryption {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        })?;
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// A wrong passphrase and a tampered file are indistinguishable here; both
+/// surface as `WrongPassphrase`.
+pub fn decrypt(data: &[u8], passphrase: Option<&str>, path: &Path) -> WorkspaceResult<Vec<u8>> {
+    let passphrase = passphrase.ok_or_else(|| WorkspaceError::PassphraseRequired {
+        path: path.display().to_string(),
+    })?;
+    let wrong = || WorkspaceError::WrongPassphrase {
+        path: path.display().to_string(),
+    };
+    if data.len() < HEADER_LEN || !is_encrypted(data) {
+        return Err(wrong());
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = Nonce::from_slice(&header[MAGIC.len() + SALT_LEN..]);
+
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, salt, path)?);
+    cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| wrong())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_wrong_passphrase() {
+        let path = Path::new("book.json");
+        let sealed = encrypt(b"{\"secret\":1}", "correct horse", path).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(6).any(|window| window == b"secret"));
+
+        assert_eq!(
+            decrypt(&sealed, Some("correct horse"), path).unwrap(),
+            b"{\"secret\":1}"
+        );
+        assert!(matches!(
+            decrypt(&sealed, Some("wrong"), path),
+            Err(WorkspaceError::WrongPassphrase { .. })
+        ));
+        assert!(matches!(
+            decrypt(&sealed, None, path),
+            Err(WorkspaceError::PassphraseRequired { .. })
+        ));
+    }
+}
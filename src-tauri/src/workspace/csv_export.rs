@@ -0,0 +1,433 @@
+use super::cells::{column_index, column_label, parse_address, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_json_file, write_atomic};
+use super::schema::validate_book;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Borrow;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CsvQuoteStyle {
+    /// Quote fields containing the delimiter, quotes or line breaks, and
+    /// text cells that would otherwise read back as numbers (`"007"`).
+    #[default]
+    Necessary,
+    Always,
+    /// Quote every text cell; numbers and booleans stay bare.
+    NonNumeric,
+    /// Never quote, even if the output becomes ambiguous.
+    Never,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CsvRange {
+    /// Up to the last row and column holding a value.
+    #[default]
+    Used,
+    /// At least the sheet's `gridSize`.
+    Grid,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvExportOptions {
+    /// Sheet to export; the first sheet when omitted.
+    pub sheet_id: Option<String>,
+    pub delimiter: char,
+    pub quote_style: CsvQuoteStyle,
+    /// Text written for cells without a value.
+    pub empty_value: String,
+    pub range: CsvRange,
+}
+
+impl Default for CsvExportOptions {
+    fn default() -> Self {
+        Self {
+            sheet_id: None,
+            delimiter: ',',
+            quote_style: CsvQuoteStyle::default(),
+            empty_value: String::new(),
+            range: CsvRange::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvExportResult {
+    pub sheet_id: String,
+    pub rows: usize,
+    pub columns: usize,
+}
+
+/// A cell rendered for CSV, remembering whether it was text so quoting can
+/// keep `"007"` from turning into the number 7.
+pub(super) struct Field {
+    pub text: String,
+    pub is_text: bool,
+}
+
+fn cell_field(cell: &Value) -> Option<Field> {
+    let value = cell.get("value")?;
+    let (text, is_text) = match value {
+        Value::Null => return None,
+        Value::String(text) if text.is_empty() => return None,
+        Value::String(text) => (text.clone(), true),
+        Value::Number(number) => (number.to_string(), false),
+        Value::Bool(flag) => (flag.to_string(), false),
+        _ => return None,
+    };
+    Some(Field { text, is_text })
+}
+
+pub(super) fn looks_numeric(text: &str) -> bool {
+    text.trim().parse::<f64>().is_ok()
+}
+
+pub(super) fn quote(field: &str, delimiter: u8, style: CsvQuoteStyle, is_text: bool) -> String {
+    let needs_quotes = match style {
+        CsvQuoteStyle::Always => true,
+        CsvQuoteStyle::Never => false,
+        CsvQuoteStyle::NonNumeric if is_text => true,
+        CsvQuoteStyle::Necessary | CsvQuoteStyle::NonNumeric => {
+            (is_text && looks_numeric(field))
+                || field
+                    .bytes()
+                    .any(|byte| matches!(byte, b'"' | b'\r' | b'\n') || byte == delimiter)
+        }
+    };
+    if needs_quotes {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub(super) fn select_sheet<'a>(
+    book: &'a Value,
+    path: &Path,
+    sheet_id: Option<&str>,
+) -> WorkspaceResult<&'a Value> {
+    let sheets = book["sheets"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let sheet = match sheet_id {
+        Some(id) => sheets.iter().find(|sheet| sheet["id"] == id),
+        None => sheets.first(),
+    };
+    sheet.ok_or_else(|| match sheet_id {
+        Some(id) => WorkspaceError::SheetNotFound {
+            path: path.display().to_string(),
+            sheet_id: id.to_string(),
+        },
+        None => WorkspaceError::invalid_schema(format!("{} has no sheets", path.display())),
+    })
+}
+
+/// Lays the sheet's sparse `rows` out as a dense grid of 0-based cells.
+pub(super) fn sheet_grid(sheet: &Value, range: CsvRange) -> Vec<Vec<Option<Field>>> {
+    let mut cells = Vec::new();
+    let (mut rows, mut columns) = (0, 0);
+    if let Some(row_map) = sheet["rows"].as_object() {
+        for (row_key, row) in row_map {
+            let (Some(row), Some(row_cells)) = (row_index(row_key), row.as_object()) else {
+                continue;
+            };
+            for (column_key, cell) in row_cells {
+                let (Some(column), Some(field)) = (column_index(column_key), cell_field(cell))
+                else {
+                    continue;
+                };
+                rows = rows.max(row as usize);
+                columns = columns.max(column as usize);
+                cells.push((row as usize - 1, column as usize - 1, field));
+            }
+        }
+    }
+    if range == CsvRange::Grid {
+        let dimension = |name: &str| sheet["gridSize"][name].as_u64().unwrap_or(0) as usize;
+        rows = rows.max(dimension("rows"));
+        columns = columns.max(dimension("cols"));
+    }
+
+    let mut grid: Vec<Vec<Option<Field>>> = (0..rows)
+        .map(|_| (0..columns).map(|_| None).collect())
+        .collect();
+    for (row, column, field) in cells {
+        grid[row][column] = Some(field);
+    }
+    grid
+}
+
+fn delimiter_byte(delimiter: char) -> WorkspaceResult<u8> {
+    if !delimiter.is_ascii() || matches!(delimiter, '"' | '\r' | '\n') {
+        return Err(WorkspaceError::InvalidOption {
+            name: "delimiter",
+            message: format!("{:?} cannot be used as a CSV delimiter", delimiter),
+        });
+    }
+    Ok(delimiter as u8)
+}
+
+fn read_sheet_book(book_file_path: &str) -> WorkspaceResult<Value> {
+    let book = read_json_file(Path::new(book_file_path))?;
+    validate_book(&book)?;
+    Ok(book)
+}
+
+/// Writes `rows` of optional fields to `output_path`, creating its
+/// directory. Cells without a field are written as `empty_value`.
+fn write_fields<R, F>(
+    output_path: &Path,
+    delimiter: u8,
+    options: &CsvExportOptions,
+    rows: impl Iterator<Item = R>,
+) -> WorkspaceResult<()>
+where
+    R: IntoIterator<Item = Option<F>>,
+    F: Borrow<Field>,
+{
+    if let Some(parent) = output_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .map_err(|err| WorkspaceError::io("create directory", parent, err))?;
+    }
+    // Quoting is decided per cell above, so the writer itself never quotes.
+    write_atomic(output_path, |file| {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .quote_style(csv::QuoteStyle::Never)
+            .flexible(true)
+            .from_writer(file);
+        for row in rows {
+            writer.write_record(row.into_iter().map(|field| match field {
+                Some(field) => {
+                    let field = field.borrow();
+                    quote(&field.text, delimiter, options.quote_style, field.is_text)
+                }
+                None => quote(&options.empty_value, delimiter, options.quote_style, false),
+            }))?;
+        }
+        writer.flush()
+    })
+    .map_err(|err| WorkspaceError::io("write", output_path, err))
+}
+
+/// Writes one sheet of a book file as CSV, padding gaps with empty cells.
+#[tauri::command]
+pub fn export_book_to_csv(
+    book_file_path: String,
+    output_path: String,
+    options: Option<CsvExportOptions>,
+) -> WorkspaceResult<CsvExportResult> {
+    let options = options.unwrap_or_default();
+    let delimiter = delimiter_byte(options.delimiter)?;
+
+    let book = read_sheet_book(&book_file_path)?;
+    let sheet = select_sheet(
+        &book,
+        Path::new(&book_file_path),
+        options.sheet_id.as_deref(),
+    )?;
+    let grid = sheet_grid(sheet, options.range);
+    write_fields(
+        Path::new(&output_path),
+        delimiter,
+        &options,
+        grid.iter().map(|row| row.iter().map(Option::as_ref)),
+    )?;
+
+    Ok(CsvExportResult {
+        sheet_id: sheet["id"].as_str().unwrap_or_default().to_string(),
+        rows: grid.len(),
+        columns: grid.first().map_or(0, Vec::len),
+    })
+}
+
+/// Corners of a rectangle of cells, e.g. `{ "start": "A1", "end": "C10" }`.
+/// Either corner may come first.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CellRange {
+    pub start: String,
+    pub end: String,
+}
+
+impl CellRange {
+    /// 1-based inclusive `(rows, columns)` covered by the range.
+    pub(super) fn bounds(&self) -> WorkspaceResult<(RangeInclusive<u32>, RangeInclusive<u32>)> {
+        let corner = |address: &str| {
+            parse_address(address).ok_or_else(|| WorkspaceError::InvalidOption {
+                name: "range",
+                message: format!("{:?} is not a cell address like \"A1\"", address),
+            })
+        };
+        let (start_row, start_column) = corner(&self.start)?;
+        let (end_row, end_column) = corner(&self.end)?;
+        Ok((
+            start_row.min(end_row)..=start_row.max(end_row),
+            start_column.min(end_column)..=start_column.max(end_column),
+        ))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CellRangeFormat {
+    /// Separated by `delimiter` of the options, `,` by default.
+    #[default]
+    Csv,
+    /// Tab separated, whatever `delimiter` says.
+    Tsv,
+}
+
+/// Writes the cells of `range` in one sheet of a book file as CSV or TSV.
+/// Cells without a value, including any beyond the sheet's data, are
+/// written as `emptyValue`, so the output always has the range's shape.
+/// `sheetId`, `delimiter`, `quoteStyle` and `emptyValue` of `options`
+/// apply as in `export_book_to_csv`; its `range` is ignored.
+#[tauri::command]
+pub fn export_cell_range(
+    book_file_path: String,
+    range: CellRange,
+    format: CellRangeFormat,
+    output_path: String,
+    options: Option<CsvExportOptions>,
+) -> WorkspaceResult<CsvExportResult> {
+    let options = options.unwrap_or_default();
+    let delimiter = match format {
+        CellRangeFormat::Csv => delimiter_byte(options.delimiter)?,
+        CellRangeFormat::Tsv => b'\t',
+    };
+    let (rows, columns) = range.bounds()?;
+
+    let book = read_sheet_book(&book_file_path)?;
+    let sheet = select_sheet(
+        &book,
+        Path::new(&book_file_path),
+        options.sheet_id.as_deref(),
+    )?;
+    let labels: Vec<String> = columns.map(column_label).collect();
+    // Rows are looked up as they are written, so a range far larger than
+    // the sheet's data never exists in memory as a grid.
+    write_fields(
+        Path::new(&output_path),
+        delimiter,
+        &options,
+        rows.clone().map(|row| {
+            let cells = &sheet["rows"][row.to_string()];
+            labels
+                .iter()
+                .map(move |label| cell_field(&cells[label.as_str()]))
+        }),
+    )?;
+
+    Ok(CsvExportResult {
+        sheet_id: sheet["id"].as_str().unwrap_or_default().to_string(),
+        rows: rows.count(),
+        columns: labels.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_book(dir: &Path) -> String {
+        let path = dir.join("book.json");
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book", "createdAt": "2024-01-01T00:00:00Z" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": 4, "cols": 4 },
+                "rows": {
+                    "1": {
+                        "A": { "value": "code", "type": "string" },
+                        "C": { "value": "note, with comma", "type": "string" }
+                    },
+                    "3": {
+                        "A": { "value": "007", "type": "string" },
+                        "B": { "value": 1.5, "type": "number" }
+                    }
+                }
+            }]
+        });
+        fs::write(&path, book.to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn exports_used_range_and_keeps_text_cells_textual() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = write_book(dir.path());
+        let output = dir.path().join("out").join("sheet.csv");
+
+        let result = export_book_to_csv(book, output.to_string_lossy().into_owned(), None).unwrap();
+        assert_eq!((result.rows, result.columns), (3, 3));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "code,,\"note, with comma\"\n,,\n\"007\",1.5,\n"
+        );
+    }
+
+    #[test]
+    fn applies_delimiter_quoting_and_grid_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = write_book(dir.path());
+        let output = dir.path().join("sheet.tsv");
+        let options = CsvExportOptions {
+            delimiter: '\t',
+            quote_style: CsvQuoteStyle::NonNumeric,
+            empty_value: "-".into(),
+            range: CsvRange::Grid,
+            ..Default::default()
+        };
+
+        let result =
+            export_book_to_csv(book, output.to_string_lossy().into_owned(), Some(options)).unwrap();
+        assert_eq!((result.rows, result.columns), (4, 4));
+        let csv = fs::read_to_string(&output).unwrap();
+        assert_eq!(csv.lines().nth(2), Some("\"007\"\t1.5\t-\t-"));
+    }
+
+    #[test]
+    fn exports_only_the_requested_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = write_book(dir.path());
+        let output = dir.path().join("range.tsv");
+        let range = CellRange {
+            start: "b5".into(),
+            end: "A3".into(),
+        };
+
+        let result = export_cell_range(
+            book.clone(),
+            range,
+            CellRangeFormat::Tsv,
+            output.to_string_lossy().into_owned(),
+            None,
+        )
+        .unwrap();
+        assert_eq!((result.rows, result.columns), (3, 2));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "\"007\"\t1.5\n\t\n\t\n"
+        );
+
+        let bad = CellRange {
+            start: "A1".into(),
+            end: "1A".into(),
+        };
+        assert!(matches!(
+            export_cell_range(book, bad, CellRangeFormat::Csv, String::new(), None),
+            Err(WorkspaceError::InvalidOption { name: "range", .. })
+        ));
+    }
+}
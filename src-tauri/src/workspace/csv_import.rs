@@ -0,0 +1,643 @@
+use super::books::{add_book, entry_name, overwrite_book, NewBook, DEFAULT_COLS, DEFAULT_ROWS};
+
+use super::cells::column_label;
+use super::error::{FileKind, WorkspaceError, WorkspaceResult};
+use super::io::read_workspace_json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Number, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DELIMITER_CANDIDATES: [u8; 3] = [b',', b'\t', b';'];
+const SNIFF_LINES: usize = 20;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CsvEncoding {
+    #[default]
+    Utf8,
+    ShiftJis,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvImportOptions {
+    /// Detected from the first lines (comma, tab or semicolon) when omitted.
+    pub delimiter: Option<char>,
+    pub encoding: CsvEncoding,
+    /// Store numeric-looking columns as numbers; when off every value is a
+    /// string.
+    pub infer_types: bool,
+    /// Defaults to the CSV file name without extension.
+    pub book_name: Option<String>,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: None,
+            encoding: CsvEncoding::default(),
+            infer_types: true,
+            book_name: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportResult {
+    pub book_id: String,
+    pub sheet_id: String,
+    pub file_path: String,
+    pub data_path: String,
+    pub delimiter: char,
+    pub rows: usize,
+    pub columns: usize,
+}
+
+pub(super) fn decode(bytes: &[u8], path: &Path, encoding: CsvEncoding) -> WorkspaceResult<String> {
+    let decoded = match encoding {
+        CsvEncoding::Utf8 => {
+            let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+            std::str::from_utf8(bytes).ok().map(str::to_string)
+        }
+        CsvEncoding::ShiftJis => encoding_rs::SHIFT_JIS
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|text| text.into_owned()),
+    };
+    decoded.ok_or_else(|| WorkspaceError::Encoding {
+        path: path.display().to_string(),
+        encoding: match encoding {
+            CsvEncoding::Utf8 => "UTF-8",
+            CsvEncoding::ShiftJis => "Shift_JIS",
+        },
+    })
+}
+
+fn count_outside_quotes(line: &str, delimiter: u8) -> usize {
+    let mut quoted = false;
+    line.bytes()
+        .filter(|&byte| {
+            if byte == b'"' {
+                quoted = !quoted;
+            }
+            !quoted && byte == delimiter
+        })
+        .count()
+}
+
+/// Picks the candidate that splits the most sample lines into the same
+/// number of fields, preferring commas on ties.
+fn sniff_delimiter(text: &str) -> u8 {
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(SNIFF_LINES)
+        .collect();
+    let mut best = (b',', (0, 0));
+    for delimiter in DELIMITER_CANDIDATES {
+        let counts: Vec<usize> = lines
+            .iter()
+            .map(|line| count_outside_quotes(line, delimiter))
+            .collect();
+        let score = counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let agreeing = counts.iter().filter(|&&other| other == count).count();
+                (agreeing, count)
+            })
+            .max()
+            .unwrap_or((0, 0));
+        if score > best.1 {
+            best = (delimiter, score);
+        }
+    }
+    best.0
+}
+
+/// JSON-style numbers only: `"007"` and `"1,000"` stay text.
+pub(super) fn parse_number(text: &str) -> Option<Number> {
+    let text = text.trim();
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    let well_formed = unsigned.starts_with(|c: char| c.is_ascii_digit())
+        && text
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E'));
+    let leading_zero = unsigned.len() > 1
+        && unsigned.starts_with('0')
+        && !unsigned[1..].starts_with(['.', 'e', 'E']);
+    if !well_formed || leading_zero {
+        return None;
+    }
+    match text.parse::<i64>() {
+        Ok(integer) => Some(integer.into()),
+        // Keeps the digits as written, e.g. `1.50` or integers beyond i64.
+        Err(_) => text
+            .parse::<Number>()
+            .ok()
+            .or_else(|| text.parse::<f64>().ok().and_then(Number::from_f64)),
+    }
+}
+
+/// A column is numeric when every non-empty value parses as a number,
+/// allowing a text header in the first row.
+pub(super) fn numeric_columns(records: &[Vec<String>], columns: usize) -> Vec<bool> {
+    (0..columns)
+        .map(|column| {
+            let mut values = records
+                .iter()
+                .enumerate()
+                .filter_map(|(row, record)| Some((row, record.get(column)?)))
+                .filter(|(_, value)| !value.trim().is_empty())
+                .peekable();
+            if values
+                .peek()
+                .is_some_and(|(row, value)| *row == 0 && parse_number(value).is_none())
+            {
+                values.next();
+            }
+            let mut any = false;
+            values.all(|(_, value)| {
+                any = true;
+                parse_number(value).is_some()
+            }) && any
+        })
+        .collect()
+}
+
+pub(super) fn build_rows(records: &[Vec<String>], numeric: &[bool]) -> Map<String, Value> {
+    let mut rows = Map::new();
+    for (row_offset, record) in records.iter().enumerate() {
+        let mut cells = Map::new();
+        for (column_offset, value) in record.iter().enumerate() {
+            if value.is_empty() {
+                continue;
+            }
+            let number = numeric[column_offset]
+                .then(|| parse_number(value))
+                .flatten();
+            let cell = match number {
+                Some(number) => json!({ "value": number, "type": "number" }),
+                None => json!({ "value": value, "type": "string" }),
+            };
+            cells.insert(column_label(column_offset as u32 + 1), cell);
+        }
+        if !cells.is_empty() {
+            rows.insert((row_offset + 1).to_string(), Value::Object(cells));
+        }
+    }
+    rows
+}
+
+/// A CSV file read into records, ready to become a sheet.
+struct ParsedCsv {
+    delimiter: u8,
+    records: Vec<Vec<String>>,
+    columns: usize,
+    numeric: Vec<bool>,
+}
+
+impl ParsedCsv {
+    fn grid_size(&self) -> (usize, usize) {
+        (
+            self.records.len().max(DEFAULT_ROWS),
+            self.columns.max(DEFAULT_COLS),
+        )
+    }
+
+    fn result(&self, book: NewBook) -> CsvImportResult {
+        CsvImportResult {
+            book_id: book.book_id,
+            sheet_id: book.sheet_id,
+            file_path: book.file_path,
+            data_path: book.data_path,
+            delimiter: char::from(self.delimiter),
+            rows: self.records.len(),
+            columns: self.columns,
+        }
+    }
+}
+
+fn delimiter_option(options: &CsvImportOptions) -> WorkspaceResult<Option<u8>> {
+    match options.delimiter {
+        Some(delimiter) if !delimiter.is_ascii() || matches!(delimiter, '"' | '\r' | '\n') => {
+            Err(WorkspaceError::InvalidOption {
+                name: "delimiter",
+                message: format!("{:?} cannot be used as a CSV delimiter", delimiter),
+            })
+        }
+        Some(delimiter) => Ok(Some(delimiter as u8)),
+        None => Ok(None),
+    }
+}
+
+/// The file name without extension, trimmed; `None` when that is empty.
+fn name_from_path(csv_path: &Path) -> Option<String> {
+    let stem = csv_path.file_stem()?.to_string_lossy();
+    Some(stem.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// The fields of each line of `text`, which is only used in errors.
+pub(super) fn csv_records(
+    text: &str,
+    delimiter: u8,
+    path: &Path,
+) -> WorkspaceResult<Vec<Vec<String>>> {
+    // Blank lines carry no cells, so the reader dropping them loses nothing
+    // but row positions; ragged rows are allowed and padded on export.
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(text.as_bytes());
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|err| WorkspaceError::ParseError {
+            path: path.display().to_string(),
+            line: err
+                .position()
+                .map_or(0, |position| position.line() as usize),
+            column: 0,
+            message: err.to_string(),
+            file_kind: FileKind::Other,
+        })?;
+        records.push(record.iter().map(str::to_string).collect::<Vec<_>>());
+    }
+    Ok(records)
+}
+
+fn parse_csv(
+    csv_path: &Path,
+    delimiter: Option<u8>,
+    options: &CsvImportOptions,
+) -> WorkspaceResult<ParsedCsv> {
+    let bytes = fs::read(csv_path).map_err(|err| WorkspaceError::io("read", csv_path, err))?;
+    let text = decode(&bytes, csv_path, options.encoding)?;
+    let delimiter = delimiter.unwrap_or_else(|| sniff_delimiter(&text));
+    let records = csv_records(&text, delimiter, csv_path)?;
+    let columns = records.iter().map(Vec::len).max().unwrap_or(0);
+    let numeric = if options.infer_types {
+        numeric_columns(&records, columns)
+    } else {
+        vec![false; columns]
+    };
+    Ok(ParsedCsv {
+        delimiter,
+        records,
+        columns,
+        numeric,
+    })
+}
+
+/// Converts a CSV file into a new single-sheet book under `books/` and
+/// appends it to `workspace.json`. The frontend should reload the workspace
+/// afterwards.
+#[tauri::command]
+pub fn import_csv_as_book(
+    csv_path: String,
+    workspace_path: String,
+    options: Option<CsvImportOptions>,
+) -> WorkspaceResult<CsvImportResult> {
+    let options = options.unwrap_or_default();
+    let csv_path = PathBuf::from(csv_path);
+    let workspace_path = PathBuf::from(workspace_path);
+
+    let delimiter = delimiter_option(&options)?;
+    let name = options
+        .book_name
+        .clone()
+        .or_else(|| name_from_path(&csv_path))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| WorkspaceError::InvalidOption {
+            name: "bookName",
+            message: "book name must not be empty".into(),
+        })?;
+
+    let csv = parse_csv(&csv_path, delimiter, &options)?;
+    let book = add_book(
+        &workspace_path,
+        &name,
+        csv.grid_size(),
+        build_rows(&csv.records, &csv.numeric),
+    )?;
+    Ok(csv.result(book))
+}
+
+/// What to do with a CSV whose name is already taken by a book.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CsvNameConflict {
+    /// Leave the book alone and list the CSV in `skipped`.
+    #[default]
+    Skip,
+    /// Import under the first free name of `name (2)`, `name (3)`, ...
+    Rename,
+    /// Replace the book's contents, keeping its id and file.
+    Overwrite,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvDirectoryImportOptions {
+    /// Applied to every file. `bookName` is ignored: each book is named
+    /// after its file.
+    pub csv: CsvImportOptions,
+    /// Also import CSV files in subdirectories, except hidden ones.
+    pub recursive: bool,
+    pub on_conflict: CsvNameConflict,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvDirectoryImported {
+    pub csv_path: String,
+    pub book_name: String,
+    /// An existing book's contents were replaced rather than a book added.
+    pub overwritten: bool,
+    pub book: CsvImportResult,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvDirectoryFailure {
+    pub csv_path: String,
+    pub error: WorkspaceError,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvDirectoryImportResult {
+    pub imported: Vec<CsvDirectoryImported>,
+    /// CSV files not imported because a book already had their name.
+    pub skipped: Vec<String>,
+    pub failed: Vec<CsvDirectoryFailure>,
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+/// CSV files in `dir`, sorted by path so imports happen in a stable order.
+fn find_csv_files(dir: &Path, recursive: bool) -> WorkspaceResult<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            fs::read_dir(&dir).map_err(|err| WorkspaceError::io("read directory", &dir, err))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| WorkspaceError::io("read directory", &dir, err))?;
+            let path = entry.path();
+            let file_type = entry
+                .file_type()
+                .map_err(|err| WorkspaceError::io("stat", &path, err))?;
+            if file_type.is_dir() {
+                if recursive && !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(path);
+                }
+            } else if is_csv(&path) {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Index of the book named `name`, ignoring case and the `.json` the
+/// workspace entry names carry.
+fn book_named(workspace: &Value, name: &str) -> Option<usize> {
+    let wanted = entry_name(name).to_lowercase();
+    workspace["books"]
+        .as_array()?
+        .iter()
+        .position(|book| match book["name"].as_str() {
+            Some(existing) => {
+                let existing = existing.to_lowercase();
+                existing == wanted || existing + ".json" == wanted
+            }
+            None => false,
+        })
+}
+
+fn import_one(
+    csv_path: &Path,
+    workspace_path: &Path,
+    delimiter: Option<u8>,
+    options: &CsvDirectoryImportOptions,
+) -> WorkspaceResult<Option<CsvDirectoryImported>> {
+    let name = name_from_path(csv_path).ok_or_else(|| WorkspaceError::InvalidOption {
+        name: "bookName",
+        message: "book name must not be empty".into(),
+    })?;
+    let workspace = read_workspace_json(workspace_path)?;
+    let existing = book_named(&workspace, &name);
+    let (name, overwrite) = match (existing, options.on_conflict) {
+        (None, _) => (name, None),
+        (Some(_), CsvNameConflict::Skip) => return Ok(None),
+        (Some(index), CsvNameConflict::Overwrite) => (name, Some(index)),
+        (Some(_), CsvNameConflict::Rename) => {
+            let free = (2..)
+                .map(|attempt| format!("{} ({})", name, attempt))
+                .find(|candidate| book_named(&workspace, candidate).is_none())
+                .expect("some suffix is always free");
+            (free, None)
+        }
+    };
+
+    let csv = parse_csv(csv_path, delimiter, &options.csv)?;
+    let rows = build_rows(&csv.records, &csv.numeric);
+    let book = match overwrite {
+        Some(index) => overwrite_book(workspace_path, index, &name, csv.grid_size(), rows)?,
+        None => add_book(workspace_path, &name, csv.grid_size(), rows)?,
+    };
+    Ok(Some(CsvDirectoryImported {
+        csv_path: csv_path.to_string_lossy().into_owned(),
+        book_name: name,
+        overwritten: overwrite.is_some(),
+        book: csv.result(book),
+    }))
+}
+
+/// Imports every `.csv` in `dir_path` as a book named after the file, one
+/// after another, each appended to `workspace.json` as it is converted. A
+/// file that fails is listed in `failed` and the rest still go ahead. The
+/// frontend should reload the workspace afterwards.
+#[tauri::command]
+pub fn import_csv_directory(
+    dir_path: String,
+    workspace_path: String,
+    options: Option<CsvDirectoryImportOptions>,
+) -> WorkspaceResult<CsvDirectoryImportResult> {
+    let options = options.unwrap_or_default();
+    let delimiter = delimiter_option(&options.csv)?;
+    let workspace_path = PathBuf::from(workspace_path);
+    let files = find_csv_files(Path::new(&dir_path), options.recursive)?;
+
+    let mut result = CsvDirectoryImportResult::default();
+    for csv_path in files {
+        match import_one(&csv_path, &workspace_path, delimiter, &options) {
+            Ok(Some(imported)) => result.imported.push(imported),
+            Ok(None) => result.skipped.push(csv_path.to_string_lossy().into_owned()),
+            Err(error) => result.failed.push(CsvDirectoryFailure {
+                csv_path: csv_path.to_string_lossy().into_owned(),
+                error,
+            }),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file, FileEncoding};
+    use crate::workspace::schema::validate_book;
+
+    fn import(dir: &Path, csv: &[u8], options: CsvImportOptions) -> (CsvImportResult, Value) {
+        let workspace_path = dir.join("workspace.json");
+        if !workspace_path.exists() {
+            write_json_file(
+                &workspace_path,
+                &json!({ "workspace": {}, "books": [] }),
+                FileEncoding::default(),
+            )
+            .unwrap();
+        }
+        let csv_path = dir.join("data.csv");
+        fs::write(&csv_path, csv).unwrap();
+        let result = import_csv_as_book(
+            csv_path.to_string_lossy().into_owned(),
+            workspace_path.to_string_lossy().into_owned(),
+            Some(options),
+        )
+        .unwrap();
+        let book = read_json_file(Path::new(&result.file_path)).unwrap();
+        validate_book(&book).unwrap();
+        (result, book)
+    }
+
+    #[test]
+    fn infers_numeric_columns_and_keeps_leading_zeros() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = "code;price;note\n007;1.5;a\n\n010;2;b;extra\n";
+        let (result, book) = import(dir.path(), csv.as_bytes(), CsvImportOptions::default());
+
+        assert_eq!((result.delimiter, result.rows, result.columns), (';', 3, 4));
+        assert_eq!(result.data_path, "books/data.json");
+        let rows = &book["sheets"][0]["rows"];
+        assert_eq!(
+            rows["1"]["B"],
+            json!({ "value": "price", "type": "string" })
+        );
+        assert_eq!(rows["2"]["A"], json!({ "value": "007", "type": "string" }));
+        assert_eq!(rows["2"]["B"], json!({ "value": 1.5, "type": "number" }));
+        assert_eq!(
+            rows["3"]["D"],
+            json!({ "value": "extra", "type": "string" })
+        );
+
+        let workspace = read_json_file(&dir.path().join("workspace.json")).unwrap();
+        assert_eq!(workspace["books"][0]["id"], json!(result.book_id));
+        assert_eq!(workspace["books"][0]["dataPath"], json!("books/data.json"));
+    }
+
+    #[test]
+    fn decodes_shift_jis_and_can_skip_type_inference() {
+        let dir = tempfile::tempdir().unwrap();
+        let (csv, _, _) = encoding_rs::SHIFT_JIS.encode("名前\t数量\nりんご\t3\n");
+        let options = CsvImportOptions {
+            encoding: CsvEncoding::ShiftJis,
+            infer_types: false,
+            ..Default::default()
+        };
+        let (result, book) = import(dir.path(), &csv, options);
+
+        assert_eq!(result.delimiter, '\t');
+        let rows = &book["sheets"][0]["rows"];
+        assert_eq!(rows["2"]["A"]["value"], json!("りんご"));
+        assert_eq!(rows["2"]["B"], json!({ "value": "3", "type": "string" }));
+
+        let (second, _) = import(dir.path(), b"a,b\n", CsvImportOptions::default());
+        assert_eq!(second.data_path, "books/data (2).json");
+    }
+
+    #[test]
+    fn imports_a_directory_and_resolves_name_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        // Reuses a workspace with one imported book named "sales".
+        let (existing, _) = import(dir.path(), b"x\n", CsvImportOptions::default());
+        let workspace_path = dir.path().join("workspace.json");
+        let mut workspace = read_json_file(&workspace_path).unwrap();
+        workspace["books"][0]["name"] = json!("sales.json");
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+
+        let csv_dir = dir.path().join("csv");
+        fs::create_dir_all(csv_dir.join("nested")).unwrap();
+        fs::create_dir_all(csv_dir.join(".hidden")).unwrap();
+        fs::write(csv_dir.join("Sales.CSV"), "a,b\n1,2\n").unwrap();
+        fs::write(csv_dir.join("costs.csv"), "c\n3\n").unwrap();
+        fs::write(csv_dir.join("broken.csv"), b"\xff\xfe\x00").unwrap();
+        fs::write(csv_dir.join("notes.txt"), "ignored").unwrap();
+        fs::write(csv_dir.join("nested/costs.csv"), "d\n4\n").unwrap();
+        fs::write(csv_dir.join(".hidden/secret.csv"), "e\n").unwrap();
+        let run = |options: CsvDirectoryImportOptions| {
+            import_csv_directory(
+                csv_dir.to_string_lossy().into_owned(),
+                workspace_path.to_string_lossy().into_owned(),
+                Some(options),
+            )
+            .unwrap()
+        };
+
+        let result = run(CsvDirectoryImportOptions::default());
+        let names: Vec<_> = result
+            .imported
+            .iter()
+            .map(|i| i.book_name.as_str())
+            .collect();
+        assert_eq!(names, ["costs"]);
+        assert_eq!(result.skipped.len(), 1);
+        assert!(result.skipped[0].ends_with("Sales.CSV"));
+        assert_eq!(result.failed.len(), 1);
+        assert!(matches!(
+            result.failed[0].error,
+            WorkspaceError::Encoding { .. }
+        ));
+
+        let result = run(CsvDirectoryImportOptions {
+            recursive: true,
+            on_conflict: CsvNameConflict::Rename,
+            ..Default::default()
+        });
+        let names: Vec<_> = result
+            .imported
+            .iter()
+            .map(|i| i.book_name.as_str())
+            .collect();
+        assert_eq!(names, ["Sales (2)", "costs (2)", "costs (3)"]);
+
+        let result = run(CsvDirectoryImportOptions {
+            on_conflict: CsvNameConflict::Overwrite,
+            ..Default::default()
+        });
+        let sales = result
+            .imported
+            .iter()
+            .find(|imported| imported.book_name == "Sales")
+            .unwrap();
+        assert!(sales.overwritten);
+        assert_eq!(sales.book.book_id, existing.book_id);
+        let book = read_json_file(Path::new(&sales.book.file_path)).unwrap();
+        validate_book(&book).unwrap();
+        assert_eq!(book["sheets"][0]["rows"]["2"]["B"]["value"], json!(2));
+        let workspace = read_json_file(&workspace_path).unwrap();
+        assert_eq!(workspace["books"].as_array().unwrap().len(), 5);
+        assert_eq!(
+            workspace["books"][0]["activeSheetId"],
+            json!(sales.book.sheet_id)
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! `diagnose_workspace`: one read-only pass over a workspace directory that
+//! collects every problem found instead of stopping at the first, for users
+//! to run (and attach to a bug report) when a workspace misbehaves.
+
+use super::backup::BACKUP_DIR;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_encrypted_file, read_workspace_json};
+use super::journal::JOURNAL_FILE;
+use super::lock::LOCK_FILE;
+use super::manifest::MANIFEST_FILE_NAME;
+use super::migrate::migrate_workspace;
+use super::parallel::parallel_map;
+use super::paths::{
+    duplicate_data_paths, ensure_links_within, normalize_lexically, workspace_dir_of, BookRoots,
+};
+use super::schema::validate_workspace;
+use super::trash::TRASH_DIR;
+use super::{allowed_book_file_path, load_book, WORKSPACE_FILE_NAME};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories the app keeps its own copies of books in, plus version
+/// control metadata; their `.json` files are never orphans.
+pub(super) const IGNORED_DIRS: [&str; 3] = [BACKUP_DIR, TRASH_DIR, ".git"];
+/// Files of the app itself that look like books by extension.
+const IGNORED_FILES: [&str; 4] = [
+    WORKSPACE_FILE_NAME,
+    MANIFEST_FILE_NAME,
+    JOURNAL_FILE,
+    LOCK_FILE,
+];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceFileDiagnosis {
+    pub size_bytes: u64,
+    /// Why `workspace.json` cannot be read, or why it is not valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<WorkspaceError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookDiagnosis {
+    pub index: usize,
+    pub id: Option<String>,
+    pub data_path: Option<String>,
+    pub file_path: Option<String>,
+    /// `None` when the file does not exist or its path is unusable.
+    pub size_bytes: Option<u64>,
+    /// Encrypted books are only checked for existence and size, since
+    /// reading them takes the passphrase.
+    pub encrypted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<WorkspaceError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiagnosis {
+    pub workspace: WorkspaceFileDiagnosis,
+    pub books: Vec<BookDiagnosis>,
+    /// `dataPath`s shared by several entries.
+    pub duplicates: Vec<WorkspaceError>,
+    /// Book-like files (`.json`, `.json.gz`) under the workspace directory
+    /// that no entry refers to, relative to it with `/` separators. Left
+    /// empty when `workspace.json` cannot be parsed.
+    pub orphans: Vec<String>,
+    /// Nothing above reports a problem.
+    pub healthy: bool,
+}
+
+fn is_book_like(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".json") || name.ends_with(".json.gz")
+}
+
+fn diagnose_book(
+    roots: &BookRoots,
+    real_roots: &[PathBuf],
+    index: usize,
+    book_ref: &Value,
+) -> BookDiagnosis {
+    let text = |field: &str| book_ref[field].as_str().map(str::to_string);
+    let mut diagnosis = BookDiagnosis {
+        index,
+        id: text("id"),
+        data_path: text("dataPath"),
+        file_path: None,
+        size_bytes: None,
+        encrypted: false,
+        error: None,
+    };
+    let checked = allowed_book_file_path(roots, book_ref, index).and_then(|path| {
+        diagnosis.file_path = Some(path.to_string_lossy().into_owned());
+        ensure_links_within(real_roots, &path)?;
+        let stat = fs::metadata(&path).map_err(|err| WorkspaceError::io("stat", &path, err))?;
+        diagnosis.size_bytes = Some(stat.len());
+        diagnosis.encrypted = is_encrypted_file(&path);
+        if !diagnosis.encrypted {
+            load_book(&path, None, None)?;
+        }
+        Ok(())
+    });
+    diagnosis.error = checked.err();
+    diagnosis
+}
+
+/// Book-like files below `dir`, skipping ignored directories and not
+/// following directory links, so a link cycle cannot trap the walk.
+fn book_like_files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !IGNORED_DIRS.contains(&name.as_str()) {
+                book_like_files(&entry.path(), found);
+            }
+        } else if is_book_like(&name) && !IGNORED_FILES.contains(&name.as_str()) {
+            found.push(entry.path());
+        }
+    }
+}
+
+/// Book-like files under `workspace_dir` that are not among `referenced`,
+/// as paths relative to it with `/` separators next to the full path,
+/// sorted by the former. Internal files of the app are never included.
+pub(super) fn orphan_files(
+    workspace_dir: &Path,
+    referenced: impl IntoIterator<Item = PathBuf>,
+) -> Vec<(String, PathBuf)> {
+    let referenced: HashSet<PathBuf> = referenced
+        .into_iter()
+        .filter_map(|path| normalize_lexically(&path))
+        .collect();
+    let mut found = Vec::new();
+    book_like_files(workspace_dir, &mut found);
+    let mut orphans: Vec<(String, PathBuf)> = found
+        .into_iter()
+        .filter(|path| !normalize_lexically(path).is_some_and(|path| referenced.contains(&path)))
+        .filter_map(|path| {
+            let relative = path.strip_prefix(workspace_dir).ok()?;
+            let parts: Vec<_> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            Some((parts.join("/"), path))
+        })
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+/// Checks `workspace.json`, every book it lists and the files around them
+/// without writing anything: no migration is saved, no missing book is
+/// created and no lock is taken.
+#[tauri::command(async)]
+pub fn diagnose_workspace(workspace_path: String) -> WorkspaceResult<WorkspaceDiagnosis> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let size_bytes = fs::metadata(&workspace_path)
+        .map(|stat| stat.len())
+        .map_err(|err| WorkspaceError::io("read", &workspace_path, err))?;
+
+    let (data, workspace_error) = match read_workspace_json(&workspace_path) {
+        Ok(data) => {
+            let checked = migrate_workspace(data.clone(), &workspace_path)
+                .and_then(|(migrated, _)| validate_workspace(&migrated));
+            (Some(data), checked.err())
+        }
+        Err(err) => (None, Some(err)),
+    };
+    let book_refs = data
+        .as_ref()
+        .and_then(|data| data["books"].as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let roots = BookRoots::new(&workspace_dir, data.as_ref().unwrap_or(&Value::Null));
+    let real_roots = roots.resolved();
+    let books = parallel_map(book_refs, |index, book_ref| {
+        diagnose_book(&roots, &real_roots, index, book_ref)
+    });
+    let duplicates = duplicate_data_paths(
+        book_refs
+            .iter()
+            .map(|book_ref| book_ref["dataPath"].as_str()),
+        false,
+    );
+    let orphans = match data {
+        Some(_) => {
+            let referenced = books
+                .iter()
+                .filter_map(|book| book.file_path.as_ref().map(PathBuf::from));
+            orphan_files(&workspace_dir, referenced)
+                .into_iter()
+                .map(|(relative, _)| relative)
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let healthy = workspace_error.is_none()
+        && books.iter().all(|book| book.error.is_none())
+        && duplicates.is_empty()
+        && orphans.is_empty();
+    Ok(WorkspaceDiagnosis {
+        workspace: WorkspaceFileDiagnosis {
+            size_bytes,
+            error: workspace_error,
+        },
+        books,
+        duplicates,
+        orphans,
+        healthy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+
+    fn book(id: &str) -> Value {
+        json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": id, "name": id },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet 1",
+                "gridSize": { "rows": 10, "cols": 5 },
+                "rows": {}
+            }]
+        })
+    }
+
+    fn listing(dir: &Path) -> BTreeMap<PathBuf, SystemTime> {
+        let mut files = BTreeMap::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(dir).unwrap().flatten() {
+                let stat = entry.metadata().unwrap();
+                if stat.is_dir() {
+                    pending.push(entry.path());
+                }
+                files.insert(entry.path(), stat.modified().unwrap());
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn reports_every_problem_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = FileEncoding::default();
+        write_json_file(&dir.path().join("books/good.json"), &book("good"), plain).unwrap();
+        write_json_file(&dir.path().join("books/old.json"), &book("old"), plain).unwrap();
+        write_json_file(
+            &dir.path().join(".sheet-up-backups/x.json"),
+            &json!({}),
+            plain,
+        )
+        .unwrap();
+        write_json_file(&dir.path().join(MANIFEST_FILE_NAME), &json!({}), plain).unwrap();
+        fs::write(dir.path().join("books/broken.json"), "{ nope").unwrap();
+        let workspace_path = dir.path().join(WORKSPACE_FILE_NAME);
+        let entries = [
+            ("good", "books/good.json"),
+            ("missing", "books/missing.json"),
+            ("broken", "books/broken.json"),
+            ("escape", "../outside.json"),
+            ("again", "books/good.json"),
+        ];
+        let books: Vec<Value> = entries
+            .iter()
+            .map(|(id, data_path)| json!({ "id": id, "name": id, "dataPath": data_path }))
+            .collect();
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "books": books }),
+            plain,
+        )
+        .unwrap();
+        let before = listing(dir.path());
+
+        let report = diagnose_workspace(workspace_path.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(listing(dir.path()), before);
+        assert!(!report.healthy);
+        assert!(report.workspace.error.is_none());
+        let errors: Vec<Option<&str>> = report
+            .books
+            .iter()
+            .map(|book| {
+                book.error.as_ref().map(|err| match err {
+                    WorkspaceError::NotFound { .. } => "notFound",
+                    WorkspaceError::ParseError { .. } => "parseError",
+                    WorkspaceError::PathOutsideWorkspace { .. } => "pathOutsideWorkspace",
+                    other => panic!("unexpected {:?}", other),
+                })
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                None,
+                Some("notFound"),
+                Some("parseError"),
+                Some("pathOutsideWorkspace"),
+                None
+            ]
+        );
+        assert!(report.books[0].size_bytes.is_some_and(|size| size > 0));
+        assert!(matches!(
+            &report.duplicates[..],
+            [WorkspaceError::DuplicateDataPath { indices, .. }] if *indices == [0, 4]
+        ));
+        assert_eq!(report.orphans, ["books/old.json"]);
+    }
+
+    #[test]
+    fn unparsable_workspace_json_is_reported_not_raised() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join(WORKSPACE_FILE_NAME);
+        fs::write(&workspace_path, "{ \"books\": [").unwrap();
+        write_json_file(
+            &dir.path().join("books/a.json"),
+            &book("a"),
+            FileEncoding::default(),
+        )
+        .unwrap();
+
+        let report = diagnose_workspace(workspace_path.to_string_lossy().into_owned()).unwrap();
+        assert!(matches!(
+            report.workspace.error,
+            Some(WorkspaceError::ParseError { .. })
+        ));
+        assert!(report.books.is_empty() && report.orphans.is_empty());
+        assert!(!report.healthy);
+    }
+}
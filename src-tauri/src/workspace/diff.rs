@@ -0,0 +1,372 @@
+use super::cells::{column_index, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::SizeLimits;
+use super::paths::workspace_dir_of;
+use super::{book_file_path, load_book, load_workspace_file};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiffOptions {
+    /// Treat numbers that differ only in representation (`1` and `1.0`) as
+    /// equal.
+    pub numbers_by_value: bool,
+    /// Stop collecting cell changes after this many and set `truncated`.
+    pub max_cell_changes: Option<usize>,
+    /// Decrypts encrypted books on either side.
+    pub passphrase: Option<String>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            numbers_by_value: true,
+            max_cell_changes: None,
+            passphrase: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookSummary {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetChange {
+    pub book_id: String,
+    pub sheet_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellChange {
+    pub book_id: String,
+    pub sheet_id: String,
+    /// A1-style address such as `"B3"`.
+    pub cell: String,
+    /// `None` when the cell only exists on the other side.
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffFailure {
+    pub book_id: String,
+    pub error: WorkspaceError,
+}
+
+/// Differences from workspace A to workspace B. Books and sheets are matched
+/// by id; cells are listed in sheet, row, column order.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiff {
+    pub added_books: Vec<BookSummary>,
+    pub removed_books: Vec<BookSummary>,
+    pub common_books: Vec<String>,
+    pub added_sheets: Vec<SheetChange>,
+    pub removed_sheets: Vec<SheetChange>,
+    pub changed_cells: Vec<CellChange>,
+    /// Set when `maxCellChanges` cut the cell list short.
+    pub truncated: bool,
+    /// Common books that could not be compared because one side failed to
+    /// load.
+    pub failed: Vec<DiffFailure>,
+}
+
+fn values_equal(a: &Value, b: &Value, numbers_by_value: bool) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) if numbers_by_value => {
+            match (a.as_i64(), b.as_i64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => a.as_f64() == b.as_f64(),
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|(a, b)| values_equal(a, b, numbers_by_value))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(key, a)| {
+                    b.get(key)
+                        .is_some_and(|b| values_equal(a, b, numbers_by_value))
+                })
+        }
+        _ => a == b,
+    }
+}
+
+/// Keys present on either side, sorted by `index` (unparsable keys last).
+fn union_keys<'a>(
+    a: Option<&'a Map<String, Value>>,
+    b: Option<&'a Map<String, Value>>,
+    index: fn(&str) -> Option<u32>,
+) -> Vec<&'a str> {
+    let mut keys: Vec<&str> = a
+        .into_iter()
+        .chain(b)
+        .flat_map(|map| map.keys().map(String::as_str))
+        .collect();
+    keys.sort_by_key(|key| (index(key).unwrap_or(u32::MAX), *key));
+    keys.dedup();
+    keys
+}
+
+fn summaries<'a>(
+    refs: &'a [Value],
+    mut keep: impl FnMut(&str) -> bool + 'a,
+) -> impl Iterator<Item = BookSummary> + 'a {
+    refs.iter()
+        .filter_map(|book| book["id"].as_str().map(|id| (id, book)))
+        .filter(move |(id, _)| keep(id))
+        .map(|(id, book)| BookSummary {
+            id: id.to_string(),
+            name: book["name"].as_str().map(str::to_string),
+        })
+}
+
+struct Differ<'a> {
+    options: &'a DiffOptions,
+    diff: WorkspaceDiff,
+}
+
+impl Differ<'_> {
+    fn is_full(&self) -> bool {
+        self.options
+            .max_cell_changes
+            .is_some_and(|max| self.diff.changed_cells.len() >= max)
+    }
+
+    fn sheets(&mut self, book_id: &str, a: &Value, b: &Value) {
+        let sheets = |book| -> Vec<(&str, &Value)> {
+            Value::as_array(book)
+                .into_iter()
+                .flatten()
+                .filter_map(|sheet| Some((sheet["id"].as_str()?, sheet)))
+                .collect()
+        };
+        let (a_sheets, b_sheets) = (sheets(&a["sheets"]), sheets(&b["sheets"]));
+        fn find<'a>(sheets: &[(&str, &'a Value)], id: &str) -> Option<&'a Value> {
+            sheets
+                .iter()
+                .find(|(other, _)| *other == id)
+                .map(|(_, sheet)| *sheet)
+        }
+        for &(id, _) in &b_sheets {
+            if find(&a_sheets, id).is_none() {
+                self.diff.added_sheets.push(SheetChange {
+                    book_id: book_id.to_string(),
+                    sheet_id: id.to_string(),
+                });
+            }
+        }
+        for &(id, a_sheet) in &a_sheets {
+            match find(&b_sheets, id) {
+                Some(b_sheet) => self.cells(book_id, id, a_sheet, b_sheet),
+                None => self.diff.removed_sheets.push(SheetChange {
+                    book_id: book_id.to_string(),
+                    sheet_id: id.to_string(),
+                }),
+            }
+        }
+    }
+
+    fn cells(&mut self, book_id: &str, sheet_id: &str, a: &Value, b: &Value) {
+        let (a_rows, b_rows) = (a["rows"].as_object(), b["rows"].as_object());
+        for row in union_keys(a_rows, b_rows, row_index) {
+            let a_row = a_rows.and_then(|rows| rows.get(row)?.as_object());
+            let b_row = b_rows.and_then(|rows| rows.get(row)?.as_object());
+            for column in union_keys(a_row, b_row, column_index) {
+                let old = a_row.and_then(|cells| cells.get(column));
+                let new = b_row.and_then(|cells| cells.get(column));
+                let same = match (old, new) {
+                    (Some(old), Some(new)) => values_equal(old, new, self.options.numbers_by_value),
+                    (old, new) => old.is_none() && new.is_none(),
+                };
+                if same {
+                    continue;
+                }
+                if self.is_full() {
+                    self.diff.truncated = true;
+                    return;
+                }
+                self.diff.changed_cells.push(CellChange {
+                    book_id: book_id.to_string(),
+                    sheet_id: sheet_id.to_string(),
+                    cell: format!("{}{}", column, row),
+                    old: old.cloned(),
+                    new: new.cloned(),
+                });
+            }
+        }
+    }
+}
+
+fn load_ref(
+    workspace_dir: &Path,
+    refs: &[Value],
+    id: &str,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<Value> {
+    let (index, book_ref) = refs
+        .iter()
+        .enumerate()
+        .find(|(_, book)| book["id"] == id)
+        .expect("id was taken from this list");
+    let path = book_file_path(workspace_dir, book_ref, index)?;
+    Ok(load_book(&path, passphrase, SizeLimits::default().book)?.data)
+}
+
+/// Compares two workspaces book by book. Only one pair of books is held in
+/// memory at a time, so large workspaces can be diffed without loading
+/// everything up front.
+#[tauri::command]
+pub fn diff_workspaces(
+    path_a: String,
+    path_b: String,
+    options: Option<DiffOptions>,
+) -> WorkspaceResult<WorkspaceDiff> {
+    let options = options.unwrap_or_default();
+    let (path_a, path_b) = (PathBuf::from(path_a), PathBuf::from(path_b));
+    let (workspace_a, workspace_b) = (
+        load_workspace_file(&path_a, SizeLimits::default().workspace)?.data,
+        load_workspace_file(&path_b, SizeLimits::default().workspace)?.data,
+    );
+    let refs = |workspace: &Value| workspace["books"].as_array().cloned().unwrap_or_default();
+    let (refs_a, refs_b) = (refs(&workspace_a), refs(&workspace_b));
+    let has = |refs: &[Value], id: &str| refs.iter().any(|book| book["id"] == id);
+
+    let mut differ = Differ {
+        options: &options,
+        diff: WorkspaceDiff::default(),
+    };
+    differ.diff.added_books = summaries(&refs_b, |id| !has(&refs_a, id)).collect();
+    differ.diff.removed_books = summaries(&refs_a, |id| !has(&refs_b, id)).collect();
+    differ.diff.common_books = summaries(&refs_a, |id| has(&refs_b, id))
+        .map(|book| book.id)
+        .collect();
+
+    let (dir_a, dir_b) = (workspace_dir_of(&path_a), workspace_dir_of(&path_b));
+    let passphrase = options.passphrase.as_deref();
+    for book_id in differ.diff.common_books.clone() {
+        if differ.diff.truncated {
+            break;
+        }
+        let books = load_ref(&dir_a, &refs_a, &book_id, passphrase)
+            .and_then(|a| Ok((a, load_ref(&dir_b, &refs_b, &book_id, passphrase)?)));
+        match books {
+            Ok((a, b)) => differ.sheets(&book_id, &a, &b),
+            Err(error) => differ.diff.failed.push(DiffFailure { book_id, error }),
+        }
+    }
+    Ok(differ.diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+
+    fn write_workspace(dir: &Path, books: &[(&str, Value)]) -> String {
+        let refs: Vec<Value> = books
+            .iter()
+            .map(|(id, _)| json!({ "id": id, "name": id, "dataPath": format!("{}.json", id) }))
+            .collect();
+        let workspace_path = dir.join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": refs }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        for (id, rows) in books {
+            let book = json!({
+                "schemaVersion": "1.0.0",
+                "book": { "id": id, "name": id },
+                "sheets": [{
+                    "id": "sheet-1",
+                    "name": "Sheet",
+                    "gridSize": { "rows": 100, "cols": 26 },
+                    "rows": rows
+                }]
+            });
+            write_json_file(
+                &dir.join(format!("{}.json", id)),
+                &book,
+                FileEncoding::default(),
+            )
+            .unwrap();
+        }
+        workspace_path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn reports_books_and_cells_that_changed() {
+        let (a, b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let cell = |value: Value| json!({ "value": value, "type": "number" });
+        let path_a = write_workspace(
+            a.path(),
+            &[
+                (
+                    "kept",
+                    json!({ "2": { "A": cell(json!(1)) }, "10": { "B": cell(json!(5)) } }),
+                ),
+                ("gone", json!({})),
+            ],
+        );
+        let path_b = write_workspace(
+            b.path(),
+            &[
+                (
+                    "kept",
+                    json!({ "2": { "A": cell(json!(1.0)), "C": cell(json!(2)) }, "10": {} }),
+                ),
+                ("new", json!({})),
+            ],
+        );
+
+        let diff = diff_workspaces(path_a.clone(), path_b.clone(), None).unwrap();
+        assert_eq!(diff.added_books[0].id, "new");
+        assert_eq!(diff.removed_books[0].id, "gone");
+        assert_eq!(diff.common_books, vec!["kept"]);
+        assert!(diff.failed.is_empty());
+        let cells: Vec<(&str, Option<&Value>, Option<&Value>)> = diff
+            .changed_cells
+            .iter()
+            .map(|change| {
+                (
+                    change.cell.as_str(),
+                    change.old.as_ref(),
+                    change.new.as_ref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                ("C2", None, Some(&cell(json!(2)))),
+                ("B10", Some(&cell(json!(5))), None)
+            ]
+        );
+
+        let strict = DiffOptions {
+            numbers_by_value: false,
+            max_cell_changes: Some(1),
+            ..Default::default()
+        };
+        let diff = diff_workspaces(path_a, path_b, Some(strict)).unwrap();
+        assert_eq!(diff.changed_cells.len(), 1);
+        assert_eq!(diff.changed_cells[0].cell, "A2");
+        assert!(diff.truncated);
+    }
+}
@@ -0,0 +1,210 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_workspace_json, write_json_file, FileEncoding};
+use super::manifest::MANIFEST_FILE_NAME;
+use super::paths::{normalize_lexically, workspace_dir_of};
+use super::schema::validate_workspace;
+use super::{book_file_path, WORKSPACE_FILE_NAME};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateWorkspaceResult {
+    pub workspace_path: String,
+    /// Book files copied, not counting `workspace.json` and the manifest.
+    pub book_files: usize,
+}
+
+/// Every referenced book file once, relative to the workspace directory.
+pub(super) fn files_to_copy(workspace: &Value) -> WorkspaceResult<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for (index, book_ref) in workspace["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let relative = book_file_path(Path::new(""), book_ref, index)?;
+        if !files.contains(&relative) {
+            files.push(relative);
+        }
+    }
+    Ok(files)
+}
+
+/// A fresh directory next to `dest_dir` to assemble files in before they are
+/// moved into place, so the move stays on one file system.
+pub(super) fn staging_dir_for(dest_dir: &Path) -> PathBuf {
+    let dest_name = normalize_lexically(dest_dir)
+        .and_then(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "workspace".into());
+    workspace_dir_of(dest_dir).join(format!(
+        ".{}.sheet-up-tmp-{}",
+        dest_name,
+        uuid::Uuid::new_v4()
+    ))
+}
+
+/// Moves every staged file into `dest_dir`, putting back what was already
+/// moved if one of them fails.
+pub(super) fn move_into_place(
+    staging: &Path,
+    dest_dir: &Path,
+    files: &[PathBuf],
+) -> WorkspaceResult<()> {
+    let mut moved: Vec<&PathBuf> = Vec::new();
+    let result = files.iter().try_for_each(|relative| {
+        let target = dest_dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| WorkspaceError::io("create", parent, err))?;
+        }
+        fs::rename(staging.join(relative), &target)
+            .map_err(|err| WorkspaceError::io("move", &target, err))?;
+        moved.push(relative);
+        Ok(())
+    });
+    if result.is_err() {
+        for relative in moved {
+            let _ = fs::rename(dest_dir.join(relative), staging.join(relative));
+        }
+    }
+    result
+}
+
+/// Copies a workspace and all of its books to `dest_dir` ("Save As"). The
+/// copy is assembled in a staging directory next to `dest_dir` and only
+/// moved in once complete; nothing is written if any target file already
+/// exists. The source is never modified.
+#[tauri::command]
+pub fn duplicate_workspace(
+    source_path: String,
+    dest_dir: String,
+    new_name: Option<String>,
+) -> WorkspaceResult<DuplicateWorkspaceResult> {
+    let source_path = PathBuf::from(source_path);
+    let source_dir = workspace_dir_of(&source_path);
+    let dest_dir = PathBuf::from(dest_dir);
+    let mut workspace = read_workspace_json(&source_path)?;
+    validate_workspace(&workspace)?;
+
+    let books = files_to_copy(&workspace)?;
+    let manifest = Path::new(MANIFEST_FILE_NAME);
+    let mut files = books.clone();
+    if source_dir.join(manifest).is_file() {
+        files.push(manifest.to_path_buf());
+    }
+    let workspace_file = Path::new(WORKSPACE_FILE_NAME);
+    for relative in files.iter().map(PathBuf::as_path).chain([workspace_file]) {
+        let target = dest_dir.join(relative);
+        if target.exists() {
+            return Err(WorkspaceError::AlreadyExists {
+                path: target.display().to_string(),
+            });
+        }
+    }
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert(
+            "id".into(),
+            json!(format!("workspace-{}", uuid::Uuid::new_v4())),
+        );
+        if let Some(name) = new_name.map(|name| name.trim().to_string()) {
+            if !name.is_empty() {
+                meta.insert("name".into(), json!(name));
+            }
+        }
+        meta.insert("createdAt".into(), json!(now));
+        meta.insert("updatedAt".into(), json!(now));
+    }
+
+    let staging = staging_dir_for(&dest_dir);
+    let staged = (|| {
+        for relative in &files {
+            let target = staging.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|err| WorkspaceError::io("create", parent, err))?;
+            }
+            let source = source_dir.join(relative);
+            fs::copy(&source, &target).map_err(|err| WorkspaceError::io("copy", &source, err))?;
+        }
+        write_json_file(
+            &staging.join(workspace_file),
+            &workspace,
+            FileEncoding::default(),
+        )?;
+        files.push(workspace_file.to_path_buf());
+        move_into_place(&staging, &dest_dir, &files)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    staged?;
+
+    Ok(DuplicateWorkspaceResult {
+        workspace_path: dest_dir.join(workspace_file).to_string_lossy().into_owned(),
+        book_files: books.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::read_json_file;
+
+    #[test]
+    fn copies_books_without_touching_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_path = dir.path().join("source/workspace.json");
+        let workspace = json!({
+            "workspace": { "id": "workspace-1", "name": "Source" },
+            "books": [
+                { "id": "book-1", "name": "a.json", "dataPath": "books/a.json" },
+                { "id": "book-2", "name": "b.json", "dataPath": "books\\b.json.gz" }
+            ]
+        });
+        write_json_file(&source_path, &workspace, FileEncoding::default()).unwrap();
+        for name in ["a.json", "b.json.gz"] {
+            let book = dir.path().join("source/books").join(name);
+            write_json_file(&book, &json!({ "name": name }), FileEncoding::default()).unwrap();
+        }
+        let source = source_path.to_string_lossy().into_owned();
+        let dest = dir.path().join("copy");
+
+        let result = duplicate_workspace(
+            source.clone(),
+            dest.to_string_lossy().into_owned(),
+            Some("Copy".into()),
+        )
+        .unwrap();
+        assert_eq!(result.book_files, 2);
+        let copied = read_json_file(Path::new(&result.workspace_path)).unwrap();
+        assert_eq!(copied["workspace"]["name"], "Copy");
+        assert_ne!(copied["workspace"]["id"], "workspace-1");
+        assert_eq!(copied["books"], workspace["books"]);
+        assert_eq!(
+            read_json_file(&dest.join("books/b.json.gz")).unwrap(),
+            json!({ "name": "b.json.gz" })
+        );
+        assert_eq!(read_json_file(&source_path).unwrap(), workspace);
+
+        // A second copy to the same place would overwrite the first.
+        fs::remove_file(dest.join(WORKSPACE_FILE_NAME)).unwrap();
+        assert!(matches!(
+            duplicate_workspace(source, dest.to_string_lossy().into_owned(), None),
+            Err(WorkspaceError::AlreadyExists { path }) if path.ends_with("a.json")
+        ));
+        assert!(!dest.join(WORKSPACE_FILE_NAME).exists());
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().contains("sheet-up-tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+use super::three_way::ThreeWayConflict;
+use serde::{Serialize, Serializer};
+use std::io;
+use std::path::Path;
+
+/// Errors returned by workspace commands. The frontend receives them as
+/// `{ code, message, ...fields }` so it can branch on `code`.
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(
+    remote = "Self",
+    tag = "code",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum WorkspaceError {
+    #[error("File not found: {path}")]
+    NotFound { path: String },
+    #[error("Permission denied: {path}")]
+    PermissionDenied { path: String },
+    #[error("{path} is on a read-only file system")]
+    ReadOnlyFilesystem { path: String },
+    #[error("No space left for {path}")]
+    StorageFull { path: String },
+    #[error("Failed to parse {path} at line {line}, column {column}: {message}")]
+    ParseError {
+        path: String,
+        /// 1-based position of the error. The column counts bytes of the
+        /// UTF-8 text; for compressed, encrypted or converted files both
+        /// refer to the decoded text.
+        line: usize,
+        column: usize,
+        message: String,
+        /// Whether the broken file is `workspace.json` or a book.
+        file_kind: FileKind,
+    },
+    #[error("{message}")]
+    InvalidSchema {
+        message: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        issues: Vec<SchemaIssue>,
+    },
+    #[error("{field} escapes workspace directory: {path}")]
+    PathOutsideWorkspace { field: String, path: String },
+    #[error("{path} links to {target}, outside the workspace directory")]
+    SymlinkEscapesWorkspace { path: String, target: String },
+    #[error("Failed to {action} {path}: {message}")]
+    Io {
+        action: &'static str,
+        path: String,
+        message: String,
+    },
+    #[error("Failed to serialize JSON for {path}: {message}")]
+    Serialize { path: String, message: String },
+    #[error("File was modified externally: {path}")]
+    Conflict { path: String },
+    #[error("{} cells of {path} were changed both here and on disk", conflicts.len())]
+    MergeConflict {
+        path: String,
+        conflicts: Vec<ThreeWayConflict>,
+    },
+    #[error("Backup {backup_id} not found for {path}")]
+    BackupNotFound { path: String, backup_id: String },
+    #[error("{path}, line {line}: {message}")]
+    InvalidPatch {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    #[error("Could not tell the text encoding of {path}")]
+    InvalidEncoding { path: String },
+    #[error("Failed to decode {path} as {encoding}")]
+    Encoding {
+        path: String,
+        encoding: &'static str,
+    },
+    #[error("{path} is not in a book format this version can read (.{extension})")]
+    UnknownBookFormat { path: String, extension: String },
+    #[error("A passphrase is required to open {path}")]
+    PassphraseRequired { path: String },
+    #[error("Wrong passphrase for {path}")]
+    WrongPassphrase { path: String },
+    #[error("Failed to encrypt {path}: {message}")]
+    Encryption { path: String, message: String },
+    #[error("Book {book_id} is not listed in {path}")]
+    BookNotFound { path: String, book_id: String },
+    #[error("File already exists: {path}")]
+    AlreadyExists { path: String },
+    #[error("Book {book_id} is already listed in {path}")]
+    DuplicateBookId { path: String, book_id: String },
+    #[error(
+        "Book order does not match {path} (missing: {}; duplicated: {}; unknown: {})",
+        missing.join(", "),
+        duplicated.join(", "),
+        unknown.join(", ")
+    )]
+    InvalidBookOrder {
+        path: String,
+        missing: Vec<String>,
+        duplicated: Vec<String>,
+        unknown: Vec<String>,
+    },
+    #[error(
+        "Books do not match {path} (not listed: {}; without a file: {})",
+        unreferenced.join(", "),
+        missing.join(", ")
+    )]
+    InconsistentSnapshot {
+        path: String,
+        /// Book payloads no `books[].dataPath` points at.
+        unreferenced: Vec<String>,
+        /// `dataPath`s with neither a payload nor a file on disk.
+        missing: Vec<String>,
+    },
+    #[error("Trash entry {trash_id} not found in {path}")]
+    TrashEntryNotFound { path: String, trash_id: String },
+    #[error("{path} does not match its recorded checksum and may be damaged")]
+    IntegrityMismatch { path: String },
+    #[error("Sheet {sheet_id} not found in {path}")]
+    SheetNotFound { path: String, sheet_id: String },
+    #[error("Invalid option {name}: {message}")]
+    InvalidOption { name: &'static str, message: String },
+    #[error("Formulas in sheet {sheet_id} of {path} refer to each other: {}", cells.join(" -> "))]
+    CircularReference {
+        path: String,
+        sheet_id: String,
+        /// The cells of the cycle in reference order, each referencing the
+        /// next and the last one the first.
+        cells: Vec<String>,
+    },
+    #[error("{path} uses schema version {version}, newer than the supported {supported}")]
+    VersionTooNew {
+        path: String,
+        version: String,
+        supported: String,
+    },
+    #[error("{path} uses unsupported bundle version {version}")]
+    UnsupportedBundleVersion { path: String, version: String },
+    #[error("{path} is not a usable zip archive: {message}")]
+    InvalidArchive { path: String, message: String },
+    #[error("{path} contains the entry {entry}, which would be extracted outside the destination")]
+    UnsafeArchiveEntry { path: String, entry: String },
+    #[error(
+        "books[{}] all use the data file {path}",
+        indices.iter().map(usize::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    DuplicateDataPath { path: String, indices: Vec<usize> },
+    #[error(
+        "Not enough disk space for {path}: about {required} bytes needed, {available} available"
+    )]
+    InsufficientDiskSpace {
+        path: String,
+        required: u64,
+        available: u64,
+    },
+    #[error("{path} is {size} bytes, more than the limit of {limit}")]
+    FileTooLarge { path: String, size: u64, limit: u64 },
+    #[error("{path} lists {count} books, more than the limit of {limit}")]
+    TooManyBooks {
+        path: String,
+        count: u64,
+        limit: u64,
+    },
+    #[error("{path} is held by another running instance (pid {pid})")]
+    WorkspaceLocked {
+        path: String,
+        pid: u32,
+        acquired_at: String,
+    },
+    #[error("{path} is read-only")]
+    ReadOnlyWorkspace { path: String },
+    #[error("Database error in {path}: {message}")]
+    Database { path: String, message: String },
+    #[error("Gave up saving {path} after waiting {waited_ms} ms for another save of it")]
+    SaveTimeout { path: String, waited_ms: u64 },
+    #[error("Loading {path} was cancelled")]
+    Cancelled { path: String },
+    #[error("books[{index}]: {source}")]
+    BookLoad {
+        index: usize,
+        source: Box<WorkspaceError>,
+    },
+    #[error("{source} (already saved: {})", saved.join(", "))]
+    PartialSave {
+        source: Box<WorkspaceError>,
+        saved: Vec<String>,
+    },
+    #[error("{source} (gave up after {attempts} attempts)")]
+    RetriesExhausted {
+        attempts: u32,
+        source: Box<WorkspaceError>,
+    },
+}
+
+impl WorkspaceError {
+    /// Maps the failures the frontend can help with (another location,
+    /// freeing space) to their own codes and everything else to `io`.
+    pub fn io(action: &'static str, path: &Path, err: io::Error) -> Self {
+        let path = path.display().to_string();
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::NotFound { path },
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied { path },
+            io::ErrorKind::ReadOnlyFilesystem => Self::ReadOnlyFilesystem { path },
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => Self::StorageFull { path },
+            _ => Self::Io {
+                action,
+                path,
+                message: err.to_string(),
+            },
+        }
+    }
+
+    /// serde_json tracks the position while parsing, also when streaming,
+    /// so reporting it costs nothing extra on large files.
+    pub fn parse(path: &Path, err: serde_json::Error) -> Self {
+        let (line, column) = (err.line(), err.column());
+        let message = err.to_string();
+        let position = format!(" at line {} column {}", line, column);
+        Self::ParseError {
+            path: path.display().to_string(),
+            line,
+            column,
+            message: message
+                .strip_suffix(&position)
+                .unwrap_or(&message)
+                .to_string(),
+            file_kind: FileKind::Other,
+        }
+    }
+
+    /// Records what kind of file a `parseError` came from; other errors are
+    /// returned as they are.
+    pub fn in_file(mut self, kind: FileKind) -> Self {
+        if let Self::ParseError { file_kind, .. } = &mut self {
+            *file_kind = kind;
+        }
+        self
+    }
+
+    pub fn invalid_schema(message: impl Into<String>) -> Self {
+        Self::InvalidSchema {
+            message: message.into(),
+            issues: Vec::new(),
+        }
+    }
+
+    pub fn schema(subject: &str, issues: Vec<SchemaIssue>) -> Self {
+        let details: Vec<String> = issues.iter().map(ToString::to_string).collect();
+        Self::InvalidSchema {
+            message: format!(
+                "{} has an invalid structure: {}",
+                subject,
+                details.join("; ")
+            ),
+            issues,
+        }
+    }
+}
+
+impl Serialize for WorkspaceError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Payload<'a> {
+            #[serde(flatten, serialize_with = "serialize_fields")]
+            error: &'a WorkspaceError,
+            message: String,
+        }
+
+        fn serialize_fields<S: Serializer>(
+            error: &&WorkspaceError,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            WorkspaceError::serialize(error, serializer)
+        }
+
+        Payload {
+            error: self,
+            message: self.to_string(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileKind {
+    Workspace,
+    Book,
+    /// Anything else read as JSON or CSV, e.g. a backup or an import.
+    #[default]
+    Other,
+}
+
+/// A single structural problem, addressed like `books[2].dataPath`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaIssue {
+    pub field: String,
+    pub message: String,
+}
+
+impl SchemaIssue {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+pub type WorkspaceResult<T> = Result<T, WorkspaceError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn errors_serialize_with_code_and_message() {
+        let err = WorkspaceError::NotFound {
+            path: "/w/books/a.json".into(),
+        };
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            json!({
+                "code": "notFound",
+                "path": "/w/books/a.json",
+                "message": "File not found: /w/books/a.json"
+            })
+        );
+    }
+
+    fn code_for(err: io::Error) -> String {
+        let err = WorkspaceError::io("write", Path::new("/w/a.json"), err);
+        serde_json::to_value(&err).unwrap()["code"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn io_errors_are_classified_by_kind() {
+        for (kind, code) in [
+            (io::ErrorKind::NotFound, "notFound"),
+            (io::ErrorKind::PermissionDenied, "permissionDenied"),
+            (io::ErrorKind::ReadOnlyFilesystem, "readOnlyFilesystem"),
+            (io::ErrorKind::StorageFull, "storageFull"),
+            (io::ErrorKind::QuotaExceeded, "storageFull"),
+            (io::ErrorKind::Interrupted, "io"),
+        ] {
+            assert_eq!(code_for(io::Error::new(kind, "mock")), code, "{:?}", kind);
+        }
+        match WorkspaceError::io("write", Path::new("/w/a.json"), io::Error::other("boom")) {
+            WorkspaceError::Io {
+                action, message, ..
+            } => {
+                assert_eq!((action, message.as_str()), ("write", "boom"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn os_error_codes_reach_the_same_classes() {
+        // EACCES, EROFS, ENOSPC and EDQUOT as the kernel reports them.
+        for (errno, code) in [
+            (13, "permissionDenied"),
+            (30, "readOnlyFilesystem"),
+            (28, "storageFull"),
+            (122, "storageFull"),
+        ] {
+            assert_eq!(code_for(io::Error::from_raw_os_error(errno)), code);
+        }
+    }
+}
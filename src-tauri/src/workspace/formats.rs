@@ -0,0 +1,398 @@
+//! Book file formats by extension of `dataPath`. `.json` and `.json.gz`
+//! books are streamed by [`io`](super::io) itself, with encoding detection,
+//! text styles and interning; books in any other registered format are read
+//! and written whole through [`BookFormat`], so every reader and writer of
+//! book files handles them alike. Encryption wraps the bytes of every
+//! format the same way.
+
+use super::books::{DEFAULT_COLS, DEFAULT_ROWS, DEFAULT_SHEET_NAME};
+use super::csv_export::{quote, select_sheet, sheet_grid, CsvQuoteStyle, CsvRange};
+use super::csv_import::{build_rows, csv_records, decode, numeric_columns, CsvEncoding};
+use super::error::{FileKind, WorkspaceError, WorkspaceResult};
+use super::io::{json_bytes, parse_json_bytes};
+use super::migrate::CURRENT_SCHEMA_VERSION;
+use serde_json::{json, Map, Number, Value};
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Turns the bytes of a book file into the book's JSON value and back.
+/// `path` is only used in errors.
+pub trait BookFormat: Send + Sync {
+    fn parse(&self, bytes: &[u8], path: &Path) -> WorkspaceResult<Value>;
+    fn serialize(&self, value: &Value, path: &Path) -> WorkspaceResult<Vec<u8>>;
+
+    /// Whether the files are JSON text, which `io` streams itself rather
+    /// than going through `parse` and `serialize`.
+    fn is_json(&self) -> bool {
+        false
+    }
+}
+
+/// The formats books can be stored in, by extension without the leading
+/// dot, e.g. `"json.gz"`.
+pub struct BookFormats {
+    formats: Vec<(&'static str, Box<dyn BookFormat>)>,
+}
+
+impl BookFormats {
+    fn builtin() -> Self {
+        let mut formats = Self {
+            formats: Vec::new(),
+        };
+        formats.register("json", JsonFormat { compressed: false });
+        formats.register("json.gz", JsonFormat { compressed: true });
+        formats.register("csv", CsvFormat);
+        formats.register("msgpack", MsgpackFormat);
+        formats
+    }
+
+    /// Adds `format` for files ending in `.extension`, replacing any format
+    /// registered for the same extension.
+    pub fn register(&mut self, extension: &'static str, format: impl BookFormat + 'static) {
+        self.formats
+            .retain(|(registered, _)| *registered != extension);
+        self.formats.push((extension, Box::new(format)));
+    }
+
+    /// The format of the longest extension `path` ends in, ignoring case.
+    pub fn for_path(&self, path: &Path) -> Option<&dyn BookFormat> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        self.formats
+            .iter()
+            .filter(|(extension, _)| {
+                name.strip_suffix(extension)
+                    .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+            })
+            .max_by_key(|(extension, _)| extension.len())
+            .map(|(_, format)| format.as_ref())
+    }
+}
+
+static FORMATS: LazyLock<BookFormats> = LazyLock::new(BookFormats::builtin);
+
+/// The format of the book at `path`; `unknownBookFormat` when its extension
+/// has none.
+pub fn book_format(path: &Path) -> WorkspaceResult<&'static dyn BookFormat> {
+    FORMATS
+        .for_path(path)
+        .ok_or_else(|| WorkspaceError::UnknownBookFormat {
+            path: path.display().to_string(),
+            extension: path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default(),
+        })
+}
+
+/// The format of `path` when it is not JSON, for `io` to go through. Files
+/// with no known extension, such as caches and manifests, are JSON as
+/// they always were.
+pub fn non_json_format(path: &Path) -> Option<&'static dyn BookFormat> {
+    FORMATS.for_path(path).filter(|format| !format.is_json())
+}
+
+struct JsonFormat {
+    compressed: bool,
+}
+
+impl BookFormat for JsonFormat {
+    fn parse(&self, bytes: &[u8], path: &Path) -> WorkspaceResult<Value> {
+        parse_json_bytes(bytes, self.compressed, None, path)
+    }
+
+    fn serialize(&self, value: &Value, path: &Path) -> WorkspaceResult<Vec<u8>> {
+        json_bytes(value, self.compressed, path)
+    }
+
+    fn is_json(&self) -> bool {
+        true
+    }
+}
+
+/// The first sheet's cell values as comma-separated UTF-8 text. Only those
+/// survive a save: other sheets, formulas and formats are dropped, and on
+/// load the book takes its id and name from the file name and numeric
+/// columns are told apart as by `import_csv_as_book`.
+struct CsvFormat;
+
+impl BookFormat for CsvFormat {
+    fn parse(&self, bytes: &[u8], path: &Path) -> WorkspaceResult<Value> {
+        let text = decode(bytes, path, CsvEncoding::Utf8)?;
+        let records = csv_records(&text, b',', path)?;
+        let columns = records.iter().map(Vec::len).max().unwrap_or(0);
+        let numeric = numeric_columns(&records, columns);
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(json!({
+            "schemaVersion": CURRENT_SCHEMA_VERSION,
+            "book": { "id": name, "name": name },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": DEFAULT_SHEET_NAME,
+                "gridSize": {
+                    "rows": records.len().max(DEFAULT_ROWS),
+                    "cols": columns.max(DEFAULT_COLS)
+                },
+                "rows": build_rows(&records, &numeric)
+            }]
+        }))
+    }
+
+    fn serialize(&self, value: &Value, path: &Path) -> WorkspaceResult<Vec<u8>> {
+        let sheet = select_sheet(value, path, None)?;
+        let mut writer = csv::WriterBuilder::new()
+            .quote_style(csv::QuoteStyle::Never)
+            .flexible(true)
+            .from_writer(Vec::new());
+        for row in sheet_grid(sheet, CsvRange::Used) {
+            let fields = row.iter().map(|field| match field {
+                Some(field) => quote(&field.text, b',', CsvQuoteStyle::Necessary, field.is_text),
+                None => String::new(),
+            });
+            writer
+                .write_record(fields)
+                .map_err(|err| serialize_error(path, err))?;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| serialize_error(path, err.error()))
+    }
+}
+
+fn serialize_error(path: &Path, err: impl ToString) -> WorkspaceError {
+    WorkspaceError::Serialize {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    }
+}
+
+/// The book's JSON value as plain MessagePack, readable by any MessagePack
+/// library. Numbers are native integers, or 64-bit floats when that keeps
+/// them exact; any other number, such as `1.50` or a 30-digit integer, is
+/// stored as a string of its JSON text and loads back as that string.
+struct MsgpackFormat;
+
+impl BookFormat for MsgpackFormat {
+    fn parse(&self, bytes: &[u8], path: &Path) -> WorkspaceResult<Value> {
+        let mut rest = bytes;
+        let value = rmpv::decode::read_value_with_max_depth(&mut rest, MAX_NESTING)
+            .map_err(|err| err.to_string())
+            .and_then(|value| match rest.is_empty() {
+                true => from_msgpack(value).map_err(str::to_string),
+                false => Err("trailing bytes after the value".to_string()),
+            });
+        value.map_err(|message| WorkspaceError::ParseError {
+            path: path.display().to_string(),
+            line: 0,
+            column: 0,
+            message: format!("{} at byte {}", message, bytes.len() - rest.len()),
+            file_kind: FileKind::Other,
+        })
+    }
+
+    fn serialize(&self, value: &Value, path: &Path) -> WorkspaceResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &to_msgpack(value))
+            .map_err(|err| serialize_error(path, err))?;
+        Ok(bytes)
+    }
+}
+
+/// How deeply arrays and maps may nest, as serde_json limits JSON.
+const MAX_NESTING: usize = 128;
+
+/// `number` as the MessagePack integer or float that gives back its exact
+/// JSON text, or as that text when none does. serde_json's
+/// `arbitrary_precision` keeps numbers as written, so this is done by hand
+/// rather than through a serde serializer, which would see them as maps.
+fn msgpack_number(number: &Number) -> rmpv::Value {
+    let text = number.to_string();
+    if let Some(unsigned) = number
+        .as_u64()
+        .filter(|unsigned| unsigned.to_string() == text)
+    {
+        return rmpv::Value::from(unsigned);
+    }
+    if let Some(signed) = number.as_i64().filter(|signed| signed.to_string() == text) {
+        return rmpv::Value::from(signed);
+    }
+    let float = number
+        .as_f64()
+        .filter(|float| Number::from_f64(*float).is_some_and(|exact| exact.to_string() == text));
+    match float {
+        Some(float) => rmpv::Value::from(float),
+        None => rmpv::Value::from(text),
+    }
+}
+
+fn to_msgpack(value: &Value) -> rmpv::Value {
+    match value {
+        Value::Null => rmpv::Value::Nil,
+        Value::Bool(flag) => rmpv::Value::from(*flag),
+        Value::Number(number) => msgpack_number(number),
+        Value::String(text) => rmpv::Value::from(text.as_str()),
+        Value::Array(items) => rmpv::Value::Array(items.iter().map(to_msgpack).collect()),
+        Value::Object(map) => rmpv::Value::Map(
+            map.iter()
+                .map(|(key, item)| (rmpv::Value::from(key.as_str()), to_msgpack(item)))
+                .collect(),
+        ),
+    }
+}
+
+fn from_msgpack(value: rmpv::Value) -> Result<Value, &'static str> {
+    Ok(match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(flag) => Value::Bool(flag),
+        rmpv::Value::Integer(integer) => match integer.as_u64() {
+            Some(unsigned) => Value::from(unsigned),
+            None => Value::from(integer.as_i64().ok_or("integer out of range")?),
+        },
+        rmpv::Value::F32(float32) => float(float32 as f64)?,
+        rmpv::Value::F64(float64) => float(float64)?,
+        rmpv::Value::String(text) => {
+            Value::String(text.into_str().ok_or("string is not valid UTF-8")?)
+        }
+        rmpv::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(from_msgpack)
+                .collect::<Result<_, _>>()?,
+        ),
+        rmpv::Value::Map(entries) => {
+            let mut map = Map::new();
+            for (key, item) in entries {
+                let rmpv::Value::String(key) = key else {
+                    return Err("map keys must be strings");
+                };
+                let key = key.into_str().ok_or("string is not valid UTF-8")?;
+                map.insert(key, from_msgpack(item)?);
+            }
+            Value::Object(map)
+        }
+        rmpv::Value::Binary(_) => return Err("binary data is not supported"),
+        rmpv::Value::Ext(..) => return Err("extension types are not supported"),
+    })
+}
+
+fn float(value: f64) -> Result<Value, &'static str> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or("NaN and infinite numbers are not supported")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_formats_by_the_longest_extension() {
+        let is_json = |path: &str| book_format(Path::new(path)).map(|format| format.is_json());
+        assert!(is_json("books/a.json").unwrap());
+        assert!(is_json("books/A.JSON.GZ").unwrap());
+        assert!(!is_json("books/a.csv").unwrap());
+        assert!(!is_json("books/a.msgpack").unwrap());
+        assert!(non_json_format(Path::new("books/a.json.gz")).is_none());
+        assert!(non_json_format(Path::new("books/a.csv")).is_some());
+        // No stem, no format.
+        assert!(book_format(Path::new("books/.json")).is_err());
+        assert!(matches!(
+            book_format(Path::new("books/a.xlsx")),
+            Err(WorkspaceError::UnknownBookFormat { extension, .. }) if extension == "xlsx"
+        ));
+    }
+
+    #[test]
+    fn msgpack_round_trips_any_book_value() {
+        let long = "x".repeat(300);
+        let value = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "b", "name": "日本語" },
+            "numbers": [0, 127, 128, 70000, u64::MAX, -1, -32, -33, i64::MIN, 1.5, -0.25],
+            "flags": [true, false, null],
+            "long": long,
+            "wide": (0..20).collect::<Vec<_>>()
+        });
+        let path = Path::new("book.msgpack");
+        let bytes = MsgpackFormat.serialize(&value, path).unwrap();
+        assert_eq!(MsgpackFormat.parse(&bytes, path).unwrap(), value);
+
+        // Numbers with no exact MessagePack form come back as their JSON text.
+        let text = r#"[0.1, 1.50, 1e3, -0, 3.14159265358979323846264338327950288,
+            123456789012345678901234567890, -9223372036854775809, 5e-324,
+            "", "\u0000\"\\", "😀 改行\r\n", "長い文字列"]"#;
+        let value: Value = serde_json::from_str(text).unwrap();
+        let value = json!({ "values": value, "huge": "é".repeat(40_000) });
+        let bytes = MsgpackFormat.serialize(&value, path).unwrap();
+        let parsed = MsgpackFormat.parse(&bytes, path).unwrap();
+        assert_eq!(parsed["huge"], value["huge"]);
+        assert_eq!(
+            parsed["values"],
+            json!([
+                0.1,
+                "1.50",
+                "1e+3",
+                0,
+                "3.14159265358979323846264338327950288",
+                "123456789012345678901234567890",
+                "-9223372036854775809",
+                5e-324,
+                "",
+                "\u{0}\"\\",
+                "😀 改行\r\n",
+                "長い文字列"
+            ])
+        );
+        // Plain MessagePack: `1.50` is a string, not a private extension.
+        let mut rest = &bytes[..];
+        let raw = rmpv::decode::read_value(&mut rest).unwrap();
+        assert_eq!(raw["values"][0].as_f64(), Some(0.1));
+        assert_eq!(raw["values"][1].as_str(), Some("1.50"));
+
+        // rmpv reads the reserved marker 0xc1 as nil, so it is not here.
+        for broken in [
+            &bytes[..bytes.len() - 1],
+            &[0x81, 0x01, 0xc0],
+            &[0x91, 0xc0, 0xc0],
+            &[0xd4, 7, 0],
+        ] {
+            assert!(matches!(
+                MsgpackFormat.parse(broken, path),
+                Err(WorkspaceError::ParseError { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn csv_keeps_the_values_of_the_first_sheet() {
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "other", "name": "Other" },
+            "sheets": [{
+                "id": "s",
+                "name": "Sheet",
+                "gridSize": { "rows": 100, "cols": 26 },
+                "rows": {
+                    "1": { "A": { "value": "name" }, "B": { "value": "amount" } },
+                    "2": { "A": { "value": "a, b" }, "B": { "value": 10 } },
+                    "3": { "A": { "value": "007" }, "B": { "value": 2.5 } }
+                }
+            }]
+        });
+        let path = Path::new("books/sales.csv");
+        let bytes = CsvFormat.serialize(&book, path).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            "name,amount\n\"a, b\",10\n\"007\",2.5\n"
+        );
+        let parsed = CsvFormat.parse(&bytes, path).unwrap();
+        assert_eq!(parsed["book"], json!({ "id": "sales", "name": "sales" }));
+        let rows = &parsed["sheets"][0]["rows"];
+        assert_eq!(rows["2"]["A"]["value"], "a, b");
+        assert_eq!(rows["2"]["B"]["value"], 10);
+        assert_eq!(rows["3"]["A"]["value"], "007");
+        assert_eq!(CsvFormat.serialize(&parsed, path).unwrap(), bytes);
+    }
+}
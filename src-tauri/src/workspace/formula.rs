@@ -0,0 +1,742 @@
+//! `evaluate_book_formulas`: computes the formula cells of a book. Formulas
+//! use a small part of spreadsheet syntax: numbers, `A1` references (`$`
+//! markers are ignored), `A1:B3` ranges as function arguments, `+ - * /`
+//! with the usual precedence, parentheses, and `SUM`, `AVERAGE`, `MIN` and
+//! `MAX`. References stay within the formula's sheet.
+
+use super::cells::{column_index, column_label, parse_address, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_encrypted_file, FileEncoding, SizeLimits};
+use super::{load_book, write_tracked};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FormulaOptions {
+    /// Only this sheet; every sheet when omitted.
+    pub sheet_id: Option<String>,
+    /// Stores each result as the cell's `computedValue` and writes the book.
+    /// Otherwise the results are only returned.
+    pub store: bool,
+    /// Decrypts an encrypted book and re-encrypts it when writing.
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormulaCell {
+    pub sheet_id: String,
+    pub cell: String,
+    pub formula: String,
+    /// The result; `None` when `error` is set.
+    pub value: Option<f64>,
+    /// `#ERROR!` for a formula that cannot be parsed, `#VALUE!` for text
+    /// used as a number, `#DIV/0!`, or `#NUM!` for a result too large to
+    /// represent.
+    pub error: Option<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormulaResult {
+    /// Every formula cell, by sheet and then by row and column.
+    pub cells: Vec<FormulaCell>,
+    /// Whether `computedValue`s were written to the book.
+    pub written: bool,
+}
+
+/// `(row, column)`, both 1-based.
+type Address = (u32, u32);
+
+type Outcome = Result<f64, &'static str>;
+
+/// Formulas nested deeper or longer than this are refused rather than
+/// risking the stack while parsing and evaluating them.
+const MAX_NESTING: usize = 64;
+const MAX_TOKENS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Sum,
+    Average,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn named(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "SUM" => Some(Self::Sum),
+            "AVERAGE" => Some(Self::Average),
+            "MIN" => Some(Self::Min),
+            "MAX" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    fn apply(self, numbers: &[f64]) -> Outcome {
+        let fold = |start: f64, combine: fn(f64, f64) -> f64| match numbers {
+            [] => 0.0,
+            _ => numbers.iter().copied().fold(start, combine),
+        };
+        Ok(match self {
+            Self::Sum => numbers.iter().sum(),
+            Self::Average if numbers.is_empty() => return Err("#DIV/0!"),
+            Self::Average => numbers.iter().sum::<f64>() / numbers.len() as f64,
+            Self::Min => fold(f64::INFINITY, f64::min),
+            Self::Max => fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    Cell(Address),
+    Function(Function),
+    /// One of `+ - * / ( ) , :`.
+    Symbol(u8),
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while let Some(&byte) = bytes.get(index) {
+        let start = index;
+        match byte {
+            b' ' | b'\t' => index += 1,
+            b'+' | b'-' | b'*' | b'/' | b'(' | b')' | b',' | b':' => {
+                tokens.push(Token::Symbol(byte));
+                index += 1;
+            }
+            b'0'..=b'9' | b'.' => {
+                while bytes
+                    .get(index)
+                    .is_some_and(|byte| byte.is_ascii_digit() || *byte == b'.')
+                {
+                    index += 1;
+                }
+                tokens.push(Token::Number(text[start..index].parse().ok()?));
+            }
+            b'$' | b'A'..=b'Z' | b'a'..=b'z' => {
+                while bytes
+                    .get(index)
+                    .is_some_and(|byte| byte.is_ascii_alphanumeric() || *byte == b'$')
+                {
+                    index += 1;
+                }
+                let word = text[start..index].replace('$', "");
+                tokens.push(match Function::named(&word) {
+                    Some(function) => Token::Function(function),
+                    None => Token::Cell(parse_address(&word)?),
+                });
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Cell(Address),
+    /// Only valid as a function argument.
+    Range(Address, Address),
+    Negate(Box<Expr>),
+    /// `+ - * /` applied to two operands.
+    Binary(u8, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+/// Recursive descent over the tokens of one formula:
+///
+/// ```text
+/// expr    = term (("+" | "-") term)*
+/// term    = unary (("*" | "/") unary)*
+/// unary   = ("+" | "-") unary | primary
+/// primary = number | cell [":" cell] | function "(" [expr ("," expr)*] ")" | "(" expr ")"
+/// ```
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn parse(text: &str) -> Option<Expr> {
+        let tokens = tokenize(text)?;
+        if tokens.len() > MAX_TOKENS {
+            return None;
+        }
+        let mut parser = Self {
+            tokens,
+            position: 0,
+            depth: 0,
+        };
+        let expr = parser.expr()?;
+        (parser.position == parser.tokens.len()).then_some(expr)
+    }
+
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek()?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn eat(&mut self, symbol: u8) -> bool {
+        let found = self.peek() == Some(Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expr(&mut self) -> Option<Expr> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return None;
+        }
+        let mut left = self.term()?;
+        while let Some(Token::Symbol(op @ (b'+' | b'-'))) = self.peek() {
+            self.position += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        self.depth -= 1;
+        Some(left)
+    }
+
+    fn term(&mut self) -> Option<Expr> {
+        let mut left = self.unary()?;
+        while let Some(Token::Symbol(op @ (b'*' | b'/'))) = self.peek() {
+            self.position += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        while self.eat(b'+') {}
+        if self.eat(b'-') {
+            self.depth += 1;
+            if self.depth > MAX_NESTING {
+                return None;
+            }
+            let operand = self.unary()?;
+            self.depth -= 1;
+            return Some(Expr::Negate(Box::new(operand)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<Expr> {
+        match self.next()? {
+            Token::Number(number) => Some(Expr::Number(number)),
+            Token::Cell(start) if self.eat(b':') => match self.next()? {
+                Token::Cell(end) => Some(Expr::Range(start, end)),
+                _ => None,
+            },
+            Token::Cell(address) => Some(Expr::Cell(address)),
+            Token::Function(function) => {
+                if !self.eat(b'(') {
+                    return None;
+                }
+                let mut args = Vec::new();
+                if !self.eat(b')') {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(b')') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Expr::Call(function, args))
+            }
+            Token::Symbol(b'(') => {
+                let inner = self.expr()?;
+                self.eat(b')').then_some(inner)
+            }
+            Token::Symbol(_) => None,
+        }
+    }
+}
+
+/// The formula of a cell: its `formula` field, or a `value` starting with
+/// `=` in a cell of type `formula`. The leading `=` is optional.
+fn formula_of(cell: &Value) -> Option<&str> {
+    let text = match cell["formula"].as_str() {
+        Some(formula) => formula,
+        None if cell["type"] == "formula" => cell["value"].as_str()?,
+        None => return None,
+    };
+    let text = text.trim();
+    Some(text.strip_prefix('=').unwrap_or(text))
+}
+
+fn bounds(start: Address, end: Address) -> (Address, Address) {
+    (
+        (start.0.min(end.0), start.1.min(end.1)),
+        (start.0.max(end.0), start.1.max(end.1)),
+    )
+}
+
+fn within((low, high): (Address, Address), address: Address) -> bool {
+    (low.0..=high.0).contains(&address.0) && (low.1..=high.1).contains(&address.1)
+}
+
+/// One sheet's formulas, evaluated so that every formula cell comes after
+/// the formula cells it reads.
+struct Sheet<'a> {
+    rows: &'a Map<String, Value>,
+    /// Parsed formulas; `None` for one that cannot be parsed.
+    formulas: BTreeMap<Address, Option<Expr>>,
+    results: HashMap<Address, Outcome>,
+}
+
+impl<'a> Sheet<'a> {
+    fn new(rows: &'a Map<String, Value>) -> (Self, BTreeMap<Address, String>) {
+        let mut texts = BTreeMap::new();
+        for (row_key, cells) in rows {
+            let Some(row) = row_index(row_key) else {
+                continue;
+            };
+            for (column_key, cell) in cells.as_object().into_iter().flatten() {
+                let (Some(column), Some(text)) = (column_index(column_key), formula_of(cell))
+                else {
+                    continue;
+                };
+                texts.insert((row, column), text.to_string());
+            }
+        }
+        let formulas = texts
+            .iter()
+            .map(|(&address, text)| (address, Parser::parse(text)))
+            .collect();
+        let sheet = Self {
+            rows,
+            formulas,
+            results: HashMap::new(),
+        };
+        (sheet, texts)
+    }
+
+    /// Formula cells `expr` reads, directly or through a range.
+    fn dependencies(&self, expr: &Expr, found: &mut Vec<Address>) {
+        match expr {
+            Expr::Number(_) => {}
+            Expr::Cell(address) => {
+                if self.formulas.contains_key(address) {
+                    found.push(*address);
+                }
+            }
+            Expr::Range(start, end) => {
+                let area = bounds(*start, *end);
+                found.extend(
+                    self.formulas
+                        .range(area.0..=area.1)
+                        .map(|(address, _)| *address)
+                        .filter(|address| within(area, *address)),
+                );
+            }
+            Expr::Negate(operand) => self.dependencies(operand, found),
+            Expr::Binary(_, left, right) => {
+                self.dependencies(left, found);
+                self.dependencies(right, found);
+            }
+            Expr::Call(_, args) => {
+                for arg in args {
+                    self.dependencies(arg, found);
+                }
+            }
+        }
+    }
+
+    /// Formula cells in evaluation order, or the cells of a cycle. Uses an
+    /// explicit stack, so long chains of references cannot overflow it.
+    fn order(&self) -> Result<Vec<Address>, Vec<Address>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Visiting,
+            Done,
+        }
+        let mut states: HashMap<Address, State> = HashMap::new();
+        let mut order = Vec::with_capacity(self.formulas.len());
+        for &root in self.formulas.keys() {
+            if states.contains_key(&root) {
+                continue;
+            }
+            let dependencies_of = |address: Address| {
+                let mut found = Vec::new();
+                if let Some(Some(expr)) = self.formulas.get(&address) {
+                    self.dependencies(expr, &mut found);
+                }
+                found.reverse();
+                found
+            };
+            states.insert(root, State::Visiting);
+            let mut stack: Vec<(Address, Vec<Address>)> = vec![(root, dependencies_of(root))];
+            while let Some((address, pending)) = stack.last_mut() {
+                let address = *address;
+                let Some(next) = pending.pop() else {
+                    stack.pop();
+                    states.insert(address, State::Done);
+                    order.push(address);
+                    continue;
+                };
+                match states.get(&next) {
+                    Some(State::Done) => {}
+                    Some(State::Visiting) => {
+                        let start = stack
+                            .iter()
+                            .position(|(visiting, _)| *visiting == next)
+                            .unwrap_or_default();
+                        return Err(stack[start..].iter().map(|(cell, _)| *cell).collect());
+                    }
+                    None => {
+                        states.insert(next, State::Visiting);
+                        stack.push((next, dependencies_of(next)));
+                    }
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// A cell that is not a formula as an operand: numbers as they are,
+    /// booleans as 1 and 0, blank cells as 0 and numeric text parsed.
+    fn literal(&self, (row, column): Address) -> Outcome {
+        let value = &self.rows.get(&row.to_string()).unwrap_or(&Value::Null)
+            [column_label(column).as_str()]["value"];
+        match value {
+            Value::Null => Ok(0.0),
+            Value::Bool(flag) => Ok(f64::from(u8::from(*flag))),
+            Value::Number(number) => number.as_f64().ok_or("#VALUE!"),
+            Value::String(text) if text.trim().is_empty() => Ok(0.0),
+            Value::String(text) => text.trim().parse().map_err(|_| "#VALUE!"),
+            _ => Err("#VALUE!"),
+        }
+    }
+
+    fn cell(&self, address: Address) -> Outcome {
+        match self.results.get(&address) {
+            Some(outcome) => *outcome,
+            None => self.literal(address),
+        }
+    }
+
+    /// The numbers of a range for a function: formula results and number
+    /// cells. Text, booleans and blank cells are skipped.
+    fn range_numbers(
+        &self,
+        start: Address,
+        end: Address,
+        numbers: &mut Vec<f64>,
+    ) -> Result<(), &'static str> {
+        let area = bounds(start, end);
+        for (row_key, cells) in self.rows {
+            let Some(row) = row_index(row_key).filter(|row| (area.0 .0..=area.1 .0).contains(row))
+            else {
+                continue;
+            };
+            for (column_key, cell) in cells.as_object().into_iter().flatten() {
+                let Some(address) = column_index(column_key)
+                    .map(|column| (row, column))
+                    .filter(|address| within(area, *address))
+                else {
+                    continue;
+                };
+                if let Some(outcome) = self.results.get(&address) {
+                    numbers.push((*outcome)?);
+                } else if let Some(number) = cell["value"].as_f64() {
+                    numbers.push(number);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Outcome {
+        match expr {
+            Expr::Number(number) => Ok(*number),
+            Expr::Cell(address) => self.cell(*address),
+            Expr::Range(..) => Err("#VALUE!"),
+            Expr::Negate(operand) => Ok(-self.eval(operand)?),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (self.eval(left)?, self.eval(right)?);
+                match op {
+                    b'+' => Ok(left + right),
+                    b'-' => Ok(left - right),
+                    b'*' => Ok(left * right),
+                    _ if right == 0.0 => Err("#DIV/0!"),
+                    _ => Ok(left / right),
+                }
+            }
+            Expr::Call(function, args) => {
+                let mut numbers = Vec::new();
+                for arg in args {
+                    match arg {
+                        Expr::Range(start, end) => {
+                            self.range_numbers(*start, *end, &mut numbers)?;
+                        }
+                        arg => numbers.push(self.eval(arg)?),
+                    }
+                }
+                function.apply(&numbers)
+            }
+        }
+    }
+
+    fn evaluate(&mut self, order: &[Address]) {
+        for address in order {
+            let outcome = match &self.formulas[address] {
+                Some(expr) => self.eval(expr).and_then(|number| {
+                    if number.is_finite() {
+                        Ok(number)
+                    } else {
+                        Err("#NUM!")
+                    }
+                }),
+                None => Err("#ERROR!"),
+            };
+            self.results.insert(*address, outcome);
+        }
+    }
+}
+
+fn cell_label((row, column): Address) -> String {
+    format!("{}{}", column_label(column), row)
+}
+
+/// Evaluates the formula cells of a book file and returns their results.
+/// Referenced cells that are not formulas count as numbers where they hold
+/// one (blank cells as 0), and a formula cell's result is used by the
+/// formulas that reference it. Formulas that reference each other in a
+/// cycle fail the whole call with `circularReference`. With `store`, each
+/// result is kept as the cell's `computedValue` (the error code when it
+/// failed) and the book is rewritten, keeping its line endings and
+/// encryption.
+#[tauri::command]
+pub fn evaluate_book_formulas(
+    book_file_path: String,
+    options: Option<FormulaOptions>,
+) -> WorkspaceResult<FormulaResult> {
+    let options = options.unwrap_or_default();
+    let path = Path::new(&book_file_path);
+    let passphrase = options.passphrase.as_deref();
+    let mut book = load_book(path, passphrase, SizeLimits::default().book)?;
+
+    let mut result = FormulaResult::default();
+    let mut computed: Vec<(usize, Vec<(Address, Outcome)>)> = Vec::new();
+    let sheets = book.data["sheets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate();
+    for (index, sheet) in sheets.filter(|(_, sheet)| {
+        options
+            .sheet_id
+            .as_ref()
+            .is_none_or(|id| sheet["id"] == id.as_str())
+    }) {
+        let sheet_id = sheet["id"].as_str().unwrap_or_default().to_string();
+        let empty = Map::new();
+        let (mut formulas, texts) = Sheet::new(sheet["rows"].as_object().unwrap_or(&empty));
+        let order = formulas
+            .order()
+            .map_err(|cycle| WorkspaceError::CircularReference {
+                path: book_file_path.clone(),
+                sheet_id: sheet_id.clone(),
+                cells: cycle.into_iter().map(cell_label).collect(),
+            })?;
+        formulas.evaluate(&order);
+        let outcomes: Vec<(Address, Outcome)> = texts
+            .into_iter()
+            .map(|(address, formula)| {
+                let outcome = formulas.results[&address];
+                result.cells.push(FormulaCell {
+                    sheet_id: sheet_id.clone(),
+                    cell: cell_label(address),
+                    formula,
+                    value: outcome.ok(),
+                    error: outcome.err(),
+                });
+                (address, outcome)
+            })
+            .collect();
+        computed.push((index, outcomes));
+    }
+    if options.sheet_id.is_some() && computed.is_empty() {
+        return Err(WorkspaceError::SheetNotFound {
+            path: book_file_path,
+            sheet_id: options.sheet_id.unwrap_or_default(),
+        });
+    }
+    if !options.store || result.cells.is_empty() {
+        return Ok(result);
+    }
+
+    for (index, outcomes) in computed {
+        let rows = &mut book.data["sheets"][index]["rows"];
+        for ((row, column), outcome) in outcomes {
+            rows[row.to_string()][column_label(column)]["computedValue"] = match outcome {
+                Ok(number) => json!(number),
+                Err(error) => json!(error),
+            };
+        }
+    }
+    let encoding = FileEncoding {
+        passphrase: passphrase.filter(|_| is_encrypted_file(path)),
+        style: book.text_style.unwrap_or_default(),
+        ..Default::default()
+    };
+    write_tracked(path, &book.data, encoding)?;
+    result.written = true;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+
+    fn write_book(dir: &Path, cells: Value) -> String {
+        let path = dir.join("book.json");
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": 10, "cols": 6 },
+                "rows": cells
+            }]
+        });
+        write_json_file(&path, &book, FileEncoding::default()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn formula(text: &str) -> Value {
+        json!({ "value": text, "type": "formula" })
+    }
+
+    #[test]
+    fn parser_follows_precedence_and_parentheses() {
+        let sheet_rows = Map::new();
+        let (sheet, _) = Sheet::new(&sheet_rows);
+        let eval = |text: &str| sheet.eval(&Parser::parse(text).unwrap());
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(eval("8 / 4 / 2"), Ok(1.0));
+        assert_eq!(eval("-2 * -(3 - 1)"), Ok(4.0));
+        assert_eq!(eval("max(1, 2 + 5, 3) / 0"), Err("#DIV/0!"));
+        for broken in ["1 +", "(1 + 2", "1 2", "SUM 1", "A1:", "1 % 2"] {
+            assert_eq!(Parser::parse(broken), None, "{}", broken);
+        }
+        assert_eq!(Parser::parse(&"(".repeat(100)), None);
+    }
+
+    #[test]
+    fn formulas_read_cells_ranges_and_other_formulas() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_book(
+            dir.path(),
+            json!({
+                "1": {
+                    "A": { "value": 2, "type": "number" },
+                    "B": { "value": "3", "type": "string" },
+                    "C": { "value": 0, "type": "number", "formula": "=D1*2" },
+                    "D": formula("=A1+B1")
+                },
+                "2": {
+                    "A": { "value": 4, "type": "number" },
+                    "B": { "value": "note", "type": "string" },
+                    "C": formula("=AVERAGE(A1:B2)"),
+                    "D": formula("=SUM($A$1:C1) - B2")
+                },
+                "3": {
+                    "A": formula("=1/0"),
+                    "B": formula("=A3+1"),
+                    "C": formula("=2 *"),
+                    "D": formula("=MIN(A1:A2, C1) + MAX() + E9")
+                }
+            }),
+        );
+
+        let result = evaluate_book_formulas(path.clone(), None).unwrap();
+        let outcomes: Vec<(&str, Option<f64>, Option<&str>)> = result
+            .cells
+            .iter()
+            .map(|cell| (cell.cell.as_str(), cell.value, cell.error))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("C1", Some(10.0), None),
+                ("D1", Some(5.0), None),
+                ("C2", Some(3.0), None),
+                ("D2", None, Some("#VALUE!")),
+                ("A3", None, Some("#DIV/0!")),
+                ("B3", None, Some("#DIV/0!")),
+                ("C3", None, Some("#ERROR!")),
+                ("D3", Some(2.0), None),
+            ]
+        );
+        assert!(!result.written);
+        let book = read_json_file(Path::new(&path)).unwrap();
+        assert!(book["sheets"][0]["rows"]["1"]["C"]
+            .get("computedValue")
+            .is_none());
+
+        let store = FormulaOptions {
+            store: true,
+            ..Default::default()
+        };
+        assert!(
+            evaluate_book_formulas(path.clone(), Some(store))
+                .unwrap()
+                .written
+        );
+        let rows = &read_json_file(Path::new(&path)).unwrap()["sheets"][0]["rows"];
+        assert_eq!(rows["1"]["C"]["computedValue"], 10.0);
+        assert_eq!(rows["1"]["C"]["value"], 0);
+        assert_eq!(rows["3"]["A"]["computedValue"], "#DIV/0!");
+        assert!(rows["1"]["A"].get("computedValue").is_none());
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_cells() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_book(
+            dir.path(),
+            json!({
+                "1": { "A": formula("=B1+1"), "B": formula("=SUM(C1:C2)") },
+                "2": { "C": formula("=A1") },
+                "3": { "A": formula("=1") }
+            }),
+        );
+        let err = evaluate_book_formulas(path.clone(), None).unwrap_err();
+        assert!(matches!(
+            err,
+            WorkspaceError::CircularReference { sheet_id, cells, .. }
+                if sheet_id == "sheet-1" && cells == ["A1", "B1", "C2"]
+        ));
+
+        let path = write_book(dir.path(), json!({ "1": { "A": formula("=SUM(A1:B1)") } }));
+        assert!(matches!(
+            evaluate_book_formulas(path, None),
+            Err(WorkspaceError::CircularReference { cells, .. }) if cells == ["A1"]
+        ));
+    }
+}
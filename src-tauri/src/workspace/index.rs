@@ -0,0 +1,252 @@
+//! `scan_workspace_index`: finds the workspaces below a directory and
+//! summarises each from its `workspace.json` alone, for the list of recent
+//! workspaces shown at startup. Summaries are cached in the app config
+//! directory; the next scan reads again only the `workspace.json` files
+//! whose modification time changed. Nothing is ever written to the
+//! workspaces themselves.
+
+use super::diagnose::IGNORED_DIRS;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{
+    modified_millis, read_json_file, read_workspace_json, write_json_file, FileEncoding,
+};
+use super::WORKSPACE_FILE_NAME;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+/// File in the app config directory holding the summaries of every root
+/// scanned so far: `{ roots: { [rootDir]: { [workspacePath]: summary } } }`.
+pub(super) const INDEX_CACHE_FILE: &str = "workspace-index.json";
+/// How many directories deep below the root workspaces are looked for, so
+/// that pointing the scan at a home directory stays quick.
+const MAX_DEPTH: usize = 6;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSummary {
+    pub workspace_path: String,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub book_count: usize,
+    /// Modification time of `workspace.json` in epoch millis; it is what
+    /// tells the next scan whether the summary is still current.
+    pub modified: Option<u64>,
+    /// `workspace.lastOpened`, when loads record it.
+    pub last_opened: Option<String>,
+    /// Set when `workspace.json` could not be read; such summaries are not
+    /// cached and are read again by every scan.
+    #[serde(default, skip_serializing_if = "Option::is_none", skip_deserializing)]
+    pub error: Option<WorkspaceError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceIndex {
+    pub root_dir: String,
+    /// Sorted by `workspacePath`.
+    pub workspaces: Vec<WorkspaceSummary>,
+    /// How many `workspace.json` files were read by this scan rather than
+    /// taken from the cache.
+    pub rescanned: usize,
+    /// Directories that could not be listed, e.g. for lack of permission;
+    /// the scan went on without them.
+    pub skipped: Vec<String>,
+}
+
+/// `workspace.json` files below `dir`, without following directory links
+/// and without looking inside workspaces or the app's own directories.
+pub(super) fn workspace_files(
+    dir: &Path,
+    depth: usize,
+    found: &mut Vec<PathBuf>,
+    skipped: &mut Vec<String>,
+) {
+    let workspace_file = dir.join(WORKSPACE_FILE_NAME);
+    if workspace_file.is_file() {
+        found.push(workspace_file);
+        return;
+    }
+    if depth == MAX_DEPTH {
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            skipped.push(dir.display().to_string());
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+            && !IGNORED_DIRS.contains(&name.as_str())
+        {
+            workspace_files(&entry.path(), depth + 1, found, skipped);
+        }
+    }
+}
+
+fn summarize(path: &Path, modified: Option<u64>) -> WorkspaceSummary {
+    let mut summary = WorkspaceSummary {
+        workspace_path: path.to_string_lossy().into_owned(),
+        id: None,
+        name: None,
+        book_count: 0,
+        modified,
+        last_opened: None,
+        error: None,
+    };
+    match read_workspace_json(path) {
+        Ok(data) => {
+            let text = |field: &str| data["workspace"][field].as_str().map(str::to_string);
+            summary.id = text("id");
+            summary.name = text("name");
+            summary.last_opened = text("lastOpened");
+            summary.book_count = data["books"].as_array().map_or(0, Vec::len);
+        }
+        Err(err) => summary.error = Some(err),
+    }
+    summary
+}
+
+/// Scans `root_dir` using and updating the cache at `cache_path`. A cache
+/// that cannot be read is ignored, and one that cannot be written leaves
+/// the next scan to read everything again; neither fails the scan.
+pub(super) fn scan_index(
+    root_dir: &Path,
+    cache_path: Option<&Path>,
+) -> WorkspaceResult<WorkspaceIndex> {
+    fs::read_dir(root_dir).map_err(|err| WorkspaceError::io("read", root_dir, err))?;
+    let mut cache = cache_path
+        .and_then(|path| read_json_file(path).ok())
+        .filter(|cache| cache["roots"].is_object())
+        .unwrap_or_else(|| json!({ "roots": {} }));
+    let root_key = root_dir.to_string_lossy().into_owned();
+    let mut cached: BTreeMap<String, WorkspaceSummary> =
+        serde_json::from_value(cache["roots"][&root_key].take()).unwrap_or_default();
+
+    let (mut found, mut skipped) = (Vec::new(), Vec::new());
+    workspace_files(root_dir, 0, &mut found, &mut skipped);
+    found.sort();
+    let mut rescanned = 0;
+    let workspaces: Vec<WorkspaceSummary> = found
+        .iter()
+        .map(|path| {
+            let modified = modified_millis(path).ok().flatten();
+            match cached.remove(path.to_string_lossy().as_ref()) {
+                Some(summary) if modified.is_some() && summary.modified == modified => summary,
+                _ => {
+                    rescanned += 1;
+                    summarize(path, modified)
+                }
+            }
+        })
+        .collect();
+
+    if let Some(cache_path) = cache_path {
+        let current: BTreeMap<&str, &WorkspaceSummary> = workspaces
+            .iter()
+            .filter(|summary| summary.error.is_none())
+            .map(|summary| (summary.workspace_path.as_str(), summary))
+            .collect();
+        cache["roots"][&root_key] = json!(current);
+        let _ = write_json_file(cache_path, &cache, FileEncoding::default());
+    }
+    Ok(WorkspaceIndex {
+        root_dir: root_key,
+        workspaces,
+        rescanned,
+        skipped,
+    })
+}
+
+/// Lists the workspaces below `root_dir` with their name, book count and
+/// last modification, reading only `workspace.json` files. Directories
+/// that cannot be listed end up in `skipped` and broken `workspace.json`
+/// files carry an `error`; only an unreadable `root_dir` fails the scan.
+#[tauri::command(async)]
+pub fn scan_workspace_index(app: AppHandle, root_dir: String) -> WorkspaceResult<WorkspaceIndex> {
+    let cache_path = app
+        .path()
+        .app_config_dir()
+        .ok()
+        .map(|dir| dir.join(INDEX_CACHE_FILE));
+    scan_index(Path::new(&root_dir), cache_path.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::time::{Duration, SystemTime};
+
+    fn write_workspace(dir: &Path, name: &str, books: usize) -> PathBuf {
+        let path = dir.join(WORKSPACE_FILE_NAME);
+        let books: Vec<Value> = (0..books)
+            .map(|index| json!({ "id": index.to_string(), "dataPath": "missing.json" }))
+            .collect();
+        let workspace = json!({
+            "schemaVersion": "1.0.0",
+            "workspace": { "id": name, "name": name },
+            "books": books
+        });
+        write_json_file(&path, &workspace, FileEncoding::default()).unwrap();
+        path
+    }
+
+    #[test]
+    fn rereads_only_workspaces_changed_since_the_cached_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let cache = dir.path().join("config").join(INDEX_CACHE_FILE);
+        let first = write_workspace(&root.join("a"), "first", 2);
+        write_workspace(&root.join("group/b"), "second", 0);
+        // Neither nested workspaces nor backups are listed.
+        write_workspace(&root.join("a/inner"), "nested", 0);
+        write_workspace(&root.join("group/b/.sheet-up-backups"), "backup", 0);
+        fs::create_dir_all(root.join("c")).unwrap();
+        fs::write(root.join("c").join(WORKSPACE_FILE_NAME), "{ broken").unwrap();
+
+        let index = scan_index(&root, Some(&cache)).unwrap();
+        let names: Vec<Option<&str>> = index
+            .workspaces
+            .iter()
+            .map(|summary| summary.name.as_deref())
+            .collect();
+        assert_eq!(names, [Some("first"), None, Some("second")]);
+        assert_eq!(index.workspaces[0].book_count, 2);
+        assert!(matches!(
+            index.workspaces[1].error,
+            Some(WorkspaceError::ParseError { .. })
+        ));
+        assert_eq!(index.rescanned, 3);
+        assert!(index.skipped.is_empty());
+
+        // Unchanged files come from the cache; the broken one is read again.
+        let index = scan_index(&root, Some(&cache)).unwrap();
+        assert_eq!(index.rescanned, 1);
+        assert_eq!(index.workspaces[0].book_count, 2);
+
+        write_workspace(&root.join("a"), "renamed", 3);
+        let later = SystemTime::now() + Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&first)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let index = scan_index(&root, Some(&cache)).unwrap();
+        assert_eq!(index.rescanned, 2);
+        assert_eq!(index.workspaces[0].name.as_deref(), Some("renamed"));
+        assert_eq!(index.workspaces[0].book_count, 3);
+
+        assert!(matches!(
+            scan_index(&dir.path().join("missing"), Some(&cache)),
+            Err(WorkspaceError::NotFound { .. })
+        ));
+    }
+}
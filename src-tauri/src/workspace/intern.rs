@@ -0,0 +1,235 @@
+//! Interned book files: cell strings that repeat across many cells (labels,
+//! category values) are stored once in a table and referenced by index.
+//!
+//! The file stays a `.json` book. A top-level `_interned` object holds the
+//! table, and an interned cell has `"$value": <index>` where its `"value"`
+//! was, in the same position, so expanding restores the original document
+//! exactly, key order included.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Top-level key marking an interned book.
+pub const MARKER: &str = "_interned";
+/// Cell key holding an index into the string table.
+pub const REF_KEY: &str = "$value";
+const FORMAT_VERSION: u64 = 1;
+
+/// What a table entry costs beyond the string itself: indentation, comma
+/// and line break in the pretty-printed table.
+const ENTRY_OVERHEAD: usize = 8;
+
+fn digits(mut number: usize) -> usize {
+    let mut count = 1;
+    while number >= 10 {
+        number /= 10;
+        count += 1;
+    }
+    count
+}
+
+/// Every cell object of every sheet.
+fn cells_mut(book: &mut Value) -> impl Iterator<Item = &mut Map<String, Value>> {
+    book.get_mut("sheets")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|sheet| sheet.get_mut("rows").and_then(Value::as_object_mut))
+        .flat_map(|rows| rows.values_mut())
+        .filter_map(Value::as_object_mut)
+        .flat_map(|cells| cells.values_mut())
+        .filter_map(Value::as_object_mut)
+}
+
+fn cells(book: &Value) -> impl Iterator<Item = &Map<String, Value>> {
+    book.get("sheets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|sheet| sheet.get("rows").and_then(Value::as_object))
+        .flat_map(|rows| rows.values())
+        .filter_map(Value::as_object)
+        .flat_map(|cells| cells.values())
+        .filter_map(Value::as_object)
+}
+
+/// Replaces the entry under `from` with `to` at the same position.
+fn rename_key(cell: &mut Map<String, Value>, from: &str, to: &str, value: Value) {
+    let entries = std::mem::take(cell);
+    for (key, old) in entries {
+        if key == from {
+            cell.insert(to.to_string(), value.clone());
+        } else {
+            cell.insert(key, old);
+        }
+    }
+}
+
+/// The interned form of `book`, or `None` when nothing repeats often enough
+/// to be worth a table entry. Books that already use the reserved keys are
+/// never interned, as reading them back would be ambiguous.
+pub fn intern(book: &Value) -> Option<Value> {
+    if book.get(MARKER).is_some() || cells(book).any(|cell| cell.contains_key(REF_KEY)) {
+        return None;
+    }
+
+    // Count and first appearance of each cell string.
+    let mut seen: HashMap<&str, (usize, usize)> = HashMap::new();
+    for text in cells(book).filter_map(|cell| cell.get("value")?.as_str()) {
+        let order = seen.len();
+        seen.entry(text).or_insert((0, order)).0 += 1;
+    }
+    let mut candidates: Vec<(&str, usize, usize)> = seen
+        .into_iter()
+        .filter(|&(text, (count, _))| count >= 2 && !text.is_empty())
+        .map(|(text, (count, order))| (text, count, order))
+        .collect();
+    candidates.sort_by_key(|&(_, count, order)| (std::cmp::Reverse(count), order));
+
+    // A reference replaces the quoted string and lengthens the key by one.
+    let mut strings = Vec::new();
+    let mut indices = HashMap::new();
+    for (text, count, _) in candidates {
+        let quoted = text.len() + 2;
+        let saved = quoted.saturating_sub(digits(strings.len()) + 1);
+        if count * saved > quoted + ENTRY_OVERHEAD {
+            indices.insert(text.to_string(), strings.len());
+            strings.push(text.to_string());
+        }
+    }
+    if strings.is_empty() {
+        return None;
+    }
+
+    let mut interned = book.clone();
+    for cell in cells_mut(&mut interned) {
+        let index = match cell.get("value").and_then(Value::as_str) {
+            Some(text) => indices.get(text).copied(),
+            None => None,
+        };
+        if let Some(index) = index {
+            rename_key(cell, "value", REF_KEY, json!(index));
+        }
+    }
+    interned.as_object_mut()?.insert(
+        MARKER.to_string(),
+        json!({ "version": FORMAT_VERSION, "strings": strings }),
+    );
+    Some(interned)
+}
+
+/// Turns an interned book back into the plain document it was made from.
+/// Other values are left untouched, including a `_interned` key that does
+/// not hold a string table.
+pub fn expand(value: &mut Value, path: &Path) -> WorkspaceResult<()> {
+    let is_table = value
+        .get(MARKER)
+        .and_then(|marker| marker.get("strings"))
+        .is_some_and(Value::is_array);
+    if !is_table {
+        return Ok(());
+    }
+    let marker = value
+        .as_object_mut()
+        .and_then(|object| object.shift_remove(MARKER))
+        .unwrap_or_default();
+    let version = marker["version"].as_u64().unwrap_or(FORMAT_VERSION);
+    if version > FORMAT_VERSION {
+        return Err(WorkspaceError::invalid_schema(format!(
+            "{} uses interned format version {}, newer than this app supports",
+            path.display(),
+            version
+        )));
+    }
+    let Value::Array(strings) = &marker["strings"] else {
+        return Ok(());
+    };
+
+    for cell in cells_mut(value) {
+        let Some(reference) = cell.get(REF_KEY) else {
+            continue;
+        };
+        let text = reference
+            .as_u64()
+            .and_then(|index| strings.get(index as usize))
+            .filter(|text| text.is_string())
+            .cloned()
+            .ok_or_else(|| {
+                WorkspaceError::invalid_schema(format!(
+                    "{} has a cell referring to missing interned string {}",
+                    path.display(),
+                    reference
+                ))
+            })?;
+        rename_key(cell, REF_KEY, "value", text);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book_with(row_count: usize) -> Value {
+        let mut rows = Map::new();
+        for row in 1..=row_count {
+            let region = ["north", "south"][row % 2];
+            rows.insert(
+                row.to_string(),
+                json!({
+                    "A": { "type": "string", "value": region, "style": {} },
+                    "B": { "value": row },
+                    "C": { "value": format!("note {}", row) }
+                }),
+            );
+        }
+        json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": row_count, "cols": 3 },
+                "rows": rows
+            }]
+        })
+    }
+
+    #[test]
+    fn round_trips_exactly_with_key_order() {
+        let book = book_with(50);
+        let interned = intern(&book).unwrap();
+        assert_eq!(interned[MARKER]["strings"], json!(["south", "north"]));
+        let cell = interned["sheets"][0]["rows"]["1"]["A"].as_object().unwrap();
+        assert_eq!(cell.keys().collect::<Vec<_>>(), ["type", REF_KEY, "style"]);
+        assert_eq!(interned["sheets"][0]["rows"]["1"]["C"]["value"], "note 1");
+
+        let mut expanded = interned;
+        expand(&mut expanded, Path::new("book.json")).unwrap();
+        assert_eq!(
+            serde_json::to_string(&expanded).unwrap(),
+            serde_json::to_string(&book).unwrap()
+        );
+    }
+
+    #[test]
+    fn leaves_books_without_repeats_or_with_reserved_keys_alone() {
+        assert!(intern(&book_with(1)).is_none());
+        let mut reserved = book_with(50);
+        reserved["sheets"][0]["rows"]["2"]["B"][REF_KEY] = json!(0);
+        assert!(intern(&reserved).is_none());
+
+        let mut plain = json!({ MARKER: "user data", "sheets": [] });
+        expand(&mut plain, Path::new("book.json")).unwrap();
+        assert_eq!(plain[MARKER], "user data");
+
+        let mut broken = intern(&book_with(50)).unwrap();
+        broken[MARKER]["strings"] = json!([]);
+        assert!(matches!(
+            expand(&mut broken, Path::new("book.json")),
+            Err(WorkspaceError::InvalidSchema { .. })
+        ));
+    }
+}
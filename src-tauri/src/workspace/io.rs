@@ -0,0 +1,2033 @@
+use super::crypto::{decrypt, encrypt, is_encrypted};
+use super::error::{FileKind, WorkspaceError, WorkspaceResult};
+use super::formats::{non_json_format, BookFormat};
+use super::intern;
+use super::jsonc;
+use super::metrics::{self, Timed};
+use encoding_rs::Encoding;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Book files whose `dataPath` ends in `.json.gz` are stored gzip-compressed.
+pub fn is_compressed(path: &Path) -> bool {
+    path.to_string_lossy()
+        .to_ascii_lowercase()
+        .ends_with(".json.gz")
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+/// Line breaks of a JSON file as found on disk or as it should be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextStyle {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    /// Starts with a UTF-8 byte order mark. Only kept by `preserve` saves;
+    /// every other save writes plain UTF-8.
+    #[serde(default)]
+    pub bom: bool,
+    /// All on one line instead of indented. A final newline may still follow.
+    #[serde(default)]
+    pub minified: bool,
+    /// Keys of every object in code point order instead of the order they
+    /// have in the value. Never detected from a file; only `sortKeys` of
+    /// [`WriteOptions`] sets it.
+    #[serde(skip)]
+    pub sort_keys: bool,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+            minified: false,
+            sort_keys: false,
+        }
+    }
+}
+
+/// Whether JSON text with `breaks` line breaks, the last one at the very end
+/// when `final_newline`, is on one line. Pretty output of an empty object or
+/// array is too, which is fine since its minified form is the same.
+fn is_minified(breaks: usize, final_newline: bool) -> bool {
+    breaks == 0 || (breaks == 1 && final_newline)
+}
+
+impl TextStyle {
+    /// Judged by the first line break; files without any count as LF.
+    pub fn detect(text: &[u8]) -> Self {
+        let crlf = text
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .is_some_and(|index| index > 0 && text[index - 1] == b'\r');
+        Self {
+            line_ending: if crlf {
+                LineEnding::Crlf
+            } else {
+                LineEnding::Lf
+            },
+            final_newline: text.ends_with(b"\n"),
+            bom: false,
+            minified: is_minified(
+                text.iter().filter(|&&byte| byte == b'\n').take(2).count(),
+                text.ends_with(b"\n"),
+            ),
+            sort_keys: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineEndingMode {
+    #[default]
+    Lf,
+    Crlf,
+    /// Keep whatever the file used when it was loaded.
+    Preserve,
+}
+
+/// Indented (`true`) or minified JSON, chosen separately for
+/// `workspace.json` and the books. Each defaults to `true`, or to the loaded
+/// file's layout in `preserve` mode.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrettyOptions {
+    pub workspace: Option<bool>,
+    pub books: Option<bool>,
+}
+
+/// Text layout requested for a save.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WriteOptions {
+    pub line_ending: LineEndingMode,
+    /// Defaults to `true`, or to the loaded file's style in `preserve` mode.
+    /// Applies to minified files as well.
+    pub final_newline: Option<bool>,
+    pub pretty: PrettyOptions,
+    /// Write the keys of every object, at any depth, in alphabetical (code
+    /// point) order so that the same data always gives the same file and
+    /// diffs stay small. Arrays keep their order. Without it keys are
+    /// written in the order of the payload, which loads keep as read; with
+    /// it that order is dropped, in `preserve` mode too. Only JSON files
+    /// are affected.
+    pub sort_keys: bool,
+}
+
+impl WriteOptions {
+    /// `loaded` is the style detected when the file was read, if known;
+    /// `is_workspace` picks which of the `pretty` settings applies.
+    pub fn style_for(&self, loaded: Option<TextStyle>, is_workspace: bool) -> TextStyle {
+        let base = match self.line_ending {
+            LineEndingMode::Lf => TextStyle::default(),
+            LineEndingMode::Crlf => TextStyle {
+                line_ending: LineEnding::Crlf,
+                ..TextStyle::default()
+            },
+            LineEndingMode::Preserve => loaded.unwrap_or_default(),
+        };
+        let pretty = if is_workspace {
+            self.pretty.workspace
+        } else {
+            self.pretty.books
+        };
+        TextStyle {
+            final_newline: self.final_newline.unwrap_or(base.final_newline),
+            minified: pretty.map_or(base.minified, |pretty| !pretty),
+            sort_keys: self.sort_keys,
+            ..base
+        }
+    }
+}
+
+/// How often a write is attempted when it fails in a way that usually
+/// clears up by itself, as when a cloud sync client (OneDrive, Dropbox, ...)
+/// briefly holds the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first included; 1 disables retrying.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled before each one after it.
+    pub initial_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Retry settings of a save; omitted fields keep [`RetryPolicy::default`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryOptions {
+    pub max_attempts: Option<u32>,
+    pub initial_delay_ms: Option<u64>,
+}
+
+impl RetryOptions {
+    pub fn policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(default.max_attempts),
+            initial_delay: self
+                .initial_delay_ms
+                .map_or(default.initial_delay, Duration::from_millis),
+        }
+    }
+}
+
+/// Errors that a file locked by another process typically produces, as
+/// opposed to ones like `NotFound` that retrying cannot fix.
+fn is_transient(err: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION, and the access denied
+    // a file being replaced or scanned gives there. On Unix, EACCES is real.
+    let windows_lock = cfg!(windows)
+        && (matches!(err.raw_os_error(), Some(32 | 33))
+            || err.kind() == io::ErrorKind::PermissionDenied);
+    windows_lock
+        || matches!(
+            err.kind(),
+            io::ErrorKind::ResourceBusy
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut
+        )
+}
+
+/// Runs `op` until it succeeds, fails with a permanent error, or `policy`
+/// runs out, calling `sleep` with the growing delay between attempts. A
+/// failure comes with the number of attempts made.
+fn retry<T>(
+    policy: RetryPolicy,
+    mut sleep: impl FnMut(Duration),
+    mut op: impl FnMut() -> io::Result<T>,
+) -> Result<T, (io::Error, u32)> {
+    let mut delay = policy.initial_delay;
+    let mut attempts = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempts < policy.max_attempts && is_transient(&err) => {
+                sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempts += 1;
+            }
+            Err(err) => return Err((err, attempts)),
+        }
+    }
+}
+
+/// Largest files a load accepts, checked against the size on disk before
+/// anything is read so that a broken or runaway file fails fast instead of
+/// exhausting memory. `null` lifts a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SizeLimits {
+    pub workspace: Option<u64>,
+    pub book: Option<u64>,
+    /// Most entries in `books` of `workspace.json`, checked before any book
+    /// is opened so that a broken file listing tens of thousands of them
+    /// doesn't run out of memory and file handles.
+    pub book_count: Option<u64>,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            workspace: Some(16 * 1024 * 1024),
+            book: Some(512 * 1024 * 1024),
+            book_count: Some(10_000),
+        }
+    }
+}
+
+/// Fails with `fileTooLarge` when `path` is bigger than `limit`. Missing
+/// files pass; reading them reports the problem.
+pub fn ensure_size_within(path: &Path, limit: Option<u64>) -> WorkspaceResult<()> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    match fs::metadata(path) {
+        Ok(stat) if stat.len() > limit => Err(WorkspaceError::FileTooLarge {
+            path: path.display().to_string(),
+            size: stat.len(),
+            limit,
+        }),
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(WorkspaceError::io("stat", path, err)),
+    }
+}
+
+/// How a file is stored beyond what its extension implies.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileEncoding<'a> {
+    /// gzip level (0-9, default 6); only used for `.json.gz` files.
+    pub compression_level: Option<u32>,
+    /// Encrypts the file when set.
+    pub passphrase: Option<&'a str>,
+    pub style: TextStyle,
+    pub retry: RetryPolicy,
+    /// Stores repeated cell strings once (see [`intern`]) when that makes
+    /// the file noticeably smaller.
+    pub intern_strings: bool,
+    /// Writes even when the file already holds the same content, for
+    /// callers that just checked [`matches_on_disk`] themselves.
+    pub force: bool,
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Turns decoded contents into UTF-8 JSON text. A UTF-8 byte order mark is
+/// dropped (the returned flag records it); UTF-16 with a byte order mark is
+/// converted to UTF-8.
+fn utf8_text(bytes: Vec<u8>, path: &Path) -> WorkspaceResult<(Vec<u8>, bool)> {
+    let utf16 = match bytes.get(..2) {
+        Some(b"\xFF\xFE") => encoding_rs::UTF_16LE,
+        Some(b"\xFE\xFF") => encoding_rs::UTF_16BE,
+        _ => {
+            return Ok(match bytes.strip_prefix(UTF8_BOM) {
+                Some(rest) => (rest.to_vec(), true),
+                None => (bytes, false),
+            })
+        }
+    };
+    let (text, had_errors) = utf16.decode_without_bom_handling(&bytes[2..]);
+    if had_errors {
+        return Err(WorkspaceError::Encoding {
+            path: path.display().to_string(),
+            encoding: utf16.name(),
+        });
+    }
+    Ok((text.into_owned().into_bytes(), false))
+}
+
+/// Notes the line breaks of the text read through it, as
+/// [`TextStyle::detect`] would for the whole text.
+struct StyleSniffer<R> {
+    inner: R,
+    line_ending: Option<LineEnding>,
+    last: Option<u8>,
+    /// Line breaks seen, counted up to two.
+    breaks: usize,
+}
+
+impl<R: Read> Read for StyleSniffer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let chunk = &buf[..read];
+        if self.line_ending.is_none() {
+            if let Some(index) = chunk.iter().position(|&byte| byte == b'\n') {
+                let before = index.checked_sub(1).map(|i| chunk[i]).or(self.last);
+                self.line_ending = Some(if before == Some(b'\r') {
+                    LineEnding::Crlf
+                } else {
+                    LineEnding::Lf
+                });
+            }
+        }
+        if self.breaks < 2 {
+            self.breaks += chunk
+                .iter()
+                .filter(|&&byte| byte == b'\n')
+                .take(2 - self.breaks)
+                .count();
+        }
+        if let Some(&byte) = chunk.last() {
+            self.last = Some(byte);
+        }
+        Ok(read)
+    }
+}
+
+/// Encodings a book file can be read from, for files written by other
+/// tools; saving always writes UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    ShiftJis,
+    EucJp,
+}
+
+impl TextEncoding {
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            Self::Utf8 => encoding_rs::UTF_8,
+            Self::Utf16Le => encoding_rs::UTF_16LE,
+            Self::Utf16Be => encoding_rs::UTF_16BE,
+            Self::ShiftJis => encoding_rs::SHIFT_JIS,
+            Self::EucJp => encoding_rs::EUC_JP,
+        }
+    }
+}
+
+/// How [`parse_json_reader`] turns the contents into text.
+#[derive(Clone, Copy)]
+enum Decode {
+    /// Plain UTF-8 is parsed as it is read; contents that have to be held
+    /// in memory anyway are decoded as with `Detect`.
+    Stream,
+    /// UTF-8, UTF-16 with a byte order mark, or when the text is neither,
+    /// the legacy Japanese encoding it most likely is (see
+    /// [`detect_legacy_encoding`]).
+    Detect,
+    As(TextEncoding),
+}
+
+/// A parsed file with how its text was stored.
+pub struct Parsed<T> {
+    pub value: T,
+    pub style: TextStyle,
+    /// The encoding the text was converted from.
+    pub encoding: &'static Encoding,
+}
+
+impl<T> Parsed<T> {
+    /// The text was not UTF-8, so writing the value back changes the file.
+    pub fn converted(&self) -> bool {
+        self.encoding != encoding_rs::UTF_8
+    }
+}
+
+/// Shift_JIS or EUC-JP, whichever decodes `bytes` without errors into more
+/// kana and kanji; the same bytes read as the other encoding often come out
+/// as half-width katakana or symbols. `None` when neither decodes them.
+fn detect_legacy_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let score = |encoding: &'static Encoding| {
+        let text = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+        let japanese = text
+            .chars()
+            .filter(|c| matches!(c, '\u{3040}'..='\u{30FF}' | '\u{4E00}'..='\u{9FFF}'))
+            .count();
+        let half_width = text
+            .chars()
+            .filter(|c| matches!(c, '\u{FF61}'..='\u{FF9F}'))
+            .count();
+        Some((japanese as i64 - half_width as i64, encoding))
+    };
+    // On ties EUC-JP wins: it rejects far more byte sequences, so text it
+    // decodes is less likely to be a misreading.
+    [score(encoding_rs::SHIFT_JIS), score(encoding_rs::EUC_JP)]
+        .into_iter()
+        .flatten()
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, encoding)| encoding)
+}
+
+/// Decodes whole contents for [`Decode::Detect`] and [`Decode::As`] into
+/// UTF-8, also returning whether a UTF-8 byte order mark was dropped. A
+/// UTF-16 byte order mark is not reproduced: such files are written back as
+/// UTF-8.
+fn decode_text(
+    bytes: Vec<u8>,
+    decode: Decode,
+    path: &Path,
+) -> WorkspaceResult<(Vec<u8>, bool, &'static Encoding)> {
+    let bom = Encoding::for_bom(&bytes);
+    let encoding = match (decode, bom) {
+        (Decode::As(encoding), _) => encoding.encoding(),
+        (_, Some((encoding, _))) => encoding,
+        _ if std::str::from_utf8(&bytes).is_ok() => encoding_rs::UTF_8,
+        _ => detect_legacy_encoding(&bytes).ok_or_else(|| WorkspaceError::InvalidEncoding {
+            path: path.display().to_string(),
+        })?,
+    };
+    let invalid = || WorkspaceError::Encoding {
+        path: path.display().to_string(),
+        encoding: encoding.name(),
+    };
+    let skip = bom
+        .filter(|(found, _)| *found == encoding)
+        .map_or(0, |(_, length)| length);
+    let text = &bytes[skip..];
+    if encoding == encoding_rs::UTF_8 {
+        std::str::from_utf8(text).map_err(|_| invalid())?;
+        return Ok((text.to_vec(), skip > 0, encoding));
+    }
+    let text = encoding
+        .decode_without_bom_handling_and_without_replacement(text)
+        .ok_or_else(invalid)?;
+    Ok((text.into_owned().into_bytes(), false, encoding))
+}
+
+/// Parses file contents read from `reader`: decrypted first when they carry
+/// the encryption header, then gunzipped when `compressed`, then decoded as
+/// `decode` says. Plain and gzip-compressed UTF-8 is parsed as it is read,
+/// without holding the whole text in memory; encrypted, UTF-16 and legacy
+/// contents are decoded in memory first. `path` is only used in error
+/// messages.
+fn parse_json_reader<'a, T: DeserializeOwned>(
+    mut reader: impl BufRead + 'a,
+    compressed: bool,
+    passphrase: Option<&str>,
+    decode: Decode,
+    path: &Path,
+) -> WorkspaceResult<Parsed<T>> {
+    let read_action = if compressed { "decompress" } else { "read" };
+    let read_error = |err| WorkspaceError::io(read_action, path, err);
+
+    let head = reader
+        .fill_buf()
+        .map_err(|err| WorkspaceError::io("read", path, err))?;
+    let contents: Box<dyn Read + 'a> = if is_encrypted(head) {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|err| WorkspaceError::io("read", path, err))?;
+        Box::new(io::Cursor::new(decrypt(&bytes, passphrase, path)?))
+    } else {
+        Box::new(reader)
+    };
+    let mut contents: Box<dyn Read + 'a> = if compressed {
+        Box::new(GzDecoder::new(contents))
+    } else {
+        contents
+    };
+
+    let mut start = Vec::with_capacity(UTF8_BOM.len());
+    (&mut contents)
+        .take(UTF8_BOM.len() as u64)
+        .read_to_end(&mut start)
+        .map_err(read_error)?;
+    let utf16 = matches!(start.get(..2), Some(b"\xFF\xFE" | b"\xFE\xFF"));
+    let streamed = matches!(decode, Decode::Stream | Decode::As(TextEncoding::Utf8));
+    if utf16 || !streamed {
+        contents.read_to_end(&mut start).map_err(read_error)?;
+        let (text, bom, encoding) = decode_text(start, decode, path)?;
+        let value =
+            serde_json::from_slice(&text).map_err(|err| WorkspaceError::parse(path, err))?;
+        return Ok(Parsed {
+            value,
+            style: TextStyle {
+                bom,
+                ..TextStyle::detect(&text)
+            },
+            encoding,
+        });
+    }
+    let bom = start == UTF8_BOM;
+    if bom {
+        start.clear();
+    }
+
+    let mut sniffer = StyleSniffer {
+        inner: io::Cursor::new(start).chain(contents),
+        line_ending: None,
+        last: None,
+        breaks: 0,
+    };
+    let value = serde_json::from_reader(io::BufReader::new(&mut sniffer)).map_err(|err| {
+        if err.is_io() {
+            read_error(io::Error::from(err))
+        } else {
+            WorkspaceError::parse(path, err)
+        }
+    })?;
+    Ok(Parsed {
+        value,
+        style: TextStyle {
+            line_ending: sniffer.line_ending.unwrap_or_default(),
+            final_newline: sniffer.last == Some(b'\n'),
+            bom,
+            minified: is_minified(sniffer.breaks, sniffer.last == Some(b'\n')),
+            sort_keys: false,
+        },
+        encoding: encoding_rs::UTF_8,
+    })
+}
+
+/// [`parse_json_reader`] with `encoding`, or when that is `None` first as
+/// UTF-8 while reading and, should that not parse, again with the encoding
+/// detected. Text that is not valid UTF-8 fails the streamed parse, so
+/// files in other encodings cost one extra read.
+fn parse_json_opened<'a, T: DeserializeOwned, R: BufRead + 'a>(
+    open: impl Fn() -> WorkspaceResult<R>,
+    compressed: bool,
+    passphrase: Option<&str>,
+    encoding: Option<TextEncoding>,
+    path: &Path,
+) -> WorkspaceResult<Parsed<T>> {
+    let Some(encoding) = encoding else {
+        return match parse_json_reader(open()?, compressed, passphrase, Decode::Stream, path) {
+            Err(WorkspaceError::ParseError { .. }) => {
+                parse_json_reader(open()?, compressed, passphrase, Decode::Detect, path)
+            }
+            parsed => parsed,
+        };
+    };
+    parse_json_reader(open()?, compressed, passphrase, Decode::As(encoding), path)
+}
+
+/// [`parse_json_reader`] for contents already in memory.
+pub fn parse_json_bytes(
+    bytes: &[u8],
+    compressed: bool,
+    passphrase: Option<&str>,
+    path: &Path,
+) -> WorkspaceResult<Value> {
+    let parsed = parse_json_opened(|| Ok(bytes), compressed, passphrase, None, path)?;
+    let mut value = parsed.value;
+    intern::expand(&mut value, path)?;
+    Ok(value)
+}
+
+pub fn read_json_file(path: &Path) -> WorkspaceResult<Value> {
+    read_json_file_with_passphrase(path, None)
+}
+
+pub fn read_json_file_with_passphrase(
+    path: &Path,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<Value> {
+    read_json_file_styled(path, passphrase).map(|(value, _)| value)
+}
+
+/// Reads a JSON file straight into `T` through the same decoding as
+/// [`read_json_file_with_passphrase`]. A `T` that skips what it does not
+/// need never holds the whole document in memory.
+pub fn read_json_file_as<T: DeserializeOwned>(
+    path: &Path,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<T> {
+    read_file(path, passphrase, None).map(|parsed| parsed.value)
+}
+
+/// Like [`read_json_file_with_passphrase`], also reporting the line breaks
+/// and byte order mark the file uses. Interned books come back expanded.
+pub fn read_json_file_styled(
+    path: &Path,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<(Value, TextStyle)> {
+    read_json_file_decoded(path, passphrase, None).map(|parsed| (parsed.value, parsed.style))
+}
+
+/// Like [`read_json_file_styled`], reading the text as `encoding` instead
+/// of detecting it, and reporting what it was converted from. Text that is
+/// neither UTF-8 nor UTF-16 with a byte order mark is detected as Shift_JIS
+/// or EUC-JP; when it is neither, the error is `invalidEncoding`.
+pub fn read_json_file_decoded(
+    path: &Path,
+    passphrase: Option<&str>,
+    encoding: Option<TextEncoding>,
+) -> WorkspaceResult<Parsed<Value>> {
+    let mut parsed = read_file(path, passphrase, encoding)?;
+    intern::expand(&mut parsed.value, path)?;
+    Ok(parsed)
+}
+
+fn read_file<T: DeserializeOwned>(
+    path: &Path,
+    passphrase: Option<&str>,
+    encoding: Option<TextEncoding>,
+) -> WorkspaceResult<Parsed<T>> {
+    if let Some(format) = non_json_format(path) {
+        return read_with_format(format, path, passphrase);
+    }
+    let open = || {
+        fs::File::open(path)
+            .map(|file| io::BufReader::new(Timed(file)))
+            .map_err(|err| WorkspaceError::io("read", path, err))
+    };
+    parse_json_opened(open, is_compressed(path), passphrase, encoding, path)
+}
+
+/// Reads a book in a format other than JSON whole, decrypting it when it
+/// carries the encryption header. The text style is the default, as the
+/// format decides its own layout.
+fn read_with_format<T: DeserializeOwned>(
+    format: &dyn BookFormat,
+    path: &Path,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<Parsed<T>> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| Timed(file).read_to_end(&mut bytes))
+        .map_err(|err| WorkspaceError::io("read", path, err))?;
+    if is_encrypted(&bytes) {
+        bytes = decrypt(&bytes, passphrase, path)?;
+    }
+    let value = format.parse(&bytes, path)?;
+    Ok(Parsed {
+        value: T::deserialize(value).map_err(|err| WorkspaceError::parse(path, err))?,
+        style: TextStyle::default(),
+        encoding: encoding_rs::UTF_8,
+    })
+}
+
+/// Reads `workspace.json`, which unlike book files may hold comments and
+/// trailing commas for people editing it by hand (see [`jsonc::strip`]).
+/// They are not kept: saving writes plain JSON. The file is small, so it
+/// is read whole rather than streamed.
+pub fn read_workspace_json_styled(path: &Path) -> WorkspaceResult<(Value, TextStyle)> {
+    let file = fs::File::open(path).map_err(|err| WorkspaceError::io("read", path, err))?;
+    let file = Timed(file);
+    let mut bytes = Vec::new();
+    let read = if is_compressed(path) {
+        GzDecoder::new(file).read_to_end(&mut bytes)
+    } else {
+        io::BufReader::new(file).read_to_end(&mut bytes)
+    };
+    read.map_err(|err| WorkspaceError::io("read", path, err))?;
+    let (text, bom) = utf8_text(bytes, path)?;
+    let value = serde_json::from_slice(&jsonc::strip(&text))
+        .map_err(|err| WorkspaceError::parse(path, err).in_file(FileKind::Workspace))?;
+    Ok((
+        value,
+        TextStyle {
+            bom,
+            ..TextStyle::detect(&text)
+        },
+    ))
+}
+
+pub fn read_workspace_json(path: &Path) -> WorkspaceResult<Value> {
+    read_workspace_json_styled(path).map(|(value, _)| value)
+}
+
+/// Whether the file on disk starts with the encryption header; `false` for
+/// missing or unreadable files.
+pub fn is_encrypted_file(path: &Path) -> bool {
+    let mut header = [0u8; 8];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| is_encrypted(&header))
+}
+
+/// Modification time in epoch millis, or `None` when the file does not exist.
+pub fn modified_millis(path: &Path) -> WorkspaceResult<Option<u64>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(WorkspaceError::io("stat", path, err)),
+    };
+    let modified = metadata
+        .modified()
+        .map_err(|err| WorkspaceError::io("stat", path, err))?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_millis() as u64))
+}
+
+/// Size of the file on disk in bytes, or `None` when it cannot be read.
+pub fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.len())
+}
+
+/// Stable-within-a-build hash of a value's compact serialization. Only used
+/// to notice that a payload is unchanged since the last load or save; key
+/// order counts, since it is written back as-is.
+pub fn content_hash(value: &Value) -> String {
+    struct HashWriter(DefaultHasher);
+    impl Write for HashWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut hasher = HashWriter(DefaultHasher::new());
+    if serde_json::to_writer(&mut hasher, value).is_err() {
+        return String::new();
+    }
+    format!("{:016x}", hasher.0.finish())
+}
+
+/// A hidden sibling of `path` named after it, this process and a random
+/// suffix, e.g. `.book.json.4242.1f3a9c0d.tmp`. Staying in the same
+/// directory keeps the final rename on one file system; the pid and suffix
+/// keep concurrent saves, in this process or another, from sharing a name.
+pub(super) fn temp_path_for(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    let suffix = Uuid::new_v4().simple().to_string();
+    file_name.push(format!(".{}.{}.tmp", process::id(), &suffix[..8]));
+    path.with_file_name(file_name)
+}
+
+/// Removes a temp file when dropped unless it was renamed into place, so a
+/// failed or panicking write leaves nothing behind.
+struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TempFile {
+    /// Creates a fresh temp file next to `path`, drawing another name in the
+    /// unlikely case the first is taken.
+    fn create(path: &Path) -> io::Result<(Self, fs::File)> {
+        loop {
+            let temp_path = temp_path_for(path);
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)
+            {
+                Ok(file) => {
+                    let guard = Self {
+                        path: temp_path,
+                        persisted: false,
+                    };
+                    return Ok((guard, file));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn persist(mut self, dest: &Path) -> io::Result<()> {
+        replace_file(&self.path, dest)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Replaces `dest` with `src`. `fs::rename` already overwrites atomically on
+/// Unix; on Windows it can fail while the destination exists, so we retry
+/// after removing it.
+pub(super) fn replace_file(src: &Path, dest: &Path) -> io::Result<()> {
+    match fs::rename(src, dest) {
+        Ok(()) => Ok(()),
+        #[cfg(windows)]
+        Err(err)
+            if dest.exists()
+                && matches!(
+                    err.kind(),
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::AlreadyExists
+                ) =>
+        {
+            fs::remove_file(dest)?;
+            fs::rename(src, dest)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes through a sibling temp file that is fsynced and then renamed over
+/// `path`, so a crash mid-write never leaves a truncated file behind.
+pub fn write_atomic<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut fs::File) -> io::Result<()>,
+{
+    let (temp, mut file) = TempFile::create(path)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    drop(file);
+    temp.persist(path)?;
+
+    if let Some(parent) = path.parent() {
+        // Best effort: the rename itself has already succeeded.
+        let _ = sync_dir(parent);
+    }
+    Ok(())
+}
+
+/// Passes bytes through, turning every `\n` into `newline`. Pretty output
+/// escapes newlines inside strings, so each one it writes is a line break.
+struct LineBreaks<W> {
+    inner: W,
+    newline: &'static [u8],
+}
+
+impl<W: Write> Write for LineBreaks<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.newline == b"\n" {
+            return self.inner.write(buf);
+        }
+        for (index, line) in buf.split(|&byte| byte == b'\n').enumerate() {
+            if index > 0 {
+                self.inner.write_all(self.newline)?;
+            }
+            self.inner.write_all(line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Serializes a value with the keys of every object sorted, without
+/// building a sorted copy of it.
+struct SortedKeys<'a>(&'a Value);
+
+impl Serialize for SortedKeys<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Object(object) => {
+                let mut entries: Vec<_> = object.iter().collect();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, &SortedKeys(value))?;
+                }
+                map.end()
+            }
+            Value::Array(items) => serializer.collect_seq(items.iter().map(SortedKeys)),
+            other => other.serialize(serializer),
+        }
+    }
+}
+
+fn write_json<W: Write>(
+    writer: W,
+    value: &impl Serialize,
+    minified: bool,
+) -> serde_json::Result<()> {
+    if minified {
+        serde_json::to_writer(writer, value)
+    } else {
+        serde_json::to_writer_pretty(writer, value)
+    }
+}
+
+/// Serializes `value` as pretty or minified JSON in `style` straight into
+/// `writer`, so no copy of the whole text is ever held in memory.
+fn write_styled<W: Write>(writer: W, value: &Value, style: TextStyle) -> io::Result<()> {
+    let mut lines = LineBreaks {
+        inner: io::BufWriter::new(writer),
+        newline: match style.line_ending {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        },
+    };
+    if style.bom {
+        lines.inner.write_all(UTF8_BOM)?;
+    }
+    if style.sort_keys {
+        write_json(&mut lines, &SortedKeys(value), style.minified)?;
+    } else {
+        write_json(&mut lines, value, style.minified)?;
+    }
+    if style.final_newline {
+        lines.inner.write_all(lines.newline)?;
+    }
+    // Not `flush`, which would also force a gzip sync block.
+    lines
+        .inner
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    Ok(())
+}
+
+fn encode_json<W: Write>(
+    writer: W,
+    value: &Value,
+    style: TextStyle,
+    compression: Option<Compression>,
+) -> io::Result<()> {
+    match compression {
+        Some(level) => {
+            let mut encoder = GzEncoder::new(writer, level);
+            write_styled(&mut encoder, value, style)?;
+            encoder.finish()?.flush()
+        }
+        None => write_styled(writer, value, style),
+    }
+}
+
+/// `value` as a `.json` (or, when `compressed`, `.json.gz`) file written
+/// with the default encoding holds it.
+pub fn json_bytes(value: &Value, compressed: bool, path: &Path) -> WorkspaceResult<Vec<u8>> {
+    let compression = compressed.then(Compression::default);
+    let mut bytes = Vec::new();
+    encode_json(&mut bytes, value, TextStyle::default(), compression)
+        .map_err(|err| write_error(path, err))?;
+    Ok(bytes)
+}
+
+/// Checks bytes written to it against a reader, failing the write at the
+/// first difference so serialization stops early.
+struct Compare<R> {
+    expected: R,
+}
+
+impl<R: Read> Write for Compare<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut chunk = [0u8; 8192];
+        for part in buf.chunks(chunk.len()) {
+            let actual = &mut chunk[..part.len()];
+            self.expected.read_exact(actual)?;
+            if actual != part {
+                return Err(io::Error::other("contents differ"));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R: Read> Compare<R> {
+    fn at_end(&mut self) -> bool {
+        self.expected.read(&mut [0u8]).is_ok_and(|read| read == 0)
+    }
+}
+
+/// The interned form is only used when it is at least this much smaller;
+/// for small books the table rarely pays for itself.
+const MIN_INTERN_SAVING_PERCENT: u64 = 10;
+
+/// What writing `value` with `encoding` actually puts in the file.
+fn stored_form<'v>(value: &'v Value, encoding: FileEncoding) -> Cow<'v, Value> {
+    if !encoding.intern_strings {
+        return Cow::Borrowed(value);
+    }
+    match intern::intern(value) {
+        Some(interned)
+            if serialized_size(&interned, encoding.style) * 100
+                <= serialized_size(value, encoding.style) * (100 - MIN_INTERN_SAVING_PERCENT) =>
+        {
+            Cow::Owned(interned)
+        }
+        _ => Cow::Borrowed(value),
+    }
+}
+
+/// The decoded contents of `path` if its encryption agrees with
+/// `encoding`; `None` for missing or unreadable files and for a file that
+/// is encrypted when it should not be (or the other way round).
+fn decoded_contents(path: &Path, encoding: FileEncoding) -> Option<Box<dyn Read>> {
+    let file = fs::File::open(path).ok()?;
+    let mut reader = io::BufReader::new(file);
+    let encrypted = is_encrypted(reader.fill_buf().ok()?);
+    if encrypted != encoding.passphrase.is_some() {
+        return None;
+    }
+    let contents: Box<dyn Read> = if encrypted {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).ok()?;
+        Box::new(io::Cursor::new(
+            decrypt(&bytes, encoding.passphrase, path).ok()?,
+        ))
+    } else {
+        Box::new(reader)
+    };
+    Some(if is_compressed(path) {
+        Box::new(GzDecoder::new(contents))
+    } else {
+        contents
+    })
+}
+
+/// Whether `path` already holds what writing `value` with `encoding` would
+/// produce. The decoded text is compared, so another gzip level or a fresh
+/// encryption nonce is no change, while a file that is encrypted when it
+/// should not be (or the other way round) is. Missing or unreadable files
+/// never match.
+///
+/// Text that differs from what would be written is compared once more
+/// without the whitespace between JSON tokens: it matches when only that
+/// whitespace differs and the file uses the line breaks and byte order mark
+/// of `encoding.style` (and its key order, when sorting keys). Indentation,
+/// minification and the final newline alone are layout, not content, and
+/// do not call for a rewrite; the next save that changes the file writes
+/// them as configured. Both passes stream the file.
+pub fn matches_on_disk(path: &Path, value: &Value, encoding: FileEncoding) -> bool {
+    let Some(mut expected) = decoded_contents(path, encoding) else {
+        return false;
+    };
+    if let Some(format) = non_json_format(path) {
+        let mut bytes = Vec::new();
+        return expected.read_to_end(&mut bytes).is_ok()
+            && format
+                .serialize(value, path)
+                .is_ok_and(|serialized| serialized == bytes);
+    }
+    let stored = stored_form(value, encoding);
+    let mut compare = Compare { expected };
+    if write_styled(&mut compare, &stored, encoding.style).is_ok() && compare.at_end() {
+        return true;
+    }
+
+    let Some(contents) = decoded_contents(path, encoding) else {
+        return false;
+    };
+    let mut contents = io::BufReader::new(contents);
+    let bom = match contents.fill_buf() {
+        Ok(head) => head.starts_with(UTF8_BOM),
+        Err(_) => return false,
+    };
+    if bom != encoding.style.bom {
+        return false;
+    }
+    if bom {
+        contents.consume(UTF8_BOM.len());
+    }
+    let mut compare = Compare {
+        expected: Significant::new(contents),
+    };
+    let written = if encoding.style.sort_keys {
+        write_json(&mut compare, &SortedKeys(&stored), true)
+    } else {
+        write_json(&mut compare, &*stored, true)
+    };
+    written.is_ok()
+        && compare.at_end()
+        && compare
+            .expected
+            .line_ending
+            .is_none_or(|ending| ending == encoding.style.line_ending)
+}
+
+/// Reads JSON text without the whitespace between tokens, which is what
+/// compact serialization produces, noting the first line break it skips.
+struct Significant<R> {
+    inner: R,
+    in_string: bool,
+    escaped: bool,
+    previous: u8,
+    line_ending: Option<LineEnding>,
+}
+
+impl<R> Significant<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            in_string: false,
+            escaped: false,
+            previous: 0,
+            line_ending: None,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Significant<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            let mut used = 0;
+            for &byte in available {
+                if filled == buf.len() {
+                    break;
+                }
+                used += 1;
+                let previous = std::mem::replace(&mut self.previous, byte);
+                if self.in_string {
+                    if self.escaped {
+                        self.escaped = false;
+                    } else if byte == b'\\' {
+                        self.escaped = true;
+                    } else if byte == b'"' {
+                        self.in_string = false;
+                    }
+                } else if byte == b'"' {
+                    self.in_string = true;
+                } else if matches!(byte, b' ' | b'\t' | b'\r' | b'\n') {
+                    if byte == b'\n' && self.line_ending.is_none() {
+                        self.line_ending = Some(if previous == b'\r' {
+                            LineEnding::Crlf
+                        } else {
+                            LineEnding::Lf
+                        });
+                    }
+                    continue;
+                }
+                buf[filled] = byte;
+                filled += 1;
+            }
+            self.inner.consume(used);
+        }
+        Ok(filled)
+    }
+}
+
+/// Bytes `value` takes as uncompressed, unencrypted text in `style`,
+/// counted by serializing into a sink rather than into memory.
+pub fn serialized_size(value: &Value, style: TextStyle) -> u64 {
+    struct Counter(u64);
+    impl Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len() as u64;
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = write_styled(&mut counter, value, style);
+    counter.0
+}
+
+/// What writing `value` with `encoding` to a given path puts in the file,
+/// ready to be written any number of times (once per retry).
+struct Encoded<'v> {
+    value: Cow<'v, Value>,
+    style: TextStyle,
+    compression: Option<Compression>,
+    /// The whole file, for contents assembled in memory: encrypted files,
+    /// as the cipher seals the whole file at once, and formats other than
+    /// JSON.
+    bytes: Option<Vec<u8>>,
+}
+
+impl<'v> Encoded<'v> {
+    fn new(path: &Path, value: &'v Value, encoding: FileEncoding) -> WorkspaceResult<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| WorkspaceError::io("create", parent, err))?;
+        }
+        let compression = is_compressed(path).then(|| {
+            encoding
+                .compression_level
+                .map_or_else(Compression::default, Compression::new)
+        });
+        let format = non_json_format(path);
+        // Interning is a JSON layout; other formats get the value as it is.
+        let value = match format {
+            Some(_) => Cow::Borrowed(value),
+            None => stored_form(value, encoding),
+        };
+        let plain = match format {
+            Some(format) => Some(format.serialize(&value, path)?),
+            None if encoding.passphrase.is_some() => {
+                let mut bytes = Vec::new();
+                encode_json(&mut bytes, &value, encoding.style, compression)
+                    .map_err(|err| write_error(path, err))?;
+                Some(bytes)
+            }
+            None => None,
+        };
+        let bytes = match (plain, encoding.passphrase) {
+            (Some(plain), Some(passphrase)) => Some(encrypt(&plain, passphrase, path)?),
+            (plain, _) => plain,
+        };
+        Ok(Self {
+            value,
+            style: encoding.style,
+            compression,
+            bytes,
+        })
+    }
+
+    fn write_to(&self, file: &mut impl Write) -> io::Result<()> {
+        match &self.bytes {
+            Some(bytes) => file.write_all(bytes),
+            None => encode_json(file, &self.value, self.style, self.compression),
+        }
+    }
+}
+
+fn retries_error(path: &Path, err: io::Error, attempts: u32) -> WorkspaceError {
+    let err = write_error(path, err);
+    if attempts > 1 {
+        WorkspaceError::RetriesExhausted {
+            attempts,
+            source: Box::new(err),
+        }
+    } else {
+        err
+    }
+}
+
+/// Writes `value` to `path` unless the file already holds it (see
+/// [`matches_on_disk`]), so an unchanged file keeps its modification time
+/// and sync clients have nothing to upload.
+pub fn write_json_file(path: &Path, value: &Value, encoding: FileEncoding) -> WorkspaceResult<()> {
+    if !encoding.force && matches_on_disk(path, value, encoding) {
+        return Ok(());
+    }
+    let encoded = Encoded::new(path, value, encoding)?;
+    retry(encoding.retry, thread::sleep, || {
+        write_atomic(path, |file| encoded.write_to(file))
+    })
+    .map_err(|(err, attempts)| retries_error(path, err, attempts))
+}
+
+/// A file written in full next to `path` but not yet moved over it, from
+/// [`stage_json_file`]. Dropping it without [`commit`](Self::commit)
+/// removes the temp file and leaves `path` as it was.
+pub struct StagedFile {
+    temp: TempFile,
+    dest: PathBuf,
+}
+
+impl StagedFile {
+    pub fn dest(&self) -> &Path {
+        &self.dest
+    }
+
+    /// Bytes the file will have once committed.
+    pub fn size(&self) -> u64 {
+        fs::metadata(&self.temp.path).map_or(0, |stat| stat.len())
+    }
+
+    /// Renames the staged file over its destination.
+    pub fn commit(self) -> io::Result<()> {
+        let dest = self.dest;
+        self.temp.persist(&dest)?;
+        if let Some(parent) = dest.parent() {
+            // Best effort: the rename itself has already succeeded.
+            let _ = sync_dir(parent);
+        }
+        Ok(())
+    }
+}
+
+/// The first half of [`write_json_file`]: writes and fsyncs what `value`
+/// becomes on disk into a temp file next to `path`, which stays untouched
+/// until the returned file is committed. Always writes, whatever `path`
+/// holds.
+pub fn stage_json_file(
+    path: &Path,
+    value: &Value,
+    encoding: FileEncoding,
+) -> WorkspaceResult<StagedFile> {
+    let encoded = Encoded::new(path, value, encoding)?;
+    retry(encoding.retry, thread::sleep, || {
+        let (temp, mut file) = TempFile::create(path)?;
+        encoded.write_to(&mut Timed(&mut file))?;
+        metrics::io(|| file.sync_all())?;
+        Ok(StagedFile {
+            temp,
+            dest: path.to_path_buf(),
+        })
+    })
+    .map_err(|(err, attempts)| retries_error(path, err, attempts))
+}
+
+/// [`stage_json_file`] for contents that are already bytes, such as the
+/// lines of a patch file.
+pub fn stage_bytes(path: &Path, bytes: &[u8], policy: RetryPolicy) -> WorkspaceResult<StagedFile> {
+    retry(policy, thread::sleep, || {
+        let (temp, mut file) = TempFile::create(path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        Ok(StagedFile {
+            temp,
+            dest: path.to_path_buf(),
+        })
+    })
+    .map_err(|(err, attempts)| retries_error(path, err, attempts))
+}
+
+/// serde_json hands its own failures through the writer as `io::Error`s;
+/// those are reported as `serialize` rather than `io` errors.
+fn write_error(path: &Path, err: io::Error) -> WorkspaceError {
+    match err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<serde_json::Error>())
+    {
+        Some(inner) if !inner.is_io() => WorkspaceError::Serialize {
+            path: path.display().to_string(),
+            message: inner.to_string(),
+        },
+        _ => WorkspaceError::io("write", path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Tracks live and peak heap bytes per thread, so a test can measure
+    /// its own allocations while others run in parallel.
+    struct CountingAlloc;
+
+    thread_local! {
+        static LIVE: Cell<usize> = const { Cell::new(0) };
+        static PEAK: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = LIVE.try_with(|live| {
+                live.set(live.get() + layout.size());
+                let _ = PEAK.try_with(|peak| peak.set(peak.get().max(live.get())));
+            });
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = LIVE.try_with(|live| live.set(live.get().saturating_sub(layout.size())));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    /// Peak heap growth on this thread while `run` executes.
+    fn peak_allocation(run: impl FnOnce()) -> usize {
+        let base = LIVE.with(Cell::get);
+        PEAK.with(|peak| peak.set(base));
+        run();
+        PEAK.with(Cell::get) - base
+    }
+
+    fn temp_files(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tmp"))
+            .collect()
+    }
+
+    #[test]
+    fn write_json_file_replaces_existing_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        fs::write(&path, "{}\n").unwrap();
+
+        write_json_file(&path, &json!({ "name": "after" }), FileEncoding::default()).unwrap();
+
+        assert_eq!(read_json_file(&path).unwrap(), json!({ "name": "after" }));
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn write_json_file_keeps_key_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let original = r#"{
+  "sheets": [
+    {
+      "rows": {
+        "10": {
+          "B": 2,
+          "A": 1
+        },
+        "2": {}
+      },
+      "id": "sheet-1"
+    }
+  ],
+  "book": {
+    "name": "z",
+    "id": "a"
+  }
+}
+"#;
+        fs::write(&path, original).unwrap();
+
+        let value = read_json_file(&path).unwrap();
+        write_json_file(&path, &value, FileEncoding::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn sort_keys_writes_the_same_file_for_any_key_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let encoding = FileEncoding {
+            style: WriteOptions {
+                sort_keys: true,
+                ..Default::default()
+            }
+            .style_for(None, false),
+            ..Default::default()
+        };
+        let one: Value =
+            serde_json::from_str(r#"{"b":{"y":1,"x":[{"d":1,"c":2},3,1]},"a":1,"B":2}"#).unwrap();
+        let other: Value =
+            serde_json::from_str(r#"{"B":2,"a":1,"b":{"x":[{"c":2,"d":1},3,1],"y":1}}"#).unwrap();
+        let write = |name: &str, value: &Value| {
+            let path = dir.path().join(name);
+            write_json_file(&path, value, encoding).unwrap();
+            fs::read_to_string(&path).unwrap()
+        };
+        let text = write("one.json", &one);
+        assert_eq!(text, write("other.json", &other));
+        assert_eq!(text, write("again.json", &one));
+        // Arrays keep their order; upper case sorts before lower case.
+        let minified: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(
+            serde_json::to_string(&minified).unwrap(),
+            r#"{"B":2,"a":1,"b":{"x":[{"c":2,"d":1},3,1],"y":1}}"#
+        );
+
+        // A file with the keys in payload order is not up to date.
+        let path = dir.path().join("unsorted.json");
+        write_json_file(&path, &one, FileEncoding::default()).unwrap();
+        assert!(!matches_on_disk(&path, &one, encoding));
+        assert!(matches_on_disk(
+            &dir.path().join("one.json"),
+            &other,
+            encoding
+        ));
+    }
+
+    #[test]
+    fn number_literals_round_trip_as_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let original = r#"{
+  "z": 0.30000000000000004,
+  "price": 1.50,
+  "id": 123456789012345678901234567890,
+  "small": 1e-7,
+  "cells": [
+    -0.0,
+    2e+300,
+    10
+  ]
+}
+"#;
+        fs::write(&path, original).unwrap();
+
+        let value = read_json_file(&path).unwrap();
+        assert_eq!(
+            value.as_object().unwrap().keys().collect::<Vec<_>>(),
+            ["z", "price", "id", "small", "cells"]
+        );
+        assert_eq!(value["cells"][2], 10);
+        write_json_file(&path, &value, FileEncoding::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(matches_on_disk(&path, &value, FileEncoding::default()));
+    }
+
+    #[test]
+    fn files_differing_only_in_layout_are_not_rewritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let value = json!({ "name": "a b", "rows": [1, 2] });
+        // Indented by hand with four spaces and no final newline.
+        let original = "{\n    \"name\": \"a b\",\n    \"rows\": [1, 2]\n}";
+        fs::write(&path, original).unwrap();
+
+        assert!(matches_on_disk(&path, &value, FileEncoding::default()));
+        write_json_file(&path, &value, FileEncoding::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        let plain = FileEncoding::default();
+        assert!(!matches_on_disk(
+            &path,
+            &json!({ "name": "a  b", "rows": [1, 2] }),
+            plain
+        ));
+        assert!(!matches_on_disk(
+            &path,
+            &json!({ "rows": [1, 2], "name": "a b" }),
+            plain
+        ));
+        let crlf = FileEncoding {
+            style: TextStyle {
+                line_ending: LineEnding::Crlf,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(!matches_on_disk(&path, &value, crlf));
+
+        let forced = FileEncoding {
+            force: true,
+            ..Default::default()
+        };
+        write_json_file(&path, &value, forced).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  \"name\": \"a b\",\n  \"rows\": [\n    1,\n    2\n  ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn large_files_are_written_without_an_in_memory_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let rows: serde_json::Map<String, Value> = (1..=20_000)
+            .map(|row| {
+                let cells = json!({ "A": { "value": format!("row {} of a large sheet", row) } });
+                (row.to_string(), cells)
+            })
+            .collect();
+        let value = json!({ "sheets": [{ "rows": rows }] });
+        let encoding = FileEncoding {
+            style: TextStyle {
+                line_ending: LineEnding::Crlf,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let peak = peak_allocation(|| write_json_file(&path, &value, encoding).unwrap());
+        let size = fs::metadata(&path).unwrap().len() as usize;
+        assert!(size > 1_000_000);
+        assert!(peak < size / 20, "peak {} for a {} byte file", peak, size);
+        let (read_back, style) = read_json_file_styled(&path, None).unwrap();
+        assert_eq!(read_back, value);
+        assert_eq!(style, encoding.style);
+    }
+
+    #[test]
+    fn line_breaks_follow_the_requested_style() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json.gz");
+        let crlf = TextStyle {
+            line_ending: LineEnding::Crlf,
+            final_newline: false,
+            bom: false,
+            minified: false,
+            sort_keys: false,
+        };
+        let encoding = FileEncoding {
+            style: crlf,
+            ..Default::default()
+        };
+        write_json_file(&path, &json!({ "a": "x\ny" }), encoding).unwrap();
+
+        let (value, style) = read_json_file_styled(&path, None).unwrap();
+        assert_eq!(value, json!({ "a": "x\ny" }));
+        assert_eq!(style, crlf);
+
+        let preserve = WriteOptions {
+            line_ending: LineEndingMode::Preserve,
+            ..Default::default()
+        };
+        assert_eq!(preserve.style_for(Some(crlf), false), crlf);
+        assert_eq!(preserve.style_for(None, false), TextStyle::default());
+        let trailing = WriteOptions {
+            final_newline: Some(true),
+            ..preserve
+        };
+        assert!(trailing.style_for(Some(crlf), false).final_newline);
+        assert_eq!(
+            TextStyle::detect(b"{}"),
+            TextStyle {
+                line_ending: LineEnding::Lf,
+                final_newline: false,
+                bom: false,
+                minified: true,
+                sort_keys: false,
+            }
+        );
+    }
+
+    #[test]
+    fn pretty_and_minified_files_read_back_alike() {
+        let dir = tempfile::tempdir().unwrap();
+        let value = json!({ "book": { "name": "a\nb" }, "sheets": [1, [], {}] });
+        let minify = WriteOptions {
+            pretty: PrettyOptions {
+                books: Some(false),
+                ..Default::default()
+            },
+            final_newline: Some(false),
+            ..Default::default()
+        };
+        let preserve = WriteOptions {
+            line_ending: LineEndingMode::Preserve,
+            ..Default::default()
+        };
+        for (name, style) in [
+            ("pretty.json", minify.style_for(None, true)),
+            ("minified.json", minify.style_for(None, false)),
+            (
+                "minified.json.gz",
+                TextStyle {
+                    line_ending: LineEnding::Crlf,
+                    ..minify.style_for(None, false)
+                },
+            ),
+        ] {
+            let path = dir.path().join(name);
+            let encoding = FileEncoding {
+                style,
+                ..Default::default()
+            };
+            write_json_file(&path, &value, encoding).unwrap();
+            let (read_back, loaded) = read_json_file_styled(&path, None).unwrap();
+            assert_eq!(read_back, value, "{}", name);
+            assert_eq!(loaded.minified, style.minified, "{}", name);
+            assert_eq!(
+                preserve.style_for(Some(loaded), false).minified,
+                style.minified
+            );
+        }
+        let minified = fs::read_to_string(dir.path().join("minified.json")).unwrap();
+        assert_eq!(minified, r#"{"book":{"name":"a\nb"},"sheets":[1,[],{}]}"#);
+        let pretty = fs::read_to_string(dir.path().join("pretty.json")).unwrap();
+        assert!(pretty.starts_with("{\n  \"book\"") && !pretty.ends_with('\n'));
+    }
+
+    #[test]
+    fn byte_order_marks_are_understood() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        fs::write(&path, b"\xEF\xBB\xBF{\"name\": \"\xE8\xA1\xA8\"}\n").unwrap();
+
+        let (value, style) = read_json_file_styled(&path, None).unwrap();
+        assert_eq!(value, json!({ "name": "表" }));
+        assert!(style.bom);
+        let encoding = FileEncoding {
+            style,
+            ..Default::default()
+        };
+        write_json_file(&path, &value, encoding).unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(UTF8_BOM));
+        write_json_file(&path, &value, FileEncoding::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap()[0], b'{');
+
+        for (encoding, bom) in [
+            (encoding_rs::UTF_16LE, b"\xFF\xFE"),
+            (encoding_rs::UTF_16BE, b"\xFE\xFF"),
+        ] {
+            let units: Vec<u16> = "{\"name\": \"表\"}".encode_utf16().collect();
+            let mut bytes = bom.to_vec();
+            for unit in units {
+                let pair = if encoding == encoding_rs::UTF_16LE {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                };
+                bytes.extend_from_slice(&pair);
+            }
+            fs::write(&path, &bytes).unwrap();
+            assert_eq!(read_json_file(&path).unwrap(), json!({ "name": "表" }));
+        }
+
+        fs::write(&path, b"\xFF\xFE\x00\xD8").unwrap();
+        assert!(matches!(
+            read_json_file(&path),
+            Err(WorkspaceError::Encoding {
+                encoding: "UTF-16LE",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn legacy_japanese_encodings_are_detected_or_taken_as_given() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let text = "{\"name\": \"見積書\", \"note\": \"ひらがなとカタカナ\"}";
+        let expected = json!({ "name": "見積書", "note": "ひらがなとカタカナ" });
+        for encoding in [encoding_rs::SHIFT_JIS, encoding_rs::EUC_JP] {
+            let (bytes, _, _) = encoding.encode(text);
+            fs::write(&path, &bytes).unwrap();
+            let parsed = read_json_file_decoded(&path, None, None).unwrap();
+            assert_eq!(parsed.value, expected);
+            assert_eq!(parsed.encoding, encoding);
+            assert!(parsed.converted());
+        }
+
+        let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode("{\"name\": \"ｱｲｳ\"}");
+        fs::write(&path, &bytes).unwrap();
+        let parsed = read_json_file_decoded(&path, None, Some(TextEncoding::ShiftJis)).unwrap();
+        assert_eq!(parsed.value, json!({ "name": "ｱｲｳ" }));
+        assert!(matches!(
+            read_json_file_decoded(&path, None, Some(TextEncoding::Utf8)),
+            Err(WorkspaceError::ParseError { .. })
+        ));
+
+        fs::write(&path, text).unwrap();
+        assert!(!read_json_file_decoded(&path, None, None)
+            .unwrap()
+            .converted());
+        // Broken JSON in UTF-8 keeps its parse error.
+        fs::write(&path, "{\"name\": }").unwrap();
+        assert!(matches!(
+            read_json_file(&path),
+            Err(WorkspaceError::ParseError { .. })
+        ));
+        fs::write(&path, b"{\"name\": \"\x80\xFF\"}").unwrap();
+        assert!(matches!(
+            read_json_file(&path),
+            Err(WorkspaceError::InvalidEncoding { .. })
+        ));
+    }
+
+    #[test]
+    fn transient_failures_are_retried_with_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            initial_delay: Duration::from_millis(10),
+        };
+        let busy = || io::Error::from(io::ErrorKind::ResourceBusy);
+
+        let mut sleeps = Vec::new();
+        let mut failures = 2;
+        let result = retry(
+            policy,
+            |delay| sleeps.push(delay.as_millis()),
+            || {
+                if failures == 0 {
+                    return Ok("written");
+                }
+                failures -= 1;
+                Err(busy())
+            },
+        );
+        assert_eq!(result.unwrap(), "written");
+        assert_eq!(sleeps, vec![10, 20]);
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry(
+            policy,
+            |_| {},
+            || {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            },
+        );
+        assert_eq!(result.unwrap_err().1, 1);
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry(
+            policy,
+            |_| {},
+            || {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            },
+        );
+        let expected = if cfg!(windows) { 4 } else { 1 };
+        assert_eq!(result.unwrap_err().1, expected);
+        assert_eq!(calls, expected);
+
+        let result: Result<(), _> = retry(policy, |_| {}, || Err(busy()));
+        let (err, attempts) = result.unwrap_err();
+        assert_eq!((err.kind(), attempts), (io::ErrorKind::ResourceBusy, 4));
+    }
+
+    #[test]
+    fn interrupted_write_keeps_original_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        fs::write(&path, "{\"name\":\"before\"}\n").unwrap();
+
+        let result = write_atomic(&path, |file| {
+            file.write_all(b"{\"name\":")?;
+            Err(io::Error::other("interrupted"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(read_json_file(&path).unwrap(), json!({ "name": "before" }));
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn temp_names_are_unique_and_concurrent_writes_do_not_collide() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let first = temp_path_for(&path);
+        assert_ne!(first, temp_path_for(&path));
+        assert_eq!(first.parent(), Some(dir.path()));
+        let name = first.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with(".book.json."));
+        assert!(name.contains(&format!(".{}.", process::id())));
+
+        thread::scope(|scope| {
+            for writer in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for round in 0..20 {
+                        let value = json!({ "writer": writer, "round": round });
+                        write_json_file(path, &value, FileEncoding::default()).unwrap();
+                    }
+                });
+            }
+        });
+
+        let last = read_json_file(&path).unwrap();
+        assert_eq!(last["round"], 19);
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn failed_writes_remove_their_temp_file_even_on_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let panicked = std::panic::catch_unwind(|| {
+            let _ = write_atomic(&path, |file| {
+                file.write_all(b"{")?;
+                panic!("writer failed");
+            });
+        });
+        assert!(panicked.is_err());
+        assert!(!path.exists());
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn gz_paths_round_trip_compressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json.gz");
+        let value = json!({ "rows": vec!["same"; 200] });
+
+        write_json_file(
+            &path,
+            &value,
+            FileEncoding {
+                compression_level: Some(9),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[..2], &[0x1f, 0x8b]);
+        assert!(bytes.len() < serde_json::to_vec(&value).unwrap().len());
+        assert_eq!(read_json_file(&path).unwrap(), value);
+    }
+
+    #[test]
+    fn read_json_file_reports_error_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.json");
+        assert!(matches!(
+            read_json_file(&missing),
+            Err(WorkspaceError::NotFound { .. })
+        ));
+
+        let broken = dir.path().join("broken.json");
+        fs::write(&broken, "{\n  \"a\": ,\n}").unwrap();
+        match read_json_file(&broken) {
+            Err(WorkspaceError::ParseError {
+                line,
+                column,
+                file_kind,
+                ..
+            }) => {
+                assert_eq!((line, column), (2, 8));
+                assert_eq!(file_kind, FileKind::Other);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // Loads tell which kind of file broke.
+        let err = super::super::load_book(&broken, None, None).unwrap_err();
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            json!({
+                "code": "parseError",
+                "path": broken.display().to_string(),
+                "line": 2,
+                "column": 8,
+                "fileKind": "book",
+                "message": err.to_string()
+            })
+        );
+        assert!(matches!(
+            read_workspace_json(&broken),
+            Err(WorkspaceError::ParseError {
+                file_kind: FileKind::Workspace,
+                ..
+            })
+        ));
+
+        // Streamed gzip contents report positions in the decompressed text
+        // and keep the path in the message.
+        let broken_gz = dir.path().join("broken.json.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{\n  \"a\": 1\n  \"b\": 2\n}").unwrap();
+        fs::write(&broken_gz, encoder.finish().unwrap()).unwrap();
+        let err = read_json_file(&broken_gz).unwrap_err();
+        assert!(matches!(
+            err,
+            WorkspaceError::ParseError {
+                line: 3,
+                column: 3,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains(&broken_gz.display().to_string()));
+
+        fs::write(&broken_gz, b"\x1f\x8b not gzip").unwrap();
+        assert!(matches!(
+            read_json_file(&broken_gz),
+            Err(WorkspaceError::Io {
+                action: "decompress",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn matches_on_disk_compares_decoded_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let value = json!({ "name": "same", "rows": [1, 2, 3] });
+        let gz = dir.path().join("book.json.gz");
+        let fast = FileEncoding {
+            compression_level: Some(1),
+            ..Default::default()
+        };
+        write_json_file(&gz, &value, fast).unwrap();
+
+        assert!(matches_on_disk(&gz, &value, FileEncoding::default()));
+        assert!(!matches_on_disk(&gz, &json!({ "name": "same" }), fast));
+        let crlf = TextStyle {
+            line_ending: LineEnding::Crlf,
+            ..Default::default()
+        };
+        let styled = FileEncoding {
+            style: crlf,
+            ..Default::default()
+        };
+        assert!(!matches_on_disk(&gz, &value, styled));
+        let encrypted = FileEncoding {
+            passphrase: Some("secret"),
+            ..Default::default()
+        };
+        assert!(!matches_on_disk(&gz, &value, encrypted));
+        assert!(!matches_on_disk(
+            &dir.path().join("missing.json"),
+            &value,
+            fast
+        ));
+    }
+}
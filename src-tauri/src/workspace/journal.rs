@@ -0,0 +1,188 @@
+//! `.sheet-up-journal.jsonl` records edits made since the last save, one JSON
+//! object per line, so they can be offered back after a crash. A successful
+//! `save_workspace_snapshot` of every book clears it. Recovery only reports what was recorded; applying it is
+//! left to the user.
+
+use super::books::now_rfc3339;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::paths::workspace_dir_of;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+pub const JOURNAL_FILE: &str = ".sheet-up-journal.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub recorded_at: String,
+    /// Whatever the frontend recorded, e.g. one cell edit.
+    pub entry: Value,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalRecovery {
+    pub journal_path: String,
+    /// Readable entries in the order they were recorded; empty when there is
+    /// nothing to recover.
+    pub entries: Vec<JournalEntry>,
+    /// Bytes dropped from the end of the journal because they could not be
+    /// read, such as a line cut short by the crash.
+    pub discarded_bytes: u64,
+}
+
+fn journal_path_of(workspace_path: &Path) -> PathBuf {
+    workspace_dir_of(workspace_path).join(JOURNAL_FILE)
+}
+
+/// Appends one entry and syncs it to disk before returning, so an entry the
+/// frontend was told about survives a crash right after.
+#[tauri::command]
+pub fn append_journal_entry(workspace_path: String, entry: Value) -> WorkspaceResult<()> {
+    let path = journal_path_of(Path::new(&workspace_path));
+    let record = JournalEntry {
+        recorded_at: now_rfc3339(),
+        entry,
+    };
+    // One `write_all` per line so that appends never interleave mid-line.
+    let mut line = serde_json::to_vec(&record).map_err(|err| WorkspaceError::Serialize {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    })?;
+    line.push(b'\n');
+    let appended = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            file.write_all(&line)?;
+            file.sync_data()
+        });
+    appended.map_err(|err| WorkspaceError::io("write", &path, err))
+}
+
+/// Reads entries up to the first line that does not parse. That line and
+/// everything after it is cut off the journal, so later appends continue
+/// from the readable part. Book and workspace files are never touched.
+#[tauri::command]
+pub fn recover_from_journal(workspace_path: String) -> WorkspaceResult<JournalRecovery> {
+    let path = journal_path_of(Path::new(&workspace_path));
+    let mut recovery = JournalRecovery {
+        journal_path: path.to_string_lossy().into_owned(),
+        ..Default::default()
+    };
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(recovery),
+        Err(err) => return Err(WorkspaceError::io("read", &path, err)),
+    };
+    let total = file
+        .metadata()
+        .map_err(|err| WorkspaceError::io("stat", &path, err))?
+        .len();
+
+    let mut reader = io::BufReader::new(file);
+    let mut line = Vec::new();
+    let mut readable = 0u64;
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .map_err(|err| WorkspaceError::io("read", &path, err))?;
+        // A line without its newline was still being written.
+        if read == 0 || line.last() != Some(&b'\n') {
+            break;
+        }
+        match serde_json::from_slice::<JournalEntry>(&line) {
+            Ok(entry) => recovery.entries.push(entry),
+            Err(_) => break,
+        }
+        readable += read as u64;
+    }
+
+    recovery.discarded_bytes = total - readable;
+    if recovery.discarded_bytes > 0 {
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(readable))
+            .map_err(|err| WorkspaceError::io("truncate", &path, err))?;
+    }
+    Ok(recovery)
+}
+
+/// Throws the journal away, e.g. when the user declines to recover it.
+#[tauri::command]
+pub fn discard_journal(workspace_path: String) -> WorkspaceResult<()> {
+    clear(&workspace_dir_of(Path::new(&workspace_path)))
+}
+
+/// Called once a save has written everything the journal recorded.
+pub(super) fn clear(workspace_dir: &Path) -> WorkspaceResult<()> {
+    let path = workspace_dir.join(JOURNAL_FILE);
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(WorkspaceError::io("remove", &path, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use crate::workspace::{load_workspace_snapshot, save_snapshot_clearing_journal};
+    use serde_json::json;
+
+    #[test]
+    fn recovers_readable_entries_and_cuts_off_a_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let workspace = workspace_path.to_string_lossy().into_owned();
+        let journal = dir.path().join(JOURNAL_FILE);
+
+        assert!(recover_from_journal(workspace.clone())
+            .unwrap()
+            .entries
+            .is_empty());
+        for cell in ["A1", "B2"] {
+            append_journal_entry(workspace.clone(), json!({ "cell": cell })).unwrap();
+        }
+        let intact = fs::metadata(&journal).unwrap().len();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&journal)
+            .unwrap()
+            .write_all(b"{\"recordedAt\":\"2024-")
+            .unwrap();
+
+        let recovery = recover_from_journal(workspace.clone()).unwrap();
+        let cells: Vec<_> = recovery.entries.iter().map(|e| &e.entry["cell"]).collect();
+        assert_eq!(cells, [&json!("A1"), &json!("B2")]);
+        assert_eq!(recovery.discarded_bytes, 20);
+        assert_eq!(fs::metadata(&journal).unwrap().len(), intact);
+
+        append_journal_entry(workspace.clone(), json!({ "cell": "C3" })).unwrap();
+        assert_eq!(
+            recover_from_journal(workspace.clone())
+                .unwrap()
+                .entries
+                .len(),
+            3
+        );
+
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "workspace": {}, "books": [] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let mut snapshot = load_workspace_snapshot(workspace.clone(), None).unwrap();
+        snapshot.workspace.data["workspace"]["name"] = json!("Saved");
+        save_snapshot_clearing_journal(snapshot, None, None).unwrap();
+        assert!(!journal.exists());
+    }
+}
@@ -0,0 +1,241 @@
+//! `validate_workspace_against_schema`: checks a `workspace.json` or book
+//! file against the JSON Schemas the frontend validates with, which are
+//! compiled into the binary. Unlike the checks `schema.rs` runs on every
+//! load and save, this reports every violation with its location.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_json_file, read_workspace_json};
+use jsonschema::error::{TypeKind, ValidationErrorKind};
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::LazyLock;
+
+const WORKSPACE_SCHEMA: &str = include_str!("../../../src/schemas/workspace.schema.json");
+const BOOK_SCHEMA: &str = include_str!("../../../src/schemas/book.schema.json");
+
+/// The key under which a schema records the `schemaVersion` it describes.
+const SCHEMA_VERSION_KEY: &str = "x-schemaVersion";
+
+struct CompiledSchema {
+    validator: Validator,
+    version: Option<String>,
+}
+
+fn compile(source: &str) -> CompiledSchema {
+    let schema: Value = serde_json::from_str(source).expect("embedded schema is valid JSON");
+    let validator = jsonschema::options()
+        .should_validate_formats(true)
+        .build(&schema)
+        .expect("embedded schema compiles");
+    CompiledSchema {
+        validator,
+        version: schema[SCHEMA_VERSION_KEY].as_str().map(str::to_string),
+    }
+}
+
+static WORKSPACE: LazyLock<CompiledSchema> = LazyLock::new(|| compile(WORKSPACE_SCHEMA));
+static BOOK: LazyLock<CompiledSchema> = LazyLock::new(|| compile(BOOK_SCHEMA));
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SchemaKind {
+    #[default]
+    Workspace,
+    Book,
+}
+
+impl SchemaKind {
+    fn schema(self) -> &'static CompiledSchema {
+        match self {
+            Self::Workspace => &WORKSPACE,
+            Self::Book => &BOOK,
+        }
+    }
+
+    fn read(self, path: &Path) -> WorkspaceResult<Value> {
+        match self {
+            Self::Workspace => read_workspace_json(path),
+            Self::Book => read_json_file(path),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaIssue {
+    /// JSON Pointer to the offending value, `""` for the document itself.
+    pub pointer: String,
+    /// JSON Pointer to the schema keyword that failed.
+    pub keyword: String,
+    pub message: String,
+    /// The allowed JSON types, for type mismatches.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expected: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaValidation {
+    pub kind: SchemaKind,
+    pub valid: bool,
+    /// The version the embedded schema describes.
+    pub schema_version: Option<String>,
+    pub issues: Vec<SchemaIssue>,
+}
+
+fn expected_types(kind: &ValidationErrorKind) -> Vec<String> {
+    match kind {
+        ValidationErrorKind::Type {
+            kind: TypeKind::Single(json_type),
+        } => vec![json_type.to_string()],
+        ValidationErrorKind::Type {
+            kind: TypeKind::Multiple(json_types),
+        } => json_types
+            .iter()
+            .map(|json_type| json_type.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn validate_value(kind: SchemaKind, value: &Value) -> SchemaValidation {
+    let schema = kind.schema();
+    let mut issues: Vec<SchemaIssue> = schema
+        .validator
+        .iter_errors(value)
+        .map(|err| SchemaIssue {
+            pointer: err.instance_path.as_str().to_string(),
+            keyword: err.schema_path.as_str().to_string(),
+            message: err.to_string(),
+            expected: expected_types(&err.kind),
+        })
+        .collect();
+    // A document of another version may pass by accident, or fail for
+    // reasons a migration would fix; either way the result is misleading.
+    if let (Some(expected), Some(actual)) = (&schema.version, value["schemaVersion"].as_str()) {
+        if expected != actual {
+            issues.push(SchemaIssue {
+                pointer: "/schemaVersion".into(),
+                keyword: format!("/{}", SCHEMA_VERSION_KEY),
+                message: format!(
+                    "schemaVersion {:?} does not match the schema version {:?}",
+                    actual, expected
+                ),
+                expected: Vec::new(),
+            });
+        }
+    }
+    SchemaValidation {
+        kind,
+        valid: issues.is_empty(),
+        schema_version: schema.version.clone(),
+        issues,
+    }
+}
+
+/// Validates the file at `path` against the embedded JSON Schema for
+/// `kind` (`workspace` when omitted) and lists every violation. A file
+/// that cannot be read or parsed is an error rather than an issue.
+#[tauri::command]
+pub fn validate_workspace_against_schema(
+    path: String,
+    kind: Option<SchemaKind>,
+) -> WorkspaceResult<SchemaValidation> {
+    let kind = kind.unwrap_or_default();
+    let value = kind.read(Path::new(&path))?;
+    if !value.is_object() {
+        return Err(WorkspaceError::InvalidOption {
+            name: "path",
+            message: format!("{} does not contain a JSON object", path),
+        });
+    }
+    Ok(validate_value(kind, &value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use crate::workspace::migrate::CURRENT_SCHEMA_VERSION;
+    use serde_json::json;
+
+    const NOW: &str = "2024-01-01T00:00:00Z";
+
+    fn workspace() -> Value {
+        json!({
+            "schemaVersion": CURRENT_SCHEMA_VERSION,
+            "workspace": { "id": "ws-1", "name": "Workspace", "createdAt": NOW },
+            "folders": [],
+            "books": [{
+                "id": "book-1",
+                "name": "Book",
+                "dataPath": "books/book-1.json",
+                "order": 0,
+                "createdAt": NOW,
+                "updatedAt": NOW
+            }]
+        })
+    }
+
+    fn write(dir: &Path, name: &str, value: &Value) -> String {
+        let path = dir.join(name);
+        write_json_file(&path, value, FileEncoding::default()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn embedded_schemas_describe_the_current_version() {
+        for kind in [SchemaKind::Workspace, SchemaKind::Book] {
+            assert_eq!(
+                kind.schema().version.as_deref(),
+                Some(CURRENT_SCHEMA_VERSION)
+            );
+        }
+    }
+
+    #[test]
+    fn reports_pointers_and_expected_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let valid = write(dir.path(), "workspace.json", &workspace());
+        let result = validate_workspace_against_schema(valid, None).unwrap();
+        assert!(result.valid, "{:?}", result.issues);
+
+        let mut broken = workspace();
+        broken["books"][0]["order"] = json!("first");
+        broken["workspace"]["createdAt"] = json!("yesterday");
+        broken["schemaVersion"] = json!("0.9.0");
+        let path = write(dir.path(), "broken.json", &broken);
+        let result = validate_workspace_against_schema(path, None).unwrap();
+        assert!(!result.valid);
+        let issue = |pointer: &str| {
+            result
+                .issues
+                .iter()
+                .find(|issue| issue.pointer == pointer)
+                .unwrap_or_else(|| panic!("no issue at {}: {:?}", pointer, result.issues))
+        };
+        assert!(issue("/books/0/order")
+            .expected
+            .iter()
+            .any(|expected| expected == "integer" || expected == "number"));
+        issue("/workspace/createdAt");
+        assert!(issue("/schemaVersion").message.contains("0.9.0"));
+    }
+
+    #[test]
+    fn book_kind_uses_the_book_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = json!({
+            "schemaVersion": CURRENT_SCHEMA_VERSION,
+            "book": { "id": "book-1", "name": "Book", "createdAt": NOW },
+            "sheets": [{ "id": "sheet-1", "name": "Sheet", "gridSize": { "rows": 1, "cols": 1 } }]
+        });
+        let path = write(dir.path(), "book.json", &book);
+        let result = validate_workspace_against_schema(path, Some(SchemaKind::Book)).unwrap();
+        assert_eq!(result.kind, SchemaKind::Book);
+        assert_eq!(result.issues.len(), 1);
+        assert_eq!(result.issues[0].pointer, "/sheets/0");
+    }
+}
@@ -0,0 +1,113 @@
+//! JSON with comments, as accepted in hand-edited `workspace.json` files:
+//! `//` line comments, `/* */` block comments and trailing commas.
+
+/// Turns JSONC into plain JSON. Comments are blanked out byte for byte and
+/// trailing commas replaced by a space, keeping every other byte where it
+/// was so parse errors point at the right line and column. Strings are
+/// copied verbatim, so `"http://…"` survives. An unterminated block comment
+/// is left in place for the parser to reject.
+pub fn strip(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    // Index in `out` of a comma that is trailing if a `}` or `]` comes next.
+    let mut pending_comma = None;
+    let mut index = 0;
+    while index < text.len() {
+        let byte = text[index];
+        match (byte, text.get(index + 1)) {
+            (b'"', _) => {
+                pending_comma = None;
+                let end = string_end(text, index);
+                out.extend_from_slice(&text[index..end]);
+                index = end;
+                continue;
+            }
+            (b'/', Some(b'/')) => {
+                let end = text[index..]
+                    .iter()
+                    .position(|&byte| byte == b'\n')
+                    .map_or(text.len(), |offset| index + offset);
+                out.resize(out.len() + end - index, b' ');
+                index = end;
+                continue;
+            }
+            (b'/', Some(b'*')) => {
+                let Some(offset) = text[index + 2..].windows(2).position(|pair| pair == b"*/")
+                else {
+                    out.extend_from_slice(&text[index..]);
+                    break;
+                };
+                let end = index + 2 + offset + 2;
+                out.extend(
+                    text[index..end]
+                        .iter()
+                        .map(|&byte| if byte == b'\n' { b'\n' } else { b' ' }),
+                );
+                index = end;
+                continue;
+            }
+            (b',', _) => pending_comma = Some(out.len()),
+            (b'}' | b']', _) => {
+                if let Some(comma) = pending_comma.take() {
+                    out[comma] = b' ';
+                }
+            }
+            (b' ' | b'\t' | b'\r' | b'\n', _) => {}
+            _ => pending_comma = None,
+        }
+        out.push(byte);
+        index += 1;
+    }
+    out
+}
+
+/// Index just past the string literal opening at `start`, or the end of
+/// `text` when it is never closed.
+fn string_end(text: &[u8], start: usize) -> usize {
+    let mut index = start + 1;
+    while index < text.len() {
+        match text[index] {
+            b'\\' => index += 2,
+            b'"' => return index + 1,
+            _ => index += 1,
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(text: &str) -> serde_json::Result<Value> {
+        serde_json::from_slice(&strip(text.as_bytes()))
+    }
+
+    #[test]
+    fn drops_comments_and_trailing_commas_but_not_string_contents() {
+        let text = r#"{
+  // line comment
+  "url": "http://example.com/*not a comment*/", /* block
+     spanning lines */ "quote": "a \"// b\" c",
+  "books": [1, 2, /* last */ ],
+}
+"#;
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "url": "http://example.com/*not a comment*/",
+                "quote": "a \"// b\" c",
+                "books": [1, 2]
+            })
+        );
+        assert_eq!(strip(text.as_bytes()).len(), text.len());
+    }
+
+    #[test]
+    fn keeps_error_positions_and_rejects_unclosed_comments() {
+        let err = parse("{\n  // note\n  \"a\": 1\n  \"b\": 2\n}").unwrap_err();
+        assert_eq!((err.line(), err.column()), (4, 3));
+        assert!(parse("{ \"a\": 1 } /* open").is_err());
+        assert!(parse("[1,,]").is_err());
+    }
+}
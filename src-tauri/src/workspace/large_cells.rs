@@ -0,0 +1,141 @@
+//! Finds cells whose text is large enough to bloat the book file and slow
+//! the grid down, typically an accidental paste. Loads and saves report
+//! them without failing, and only cut them short when asked to.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Cell values above this many bytes are reported unless configured.
+pub const DEFAULT_THRESHOLD_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LargeCellOptions {
+    /// UTF-8 length above which a cell value is reported; 1 MiB when
+    /// omitted.
+    pub threshold_bytes: Option<usize>,
+    /// Cut reported values down to the threshold. The text beyond it is
+    /// lost once the book is saved.
+    pub truncate: bool,
+}
+
+impl LargeCellOptions {
+    pub fn threshold(self) -> WorkspaceResult<usize> {
+        match self.threshold_bytes {
+            Some(0) => Err(WorkspaceError::InvalidOption {
+                name: "largeCells.thresholdBytes",
+                message: "must be greater than 0".into(),
+            }),
+            threshold => Ok(threshold.unwrap_or(DEFAULT_THRESHOLD_BYTES)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeCell {
+    pub book_id: String,
+    pub sheet_id: String,
+    /// A1-style address such as `"B3"`.
+    pub cell: String,
+    /// UTF-8 length of the value as found.
+    pub size: usize,
+    /// The value was cut down to the threshold.
+    pub truncated: bool,
+}
+
+/// Shortens `text` to at most `limit` bytes without splitting a character.
+fn truncate_at(text: &mut String, limit: usize) {
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+}
+
+/// Lists the cells of `book` whose string value exceeds `threshold` bytes,
+/// truncating them when `truncate` is set. Only the cell values are
+/// looked at, so this is a length check per cell and nothing more.
+pub fn scan(book: &mut Value, threshold: usize, truncate: bool) -> Vec<LargeCell> {
+    let book_id = book["book"]["id"].as_str().unwrap_or_default().to_string();
+    let mut found = Vec::new();
+    let sheets = book["sheets"].as_array_mut().into_iter().flatten();
+    for sheet in sheets {
+        let sheet_id = sheet["id"].as_str().unwrap_or_default().to_string();
+        let rows = sheet["rows"].as_object_mut().into_iter().flatten();
+        for (row, cells) in rows {
+            let cells = cells.as_object_mut().into_iter().flatten();
+            for (column, cell) in cells {
+                let Some(Value::String(text)) = cell.get_mut("value") else {
+                    continue;
+                };
+                if text.len() <= threshold {
+                    continue;
+                }
+                found.push(LargeCell {
+                    book_id: book_id.clone(),
+                    sheet_id: sheet_id.clone(),
+                    cell: format!("{}{}", column, row),
+                    size: text.len(),
+                    truncated: truncate,
+                });
+                if truncate {
+                    truncate_at(text, threshold);
+                }
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn book() -> Value {
+        json!({
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "rows": {
+                    "1": { "A": { "value": "short" }, "B": { "value": "あいうえ" } },
+                    "2": { "C": { "value": 123456789 } }
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn reports_values_over_the_threshold() {
+        let mut book = book();
+        let found = scan(&mut book, 10, false);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].book_id, "book-1");
+        assert_eq!(found[0].sheet_id, "sheet-1");
+        assert_eq!(found[0].cell, "B1");
+        assert_eq!(found[0].size, 12);
+        assert!(!found[0].truncated);
+        assert_eq!(book["sheets"][0]["rows"]["1"]["B"]["value"], "あいうえ");
+    }
+
+    #[test]
+    fn truncates_on_a_character_boundary_only_when_asked() {
+        let mut book = book();
+        let found = scan(&mut book, 7, true);
+        assert!(found[0].truncated);
+        assert_eq!(book["sheets"][0]["rows"]["1"]["B"]["value"], "あい");
+        assert!(scan(&mut book, 7, true).is_empty());
+
+        let zero = LargeCellOptions {
+            threshold_bytes: Some(0),
+            truncate: false,
+        };
+        assert!(zero.threshold().is_err());
+        assert_eq!(
+            LargeCellOptions::default().threshold().unwrap(),
+            DEFAULT_THRESHOLD_BYTES
+        );
+    }
+}
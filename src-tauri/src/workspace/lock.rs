@@ -0,0 +1,307 @@
+//! `.sheet-up.lock` in the workspace directory keeps two app instances from
+//! saving over each other. The lock records its owner's pid, so one left
+//! behind by a crash is recognised as stale and taken over. The owner is
+//! written to a temp file that is then linked into place, so the lock never
+//! exists half-written. It is advisory: nothing stops a process that never
+//! asks for it.
+
+use super::books::now_rfc3339;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::paths::workspace_dir_of;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{LazyLock, Mutex};
+
+pub const LOCK_FILE: &str = ".sheet-up.lock";
+
+/// A stale lock can vanish or be replaced between reading and removing it;
+/// give up after this many rounds rather than spin.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Contents of the lock file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LockOwner {
+    pid: u32,
+    acquired_at: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceLock {
+    pub lock_path: String,
+    pub pid: u32,
+    pub acquired_at: String,
+    /// Pid recorded in a stale lock that was taken over.
+    pub stale_pid: Option<u32>,
+}
+
+/// Lock files this process holds, released when the app exits.
+static HELD: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(Default::default);
+
+fn held() -> std::sync::MutexGuard<'static, HashSet<PathBuf>> {
+    HELD.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn lock_path_of(workspace_path: &Path) -> PathBuf {
+    workspace_dir_of(workspace_path).join(LOCK_FILE)
+}
+
+/// `None` for a missing lock and for one that cannot be parsed, which only
+/// damage or an older version of the app can leave behind.
+fn read_owner(lock_path: &Path) -> Option<LockOwner> {
+    let bytes = fs::read(lock_path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// A fresh name next to the lock, for a file that becomes or was the lock.
+fn sibling(lock_path: &Path, suffix: &str) -> PathBuf {
+    lock_path.with_file_name(format!("{}.{}.{}", LOCK_FILE, uuid::Uuid::new_v4(), suffix))
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 only checks for the process. EPERM means it exists but
+    // belongs to another user.
+    // SAFETY: `kill` with signal 0 sends nothing and touches no memory.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_ACCESS_DENIED, STILL_ACTIVE,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    // SAFETY: the handle is checked before use and closed exactly once.
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code = 0;
+        let queried = GetExitCodeProcess(handle, &mut code) != 0;
+        CloseHandle(handle);
+        !queried || code == STILL_ACTIVE as u32
+    }
+}
+
+/// Without a way to ask, every recorded owner is assumed to be alive.
+#[cfg(not(any(unix, windows)))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Fails with `AlreadyExists` when another lock is in place. Linking fails
+/// rather than replace an existing file, and the linked file is already
+/// complete.
+fn create_lock(lock_path: &Path) -> io::Result<LockOwner> {
+    let owner = LockOwner {
+        pid: process::id(),
+        acquired_at: now_rfc3339(),
+    };
+    let temp = sibling(lock_path, "tmp");
+    let created = fs::File::create_new(&temp)
+        .and_then(|mut file| {
+            serde_json::to_writer(&mut file, &owner).map_err(io::Error::from)?;
+            file.flush()?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::hard_link(&temp, lock_path));
+    let _ = fs::remove_file(&temp);
+    created.map(|()| owner)
+}
+
+/// Removes the lock that read as `seen` and was judged stale. It is moved
+/// aside first and deleted only if it still reads the same; a fresh lock
+/// another process put in its place in the meantime is put back.
+fn remove_stale(lock_path: &Path, seen: &[u8]) -> io::Result<()> {
+    let aside = sibling(lock_path, "stale");
+    match fs::rename(lock_path, &aside) {
+        Ok(()) => {}
+        // Someone else took it over first.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    }
+    if fs::read(&aside).ok().as_deref() != Some(seen) {
+        match fs::hard_link(&aside, lock_path) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+            _ => {}
+        }
+    }
+    fs::remove_file(&aside)
+}
+
+fn acquire(lock_path: &Path, alive: impl Fn(u32) -> bool) -> WorkspaceResult<WorkspaceLock> {
+    let mut stale_pid = None;
+    for _ in 0..MAX_ATTEMPTS {
+        let owner = match create_lock(lock_path) {
+            Ok(owner) => owner,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let seen = match fs::read(lock_path) {
+                    Ok(seen) => seen,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(WorkspaceError::io("read", lock_path, err)),
+                };
+                match serde_json::from_slice::<LockOwner>(&seen).ok() {
+                    // Taking the same workspace again, e.g. after a reload.
+                    Some(owner) if owner.pid == process::id() => owner,
+                    Some(owner) if alive(owner.pid) => {
+                        return Err(WorkspaceError::WorkspaceLocked {
+                            path: lock_path.display().to_string(),
+                            pid: owner.pid,
+                            acquired_at: owner.acquired_at,
+                        });
+                    }
+                    stale => {
+                        stale_pid = stale.map(|owner| owner.pid).or(stale_pid);
+                        remove_stale(lock_path, &seen)
+                            .map_err(|err| WorkspaceError::io("remove", lock_path, err))?;
+                        continue;
+                    }
+                }
+            }
+            Err(err) => return Err(WorkspaceError::io("create", lock_path, err)),
+        };
+        held().insert(lock_path.to_path_buf());
+        return Ok(WorkspaceLock {
+            lock_path: lock_path.to_string_lossy().into_owned(),
+            pid: owner.pid,
+            acquired_at: owner.acquired_at,
+            stale_pid,
+        });
+    }
+    Err(WorkspaceError::io(
+        "create",
+        lock_path,
+        io::Error::other("the lock kept changing while it was being taken over"),
+    ))
+}
+
+/// Removes the lock if this process owns it; a lock taken over by another
+/// instance is left alone.
+fn release(lock_path: &Path) -> WorkspaceResult<()> {
+    held().remove(lock_path);
+    if read_owner(lock_path).is_none_or(|owner| owner.pid != process::id()) {
+        return Ok(());
+    }
+    match fs::remove_file(lock_path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(WorkspaceError::io("remove", lock_path, err)),
+    }
+}
+
+/// Takes the workspace's lock for this process. Fails with `workspaceLocked`
+/// while another running process holds it; a lock whose process is gone is
+/// taken over and its pid reported as `stalePid`. Taking a lock this
+/// process already holds succeeds.
+#[tauri::command]
+pub fn acquire_workspace_lock(workspace_path: String) -> WorkspaceResult<WorkspaceLock> {
+    acquire(&lock_path_of(Path::new(&workspace_path)), process_alive)
+}
+
+/// Gives the workspace's lock back. Doing so when this process does not hold
+/// it is not an error. Locks still held when the app exits are released
+/// then, but the frontend should release a workspace when it closes it.
+#[tauri::command]
+pub fn release_workspace_lock(workspace_path: String) -> WorkspaceResult<()> {
+    release(&lock_path_of(Path::new(&workspace_path)))
+}
+
+/// Releases every lock this process still holds. Errors are ignored since
+/// the app is going away; what remains is recognised as stale later.
+pub fn release_held_locks() {
+    let paths: Vec<PathBuf> = held().drain().collect();
+    for path in paths {
+        let _ = release(&path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn live_owners_block_and_stale_locks_are_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join(LOCK_FILE);
+        let other = LockOwner {
+            pid: process::id() + 1,
+            acquired_at: "2024-01-01T00:00:00Z".into(),
+        };
+        fs::write(&lock_path, serde_json::to_vec(&other).unwrap()).unwrap();
+
+        match acquire(&lock_path, |_| true) {
+            Err(WorkspaceError::WorkspaceLocked { pid, .. }) => assert_eq!(pid, other.pid),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        release(&lock_path).unwrap();
+        assert!(
+            lock_path.exists(),
+            "another owner's lock must survive release"
+        );
+
+        let lock = acquire(&lock_path, |_| false).unwrap();
+        assert_eq!((lock.pid, lock.stale_pid), (process::id(), Some(other.pid)));
+        assert_eq!(read_owner(&lock_path).unwrap().pid, process::id());
+        let again = acquire(&lock_path, |_| true).unwrap();
+        assert_eq!(again.acquired_at, lock.acquired_at);
+
+        release(&lock_path).unwrap();
+        assert!(!lock_path.exists());
+        release(&lock_path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_locks_are_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let lock_path = dir.path().join(LOCK_FILE);
+        fs::write(&lock_path, "{\"pid\": 4").unwrap();
+
+        let lock = acquire_workspace_lock(workspace_path.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(lock.stale_pid, None);
+        // Neither the temp file nor the stale lock is left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert!(process_alive(process::id()));
+        assert!(held().contains(&lock_path));
+
+        release_workspace_lock(workspace_path.to_string_lossy().into_owned()).unwrap();
+        assert!(!lock_path.exists());
+        assert!(!held().contains(&lock_path));
+    }
+
+    #[test]
+    fn stale_takeover_keeps_a_lock_that_changed_meanwhile() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join(LOCK_FILE);
+        fs::write(&lock_path, "fresh").unwrap();
+
+        // Judged stale from what it read before, but a new lock is there now.
+        remove_stale(&lock_path, b"stale").unwrap();
+        assert_eq!(fs::read(&lock_path).unwrap(), b"fresh");
+        remove_stale(&lock_path, b"fresh").unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        remove_stale(&lock_path, b"fresh").unwrap();
+
+        create_lock(&lock_path).unwrap();
+        let err = create_lock(&lock_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+//! `.sheet-up-manifest.json` records the SHA-256 of every book as last
+//! written by us, keyed by its `/`-separated path relative to the workspace
+//! directory. Loads compare against it to spot files damaged by sync tools.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_json_file, FileEncoding};
+use super::parallel::parallel_map;
+use super::paths::normalize_lexically;
+use super::write_tracked;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE_NAME: &str = ".sheet-up-manifest.json";
+const MANIFEST_VERSION: u64 = 1;
+
+fn manifest_path(workspace_dir: &Path) -> PathBuf {
+    workspace_dir.join(MANIFEST_FILE_NAME)
+}
+
+fn file_checksum(path: &Path) -> WorkspaceResult<String> {
+    let bytes = fs::read(path).map_err(|err| WorkspaceError::io("read", path, err))?;
+    let digest = Sha256::digest(&bytes);
+    Ok(digest.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    }))
+}
+
+fn manifest_key(workspace_dir: &Path, path: &Path) -> Option<String> {
+    let dir = normalize_lexically(workspace_dir)?;
+    let path = normalize_lexically(path)?;
+    let relative = path.strip_prefix(dir).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some(parts.join("/"))
+}
+
+/// `None` when there is no manifest yet; a manifest that cannot be read is
+/// treated the same way so it never blocks a load.
+fn read_manifest(workspace_dir: &Path) -> Option<Map<String, Value>> {
+    let manifest = read_json_file(&manifest_path(workspace_dir)).ok()?;
+    manifest.get("files")?.as_object().cloned()
+}
+
+/// Books whose current content no longer matches the manifest. Files the
+/// manifest does not know about are not reported.
+pub fn verify_books<'a, I>(workspace_dir: &Path, book_paths: I) -> Vec<WorkspaceError>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let Some(files) = read_manifest(workspace_dir) else {
+        return Vec::new();
+    };
+    let targets: Vec<(PathBuf, String)> = book_paths
+        .into_iter()
+        .filter_map(|path| {
+            let path = PathBuf::from(path);
+            let expected = files.get(&manifest_key(workspace_dir, &path)?)?.as_str()?;
+            Some((path, expected.to_string()))
+        })
+        .collect();
+    parallel_map(&targets, |_, (path, expected)| {
+        let matches = file_checksum(path).is_ok_and(|actual| actual == *expected);
+        (!matches).then(|| WorkspaceError::IntegrityMismatch {
+            path: path.display().to_string(),
+        })
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Records the checksums of `written` books and drops entries for files no
+/// longer listed in `book_paths`.
+pub fn update_manifest(
+    workspace_dir: &Path,
+    book_paths: &[PathBuf],
+    written: &[PathBuf],
+) -> WorkspaceResult<()> {
+    let mut files = read_manifest(workspace_dir).unwrap_or_default();
+    let known: Vec<String> = book_paths
+        .iter()
+        .filter_map(|path| manifest_key(workspace_dir, path))
+        .collect();
+    files.retain(|key, _| known.contains(key));
+
+    for (path, checksum) in written
+        .iter()
+        .zip(parallel_map(written, |_, path| file_checksum(path)))
+    {
+        if let Some(key) = manifest_key(workspace_dir, path) {
+            files.insert(key, Value::String(checksum?));
+        }
+    }
+
+    let manifest = json!({ "version": MANIFEST_VERSION, "files": files });
+    write_tracked(
+        &manifest_path(workspace_dir),
+        &manifest,
+        FileEncoding::default(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_books_changed_behind_our_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let books: Vec<PathBuf> = ["books/a.json", "books/b.json"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        fs::create_dir_all(dir.path().join("books")).unwrap();
+        for book in &books {
+            fs::write(book, "{}").unwrap();
+        }
+        let paths = || books.iter().map(|path| path.to_str().unwrap());
+
+        assert!(verify_books(dir.path(), paths()).is_empty());
+        update_manifest(dir.path(), &books, &books).unwrap();
+        assert!(verify_books(dir.path(), paths()).is_empty());
+
+        fs::write(&books[1], "{\"truncated\":").unwrap();
+        let mismatches = verify_books(dir.path(), paths());
+        assert_eq!(mismatches.len(), 1);
+        assert!(matches!(
+            &mismatches[0],
+            WorkspaceError::IntegrityMismatch { path } if path.ends_with("b.json")
+        ));
+
+        update_manifest(dir.path(), &books[..1], &[]).unwrap();
+        assert!(verify_books(dir.path(), paths()).is_empty());
+    }
+}
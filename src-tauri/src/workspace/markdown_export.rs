@@ -0,0 +1,303 @@
+//! Exports a sheet as a GitHub Flavored Markdown table for pasting into
+//! documents.
+
+use super::cells::column_label;
+use super::csv_export::{looks_numeric, select_sheet, sheet_grid, CsvRange, Field};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_json_file, write_atomic};
+use super::schema::validate_book;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkdownAlign {
+    /// No alignment marker; renderers usually align such columns left.
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MarkdownExportOptions {
+    /// Sheet to export; the first sheet when omitted.
+    pub sheet_id: Option<String>,
+    /// Use the first row as the table header. Otherwise the header holds the
+    /// column letters and every row becomes a body row.
+    pub header_row: bool,
+    /// Text written for cells without a value.
+    pub empty_value: String,
+    /// Pad cells with spaces so that the columns line up in plain text.
+    pub pad: bool,
+    /// Alignment of columns whose body cells are all numbers.
+    pub number_align: MarkdownAlign,
+    /// Alignment of every other column.
+    pub text_align: MarkdownAlign,
+    pub range: CsvRange,
+}
+
+impl Default for MarkdownExportOptions {
+    fn default() -> Self {
+        Self {
+            sheet_id: None,
+            header_row: true,
+            empty_value: String::new(),
+            pad: true,
+            number_align: MarkdownAlign::Right,
+            text_align: MarkdownAlign::None,
+            range: CsvRange::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkdownExportResult {
+    pub sheet_id: String,
+    /// Body rows, not counting the header.
+    pub rows: usize,
+    pub columns: usize,
+}
+
+/// Separator rows need at least three dashes per column.
+const MIN_WIDTH: usize = 3;
+
+/// Makes text safe inside a table cell: `|` would end the cell and a line
+/// break the row, so they become `\|` and `<br>`.
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['|', '\r', '\n']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '|' => escaped.push_str("\\|"),
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                escaped.push_str("<br>");
+            }
+            '\n' => escaped.push_str("<br>"),
+            _ => escaped.push(ch),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+fn cell_text<'a>(field: &'a Option<Field>, empty_value: &'a str) -> Cow<'a, str> {
+    match field {
+        Some(field) => escape(&field.text),
+        None => Cow::Borrowed(empty_value),
+    }
+}
+
+fn is_number(field: &Field) -> bool {
+    !field.is_text && looks_numeric(&field.text)
+}
+
+/// Padding counts characters, so columns of wide (e.g. CJK) text line up
+/// only in fonts that draw them as one cell wide.
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn write_cell(
+    out: &mut impl Write,
+    text: &str,
+    width: Option<usize>,
+    align: MarkdownAlign,
+) -> io::Result<()> {
+    let fill = width.map_or(0, |width| width.saturating_sub(self::width(text)));
+    let (left, right) = match align {
+        MarkdownAlign::Right => (fill, 0),
+        MarkdownAlign::Center => (fill / 2, fill - fill / 2),
+        MarkdownAlign::None | MarkdownAlign::Left => (0, fill),
+    };
+    write!(out, " {:left$}{}{:right$} |", "", text, "")
+}
+
+fn write_separator(out: &mut impl Write, width: usize, align: MarkdownAlign) -> io::Result<()> {
+    let (left, right) = match align {
+        MarkdownAlign::None => ("", ""),
+        MarkdownAlign::Left => (":", ""),
+        MarkdownAlign::Center => (":", ":"),
+        MarkdownAlign::Right => ("", ":"),
+    };
+    let dashes = width.max(MIN_WIDTH) - left.len() - right.len();
+    write!(out, " {}{}{} |", left, "-".repeat(dashes), right)
+}
+
+/// Writes one sheet of a book file as a Markdown table. Rows go straight to
+/// the file as they are rendered rather than being collected first.
+#[tauri::command]
+pub fn export_book_to_markdown(
+    book_file_path: String,
+    output_path: String,
+    options: Option<MarkdownExportOptions>,
+) -> WorkspaceResult<MarkdownExportResult> {
+    let options = options.unwrap_or_default();
+    let book_path = Path::new(&book_file_path);
+    let book = read_json_file(book_path)?;
+    validate_book(&book)?;
+    let sheet = select_sheet(&book, book_path, options.sheet_id.as_deref())?;
+    let grid = sheet_grid(sheet, options.range);
+    let columns = grid.first().map_or(0, Vec::len);
+
+    let (header, body) = match grid.split_first() {
+        Some((first, rest)) if options.header_row => (Some(first), rest),
+        _ => (None, grid.as_slice()),
+    };
+    let empty_value = escape(&options.empty_value);
+    let headings: Vec<String> = match header {
+        Some(row) => row
+            .iter()
+            .map(|field| cell_text(field, &empty_value).into_owned())
+            .collect(),
+        None => (1..=columns as u32).map(column_label).collect(),
+    };
+
+    let aligns: Vec<MarkdownAlign> = (0..columns)
+        .map(|column| {
+            let mut values = body
+                .iter()
+                .filter_map(|row| row[column].as_ref())
+                .peekable();
+            if values.peek().is_some() && values.all(is_number) {
+                options.number_align
+            } else {
+                options.text_align
+            }
+        })
+        .collect();
+    let widths: Vec<Option<usize>> = (0..columns)
+        .map(|column| {
+            options.pad.then(|| {
+                body.iter()
+                    .map(|row| width(&cell_text(&row[column], &empty_value)))
+                    .chain([width(&headings[column]), MIN_WIDTH])
+                    .max()
+                    .unwrap_or(MIN_WIDTH)
+            })
+        })
+        .collect();
+
+    let output_path = Path::new(&output_path);
+    if let Some(parent) = output_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .map_err(|err| WorkspaceError::io("create directory", parent, err))?;
+    }
+    write_atomic(output_path, |file| {
+        let mut out = BufWriter::new(file);
+        if columns == 0 {
+            return out.flush();
+        }
+        out.write_all(b"|")?;
+        for (column, heading) in headings.iter().enumerate() {
+            write_cell(&mut out, heading, widths[column], aligns[column])?;
+        }
+        out.write_all(b"\n|")?;
+        for column in 0..columns {
+            write_separator(&mut out, widths[column].unwrap_or(0), aligns[column])?;
+        }
+        out.write_all(b"\n")?;
+        for row in body {
+            out.write_all(b"|")?;
+            for (column, field) in row.iter().enumerate() {
+                write_cell(
+                    &mut out,
+                    &cell_text(field, &empty_value),
+                    widths[column],
+                    aligns[column],
+                )?;
+            }
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    })
+    .map_err(|err| WorkspaceError::io("write", output_path, err))?;
+
+    Ok(MarkdownExportResult {
+        sheet_id: sheet["id"].as_str().unwrap_or_default().to_string(),
+        rows: body.len(),
+        columns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_book(dir: &Path) -> String {
+        let path = dir.join("book.json");
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": 10, "cols": 10 },
+                "rows": {
+                    "1": { "A": { "value": "item" }, "B": { "value": "price" } },
+                    "2": { "A": { "value": "a|b\r\nc" }, "B": { "value": 120 } },
+                    "3": { "A": { "value": "pen" }, "B": { "value": 5.5 } }
+                }
+            }]
+        });
+        fs::write(&path, book.to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn writes_padded_table_with_escaped_cells_and_numeric_columns_right_aligned() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = write_book(dir.path());
+        let output = dir.path().join("out/sheet.md");
+
+        let result =
+            export_book_to_markdown(book, output.to_string_lossy().into_owned(), None).unwrap();
+        assert_eq!((result.rows, result.columns), (2, 2));
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "| item      | price |\n\
+             | --------- | ----: |\n\
+             | a\\|b<br>c |   120 |\n\
+             | pen       |   5.5 |\n"
+        );
+    }
+
+    #[test]
+    fn without_header_row_uses_column_letters_and_skips_padding() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = write_book(dir.path());
+        let output = dir.path().join("sheet.md");
+        let options = MarkdownExportOptions {
+            header_row: false,
+            pad: false,
+            empty_value: "-".into(),
+            text_align: MarkdownAlign::Left,
+            range: CsvRange::Grid,
+            ..Default::default()
+        };
+
+        let result =
+            export_book_to_markdown(book, output.to_string_lossy().into_owned(), Some(options))
+                .unwrap();
+        assert_eq!((result.rows, result.columns), (10, 10));
+        let table = fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 12);
+        assert!(lines[0].starts_with("| A | B | C |"));
+        assert!(lines[1].starts_with("| :-- | :-- | :-- |"));
+        assert!(lines[2].starts_with("| item | price | - |"));
+    }
+}
@@ -0,0 +1,440 @@
+//! `merge_books`: combines two books of the same structure cell by cell,
+//! resolving cells both books fill differently with a chosen strategy.
+
+use super::backup::{backup_id_of, create_backup, find_workspace_dir, BackupOptions};
+use super::books::now_rfc3339;
+use super::cells::{column_index, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_encrypted_file, FileEncoding, SizeLimits};
+use super::{load_book, write_tracked};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+/// Which cell wins when both books fill it differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeStrategy {
+    PreferBase,
+    PreferIncoming,
+    /// The cell with a value; the base cell when both or neither have one.
+    NonEmptyWins,
+}
+
+impl MergeStrategy {
+    fn prefers_incoming(self, base: &Value, incoming: &Value) -> bool {
+        let is_empty = |cell: &Value| match &cell["value"] {
+            Value::Null => true,
+            Value::String(text) => text.is_empty(),
+            _ => false,
+        };
+        match self {
+            Self::PreferBase => false,
+            Self::PreferIncoming => true,
+            Self::NonEmptyWins => is_empty(base) && !is_empty(incoming),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MergeOptions {
+    /// Write the result as a new book file here. An existing file is never
+    /// replaced.
+    pub output_path: Option<String>,
+    /// Write the result over the base book, backing the base up first.
+    /// Without this or `outputPath` nothing is written.
+    pub overwrite_base: bool,
+    /// Name of the new book written to `outputPath`; the base book's name
+    /// when omitted.
+    pub name: Option<String>,
+    /// Decrypts encrypted books; the result is encrypted when either book
+    /// was.
+    pub passphrase: Option<String>,
+    /// How many conflicts to list in `conflicts`; 1000 when omitted.
+    pub max_conflicts: Option<usize>,
+}
+
+const DEFAULT_MAX_CONFLICTS: usize = 1000;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeConflict {
+    pub sheet_id: String,
+    /// A1-style address such as `"B3"`.
+    pub cell: String,
+    /// The `value` of the cell in each book and in the result.
+    pub base: Value,
+    pub incoming: Value,
+    pub resolved: Value,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+    /// Cells both books fill differently, including those beyond
+    /// `conflicts`.
+    pub conflict_count: usize,
+    /// The first conflicts in sheet, row, column order.
+    pub conflicts: Vec<MergeConflict>,
+    /// Cells only the incoming book fills, taken over as they are.
+    pub cells_added: usize,
+    /// Sheets of the incoming book matching none of the base book by id or
+    /// name, appended whole.
+    pub sheets_added: usize,
+    /// The file the result was written to, if any.
+    pub written: Option<String>,
+    /// Backup of the base book taken before it was overwritten; pass to
+    /// `restore_backup` to undo the merge.
+    pub backup_id: Option<String>,
+}
+
+/// A conflict with the position it sorts by.
+type Positioned = ((usize, u32, u32), MergeConflict);
+
+/// Merges the rows of `incoming` into `base`, recording conflicts of sheet
+/// `sheet_index`.
+fn merge_rows(
+    base: &mut Map<String, Value>,
+    incoming: Map<String, Value>,
+    strategy: MergeStrategy,
+    (sheet_index, sheet_id): (usize, &str),
+    conflicts: &mut Vec<Positioned>,
+    result: &mut MergeResult,
+) {
+    for (row_key, incoming_cells) in incoming {
+        let Value::Object(incoming_cells) = incoming_cells else {
+            continue;
+        };
+        let Some(base_cells) = base
+            .entry(row_key.clone())
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+        else {
+            continue;
+        };
+        for (column_key, incoming_cell) in incoming_cells {
+            let Some(base_cell) = base_cells.get_mut(&column_key) else {
+                base_cells.insert(column_key, incoming_cell);
+                result.cells_added += 1;
+                continue;
+            };
+            if *base_cell == incoming_cell {
+                continue;
+            }
+            let position = (
+                sheet_index,
+                row_index(&row_key).unwrap_or(u32::MAX),
+                column_index(&column_key).unwrap_or(u32::MAX),
+            );
+            let base_value = base_cell["value"].clone();
+            let incoming_value = incoming_cell["value"].clone();
+            if strategy.prefers_incoming(base_cell, &incoming_cell) {
+                *base_cell = incoming_cell;
+            }
+            conflicts.push((
+                position,
+                MergeConflict {
+                    sheet_id: sheet_id.to_string(),
+                    cell: format!("{}{}", column_key, row_key),
+                    base: base_value,
+                    incoming: incoming_value,
+                    resolved: base_cell["value"].clone(),
+                },
+            ));
+        }
+    }
+}
+
+/// Grows the grid size of `base` to cover that of `incoming`.
+fn union_grid_size(base: &mut Value, incoming: &Value) {
+    for axis in ["rows", "cols"] {
+        let size = |sheet: &Value| sheet["gridSize"][axis].as_u64().unwrap_or(0);
+        let largest = size(base).max(size(incoming));
+        if let Some(grid_size) = base["gridSize"].as_object_mut() {
+            grid_size.insert(axis.into(), json!(largest));
+        }
+    }
+}
+
+/// Merges the sheets of `incoming` into those of `base`, pairing them by id
+/// and then by name.
+fn merge_sheets(
+    base: &mut Value,
+    incoming: Value,
+    strategy: MergeStrategy,
+    max_conflicts: usize,
+) -> MergeResult {
+    let mut result = MergeResult::default();
+    let mut conflicts = Vec::new();
+    let Some(sheets) = base["sheets"].as_array_mut() else {
+        return result;
+    };
+    let incoming_sheets = match incoming {
+        Value::Object(mut book) => book.remove("sheets"),
+        _ => None,
+    };
+    let Some(Value::Array(incoming_sheets)) = incoming_sheets else {
+        return result;
+    };
+    let mut paired = vec![false; sheets.len()];
+    for mut incoming_sheet in incoming_sheets {
+        let find = |field: &str| {
+            sheets.iter().enumerate().position(|(index, sheet)| {
+                !paired.get(index).copied().unwrap_or(true)
+                    && sheet[field].is_string()
+                    && sheet[field] == incoming_sheet[field]
+            })
+        };
+        let Some(index) = find("id").or_else(|| find("name")) else {
+            sheets.push(incoming_sheet);
+            result.sheets_added += 1;
+            continue;
+        };
+        paired[index] = true;
+        let sheet = &mut sheets[index];
+        union_grid_size(sheet, &incoming_sheet);
+        let sheet_id = sheet["id"].as_str().unwrap_or_default().to_string();
+        let incoming_rows = match incoming_sheet["rows"].take() {
+            Value::Object(rows) => rows,
+            _ => Map::new(),
+        };
+        if !sheet["rows"].is_object() {
+            sheet["rows"] = json!({});
+        }
+        let rows = sheet["rows"]
+            .as_object_mut()
+            .expect("rows was just made an object");
+        merge_rows(
+            rows,
+            incoming_rows,
+            strategy,
+            (index, &sheet_id),
+            &mut conflicts,
+            &mut result,
+        );
+    }
+    conflicts.sort_by_key(|(position, _)| *position);
+    result.conflict_count = conflicts.len();
+    result.conflicts = conflicts
+        .into_iter()
+        .take(max_conflicts)
+        .map(|(_, conflict)| conflict)
+        .collect();
+    result
+}
+
+/// Merges the book at `incoming_book_path` into the one at
+/// `base_book_path`. Cells and rows only one book has are kept, grids cover
+/// both, and cells both fill differently are settled by `strategy` and
+/// listed in `conflicts`. The result is written to `outputPath` as a new
+/// book, over the base book with `overwriteBase` (after a backup), or
+/// nowhere, which previews the merge.
+#[tauri::command]
+pub fn merge_books(
+    base_book_path: String,
+    incoming_book_path: String,
+    strategy: MergeStrategy,
+    options: Option<MergeOptions>,
+) -> WorkspaceResult<MergeResult> {
+    let options = options.unwrap_or_default();
+    let output = match (&options.output_path, options.overwrite_base) {
+        (Some(_), true) => {
+            return Err(WorkspaceError::InvalidOption {
+                name: "outputPath",
+                message: "cannot be combined with overwriteBase".into(),
+            })
+        }
+        (Some(output_path), false) => Some(Path::new(output_path)),
+        (None, true) => Some(Path::new(&base_book_path)),
+        (None, false) => None,
+    };
+    if let Some(output_path) = options.output_path.as_deref().map(Path::new) {
+        if output_path.exists() {
+            return Err(WorkspaceError::AlreadyExists {
+                path: output_path.display().to_string(),
+            });
+        }
+    }
+
+    let base_path = Path::new(&base_book_path);
+    let incoming_path = Path::new(&incoming_book_path);
+    let passphrase = options.passphrase.as_deref();
+    let limit = SizeLimits::default().book;
+    let mut base = load_book(base_path, passphrase, limit)?;
+    let incoming = load_book(incoming_path, passphrase, limit)?;
+
+    let max_conflicts = options.max_conflicts.unwrap_or(DEFAULT_MAX_CONFLICTS);
+    let mut result = merge_sheets(&mut base.data, incoming.data, strategy, max_conflicts);
+    let Some(output) = output else {
+        return Ok(result);
+    };
+
+    let now = now_rfc3339();
+    if let Some(meta) = base.data["book"].as_object_mut() {
+        if !options.overwrite_base {
+            let id = format!("book-{}", uuid::Uuid::new_v4());
+            meta.insert("id".into(), json!(id));
+            meta.insert("createdAt".into(), json!(now));
+            if let Some(name) = options.name.as_deref().map(str::trim) {
+                meta.insert("name".into(), json!(name));
+            }
+        }
+        meta.insert("updatedAt".into(), json!(now));
+    }
+    if options.overwrite_base {
+        let workspace_dir = find_workspace_dir(base_path);
+        let generations = BackupOptions::default().generations;
+        let backup = create_backup(&workspace_dir, base_path, generations)?;
+        result.backup_id = backup.as_deref().map(backup_id_of);
+    }
+    let encrypted = is_encrypted_file(base_path) || is_encrypted_file(incoming_path);
+    let encoding = FileEncoding {
+        passphrase: passphrase.filter(|_| encrypted),
+        style: base.text_style.unwrap_or_default(),
+        ..Default::default()
+    };
+    write_tracked(output, &base.data, encoding)?;
+    result.written = Some(output.to_string_lossy().into_owned());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+    use crate::workspace::restore_backup;
+
+    fn cell(value: Value) -> Value {
+        json!({ "value": value, "type": "string" })
+    }
+
+    fn write_book(path: &Path, grid: (u32, u32), sheets: Vec<(&str, Value)>) -> String {
+        let sheets: Vec<Value> = sheets
+            .into_iter()
+            .map(|(id, rows)| {
+                json!({
+                    "id": id,
+                    "name": id,
+                    "gridSize": { "rows": grid.0, "cols": grid.1 },
+                    "rows": rows
+                })
+            })
+            .collect();
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": sheets
+        });
+        write_json_file(path, &book, FileEncoding::default()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn setup(dir: &Path) -> (String, String) {
+        let base = write_book(
+            &dir.join("base.json"),
+            (10, 3),
+            vec![(
+                "sheet-1",
+                json!({
+                    "1": { "A": cell(json!("same")), "B": cell(json!("base")), "C": cell(json!("")) },
+                    "2": { "A": cell(json!("only base")) }
+                }),
+            )],
+        );
+        let incoming = write_book(
+            &dir.join("incoming.json"),
+            (5, 8),
+            vec![
+                (
+                    "sheet-1",
+                    json!({
+                        "1": { "A": cell(json!("same")), "B": cell(json!("incoming")), "C": cell(json!("filled")) },
+                        "4": { "H": cell(json!("only incoming")) }
+                    }),
+                ),
+                ("sheet-2", json!({})),
+            ],
+        );
+        (base, incoming)
+    }
+
+    #[test]
+    fn strategies_settle_conflicts_over_the_union_of_both_books() {
+        let dir = tempfile::tempdir().unwrap();
+        let (base, incoming) = setup(dir.path());
+        let output = dir.path().join("merged.json");
+        let options = MergeOptions {
+            output_path: Some(output.to_string_lossy().into_owned()),
+            name: Some("Merged".into()),
+            ..Default::default()
+        };
+        let result = merge_books(
+            base.clone(),
+            incoming.clone(),
+            MergeStrategy::NonEmptyWins,
+            Some(options.clone()),
+        )
+        .unwrap();
+        assert_eq!(result.conflict_count, 2);
+        let cells: Vec<_> = result.conflicts.iter().map(|c| c.cell.as_str()).collect();
+        assert_eq!(cells, ["B1", "C1"]);
+        assert_eq!(result.conflicts[0].resolved, "base");
+        assert_eq!(result.conflicts[1].resolved, "filled");
+        assert_eq!((result.cells_added, result.sheets_added), (1, 1));
+
+        let merged = read_json_file(&output).unwrap();
+        assert_ne!(merged["book"]["id"], "book-1");
+        assert_eq!(merged["book"]["name"], "Merged");
+        let sheet = &merged["sheets"][0];
+        assert_eq!(sheet["gridSize"], json!({ "rows": 10, "cols": 8 }));
+        assert_eq!(sheet["rows"]["2"]["A"]["value"], "only base");
+        assert_eq!(sheet["rows"]["4"]["H"]["value"], "only incoming");
+        assert_eq!(merged["sheets"].as_array().unwrap().len(), 2);
+
+        // The new book never replaces an existing file, and a preview
+        // writes nothing.
+        assert!(matches!(
+            merge_books(
+                base.clone(),
+                incoming.clone(),
+                MergeStrategy::PreferBase,
+                Some(options)
+            ),
+            Err(WorkspaceError::AlreadyExists { .. })
+        ));
+        let preview =
+            merge_books(base.clone(), incoming, MergeStrategy::PreferIncoming, None).unwrap();
+        assert_eq!(preview.conflicts[0].resolved, "incoming");
+        assert!(preview.written.is_none());
+        assert_eq!(
+            read_json_file(Path::new(&base)).unwrap()["sheets"][0]["rows"]["1"]["B"]["value"],
+            "base"
+        );
+    }
+
+    #[test]
+    fn overwriting_the_base_book_backs_it_up_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let (base, incoming) = setup(dir.path());
+        let options = MergeOptions {
+            overwrite_base: true,
+            ..Default::default()
+        };
+        let result = merge_books(
+            base.clone(),
+            incoming,
+            MergeStrategy::PreferIncoming,
+            Some(options),
+        )
+        .unwrap();
+        assert_eq!(result.written.as_deref(), Some(base.as_str()));
+        let merged = read_json_file(Path::new(&base)).unwrap();
+        assert_eq!(merged["book"]["id"], "book-1");
+        assert_eq!(merged["sheets"][0]["rows"]["1"]["B"]["value"], "incoming");
+
+        restore_backup(base.clone(), result.backup_id.unwrap()).unwrap();
+        let restored = read_json_file(Path::new(&base)).unwrap();
+        assert_eq!(restored["sheets"][0]["rows"]["1"]["B"]["value"], "base");
+    }
+}
@@ -0,0 +1,185 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{modified_millis, LineEnding, SizeLimits};
+use super::newlines;
+use super::parallel::parallel_map;
+use super::paths::{ensure_links_within, workspace_dir_of, BookRoots};
+use super::{allowed_book_file_path, load_book_as, load_workspace_file, FilePayload, LoadOptions};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+/// What the sidebar needs to list a book without reading its file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookMetadata {
+    pub index: usize,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub data_path: Option<String>,
+    pub file_path: Option<String>,
+    /// `None` when the file does not exist.
+    pub size_bytes: Option<u64>,
+    pub modified: Option<u64>,
+    /// Set when the entry's `dataPath` is unusable or the file cannot be
+    /// inspected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<WorkspaceError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceMetadata {
+    pub workspace: FilePayload,
+    pub books: Vec<BookMetadata>,
+}
+
+pub(super) fn book_metadata(roots: &BookRoots, index: usize, book_ref: &Value) -> BookMetadata {
+    let text = |field: &str| {
+        book_ref
+            .get(field)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let mut metadata = BookMetadata {
+        index,
+        id: text("id"),
+        name: text("name"),
+        data_path: text("dataPath"),
+        file_path: None,
+        size_bytes: None,
+        modified: None,
+        error: None,
+    };
+    let stat = allowed_book_file_path(roots, book_ref, index).and_then(|path| {
+        metadata.file_path = Some(path.to_string_lossy().into_owned());
+        let size = match fs::metadata(&path) {
+            Ok(stat) => Some(stat.len()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(WorkspaceError::io("stat", &path, err)),
+        };
+        Ok((size, modified_millis(&path)?))
+    });
+    match stat {
+        Ok((size_bytes, modified)) => {
+            metadata.size_bytes = size_bytes;
+            metadata.modified = modified;
+        }
+        Err(err) => metadata.error = Some(err),
+    }
+    metadata
+}
+
+/// Parses only `workspace.json` and stats the referenced book files, so
+/// large workspaces can open before any book is read. Fetch books on demand
+/// with `load_single_book`.
+#[tauri::command]
+pub fn load_workspace_metadata(path: String) -> WorkspaceResult<WorkspaceMetadata> {
+    let workspace_path = PathBuf::from(path);
+    let workspace = load_workspace_file(&workspace_path, SizeLimits::default().workspace)?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let books = workspace
+        .data
+        .get("books")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let roots = BookRoots::new(&workspace_dir, &workspace.data);
+    let books = parallel_map(books, |index, book_ref| {
+        book_metadata(&roots, index, book_ref)
+    });
+    Ok(WorkspaceMetadata { workspace, books })
+}
+
+#[tauri::command]
+pub fn load_single_book(
+    workspace_path: String,
+    book_id: String,
+    options: Option<LoadOptions>,
+) -> WorkspaceResult<FilePayload> {
+    let options = options.unwrap_or_default();
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace = load_workspace_file(&workspace_path, options.size_limits.workspace)?;
+    let (index, book_ref) = workspace.data["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .find(|(_, book_ref)| book_ref["id"] == book_id.as_str())
+        .ok_or_else(|| WorkspaceError::BookNotFound {
+            path: workspace_path.display().to_string(),
+            book_id: book_id.clone(),
+        })?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let roots = BookRoots::new(&workspace_dir, &workspace.data);
+    let path = allowed_book_file_path(&roots, book_ref, index)?;
+    if !options.follow_outside_links {
+        ensure_links_within(&roots.resolved(), &path)?;
+    }
+    let mut book = load_book_as(
+        &path,
+        options.passphrase.as_deref(),
+        options.size_limits.book,
+        options.encoding,
+    )?;
+    if options.normalize_line_breaks {
+        newlines::normalize(&mut book.data, LineEnding::Lf);
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+
+    #[test]
+    fn lists_books_without_reading_them_and_loads_one_on_demand() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let books = json!([
+            { "id": "book-1", "name": "One", "dataPath": "books/one.json" },
+            { "id": "book-2", "name": "Two", "dataPath": "books/missing.json" },
+            { "id": "book-3", "name": "Evil", "dataPath": "../outside.json" }
+        ]);
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": books }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "One" },
+            "sheets": []
+        });
+        write_json_file(
+            &dir.path().join("books/one.json"),
+            &book,
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let path = workspace_path.to_string_lossy().into_owned();
+
+        let metadata = load_workspace_metadata(path.clone()).unwrap();
+        assert!(metadata.books[0].size_bytes.is_some_and(|size| size > 0));
+        assert!(metadata.books[0].modified.is_some());
+        assert_eq!(metadata.books[1].size_bytes, None);
+        assert!(metadata.books[1].error.is_none());
+        assert!(matches!(
+            metadata.books[2].error,
+            Some(WorkspaceError::PathOutsideWorkspace { .. })
+        ));
+
+        let loaded = load_single_book(path.clone(), "book-1".into(), None).unwrap();
+        assert_eq!(loaded.data["book"]["name"], "One");
+        assert!(matches!(
+            load_single_book(path.clone(), "book-9".into(), None),
+            Err(WorkspaceError::BookNotFound { .. })
+        ));
+        assert!(matches!(
+            load_single_book(path, "book-3".into(), None),
+            Err(WorkspaceError::PathOutsideWorkspace { .. })
+        ));
+    }
+}
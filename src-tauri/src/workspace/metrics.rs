@@ -0,0 +1,221 @@
+//! Timings of loads and saves for tracking down slow workspaces, collected
+//! when `metrics` is set in their options. Each file's time is split into
+//! the time spent in reads and writes of the file system, measured where
+//! the bytes pass through [`Timed`], and the rest, which is parsing on load
+//! and serializing (with compression and encryption) on save. The two are
+//! interleaved while streaming, so they are told apart by timing each read
+//! or write call rather than by phases. Nothing is measured otherwise.
+
+use serde::Serialize;
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const METRICS_EVENT: &str = "workspace-metrics";
+
+thread_local! {
+    /// Time this thread spent in [`Timed`] reads and writes while a
+    /// [`measure`] runs on it; `None` outside of one.
+    static IO_TIME: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// A reader or writer whose calls count towards the I/O time of the
+/// [`measure`] running on the thread, if any.
+pub struct Timed<T>(pub T);
+
+fn timed<T>(call: impl FnOnce() -> T) -> T {
+    if IO_TIME.get().is_none() {
+        return call();
+    }
+    let started = Instant::now();
+    let value = call();
+    let elapsed = started.elapsed();
+    IO_TIME.set(IO_TIME.get().map(|io| io + elapsed));
+    value
+}
+
+impl<R: Read> Read for Timed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        timed(|| self.0.read(buf))
+    }
+}
+
+impl<W: Write> Write for Timed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        timed(|| self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        timed(|| self.0.flush())
+    }
+}
+
+/// Counts a single call such as `sync_all` as I/O.
+pub fn io<T>(call: impl FnOnce() -> T) -> T {
+    timed(call)
+}
+
+/// Runs `f` and returns its result with how long it took and how much of
+/// that went to I/O. A measure inside another adds its I/O to the outer.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, Duration, Duration) {
+    let outer = IO_TIME.replace(Some(Duration::ZERO));
+    let started = Instant::now();
+    let value = f();
+    let total = started.elapsed();
+    let io = IO_TIME.replace(outer).unwrap_or_default();
+    IO_TIME.set(outer.map(|outer| outer + io));
+    (value, total, io)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    Load,
+    Save,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTiming {
+    pub path: String,
+    /// Size of the file as read, or as written.
+    pub bytes: u64,
+    pub total_ms: f64,
+    /// Reading or writing the file, including `fsync` on save.
+    pub io_ms: f64,
+    /// `totalMs` less `ioMs` of loads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_ms: Option<f64>,
+    /// `totalMs` less `ioMs` of saves.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serialize_ms: Option<f64>,
+}
+
+/// Payload of `workspace-metrics`, and `metrics` of the load or save result.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    pub operation: Operation,
+    pub workspace_path: String,
+    /// The whole load or save, including what is not itemised in `files`
+    /// such as validation, backups and renaming files into place.
+    pub total_ms: f64,
+    /// Sum of `bytes` of `files`.
+    pub bytes: u64,
+    /// Files read or written, in the order they finished. Files a save
+    /// found already up to date, and cached files on load, are left out.
+    pub files: Vec<FileTiming>,
+    /// The book with the largest `totalMs`.
+    pub slowest_book: Option<String>,
+}
+
+/// Collects file timings for one load or save, from any thread.
+pub(super) struct Recorder {
+    started: Option<Instant>,
+    files: Mutex<Vec<FileTiming>>,
+}
+
+impl Recorder {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            started: enabled.then(Instant::now),
+            files: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs `f`, which reads or writes the file at `path`, and records its
+    /// timing with the size `bytes` tells from the result. Only runs `f`
+    /// when disabled.
+    pub fn file<T>(
+        &self,
+        path: &str,
+        operation: Operation,
+        f: impl FnOnce() -> T,
+        bytes: impl FnOnce(&T) -> u64,
+    ) -> T {
+        if self.started.is_none() {
+            return f();
+        }
+        let (value, total, io) = measure(f);
+        let rest = Some(millis(total.saturating_sub(io)));
+        let timing = FileTiming {
+            path: path.to_string(),
+            bytes: bytes(&value),
+            total_ms: millis(total),
+            io_ms: millis(io),
+            parse_ms: rest.filter(|_| operation == Operation::Load),
+            serialize_ms: rest.filter(|_| operation == Operation::Save),
+        };
+        self.files
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(timing);
+        value
+    }
+
+    /// The metrics collected, or `None` when disabled.
+    pub fn finish(self, operation: Operation, workspace_path: &str) -> Option<Metrics> {
+        let total = self.started?.elapsed();
+        let files = self
+            .files
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner());
+        let slowest_book = files
+            .iter()
+            .filter(|file| file.path != workspace_path)
+            .max_by(|a, b| a.total_ms.total_cmp(&b.total_ms))
+            .map(|file| file.path.clone());
+        Some(Metrics {
+            operation,
+            workspace_path: workspace_path.to_string(),
+            total_ms: millis(total),
+            bytes: files.iter().map(|file| file.bytes).sum(),
+            files,
+            slowest_book,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_io_from_the_rest_and_nests() {
+        let recorder = Recorder::new(true);
+        let read = recorder.file(
+            "book.json",
+            Operation::Load,
+            || {
+                let mut text = String::new();
+                Timed(&b"{}"[..]).read_to_string(&mut text).unwrap();
+                let (_, _, inner_io) =
+                    measure(|| io(|| std::thread::sleep(Duration::from_millis(5))));
+                assert!(inner_io >= Duration::from_millis(5));
+                std::thread::sleep(Duration::from_millis(5));
+                text
+            },
+            |text| text.len() as u64,
+        );
+        assert_eq!(read, "{}");
+        // Outside a measure nothing is counted.
+        io(|| ());
+        assert_eq!(IO_TIME.get(), None);
+
+        let metrics = recorder.finish(Operation::Load, "workspace.json").unwrap();
+        let file = &metrics.files[0];
+        assert_eq!((file.bytes, metrics.bytes), (2, 2));
+        assert!(file.io_ms >= 5.0 && file.parse_ms.unwrap() >= 5.0);
+        assert_eq!(file.serialize_ms, None);
+        assert_eq!(metrics.slowest_book.as_deref(), Some("book.json"));
+
+        let disabled = Recorder::new(false);
+        assert_eq!(disabled.file("x", Operation::Save, || 1, |_| 0), 1);
+        assert!(disabled.finish(Operation::Save, "workspace.json").is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! Upgrades `workspace.json` and book files written for older schema
+//! versions. Each format has a chain of migrations; a file runs every step
+//! whose target version is newer than its own `schemaVersion`, in order.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Version written by this build; the target of the last migration.
+pub const CURRENT_SCHEMA_VERSION: &str = "1.0.0";
+
+type Migration = fn(Value) -> WorkspaceResult<Value>;
+
+/// Upgrades data to the layout of `to`. The runner stamps `schemaVersion`
+/// afterwards, so migrations only reshape the data.
+struct Step {
+    to: &'static str,
+    migrate: Migration,
+}
+
+// Files from before `schemaVersion` existed already use the 1.0.0 layout
+// and only need the field.
+const WORKSPACE_MIGRATIONS: &[Step] = &[Step {
+    to: "1.0.0",
+    migrate: Ok,
+}];
+
+const BOOK_MIGRATIONS: &[Step] = &[Step {
+    to: "1.0.0",
+    migrate: Ok,
+}];
+
+type Version = (u64, u64, u64);
+
+/// `"1.2.3"` -> `(1, 2, 3)`; missing parts count as 0.
+fn parse_version(text: &str) -> Option<Version> {
+    let mut parts = text.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    parts.next().is_none().then_some((major, minor, patch))
+}
+
+fn current() -> Version {
+    parse_version(CURRENT_SCHEMA_VERSION).expect("current schema version is valid")
+}
+
+fn run(steps: &[Step], mut value: Value, path: &Path) -> WorkspaceResult<(Value, bool)> {
+    let version = match value.get("schemaVersion") {
+        None => (0, 0, 0),
+        Some(Value::String(text)) => parse_version(text).ok_or_else(|| {
+            WorkspaceError::invalid_schema(format!(
+                "{}: schemaVersion {} is not a version number",
+                path.display(),
+                text
+            ))
+        })?,
+        // Left for validation to report as the wrong type.
+        Some(_) => return Ok((value, false)),
+    };
+    // Newer minor and patch versions only add fields, which are ignored, so
+    // only a newer major version is refused.
+    if version.0 > current().0 {
+        return Err(WorkspaceError::VersionTooNew {
+            path: path.display().to_string(),
+            version: value["schemaVersion"].as_str().unwrap_or_default().into(),
+            supported: CURRENT_SCHEMA_VERSION.into(),
+        });
+    }
+    let mut migrated = false;
+    for step in steps {
+        let target = parse_version(step.to).expect("migration targets are valid");
+        if version >= target {
+            continue;
+        }
+        value = (step.migrate)(value)?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("schemaVersion".into(), json!(step.to));
+        }
+        migrated = true;
+    }
+    Ok((value, migrated))
+}
+
+/// Brings `workspace.json` data up to the current schema. The flag tells
+/// whether anything changed.
+pub fn migrate_workspace(value: Value, path: &Path) -> WorkspaceResult<(Value, bool)> {
+    run(WORKSPACE_MIGRATIONS, value, path)
+}
+
+/// Brings book data up to the current schema. The flag tells whether
+/// anything changed.
+pub fn migrate_book(value: Value, path: &Path) -> WorkspaceResult<(Value, bool)> {
+    run(BOOK_MIGRATIONS, value, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path() -> &'static Path {
+        Path::new("/w/book.json")
+    }
+
+    #[test]
+    fn versions_parse_leniently() {
+        assert_eq!(parse_version("1.0.0"), Some((1, 0, 0)));
+        assert_eq!(parse_version("2"), Some((2, 0, 0)));
+        assert_eq!(parse_version("1.x"), None);
+        assert_eq!(parse_version("1.0.0.0"), None);
+    }
+
+    #[test]
+    fn unversioned_files_are_stamped_as_1_0_0() {
+        for migrate in [migrate_workspace, migrate_book] {
+            let (value, migrated) = migrate(json!({ "books": [] }), path()).unwrap();
+            assert!(migrated);
+            assert_eq!(value, json!({ "books": [], "schemaVersion": "1.0.0" }));
+        }
+    }
+
+    #[test]
+    fn current_files_are_left_alone() {
+        let value = json!({ "schemaVersion": "1.0.0", "books": [] });
+        let (migrated_value, migrated) = migrate_workspace(value.clone(), path()).unwrap();
+        assert!(!migrated);
+        assert_eq!(migrated_value, value);
+        // Same major version: opened as-is.
+        let newer_minor = json!({ "schemaVersion": "1.4.0", "books": [] });
+        assert!(!migrate_workspace(newer_minor, path()).unwrap().1);
+    }
+
+    #[test]
+    fn newer_major_versions_are_refused() {
+        let err = migrate_book(json!({ "schemaVersion": "2.0.0" }), path()).unwrap_err();
+        assert!(matches!(
+            err,
+            WorkspaceError::VersionTooNew { version, supported, .. }
+                if version == "2.0.0" && supported == CURRENT_SCHEMA_VERSION
+        ));
+        assert!(matches!(
+            migrate_book(json!({ "schemaVersion": "latest" }), path()),
+            Err(WorkspaceError::InvalidSchema { .. })
+        ));
+    }
+
+    #[test]
+    fn steps_run_in_order_from_the_files_version() {
+        fn add_sheets(mut value: Value) -> WorkspaceResult<Value> {
+            value["sheets"] = json!([]);
+            Ok(value)
+        }
+        fn count_sheets(mut value: Value) -> WorkspaceResult<Value> {
+            let count = value["sheets"].as_array().map_or(0, Vec::len);
+            value["sheetCount"] = json!(count);
+            Ok(value)
+        }
+        let steps = [
+            Step {
+                to: "0.5.0",
+                migrate: add_sheets,
+            },
+            Step {
+                to: "0.6.0",
+                migrate: count_sheets,
+            },
+        ];
+
+        let (value, _) = run(&steps, json!({}), path()).unwrap();
+        assert_eq!(
+            value,
+            json!({ "sheets": [], "schemaVersion": "0.6.0", "sheetCount": 0 })
+        );
+        let from_middle = json!({ "schemaVersion": "0.5.0", "sheets": [{}] });
+        let (value, _) = run(&steps, from_middle, path()).unwrap();
+        assert_eq!(value["sheetCount"], 1);
+    }
+}
@@ -0,0 +1,2664 @@
+mod backup;
+mod books;
+mod bundle;
+mod cache;
+mod cancel;
+mod cell_types;
+mod cells;
+mod clipboard;
+mod consistency;
+mod crypto;
+mod csv_export;
+mod csv_import;
+mod diagnose;
+mod diff;
+mod duplicate;
+mod error;
+mod formats;
+mod formula;
+mod index;
+mod intern;
+mod io;
+mod journal;
+mod json_schema;
+mod jsonc;
+mod large_cells;
+mod lock;
+mod manifest;
+mod markdown_export;
+mod merge;
+mod metadata;
+mod metrics;
+mod migrate;
+mod newlines;
+mod normalize;
+mod parallel;
+mod patches;
+mod paths;
+mod progress;
+mod prune;
+mod readonly;
+mod relocate;
+mod replace;
+mod save_events;
+mod save_lock;
+mod save_queue;
+mod schema;
+mod search;
+mod search_all;
+mod sort;
+mod space;
+mod sqlite_export;
+mod stats;
+mod structure;
+mod template;
+mod three_way;
+mod transaction;
+mod transform;
+mod trash;
+mod trim;
+mod watcher;
+mod zip_archive;
+
+use backup::{create_backup, BackupOptions};
+pub use backup::{list_backups, restore_backup};
+pub use books::{create_book, delete_book, rename_book, reorder_books};
+use books::{now_rfc3339, BookSkeleton, DEFAULT_COLS, DEFAULT_ROWS};
+pub use bundle::{export_bundle, import_bundle};
+pub use cache::{invalidate_cache, load_workspace_snapshot_cached};
+use cancel::LoadToken;
+pub use cancel::{cancel_load, issue_load_id};
+pub use cell_types::inspect_book_types;
+pub use clipboard::parse_clipboard_table;
+use consistency::ConsistencyCheck;
+pub use csv_export::{export_book_to_csv, export_cell_range};
+pub use csv_import::{import_csv_as_book, import_csv_directory};
+pub use diagnose::diagnose_workspace;
+pub use diff::diff_workspaces;
+pub use duplicate::duplicate_workspace;
+use error::{FileKind, WorkspaceError, WorkspaceResult};
+use formats::book_format;
+pub use formula::evaluate_book_formulas;
+pub use index::scan_workspace_index;
+use io::{
+    content_hash, ensure_size_within, file_size, is_encrypted_file, matches_on_disk,
+    modified_millis, read_json_file_decoded, read_workspace_json_styled, write_json_file,
+    FileEncoding, LineEnding, LineEndingMode, RetryOptions, SizeLimits, TextEncoding, TextStyle,
+    WriteOptions,
+};
+pub use journal::{append_journal_entry, discard_journal, recover_from_journal};
+pub use json_schema::validate_workspace_against_schema;
+use large_cells::{LargeCell, LargeCellOptions};
+pub use lock::{acquire_workspace_lock, release_held_locks, release_workspace_lock};
+pub use markdown_export::export_book_to_markdown;
+pub use merge::merge_books;
+pub use metadata::{load_single_book, load_workspace_metadata};
+use metrics::{Metrics, Operation, Recorder};
+use migrate::{migrate_book, migrate_workspace, CURRENT_SCHEMA_VERSION};
+pub use normalize::normalize_workspace;
+use normalize::NormalizeChange;
+use parallel::{parallel_map, with_open_file};
+pub use patches::load_with_patches;
+use patches::{PatchOptions, PatchPlan};
+use paths::{
+    absolute, canonicalize_lenient, duplicate_data_paths, ensure_links_within, expand_home,
+    normalize_lexically, real_path, real_workspace_dir, resolve_data_path, workspace_dir_of,
+    BookRoots,
+};
+pub use progress::load_workspace_snapshot_with_progress;
+pub use prune::prune_orphan_books;
+pub use readonly::set_workspace_readonly;
+use readonly::{ensure_writable, is_read_only};
+pub use relocate::relocate_workspace;
+pub use replace::replace_in_workspace;
+pub use save_events::save_workspace_snapshot;
+pub use save_queue::{enqueue_save, flush_save_queue};
+use schema::{validate_book, validate_workspace};
+pub use search::search_workspace;
+pub use search_all::search_all_workspaces;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use space::PlannedWrite;
+pub use sqlite_export::export_workspace_to_sqlite;
+pub use stats::workspace_stats;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+pub use structure::{delete_cols, delete_rows, insert_cols, insert_rows};
+pub use template::create_workspace_from_template;
+use three_way::{BookMerge, ThreeWayMode, ThreeWayOptions};
+use transaction::Transaction;
+pub use transform::transform_book_cells;
+pub use trash::{list_trash, restore_from_trash};
+pub use trim::trim_empty_book;
+pub use watcher::{unwatch_workspace, watch_workspace, WatcherState};
+pub use zip_archive::{export_workspace_zip, import_workspace_zip};
+
+pub const WORKSPACE_FILE_NAME: &str = "workspace.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePayload {
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    pub data: Value,
+    /// Modification time (epoch millis) observed at load. Sent back on save
+    /// to detect changes made by someone else in the meantime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Content hash of `data` as last loaded or saved. Files whose `data`
+    /// still matches it are not rewritten.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Line breaks the file used at load, reproduced by `preserve` saves.
+    #[serde(rename = "textStyle", default, skip_serializing_if = "Option::is_none")]
+    pub text_style: Option<TextStyle>,
+    /// Size of the file on disk at load, e.g. for a "size" column. Only
+    /// informative: saves ignore it.
+    #[serde(rename = "sizeBytes", default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+    /// `dataPath` of the book's entry in `workspace.json` as written there,
+    /// with the `resolvedPaths` load option. Only informative: saves go by
+    /// `filePath`.
+    #[serde(rename = "dataPath", default, skip_serializing_if = "Option::is_none")]
+    pub data_path: Option<String>,
+    /// That `dataPath` as an absolute path with symbolic links resolved,
+    /// comparable with `workspaceDir`. With `resolvedPaths` only.
+    #[serde(
+        rename = "resolvedPath",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub resolved_path: Option<String>,
+}
+
+impl FilePayload {
+    fn is_dirty(&self) -> bool {
+        self.hash.as_deref() != Some(content_hash(&self.data).as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkspaceSnapshotPayload {
+    pub workspace: FilePayload,
+    pub books: Vec<FilePayload>,
+    /// Books that could not be loaded. Only filled by load; ignored on save.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<BookLoadFailure>,
+    /// Non-fatal problems found while loading, e.g. `integrityMismatch`.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<WorkspaceError>,
+    /// Book files written empty by a load with `createMissing`.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub created: Vec<String>,
+    /// Cells over the `largeCells` threshold. Only filled by load.
+    #[serde(
+        rename = "largeCells",
+        default,
+        skip_deserializing,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub large_cells: Vec<LargeCell>,
+    /// `readOnly` of `workspace.json` at load time; saves are refused while
+    /// it is set. Ignored on save, which checks the file itself.
+    #[serde(default, skip_deserializing)]
+    pub read_only: bool,
+    /// Timings of the load, with the `metrics` option. Ignored on save.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<Metrics>,
+    /// Absolute path of the directory holding `workspace.json`, with
+    /// symbolic links resolved. Only filled by load; ignored on save.
+    #[serde(
+        rename = "workspaceDir",
+        default,
+        skip_deserializing,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub workspace_dir: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookLoadFailure {
+    pub index: usize,
+    pub data_path: Option<String>,
+    pub error: WorkspaceError,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LoadOptions {
+    /// Turn the load into an error when every referenced book failed,
+    /// instead of opening an empty workspace.
+    pub fail_if_all_books_fail: bool,
+    /// Decrypts books that were saved encrypted.
+    pub passphrase: Option<String>,
+    /// Id from `issue_load_id`; lets `cancel_load` stop this load.
+    pub load_id: Option<String>,
+    /// Write files upgraded from an older schema version back to disk right
+    /// away instead of with the next save.
+    pub save_migrated: bool,
+    /// Refuse workspaces where several books share a `dataPath` instead of
+    /// reporting it in `warnings`. Saving such a workspace fails either way.
+    pub fail_on_duplicate_data_paths: bool,
+    /// Treat `dataPath`s differing only in letter case as the same file.
+    pub case_insensitive_paths: bool,
+    /// Write an empty book for entries whose file does not exist, e.g. when
+    /// only `workspace.json` was pulled from git, instead of reporting them
+    /// in `failed`. The files made are listed in `created`.
+    pub create_missing: bool,
+    /// Files over these sizes fail with `fileTooLarge` without being read;
+    /// a book over its limit ends up in `failed`. A workspace listing more
+    /// books than `bookCount` fails with `tooManyBooks` before any is read.
+    pub size_limits: SizeLimits,
+    /// Record the time of this load as `workspace.lastOpened` in
+    /// `workspace.json`, e.g. for a list of recent workspaces. Nothing else
+    /// in the file changes, though comments in it are not kept. Skipped for
+    /// read-only workspaces.
+    pub touch_last_opened: bool,
+    /// Load books whose `dataPath` passes through a symbolic link, junction
+    /// or other reparse point that leads outside the workspace directory.
+    /// Off by default: such books end up in `failed` with
+    /// `symlinkEscapesWorkspace`.
+    pub follow_outside_links: bool,
+    /// Turn CRLF and lone CR inside the string values of books into LF, so
+    /// multi-line cells read the same whichever platform typed them. Books
+    /// changed this way are rewritten with the next save.
+    pub normalize_line_breaks: bool,
+    /// Reporting, and optionally truncating, of oversized cell values.
+    /// Truncated books are rewritten with the next save.
+    pub large_cells: LargeCellOptions,
+    /// Read every book as this encoding instead of detecting it, e.g. for
+    /// Shift_JIS files that also happen to be valid EUC-JP. Books read from
+    /// anything but UTF-8 are rewritten as UTF-8 with the next save.
+    pub encoding: Option<TextEncoding>,
+    /// Time the reading and parsing of each file and return it in
+    /// `metrics`.
+    pub metrics: bool,
+    /// Give each book payload the `dataPath` of its entry and that path
+    /// resolved (`resolvedPath`), so books can be matched with their
+    /// entries even when names repeat or the order changed.
+    pub resolved_paths: bool,
+    /// Set by `load_workspace_snapshot_cached`: files unchanged since the
+    /// previous cached load come from the cache.
+    #[serde(skip)]
+    cached: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SaveOptions {
+    pub backup: BackupOptions,
+    /// gzip level (0-9) for books stored as `.json.gz`; 6 when omitted.
+    pub compression_level: Option<u32>,
+    /// Encrypts books whose `workspace.json` entry has `encrypted: true`.
+    /// The workspace file itself is never encrypted.
+    pub passphrase: Option<String>,
+    /// Line endings and final newline of written files.
+    pub write: WriteOptions,
+    /// Treat `dataPath`s differing only in letter case as the same file.
+    pub case_insensitive_paths: bool,
+    /// Retrying of writes that fail because another process holds the file.
+    pub retry: RetryOptions,
+    /// Store books with repeated cell strings in the interned form, which
+    /// keeps each string once. Books it would not shrink by at least 10%
+    /// are written normally.
+    pub intern_strings: bool,
+    /// What happens when the book payloads and the `books` of the workspace
+    /// payload disagree: `strict` refuses the save, `warn` saves and lists
+    /// the mismatch in `warnings`.
+    pub consistency_check: ConsistencyCheck,
+    /// Line break used inside the string values of books. Books whose text
+    /// uses another one are converted and written even without edits.
+    pub cell_line_breaks: Option<LineEnding>,
+    /// How long to wait for a save of the same workspace that is still
+    /// running before failing with `saveTimeout`; 30 seconds when omitted.
+    pub lock_timeout_ms: Option<u64>,
+    /// Reporting, and optionally truncating, of oversized cell values in
+    /// the books this save writes.
+    pub large_cells: LargeCellOptions,
+    /// Merging of dirty books whose files changed since they were loaded
+    /// with those changes, instead of failing with `conflict`.
+    pub three_way: Option<ThreeWayOptions>,
+    /// Saving of changed cells to patch files next to the books instead of
+    /// rewriting whole books (see `patches`).
+    pub patches: PatchOptions,
+    /// Time the serializing and writing of each file and return it in
+    /// `metrics`.
+    pub metrics: bool,
+    /// Repair the `books` of the workspace payload (see `normalize`) before
+    /// saving, listing each change in `normalized`.
+    pub normalize: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SaveResult {
+    /// Paths actually written; files without changes are skipped.
+    pub written: Vec<String>,
+    /// Paths that were due to be written but already held the same content
+    /// (see [`matches_on_disk`]), so their modification time is unchanged.
+    pub skipped: Vec<String>,
+    /// New modification time of every written file, keyed by `filePath`.
+    pub modified: BTreeMap<String, u64>,
+    /// New content hash of every written file, keyed by `filePath`.
+    pub hashes: BTreeMap<String, String>,
+    /// Line breaks each written file now uses, keyed by `filePath`.
+    #[serde(rename = "textStyles")]
+    pub text_styles: BTreeMap<String, TextStyle>,
+    /// Non-fatal problems, e.g. a backup that could not be created.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Books skipped because another `filePath` names the same file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<MergedWrite>,
+    /// Cells of the written books over the `largeCells` threshold.
+    #[serde(rename = "largeCells", skip_serializing_if = "Vec::is_empty")]
+    pub large_cells: Vec<LargeCell>,
+    /// Every file the save considered writing, in the order handled.
+    pub files: Vec<SavedFile>,
+    /// Books saved as a line in their patch file rather than in full, by
+    /// `filePath`; `written` has the patch file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub patched: Vec<String>,
+    /// Books merged with their changed files (see `threeWay`).
+    #[serde(rename = "threeWay", skip_serializing_if = "Vec::is_empty")]
+    pub three_way: Vec<BookMerge>,
+    /// Books whose rows were reordered by their `sortBy` before writing,
+    /// by `filePath`; what is on disk now differs from the payload saved.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sorted: Vec<String>,
+    /// Timings of the save, with the `metrics` option.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<Metrics>,
+    /// What the `normalize` option changed in `workspace.json`; the file on
+    /// disk differs from the saved payload by these changes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub normalized: Vec<NormalizeChange>,
+}
+
+impl SaveResult {
+    /// Adds what staging a single book recorded in `part`; each book gets
+    /// its own while books are staged in parallel.
+    fn absorb(&mut self, part: SaveResult) {
+        self.skipped.extend(part.skipped);
+        self.modified.extend(part.modified);
+        self.hashes.extend(part.hashes);
+        self.text_styles.extend(part.text_styles);
+        self.warnings.extend(part.warnings);
+        self.large_cells.extend(part.large_cells);
+        self.files.extend(part.files);
+        self.patched.extend(part.patched);
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedFile {
+    pub path: String,
+    /// Bytes now on disk; 0 when the file was left untouched.
+    pub bytes_written: u64,
+    /// The file did not exist before the save.
+    pub created: bool,
+    /// The contents differed from what was on disk. Files found identical
+    /// are not rewritten; they still get `modified`, `hashes` and
+    /// `textStyles` entries so their stamps can be refreshed.
+    pub changed: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergedWrite {
+    /// The `filePath` that was not written on its own.
+    pub file_path: String,
+    /// The `filePath` written in its place.
+    pub written_as: String,
+}
+
+struct ResolvedBooks {
+    loaded: Vec<FilePayload>,
+    failed: Vec<BookLoadFailure>,
+    created: Vec<String>,
+    large_cells: Vec<LargeCell>,
+}
+
+/// Books and workspace files are migrated to the current schema on load.
+/// `hash` stays that of the content on disk, so a migrated file counts as
+/// dirty and the next save writes the upgraded form.
+fn load_book(
+    absolute_path: &Path,
+    passphrase: Option<&str>,
+    limit: Option<u64>,
+) -> WorkspaceResult<FilePayload> {
+    load_book_as(absolute_path, passphrase, limit, None)
+}
+
+/// [`load_book`] reading the text as `encoding`, or detecting it when that
+/// is `None`. Books converted from an encoding other than UTF-8 get no
+/// `hash`, so the next save writes them as UTF-8.
+fn load_book_as(
+    absolute_path: &Path,
+    passphrase: Option<&str>,
+    limit: Option<u64>,
+    encoding: Option<TextEncoding>,
+) -> WorkspaceResult<FilePayload> {
+    book_format(absolute_path).map_err(|err| err.in_file(FileKind::Book))?;
+    ensure_size_within(absolute_path, limit)?;
+    let parsed = with_open_file(|| read_json_file_decoded(absolute_path, passphrase, encoding))
+        .map_err(|err| err.in_file(FileKind::Book))?;
+    let converted = parsed.converted();
+    let (data, style) = (parsed.value, parsed.style);
+    let hash = content_hash(&data);
+    let (data, _) = migrate_book(data, absolute_path)?;
+    validate_book(&data)?;
+    Ok(FilePayload {
+        file_path: absolute_path.to_string_lossy().into_owned(),
+        hash: Some(hash).filter(|_| !converted),
+        data,
+        modified: modified_millis(absolute_path)?,
+        text_style: Some(style),
+        size_bytes: file_size(absolute_path),
+        data_path: None,
+        resolved_path: None,
+    })
+}
+
+fn load_workspace_file(workspace_path: &Path, limit: Option<u64>) -> WorkspaceResult<FilePayload> {
+    ensure_size_within(workspace_path, limit)?;
+    let (data, style) = read_workspace_json_styled(workspace_path)?;
+    let hash = content_hash(&data);
+    let (data, _) = migrate_workspace(data, workspace_path)?;
+    validate_workspace(&data)?;
+    Ok(FilePayload {
+        file_path: workspace_path.to_string_lossy().into_owned(),
+        hash: Some(hash),
+        data,
+        modified: modified_millis(workspace_path)?,
+        text_style: Some(style),
+        size_bytes: file_size(workspace_path),
+        data_path: None,
+        resolved_path: None,
+    })
+}
+
+/// Size of a file read for [`Recorder::file`]; 0 when it failed to load.
+fn loaded_size(loaded: &WorkspaceResult<FilePayload>) -> u64 {
+    loaded
+        .as_ref()
+        .ok()
+        .and_then(|payload| payload.size_bytes)
+        .unwrap_or(0)
+}
+
+/// `duplicateDataPath` errors for the books of `workspace_data`.
+fn duplicate_book_files(workspace_data: &Value, case_insensitive: bool) -> Vec<WorkspaceError> {
+    let books = workspace_data
+        .get("books")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    duplicate_data_paths(
+        books
+            .iter()
+            .map(|book_ref| book_ref.get("dataPath").and_then(Value::as_str)),
+        case_insensitive,
+    )
+}
+
+fn data_path_of(book_ref: &Value, index: usize) -> WorkspaceResult<&str> {
+    book_ref
+        .get("dataPath")
+        .and_then(Value::as_str)
+        .ok_or_else(|| {
+            WorkspaceError::invalid_schema(format!(
+                "books[{}].dataPath is missing or invalid",
+                index
+            ))
+        })
+}
+
+/// Absolute path of `books[index]`, rejecting `dataPath`s that escape the
+/// workspace directory.
+fn book_file_path(
+    workspace_dir: &Path,
+    book_ref: &Value,
+    index: usize,
+) -> WorkspaceResult<PathBuf> {
+    resolve_data_path(workspace_dir, data_path_of(book_ref, index)?, index)
+}
+
+/// Like `book_file_path`, also accepting the `allowedExternalRoots` of the
+/// workspace. Used wherever books are loaded or saved.
+fn allowed_book_file_path(
+    roots: &BookRoots,
+    book_ref: &Value,
+    index: usize,
+) -> WorkspaceResult<PathBuf> {
+    roots.resolve(data_path_of(book_ref, index)?, index)
+}
+
+/// Writes an empty book at `path` named and identified after `book_ref`
+/// unless a file is already there. Returns whether one was written.
+fn create_missing_book(path: &Path, book_ref: &Value) -> WorkspaceResult<bool> {
+    match fs::symlink_metadata(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        _ => return Ok(false),
+    }
+    let text = |field: &str| book_ref[field].as_str().unwrap_or_default();
+    let sheet_id = match text("activeSheetId") {
+        "" => format!("sheet-{}", uuid::Uuid::new_v4()),
+        id => id.to_string(),
+    };
+    let name = text("name");
+    let book = BookSkeleton {
+        schema_version: CURRENT_SCHEMA_VERSION,
+        book_id: text("id"),
+        name: name.strip_suffix(".json").unwrap_or(name),
+        sheet_id: &sheet_id,
+        grid_size: (DEFAULT_ROWS, DEFAULT_COLS),
+        now: &now_rfc3339(),
+    }
+    .build(Map::new());
+    write_tracked(path, &book, FileEncoding::default())?;
+    Ok(true)
+}
+
+/// Loads every referenced book independently, so one broken or missing file
+/// is reported in `failed` instead of aborting the whole workspace; with
+/// `options.create_missing`, missing files are created empty first.
+/// `on_start` gets the number of books and `on_book` is called as each one
+/// is attempted, from whichever thread loaded it. Once `token` is cancelled
+/// no further books are read and everything loaded so far is dropped.
+fn resolve_books(
+    workspace_path: &Path,
+    workspace_data: &Value,
+    options: &LoadOptions,
+    token: Option<&LoadToken>,
+    recorder: &Recorder,
+    on_start: impl FnOnce(usize),
+    on_book: impl Fn(&str) + Sync,
+) -> WorkspaceResult<ResolvedBooks> {
+    let cancelled = || token.is_some_and(LoadToken::is_cancelled);
+    let workspace_dir = workspace_dir_of(workspace_path);
+    let large_cell_threshold = options.large_cells.threshold()?;
+
+    let books = workspace_data
+        .get("books")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    if let Some(limit) = options.size_limits.book_count {
+        if books.len() as u64 > limit {
+            return Err(WorkspaceError::TooManyBooks {
+                path: workspace_path.display().to_string(),
+                count: books.len() as u64,
+                limit,
+            });
+        }
+    }
+
+    let roots = BookRoots::new(&workspace_dir, workspace_data);
+    let real_roots = (!options.follow_outside_links).then(|| roots.resolved());
+    let targets: Vec<(Option<&str>, WorkspaceResult<PathBuf>)> = books
+        .iter()
+        .enumerate()
+        .map(|(index, book_ref)| {
+            let data_path = book_ref.get("dataPath").and_then(Value::as_str);
+            let path =
+                allowed_book_file_path(&roots, book_ref, index).and_then(
+                    |path| match &real_roots {
+                        Some(real_roots) => ensure_links_within(real_roots, &path).map(|()| path),
+                        None => Ok(path),
+                    },
+                );
+            (data_path, path)
+        })
+        .collect();
+
+    on_start(targets.len());
+    let results = parallel_map(&targets, |index, (data_path, absolute_path)| {
+        if cancelled() {
+            return None;
+        }
+        let result = absolute_path.as_ref().ok().map(|path| {
+            let created = options.create_missing && create_missing_book(path, &books[index])?;
+            let passphrase = options.passphrase.as_deref();
+            let read = || {
+                recorder.file(
+                    &path.to_string_lossy(),
+                    Operation::Load,
+                    || load_book_as(path, passphrase, options.size_limits.book, options.encoding),
+                    loaded_size,
+                )
+            };
+            let mut book = if options.cached {
+                cache::load(workspace_path, path, read)?
+            } else {
+                read()?
+            };
+            if options.normalize_line_breaks {
+                newlines::normalize(&mut book.data, LineEnding::Lf);
+            }
+            let truncate = options.large_cells.truncate;
+            let large = large_cells::scan(&mut book.data, large_cell_threshold, truncate);
+            Ok((book, created, large))
+        });
+        match absolute_path {
+            Ok(path) => on_book(&path.to_string_lossy()),
+            Err(_) => on_book(data_path.unwrap_or_default()),
+        }
+        result
+    });
+    if cancelled() {
+        return Err(WorkspaceError::Cancelled {
+            path: workspace_path.display().to_string(),
+        });
+    }
+
+    let mut resolved = ResolvedBooks {
+        loaded: Vec::with_capacity(books.len()),
+        failed: Vec::new(),
+        created: Vec::new(),
+        large_cells: Vec::new(),
+    };
+    for (index, ((data_path, absolute_path), result)) in
+        targets.into_iter().zip(results).enumerate()
+    {
+        let outcome = absolute_path.and_then(|_| result.expect("resolved paths are always loaded"));
+        match outcome {
+            Ok((mut book, created, large)) => {
+                if options.resolved_paths {
+                    book.resolved_path = Some(real_path(Path::new(&book.file_path)));
+                    book.data_path = data_path.map(str::to_string);
+                }
+                if created {
+                    resolved.created.push(book.file_path.clone());
+                }
+                resolved.large_cells.extend(large);
+                resolved.loaded.push(book);
+            }
+            Err(error) => resolved.failed.push(BookLoadFailure {
+                index,
+                data_path: data_path.map(str::to_string),
+                error,
+            }),
+        }
+    }
+    Ok(resolved)
+}
+
+// Runs off the main thread so `cancel_load` can be handled while it loads.
+#[tauri::command(async)]
+pub fn load_workspace_snapshot(
+    path: String,
+    options: Option<LoadOptions>,
+) -> WorkspaceResult<WorkspaceSnapshotPayload> {
+    load_snapshot(
+        Path::new(&path),
+        options.unwrap_or_default(),
+        |_| {},
+        |_| {},
+    )
+}
+
+fn load_snapshot(
+    workspace_path: &Path,
+    options: LoadOptions,
+    on_start: impl FnOnce(usize),
+    on_book: impl Fn(&str) + Sync,
+) -> WorkspaceResult<WorkspaceSnapshotPayload> {
+    // `~/...` paths from the frontend or a shortcut; the payload reports
+    // the expanded path.
+    let workspace_path = &expand_home(workspace_path);
+    let token = options
+        .load_id
+        .as_deref()
+        .map(LoadToken::claim)
+        .transpose()?;
+    let recorder = Recorder::new(options.metrics);
+    let read = || {
+        recorder.file(
+            &workspace_path.to_string_lossy(),
+            Operation::Load,
+            || load_workspace_file(workspace_path, options.size_limits.workspace),
+            loaded_size,
+        )
+    };
+    let workspace = if options.cached {
+        cache::load(workspace_path, workspace_path, read)?
+    } else {
+        read()?
+    };
+    let mut duplicates = duplicate_book_files(&workspace.data, options.case_insensitive_paths);
+    if options.fail_on_duplicate_data_paths && !duplicates.is_empty() {
+        return Err(duplicates.remove(0));
+    }
+    let ResolvedBooks {
+        loaded,
+        mut failed,
+        created,
+        large_cells,
+    } = resolve_books(
+        workspace_path,
+        &workspace.data,
+        &options,
+        token.as_ref(),
+        &recorder,
+        on_start,
+        on_book,
+    )?;
+
+    if options.fail_if_all_books_fail && loaded.is_empty() && !failed.is_empty() {
+        let first = failed.remove(0);
+        return Err(WorkspaceError::BookLoad {
+            index: first.index,
+            source: Box::new(first.error),
+        });
+    }
+
+    let mut warnings = duplicates;
+    warnings.extend(manifest::verify_books(
+        &workspace_dir_of(workspace_path),
+        loaded.iter().map(|book| book.file_path.as_str()),
+    ));
+
+    if options.cached {
+        let files = loaded.iter().map(|book| book.file_path.as_str());
+        cache::retain(workspace_path, files.chain([workspace.file_path.as_str()]));
+    }
+
+    let read_only = is_read_only(&workspace.data);
+    let mut snapshot = WorkspaceSnapshotPayload {
+        workspace,
+        books: loaded,
+        failed,
+        warnings,
+        created,
+        large_cells,
+        read_only,
+        metrics: None,
+        workspace_dir: real_workspace_dir(workspace_path),
+    };
+    if options.save_migrated && !snapshot.read_only {
+        if let Err(err) = save_migrated(&mut snapshot, options.passphrase) {
+            snapshot.warnings.push(err);
+        }
+    }
+    if options.touch_last_opened && !snapshot.read_only {
+        if let Err(err) = touch_last_opened(&mut snapshot.workspace) {
+            snapshot.warnings.push(err);
+        }
+    }
+    snapshot.metrics = recorder.finish(Operation::Load, &snapshot.workspace.file_path);
+    Ok(snapshot)
+}
+
+/// Sets `workspace.lastOpened` to now in the file on disk and in `payload`.
+/// The file is edited as it is on disk, so a migration the load made but
+/// did not save stays pending in `payload` rather than being written here.
+fn touch_last_opened(payload: &mut FilePayload) -> WorkspaceResult<()> {
+    let path = Path::new(&payload.file_path);
+    let (mut on_disk, style) = read_workspace_json_styled(path)?;
+    let now = Value::String(now_rfc3339());
+    let set = |data: &mut Value| {
+        data.get_mut("workspace")
+            .and_then(Value::as_object_mut)
+            .map(|meta| meta.insert("lastOpened".into(), now.clone()))
+            .is_some()
+    };
+    if !set(&mut on_disk) {
+        return Ok(());
+    }
+    let encoding = FileEncoding {
+        style,
+        ..Default::default()
+    };
+    payload.modified = write_tracked(path, &on_disk, encoding)?;
+    payload.hash = Some(content_hash(&on_disk));
+    payload.size_bytes = file_size(path);
+    set(&mut payload.data);
+    Ok(())
+}
+
+/// Saves the files a load migrated (the dirty ones, as nothing else has
+/// touched them yet) and brings their payloads up to date with the disk.
+fn save_migrated(
+    snapshot: &mut WorkspaceSnapshotPayload,
+    passphrase: Option<String>,
+) -> WorkspaceResult<()> {
+    let migrated = WorkspaceSnapshotPayload {
+        workspace: snapshot.workspace.clone(),
+        books: snapshot
+            .books
+            .iter()
+            .filter(|book| book.is_dirty())
+            .cloned()
+            .collect(),
+        failed: Vec::new(),
+        warnings: Vec::new(),
+        created: Vec::new(),
+        large_cells: Vec::new(),
+        read_only: false,
+        metrics: None,
+        workspace_dir: String::new(),
+    };
+    let options = SaveOptions {
+        passphrase,
+        write: WriteOptions {
+            line_ending: LineEndingMode::Preserve,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let result = save_snapshot(migrated, None, Some(options))?;
+    // Results are keyed by the expanded paths, as a load reports them.
+    for file in std::iter::once(&mut snapshot.workspace).chain(&mut snapshot.books) {
+        if let Some(hash) = result.hashes.get(&file.file_path) {
+            file.hash = Some(hash.clone());
+            file.modified = result.modified.get(&file.file_path).copied();
+            file.text_style = result.text_styles.get(&file.file_path).copied();
+            file.size_bytes = file_size(Path::new(&file.file_path));
+        }
+    }
+    Ok(())
+}
+
+/// Fails with `Conflict` when a file changed on disk since it was loaded.
+/// Files without a recorded `modified` (e.g. newly created books) are not
+/// checked.
+fn ensure_unchanged_on_disk(file: &FilePayload) -> WorkspaceResult<()> {
+    let Some(expected) = file.modified else {
+        return Ok(());
+    };
+    if modified_millis(Path::new(&file.file_path))? != Some(expected) {
+        return Err(WorkspaceError::Conflict {
+            path: file.file_path.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Writes a snapshot; `save_workspace_snapshot` is this plus its events.
+fn save_snapshot(
+    mut snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+) -> WorkspaceResult<SaveResult> {
+    let options = options.unwrap_or_default();
+    if options.compression_level.is_some_and(|level| level > 9) {
+        return Err(WorkspaceError::InvalidOption {
+            name: "compressionLevel",
+            message: "must be between 0 and 9".into(),
+        });
+    }
+    let large_cell_threshold = options.large_cells.threshold()?;
+    let recorder = Recorder::new(options.metrics);
+    // Results are keyed by the expanded paths, as a load reports them.
+    for file in std::iter::once(&mut snapshot.workspace).chain(&mut snapshot.books) {
+        file.file_path = expand_home(Path::new(&file.file_path))
+            .to_string_lossy()
+            .into_owned();
+    }
+    let normalized = match options.normalize {
+        true => normalize::normalize(&mut snapshot.workspace.data, &now_rfc3339()),
+        false => Vec::new(),
+    };
+    let workspace_path = PathBuf::from(&snapshot.workspace.file_path);
+    let lock_timeout = options
+        .lock_timeout_ms
+        .map_or(save_lock::DEFAULT_TIMEOUT, Duration::from_millis);
+    let _saving = save_lock::acquire(&workspace_path, lock_timeout)?;
+    ensure_writable(&workspace_path, &snapshot.workspace.data)?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let roots = BookRoots::new(&workspace_dir, &snapshot.workspace.data);
+    for (index, book) in snapshot.books.iter().enumerate() {
+        roots.ensure_allows(Path::new(&book.file_path), index)?;
+    }
+    let merged = merge_same_file_books(&roots.resolved(), &mut snapshot.books)?;
+    let mut warnings = Vec::new();
+    if let Some(mismatch) = consistency::check_snapshot(
+        &roots,
+        &snapshot.workspace,
+        &snapshot.books,
+        options.case_insensitive_paths,
+    ) {
+        match options.consistency_check {
+            ConsistencyCheck::Strict => return Err(mismatch),
+            ConsistencyCheck::Warn => warnings.push(mismatch.to_string()),
+        }
+    }
+    if let Some(duplicate) =
+        duplicate_book_files(&snapshot.workspace.data, options.case_insensitive_paths)
+            .into_iter()
+            .next()
+    {
+        return Err(duplicate);
+    }
+
+    let mut three_way = Vec::new();
+    if let Some(merge) = &options.three_way {
+        three_way =
+            three_way::merge_with_disk(&mut snapshot.books, merge, options.passphrase.as_deref())?;
+        if merge.mode == ThreeWayMode::Confirm {
+            return Ok(SaveResult {
+                warnings,
+                merged,
+                three_way,
+                ..Default::default()
+            });
+        }
+        if let Some(book) = three_way.iter().find(|book| !book.conflicts.is_empty()) {
+            return Err(WorkspaceError::MergeConflict {
+                path: book.file_path.clone(),
+                conflicts: book.conflicts.clone(),
+            });
+        }
+    }
+
+    if let Some(ending) = options.cell_line_breaks {
+        for book in &mut snapshot.books {
+            newlines::normalize(&mut book.data, ending);
+        }
+    }
+    let sort_specs = sort::sort_specs(&roots, &snapshot.workspace.data)?;
+    let mut sorted = Vec::new();
+    for book in &mut snapshot.books {
+        let spec =
+            normalize_lexically(Path::new(&book.file_path)).and_then(|path| sort_specs.get(&path));
+        if spec.is_some_and(|spec| sort::sort_book(&mut book.data, spec) > 0) {
+            sorted.push(book.file_path.clone());
+        }
+    }
+
+    let encrypted = encrypted_book_paths(&roots, &snapshot.workspace.data);
+    let should_encrypt = |book: &FilePayload| {
+        normalize_lexically(Path::new(&book.file_path))
+            .is_some_and(|path| encrypted.contains(&path))
+    };
+    // A book whose encryption flag was toggled is rewritten even without
+    // content changes so the file on disk matches `workspace.json`.
+    let needs_write = |book: &FilePayload| {
+        book.is_dirty() || should_encrypt(book) != is_encrypted_file(Path::new(&book.file_path))
+    };
+    if options.passphrase.is_none() {
+        if let Some(book) = snapshot
+            .books
+            .iter()
+            .find(|book| should_encrypt(book) && needs_write(book))
+        {
+            return Err(WorkspaceError::PassphraseRequired {
+                path: book.file_path.clone(),
+            });
+        }
+    }
+
+    let workspace_dirty = snapshot.workspace.is_dirty();
+    if !force.unwrap_or(false) {
+        if workspace_dirty {
+            ensure_unchanged_on_disk(&snapshot.workspace)?;
+        }
+        for book in snapshot.books.iter().filter(|book| book.is_dirty()) {
+            ensure_unchanged_on_disk(book)?;
+        }
+    }
+
+    let planned: Vec<PlannedWrite> = std::iter::once(&snapshot.workspace)
+        .filter(|_| workspace_dirty)
+        .chain(snapshot.books.iter().filter(|book| needs_write(book)))
+        .map(|file| PlannedWrite {
+            path: Path::new(&file.file_path),
+            data: &file.data,
+            style: options.write.style_for(
+                file.text_style,
+                file.file_path == snapshot.workspace.file_path,
+            ),
+            backup: options.backup.enabled,
+        })
+        .collect();
+    space::ensure_disk_space(&workspace_dir, &planned)?;
+
+    let plain = FileEncoding {
+        compression_level: options.compression_level,
+        retry: options.retry.policy(),
+        ..Default::default()
+    };
+    let mut result = SaveResult {
+        warnings,
+        merged,
+        three_way,
+        sorted,
+        normalized,
+        ..Default::default()
+    };
+    let stage_book = |mut book: FilePayload,
+                      result: &mut SaveResult,
+                      transaction: &mut Transaction|
+     -> WorkspaceResult<()> {
+        let truncate = options.large_cells.truncate;
+        let large = large_cells::scan(&mut book.data, large_cell_threshold, truncate);
+        result.large_cells.extend(large);
+        let encoding = FileEncoding {
+            intern_strings: options.intern_strings,
+            passphrase: options
+                .passphrase
+                .as_deref()
+                .filter(|_| should_encrypt(&book)),
+            style: options.write.style_for(book.text_style, false),
+            ..plain
+        };
+        let plan = if options.patches.enabled && encoding.passphrase.is_none() {
+            patches::plan(&book, options.passphrase.as_deref(), options.patches)
+        } else {
+            PatchPlan::Full
+        };
+        match plan {
+            PatchPlan::Append(contents) => patches::stage_patch(
+                &book,
+                &contents,
+                encoding.style,
+                plain.retry,
+                result,
+                transaction,
+            ),
+            PatchPlan::Unchanged => patches::record_unchanged(&book, encoding.style, result),
+            PatchPlan::Full => {
+                stage_file(
+                    &book,
+                    encoding,
+                    &workspace_dir,
+                    &options,
+                    result,
+                    transaction,
+                    &recorder,
+                )?;
+                patches::stage_clear(Path::new(&book.file_path), plain.retry, transaction)
+            }
+        }
+    };
+    // Every file is staged before any is renamed into place, so a failure
+    // leaves the workspace as it was (see `transaction`). Books are staged
+    // in parallel, which is safe as `merge_same_file_books` has made sure
+    // no two of them write the same file; each payload is freed once
+    // staged. After a failure no further book is started and the first
+    // failing book, in snapshot order, is the error. Open-file permits are
+    // only taken around the reads and writes themselves: `patches::plan`
+    // loads the book, which takes one of its own. `workspace.json` comes
+    // last, after the books it lists.
+    let pending: Vec<Mutex<Option<FilePayload>>> = snapshot
+        .books
+        .into_iter()
+        .filter(|book| needs_write(book))
+        .map(|book| Mutex::new(Some(book)))
+        .collect();
+    let failed = AtomicBool::new(false);
+    let staged = parallel_map(&pending, |_, slot| {
+        let book = slot
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+            .expect("each book is staged once");
+        if failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut part = SaveResult::default();
+        let mut transaction = Transaction::default();
+        let outcome = stage_book(book, &mut part, &mut transaction);
+        if outcome.is_err() {
+            failed.store(true, Ordering::Relaxed);
+        }
+        Some(outcome.map(|()| (part, transaction)))
+    });
+    let mut transaction = Transaction::default();
+    for outcome in staged.into_iter().flatten() {
+        let (part, staged) = outcome?;
+        result.absorb(part);
+        transaction.append(staged);
+    }
+    if workspace_dirty {
+        let encoding = FileEncoding {
+            style: options.write.style_for(snapshot.workspace.text_style, true),
+            ..plain
+        };
+        stage_file(
+            &snapshot.workspace,
+            encoding,
+            &workspace_dir,
+            &options,
+            &mut result,
+            &mut transaction,
+            &recorder,
+        )?;
+    }
+    for (path, modified) in commit_tracked(transaction)? {
+        if let Some(modified) = modified {
+            result.modified.insert(path.clone(), modified);
+        }
+        result.written.push(path);
+    }
+
+    cache::forget(&result.written);
+    let written_books: Vec<PathBuf> = result
+        .written
+        .iter()
+        .filter(|path| **path != snapshot.workspace.file_path)
+        .map(PathBuf::from)
+        .filter(|path| !patches::is_patch_file(path))
+        .collect();
+    if !written_books.is_empty() {
+        let book_paths: Vec<PathBuf> = snapshot.workspace.data["books"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(index, book_ref)| book_file_path(&workspace_dir, book_ref, index).ok())
+            .collect();
+        if let Err(err) = manifest::update_manifest(&workspace_dir, &book_paths, &written_books) {
+            result
+                .warnings
+                .push(format!("Checksum manifest not updated: {}", err));
+        }
+    }
+
+    result.metrics = recorder.finish(Operation::Save, &snapshot.workspace.file_path);
+    Ok(result)
+}
+
+/// `save_snapshot` as `save_workspace_snapshot` runs it. Once a save of a
+/// book for every entry of `workspace.json` succeeds, nothing the edit
+/// journal recorded is left unsaved and it is cleared. Partial saves, a
+/// three-way `confirm` and internal saves such as `save_migrated` keep it
+/// for `recover_from_journal`.
+fn save_snapshot_clearing_journal(
+    snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+) -> WorkspaceResult<SaveResult> {
+    let confirming = options
+        .as_ref()
+        .and_then(|options| options.three_way.as_ref())
+        .is_some_and(|merge| merge.mode == ThreeWayMode::Confirm);
+    let clears_journal = !confirming && covers_every_book(&snapshot);
+    let workspace_dir = workspace_dir_of(&expand_home(Path::new(&snapshot.workspace.file_path)));
+    let mut result = save_snapshot(snapshot, force, options)?;
+    if clears_journal {
+        if let Err(err) = journal::clear(&workspace_dir) {
+            result
+                .warnings
+                .push(format!("Edit journal not cleared: {}", err));
+        }
+    }
+    Ok(result)
+}
+
+/// Whether `snapshot` holds the file of every book in `workspace.json`
+/// whose `dataPath` resolves.
+fn covers_every_book(snapshot: &WorkspaceSnapshotPayload) -> bool {
+    let spelled = |path: &Path| normalize_lexically(&absolute(&expand_home(path)));
+    let workspace_path = expand_home(Path::new(&snapshot.workspace.file_path));
+    let roots = BookRoots::new(&workspace_dir_of(&workspace_path), &snapshot.workspace.data);
+    let held: HashSet<PathBuf> = snapshot
+        .books
+        .iter()
+        .filter_map(|book| spelled(Path::new(&book.file_path)))
+        .collect();
+    snapshot.workspace.data["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(index, book_ref)| allowed_book_file_path(&roots, book_ref, index).ok())
+        .all(|path| spelled(&path).is_some_and(|path| held.contains(&path)))
+}
+
+/// Drops books whose `filePath` names the same file as an earlier one once
+/// symbolic links, `.` and `..` are resolved, so that each file is written
+/// once. A book resolving outside every one of `real_roots` is refused like
+/// a path escaping them lexically, and two spellings of one file with
+/// different contents are a `duplicateDataPath` error since either write
+/// would lose the other.
+fn merge_same_file_books(
+    real_roots: &[PathBuf],
+    books: &mut Vec<FilePayload>,
+) -> WorkspaceResult<Vec<MergedWrite>> {
+    let mut first_by_file: HashMap<PathBuf, usize> = HashMap::new();
+    let mut merged = Vec::new();
+    let mut keep = Vec::with_capacity(books.len());
+    for (index, book) in books.iter().enumerate() {
+        let Some(real) = canonicalize_lenient(Path::new(&book.file_path)) else {
+            keep.push(true);
+            continue;
+        };
+        if !real_roots.is_empty() && !real_roots.iter().any(|root| real.starts_with(root)) {
+            return Err(WorkspaceError::PathOutsideWorkspace {
+                field: format!("books[{}].filePath", index),
+                path: real.display().to_string(),
+            });
+        }
+        match first_by_file.entry(real) {
+            Entry::Vacant(entry) => {
+                entry.insert(index);
+                keep.push(true);
+            }
+            Entry::Occupied(entry) => {
+                let first = &books[*entry.get()];
+                if first.data != book.data {
+                    return Err(WorkspaceError::DuplicateDataPath {
+                        path: first.file_path.clone(),
+                        indices: vec![*entry.get(), index],
+                    });
+                }
+                merged.push(MergedWrite {
+                    file_path: book.file_path.clone(),
+                    written_as: first.file_path.clone(),
+                });
+                keep.push(false);
+            }
+        }
+    }
+    let mut keep = keep.into_iter();
+    books.retain(|_| keep.next().unwrap_or(true));
+    Ok(merged)
+}
+
+/// Normalized paths of books marked `encrypted: true` in `workspace.json`.
+fn encrypted_book_paths(roots: &BookRoots, workspace_data: &Value) -> HashSet<PathBuf> {
+    workspace_data["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, book_ref)| book_ref["encrypted"] == true)
+        .filter_map(|(index, book_ref)| {
+            let path = allowed_book_file_path(roots, book_ref, index).ok()?;
+            normalize_lexically(&path)
+        })
+        .collect()
+}
+
+/// Writes a JSON file without triggering our own file watcher and returns
+/// its new modification time.
+fn write_tracked(
+    path: &Path,
+    value: &Value,
+    encoding: FileEncoding,
+) -> WorkspaceResult<Option<u64>> {
+    watcher::begin_self_write(path);
+    let written = write_json_file(path, value, encoding).and_then(|()| modified_millis(path));
+    watcher::end_self_write(path, written.as_ref().ok().copied().flatten());
+    written
+}
+
+/// Renames a file without triggering our own file watcher at either end.
+fn rename_tracked(from: &Path, to: &Path) -> WorkspaceResult<()> {
+    watcher::self_remove(from);
+    watcher::begin_self_write(to);
+    let moved = fs::rename(from, to).map_err(|err| WorkspaceError::io("move", from, err));
+    let modified = moved
+        .as_ref()
+        .ok()
+        .and_then(|()| modified_millis(to).ok().flatten());
+    watcher::end_self_write(to, modified);
+    moved
+}
+
+/// Commits a save's files without triggering our own file watcher and
+/// returns each one's new modification time. Files put back after a failed
+/// rename are recorded with the time they have again.
+fn commit_tracked(transaction: Transaction) -> WorkspaceResult<Vec<(String, Option<u64>)>> {
+    let paths: Vec<PathBuf> = transaction.paths().map(Path::to_path_buf).collect();
+    for path in &paths {
+        watcher::begin_self_write(path);
+    }
+    let committed = transaction.commit();
+    let times = paths
+        .iter()
+        .map(|path| {
+            let modified = modified_millis(path).ok().flatten();
+            watcher::end_self_write(path, modified);
+            (path.to_string_lossy().into_owned(), modified)
+        })
+        .collect();
+    committed.map(|()| times)
+}
+
+/// Adds `file` to the save: staged in `transaction` when it differs from
+/// what is on disk, otherwise recorded as skipped.
+fn stage_file(
+    file: &FilePayload,
+    encoding: FileEncoding,
+    workspace_dir: &Path,
+    options: &SaveOptions,
+    result: &mut SaveResult,
+    transaction: &mut Transaction,
+    recorder: &Recorder,
+) -> WorkspaceResult<()> {
+    let path = Path::new(&file.file_path);
+    let created = !path.exists();
+    let changed = created || !with_open_file(|| matches_on_disk(path, &file.data, encoding));
+    let bytes_written = if changed {
+        if options.backup.enabled {
+            if let Err(err) = create_backup(workspace_dir, path, options.backup.generations) {
+                result
+                    .warnings
+                    .push(format!("Backup skipped for {}: {}", path.display(), err));
+            }
+        }
+        recorder.file(
+            &file.file_path,
+            Operation::Save,
+            || transaction.stage(path, &file.data, encoding),
+            |staged| *staged.as_ref().unwrap_or(&0),
+        )?
+    } else {
+        result.skipped.push(file.file_path.clone());
+        if let Some(modified) = modified_millis(path)? {
+            result.modified.insert(file.file_path.clone(), modified);
+        }
+        0
+    };
+    result.files.push(SavedFile {
+        path: file.file_path.clone(),
+        bytes_written,
+        created,
+        changed,
+    });
+    result
+        .text_styles
+        .insert(file.file_path.clone(), encoding.style);
+    result
+        .hashes
+        .insert(file.file_path.clone(), content_hash(&file.data));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_book_file(path: String) -> WorkspaceResult<()> {
+    let path = PathBuf::from(path);
+    watcher::self_remove(&path);
+    match fs::remove_file(&path) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            if err.kind() == std::io::ErrorKind::NotFound {
+                Ok(())
+            } else {
+                Err(WorkspaceError::io("delete", &path, err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::{read_json_file, LineEnding, LineEndingMode};
+    use serde_json::json;
+
+    fn book_json(id: &str) -> Value {
+        json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": id, "name": id },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet 1",
+                "gridSize": { "rows": 100, "cols": 26 },
+                "rows": {}
+            }]
+        })
+    }
+
+    fn write_workspace(dir: &Path, book_count: usize, missing: &[usize]) -> String {
+        let books: Vec<Value> = (0..book_count)
+            .map(|index| {
+                json!({
+                    "id": format!("book-{}", index),
+                    "name": format!("Book {}", index),
+                    "dataPath": format!("books/book-{}.json", index),
+                })
+            })
+            .collect();
+        for index in (0..book_count).filter(|index| !missing.contains(index)) {
+            write_json_file(
+                &dir.join(format!("books/book-{}.json", index)),
+                &book_json(&format!("book-{}", index)),
+                FileEncoding::default(),
+            )
+            .unwrap();
+        }
+        let workspace_path = dir.join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "books": books }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        workspace_path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn load_keeps_readable_books_and_reports_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 20, &[7, 12]);
+
+        let snapshot = load_workspace_snapshot(path, None).unwrap();
+
+        assert_eq!(snapshot.books.len(), 18);
+        assert_eq!(snapshot.books[7].data["book"]["id"], "book-8");
+        let failed: Vec<usize> = snapshot.failed.iter().map(|f| f.index).collect();
+        assert_eq!(failed, vec![7, 12]);
+        assert!(matches!(
+            snapshot.failed[0].error,
+            WorkspaceError::NotFound { .. }
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_reports_the_real_workspace_directory() {
+        use std::os::unix::fs::symlink;
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        fs::create_dir(&real).unwrap();
+        write_workspace(&real, 1, &[]);
+        let linked = dir.path().join("linked");
+        symlink(&real, &linked).unwrap();
+
+        let path = linked
+            .join("./workspace.json")
+            .to_string_lossy()
+            .into_owned();
+        let snapshot = load_workspace_snapshot(path, None).unwrap();
+        let expected = fs::canonicalize(&real).unwrap();
+        assert_eq!(Path::new(&snapshot.workspace_dir), expected);
+        let payload = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(payload["workspaceDir"], expected.to_string_lossy().as_ref());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolved_paths_tie_books_to_their_entries() {
+        use std::os::unix::fs::symlink;
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        fs::create_dir(&real).unwrap();
+        write_workspace(&real, 3, &[]);
+        let workspace_path = real.join("workspace.json");
+        let mut workspace = read_json_file(&workspace_path).unwrap();
+        workspace["books"][1]["dataPath"] = json!("./books/../books/book-1.json");
+        workspace["books"][2]["name"] = workspace["books"][0]["name"].clone();
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+        let linked = dir.path().join("linked");
+        symlink(&real, &linked).unwrap();
+        let path = linked.join("workspace.json").to_string_lossy().into_owned();
+
+        let options = LoadOptions {
+            resolved_paths: true,
+            ..Default::default()
+        };
+        let snapshot = load_workspace_snapshot(path.clone(), Some(options)).unwrap();
+        let real = fs::canonicalize(&real).unwrap();
+        for (index, book) in snapshot.books.iter().enumerate() {
+            assert_eq!(
+                book.data_path.as_deref(),
+                workspace["books"][index]["dataPath"].as_str()
+            );
+            let resolved = real.join(format!("books/book-{}.json", index));
+            assert_eq!(book.resolved_path.as_deref(), resolved.to_str());
+        }
+        assert_eq!(Path::new(&snapshot.workspace_dir), real);
+        assert_eq!(snapshot.workspace.resolved_path, None);
+
+        let snapshot = load_workspace_snapshot(path, None).unwrap();
+        assert_eq!(snapshot.books[0].resolved_path, None);
+        let payload = serde_json::to_value(&snapshot.books[0]).unwrap();
+        assert!(payload.get("dataPath").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_refuses_links_leading_out_of_the_workspace_unless_allowed() {
+        use std::os::unix::fs::symlink;
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 3, &[0, 1]);
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("book-0.json");
+        write_json_file(&target, &book_json("book-0"), FileEncoding::default()).unwrap();
+        symlink(&target, dir.path().join("books/book-0.json")).unwrap();
+        let shared = dir.path().join("shared/book-1.json");
+        write_json_file(&shared, &book_json("book-1"), FileEncoding::default()).unwrap();
+        symlink(
+            "../shared/book-1.json",
+            dir.path().join("books/book-1.json"),
+        )
+        .unwrap();
+
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        assert_eq!(snapshot.books.len(), 2);
+        assert_eq!(snapshot.failed.len(), 1);
+        assert_eq!(snapshot.failed[0].index, 0);
+        assert!(matches!(
+            &snapshot.failed[0].error,
+            WorkspaceError::SymlinkEscapesWorkspace { target: linked, .. }
+                if Path::new(linked) == fs::canonicalize(&target).unwrap()
+        ));
+
+        let options = LoadOptions {
+            follow_outside_links: true,
+            ..Default::default()
+        };
+        let snapshot = load_workspace_snapshot(path, Some(options)).unwrap();
+        assert!(snapshot.failed.is_empty());
+        assert_eq!(snapshot.books[0].data["book"]["id"], "book-0");
+    }
+
+    #[test]
+    fn external_roots_let_books_outside_the_workspace_load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_dir = dir.path().join("ws");
+        let path = write_workspace(&workspace_dir, 1, &[]);
+        let shared = dir.path().join("shared/x.json");
+        write_json_file(&shared, &book_json("shared"), FileEncoding::default()).unwrap();
+        let list_shared = |roots: Value| {
+            let mut workspace = read_json_file(Path::new(&path)).unwrap();
+            workspace["books"].as_array_mut().unwrap().push(json!({
+                "id": "shared", "name": "Shared", "dataPath": "../shared/x.json"
+            }));
+            workspace["allowedExternalRoots"] = roots;
+            write_json_file(Path::new(&path), &workspace, FileEncoding::default()).unwrap();
+        };
+
+        list_shared(json!(["../shared"]));
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        assert!(snapshot.failed.is_empty());
+        assert_eq!(snapshot.books[1].data["book"]["id"], "shared");
+        snapshot.books[1].data["book"]["name"] = json!("Renamed");
+        save_snapshot(snapshot, None, None).unwrap();
+        assert_eq!(read_json_file(&shared).unwrap()["book"]["name"], "Renamed");
+
+        list_shared(json!(["../elsewhere"]));
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        assert!(matches!(
+            snapshot.failed[0].error,
+            WorkspaceError::PathOutsideWorkspace { .. }
+        ));
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        let mut outside = snapshot.books[0].clone();
+        outside.file_path = shared.to_string_lossy().into_owned();
+        snapshot.books.push(outside);
+        assert!(matches!(
+            save_snapshot(snapshot, None, None),
+            Err(WorkspaceError::PathOutsideWorkspace { .. })
+        ));
+    }
+
+    #[test]
+    fn line_breaks_in_cells_can_be_unified_on_load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let book_path = dir.path().join("books/book-0.json");
+        let mut book = book_json("book-0");
+        book["sheets"][0]["rows"] = json!({ "1": { "A": { "value": "one\r\ntwo\rthree" } } });
+        write_json_file(&book_path, &book, FileEncoding::default()).unwrap();
+
+        let options = LoadOptions {
+            normalize_line_breaks: true,
+            ..Default::default()
+        };
+        let snapshot = load_workspace_snapshot(path, Some(options)).unwrap();
+        let cell = &snapshot.books[0].data["sheets"][0]["rows"]["1"]["A"]["value"];
+        assert_eq!(cell, "one\ntwo\nthree");
+
+        let options = SaveOptions {
+            cell_line_breaks: Some(LineEnding::Crlf),
+            ..Default::default()
+        };
+        let result = save_snapshot(snapshot, None, Some(options)).unwrap();
+        assert_eq!(result.written, [book_path.to_string_lossy()]);
+        let saved = read_json_file(&book_path).unwrap();
+        assert_eq!(
+            saved["sheets"][0]["rows"]["1"]["A"]["value"],
+            "one\r\ntwo\r\nthree"
+        );
+    }
+
+    #[test]
+    fn large_cells_are_reported_and_only_truncated_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let book_path = dir.path().join("books/book-0.json");
+        let mut book = book_json("book-0");
+        book["sheets"][0]["rows"] = json!({ "3": { "B": { "value": "x".repeat(64) } } });
+        write_json_file(&book_path, &book, FileEncoding::default()).unwrap();
+        let large_cells = |truncate| LargeCellOptions {
+            threshold_bytes: Some(16),
+            truncate,
+        };
+
+        let options = LoadOptions {
+            large_cells: large_cells(false),
+            ..Default::default()
+        };
+        let mut snapshot = load_workspace_snapshot(path, Some(options)).unwrap();
+        assert_eq!(snapshot.large_cells.len(), 1);
+        assert_eq!(snapshot.large_cells[0].book_id, "book-0");
+        assert_eq!(snapshot.large_cells[0].cell, "B3");
+        assert_eq!(snapshot.large_cells[0].size, 64);
+
+        snapshot.books[0].data["book"]["name"] = json!("Edited");
+        let options = SaveOptions {
+            large_cells: large_cells(true),
+            ..Default::default()
+        };
+        let result = save_snapshot(snapshot, None, Some(options)).unwrap();
+        assert!(result.large_cells[0].truncated);
+        let saved = read_json_file(&book_path).unwrap();
+        assert_eq!(
+            saved["sheets"][0]["rows"]["3"]["B"]["value"],
+            "x".repeat(16)
+        );
+    }
+
+    #[test]
+    fn load_can_create_missing_books() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 3, &[1]);
+        let missing = dir.path().join("books/book-1.json");
+        let options = LoadOptions {
+            create_missing: true,
+            ..Default::default()
+        };
+
+        let snapshot = load_workspace_snapshot(path.clone(), Some(options)).unwrap();
+        assert!(snapshot.failed.is_empty());
+        assert_eq!(
+            snapshot.created,
+            vec![missing.to_string_lossy().into_owned()]
+        );
+        let created = &snapshot.books[1].data;
+        assert_eq!(created["book"]["id"], "book-1");
+        assert_eq!(created["book"]["name"], "Book 1");
+        assert_eq!(created["sheets"][0]["rows"], json!({}));
+        // Files made by an earlier load are not reported again.
+        let options = LoadOptions {
+            create_missing: true,
+            ..Default::default()
+        };
+        assert!(load_workspace_snapshot(path, Some(options))
+            .unwrap()
+            .created
+            .is_empty());
+    }
+
+    #[test]
+    fn load_can_fail_when_every_book_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[0, 1]);
+        let options = LoadOptions {
+            fail_if_all_books_fail: true,
+            ..Default::default()
+        };
+
+        let err = load_workspace_snapshot(path, Some(options)).unwrap_err();
+        assert!(err.to_string().starts_with("books[0]: File not found"));
+    }
+
+    #[test]
+    fn load_stops_once_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 40, &[]);
+        let load_id = issue_load_id();
+        let options = LoadOptions {
+            load_id: Some(load_id.clone()),
+            ..Default::default()
+        };
+        let attempted = std::sync::atomic::AtomicUsize::new(0);
+
+        let err = load_snapshot(
+            Path::new(&path),
+            options,
+            |_| {},
+            |_| {
+                attempted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                cancel_load(load_id.clone());
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, WorkspaceError::Cancelled { .. }));
+        assert!(attempted.into_inner() < 40);
+        // The id is released with the load and cannot be reused.
+        assert!(!cancel_load(load_id.clone()));
+        let options = LoadOptions {
+            load_id: Some(load_id),
+            ..Default::default()
+        };
+        assert!(matches!(
+            load_workspace_snapshot(path, Some(options)),
+            Err(WorkspaceError::InvalidOption { name: "loadId", .. })
+        ));
+    }
+
+    #[test]
+    fn books_sharing_a_data_path_are_reported_and_never_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 3, &[]);
+        let mut workspace = read_json_file(Path::new(&path)).unwrap();
+        workspace["books"][2]["dataPath"] = json!("books/Book-0.json");
+        write_json_file(Path::new(&path), &workspace, FileEncoding::default()).unwrap();
+        let insensitive = || LoadOptions {
+            case_insensitive_paths: true,
+            ..Default::default()
+        };
+
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        assert!(snapshot.warnings.is_empty());
+        let snapshot = load_workspace_snapshot(path.clone(), Some(insensitive())).unwrap();
+        assert!(matches!(
+            &snapshot.warnings[..],
+            [WorkspaceError::DuplicateDataPath { indices, .. }] if *indices == [0, 2]
+        ));
+        let strict = LoadOptions {
+            fail_on_duplicate_data_paths: true,
+            ..insensitive()
+        };
+        assert!(load_workspace_snapshot(path.clone(), Some(strict)).is_err());
+
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        snapshot.workspace.data["books"][2]["dataPath"] = json!("./books/book-0.json");
+        snapshot.books[0].data["book"]["name"] = json!("Edited");
+        let err = save_snapshot(snapshot, None, None).unwrap_err();
+        assert!(matches!(err, WorkspaceError::DuplicateDataPath { .. }));
+        assert_eq!(
+            read_json_file(&dir.path().join("books/book-0.json")).unwrap()["book"]["name"],
+            "book-0"
+        );
+    }
+
+    #[test]
+    fn save_rejects_files_changed_since_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": [] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let path = workspace_path.to_string_lossy().into_owned();
+
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        snapshot.workspace.data["workspace"] = json!({ "name": "Edited" });
+        snapshot.workspace.modified = snapshot.workspace.modified.map(|value| value - 1);
+
+        let err = save_snapshot(snapshot, None, None).unwrap_err();
+        assert!(matches!(err, WorkspaceError::Conflict { .. }));
+
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        snapshot.workspace.hash = None;
+        snapshot.workspace.modified = Some(0);
+        let result = save_snapshot(snapshot, Some(true), None).unwrap();
+        assert!(result.modified.contains_key(&path));
+    }
+
+    #[test]
+    fn compressed_and_plain_books_load_and_save_side_by_side() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for (index, data_path) in ["books/plain.json", "books/packed.json.gz"]
+            .iter()
+            .enumerate()
+        {
+            let path = dir.path().join(data_path);
+            write_json_file(
+                &path,
+                &book_json(&format!("book-{}", index)),
+                FileEncoding::default(),
+            )
+            .unwrap();
+            paths.push((data_path, path));
+        }
+        let books: Vec<Value> = paths
+            .iter()
+            .map(|(data_path, _)| json!({ "id": "book-x", "name": "x", "dataPath": data_path }))
+            .collect();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": books }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+
+        let mut snapshot =
+            load_workspace_snapshot(workspace_path.to_string_lossy().into_owned(), None).unwrap();
+        assert_eq!(snapshot.books[1].data["book"]["id"], "book-1");
+
+        snapshot.books[1].data["book"]["name"] = json!("Renamed");
+        let options = SaveOptions {
+            compression_level: Some(1),
+            ..Default::default()
+        };
+        save_snapshot(snapshot, None, Some(options)).unwrap();
+        assert_eq!(&fs::read(&paths[1].1).unwrap()[..2], &[0x1f, 0x8b]);
+        assert!(fs::read_to_string(&workspace_path)
+            .unwrap()
+            .contains("packed.json.gz"));
+        assert_eq!(
+            read_json_file(&paths[1].1).unwrap()["book"]["name"],
+            "Renamed"
+        );
+    }
+
+    #[test]
+    fn interned_books_round_trip_and_small_books_stay_plain() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut big = book_json("big");
+        let rows: serde_json::Map<String, Value> = (1..=200)
+            .map(|row| {
+                let category = ["Accounts receivable", "Accounts payable"][row % 2];
+                (row.to_string(), json!({ "A": { "value": category } }))
+            })
+            .collect();
+        big["sheets"][0]["gridSize"] = json!({ "rows": 200, "cols": 1 });
+        big["sheets"][0]["rows"] = Value::Object(rows);
+        let big_path = dir.path().join("books/big.json");
+        let small_path = dir.path().join("books/small.json");
+        write_json_file(&big_path, &big, FileEncoding::default()).unwrap();
+        write_json_file(&small_path, &book_json("small"), FileEncoding::default()).unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": [
+                { "id": "big", "name": "Big", "dataPath": "books/big.json" },
+                { "id": "small", "name": "Small", "dataPath": "books/small.json" }
+            ] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let workspace = workspace_path.to_string_lossy().into_owned();
+        let plain_size = fs::metadata(&big_path).unwrap().len();
+
+        let mut snapshot = load_workspace_snapshot(workspace.clone(), None).unwrap();
+        for book in &mut snapshot.books {
+            book.data["book"]["name"] = json!("Edited");
+        }
+        let expected: Vec<Value> = snapshot.books.iter().map(|b| b.data.clone()).collect();
+        let options = || SaveOptions {
+            intern_strings: true,
+            ..Default::default()
+        };
+        save_snapshot(snapshot, None, Some(options())).unwrap();
+
+        let stored = fs::read_to_string(&big_path).unwrap();
+        assert!(stored.contains(intern::MARKER));
+        assert!((stored.len() as u64) * 10 < plain_size * 9);
+        assert!(!fs::read_to_string(&small_path)
+            .unwrap()
+            .contains(intern::MARKER));
+
+        let snapshot = load_workspace_snapshot(workspace, None).unwrap();
+        let loaded: Vec<Value> = snapshot.books.iter().map(|b| b.data.clone()).collect();
+        assert_eq!(loaded, expected);
+        // Forgetting the hash makes the save compare against the file.
+        let mut unchanged = snapshot;
+        unchanged.books[0].hash = None;
+        let result = save_snapshot(unchanged, None, Some(options())).unwrap();
+        assert_eq!(result.files.len(), 1);
+        assert!(!result.files[0].changed);
+        assert!(result.written.is_empty());
+        assert_eq!(result.skipped, [result.files[0].path.clone()]);
+    }
+
+    #[test]
+    fn books_staged_in_parallel_end_up_whole_and_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 24, &[]);
+        let books_dir = dir.path().join("books");
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        for (index, book) in snapshot.books.iter_mut().enumerate() {
+            book.data["book"]["name"] = json!(format!("Renamed {}", index));
+        }
+        let expected: Vec<(String, Value)> = snapshot
+            .books
+            .iter()
+            .map(|book| (book.file_path.clone(), book.data.clone()))
+            .collect();
+
+        let result = save_snapshot(snapshot, None, None).unwrap();
+        let saved: Vec<&str> = result.files.iter().map(|file| file.path.as_str()).collect();
+        let order: Vec<&str> = expected.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(saved, order);
+        for (book_path, data) in &expected {
+            assert_eq!(&read_json_file(Path::new(book_path)).unwrap(), data);
+        }
+        assert_eq!(fs::read_dir(&books_dir).unwrap().count(), 24);
+
+        // A book that cannot be staged fails the save, naming its file, and
+        // no other book is written.
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        for book in &mut snapshot.books {
+            book.data["book"]["name"] = json!("Again");
+        }
+        let blocker = dir.path().join("blocker");
+        fs::write(&blocker, "").unwrap();
+        let blocked = blocker.join("book-7.json");
+        snapshot.books[7].file_path = blocked.to_string_lossy().into_owned();
+        let options = SaveOptions {
+            consistency_check: ConsistencyCheck::Warn,
+            ..Default::default()
+        };
+        let err = save_snapshot(snapshot, Some(true), Some(options)).unwrap_err();
+        assert!(
+            matches!(&err, WorkspaceError::Io { path, .. } if Path::new(path) == blocker),
+            "{:?}",
+            err
+        );
+        for (book_path, data) in &expected {
+            assert_eq!(&read_json_file(Path::new(book_path)).unwrap(), data);
+        }
+        assert_eq!(fs::read_dir(&books_dir).unwrap().count(), 24);
+    }
+
+    #[test]
+    fn a_failed_save_leaves_every_file_as_it_was() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 3, &[]);
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        let on_disk = |index: usize| {
+            fs::read_to_string(dir.path().join(format!("books/book-{}.json", index))).unwrap()
+        };
+        let before = [on_disk(0), on_disk(1)];
+        for book in &mut snapshot.books {
+            book.data["book"]["name"] = json!("Renamed");
+        }
+        snapshot.workspace.data["workspace"] = json!({ "name": "Renamed" });
+        let workspace_before = fs::read_to_string(&snapshot.workspace.file_path).unwrap();
+        // The last book cannot be renamed into place once the others are.
+        let blocked = dir.path().join("books/book-2.json");
+        fs::remove_file(&blocked).unwrap();
+        fs::create_dir(&blocked).unwrap();
+
+        assert!(save_snapshot(snapshot, Some(true), None).is_err());
+        assert_eq!([on_disk(0), on_disk(1)], before);
+        assert!(blocked.is_dir());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("workspace.json")).unwrap(),
+            workspace_before
+        );
+        let books_dir: Vec<_> = fs::read_dir(dir.path().join("books")).unwrap().collect();
+        assert_eq!(books_dir.len(), 3);
+    }
+
+    #[test]
+    fn books_changed_on_disk_are_merged_with_the_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let book_path = dir.path().join("books/book-0.json");
+        let cell = |value: &str| json!({ "value": value, "type": "string" });
+        let base = load_workspace_snapshot(path.clone(), None).unwrap().books[0]
+            .data
+            .clone();
+        let edited = |ours: &str| {
+            let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+            snapshot.books[0].data["sheets"][0]["rows"] = json!({ "1": { "A": cell(ours) } });
+            // Stands for a load made before the file below was written.
+            snapshot.books[0].modified = Some(1);
+            let three_way = ThreeWayOptions {
+                base: BTreeMap::from([(snapshot.books[0].file_path.clone(), base.clone())]),
+                mode: ThreeWayMode::Auto,
+            };
+            (snapshot, three_way)
+        };
+        let mut disk = book_json("book-0");
+        disk["sheets"][0]["rows"] = json!({ "1": { "B": cell("disk") } });
+        write_json_file(&book_path, &disk, FileEncoding::default()).unwrap();
+
+        let (snapshot, mut three_way) = edited("ours");
+        three_way.mode = ThreeWayMode::Confirm;
+        let options = SaveOptions {
+            three_way: Some(three_way),
+            ..Default::default()
+        };
+        let preview = save_snapshot(snapshot, None, Some(options)).unwrap();
+        assert!(preview.written.is_empty());
+        assert_eq!(read_json_file(&book_path).unwrap(), disk);
+        let merge = &preview.three_way[0];
+        assert_eq!(merge.modified, modified_millis(&book_path).unwrap());
+        assert!(merge.conflicts.is_empty());
+
+        let (snapshot, three_way) = edited("ours");
+        let options = SaveOptions {
+            three_way: Some(three_way),
+            ..Default::default()
+        };
+        let saved = save_snapshot(snapshot, None, Some(options)).unwrap();
+        assert_eq!(saved.written, [book_path.to_string_lossy()]);
+        let rows = &read_json_file(&book_path).unwrap()["sheets"][0]["rows"];
+        assert_eq!(
+            rows,
+            &json!({ "1": { "A": cell("ours"), "B": cell("disk") } })
+        );
+        assert_eq!(rows, &merge.data["sheets"][0]["rows"]);
+
+        // Both sides now change B1.
+        let (mut snapshot, three_way) = edited("ours");
+        snapshot.books[0].data["sheets"][0]["rows"]["1"]["B"] = cell("again");
+        let options = SaveOptions {
+            three_way: Some(three_way),
+            ..Default::default()
+        };
+        disk["sheets"][0]["rows"]["1"]["B"] = cell("disk 2");
+        write_json_file(&book_path, &disk, FileEncoding::default()).unwrap();
+        let err = save_snapshot(snapshot, None, Some(options)).unwrap_err();
+        let WorkspaceError::MergeConflict { conflicts, .. } = err else {
+            panic!("expected a merge conflict, got {:?}", err);
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].cell.as_deref(), Some("B1"));
+        assert_eq!(read_json_file(&book_path).unwrap(), disk);
+    }
+
+    #[test]
+    fn books_with_sort_by_are_sorted_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let unsorted = json!({
+            "1": { "A": { "value": 3 }, "B": { "value": "c" } },
+            "2": { "A": { "value": 1 }, "B": { "value": "a" } }
+        });
+        let snapshot = |sort_by: Value| {
+            let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+            snapshot.workspace.data["books"][0]["sortBy"] = sort_by;
+            for book in &mut snapshot.books {
+                book.data["sheets"][0]["rows"] = unsorted.clone();
+            }
+            snapshot
+        };
+
+        let saved = save_snapshot(snapshot(json!({ "column": "A" })), None, None).unwrap();
+        let book_path = |index: usize| dir.path().join(format!("books/book-{}.json", index));
+        assert_eq!(saved.sorted, [book_path(0).to_string_lossy()]);
+        assert_eq!(
+            read_json_file(&book_path(0)).unwrap()["sheets"][0]["rows"],
+            json!({
+                "1": { "A": { "value": 1 }, "B": { "value": "a" } },
+                "2": { "A": { "value": 3 }, "B": { "value": "c" } }
+            })
+        );
+        // Books without `sortBy` are written as they are.
+        assert_eq!(
+            read_json_file(&book_path(1)).unwrap()["sheets"][0]["rows"],
+            unsorted
+        );
+
+        assert!(matches!(
+            save_snapshot(snapshot(json!({ "column": "a1" })), None, None),
+            Err(WorkspaceError::InvalidSchema { .. })
+        ));
+    }
+
+    #[test]
+    fn normalize_repairs_the_workspace_before_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        let books = snapshot.workspace.data["books"].as_array_mut().unwrap();
+        books[1]["id"] = json!("book-0");
+        books[1]["dataPath"] = json!("books\\book-1.json");
+        books.insert(1, Value::Null);
+        let options = SaveOptions {
+            normalize: true,
+            ..Default::default()
+        };
+        let saved = save_snapshot(snapshot, None, Some(options)).unwrap();
+        // The test workspace also lacks `order` and the timestamps.
+        let fields: Vec<&str> = saved
+            .normalized
+            .iter()
+            .filter(|change| change.kind != normalize::ChangeKind::Filled)
+            .map(|change| change.field.as_str())
+            .collect();
+        assert_eq!(fields, ["books[1]", "books[2].id", "books[2].dataPath"]);
+        let books = read_json_file(Path::new(&path)).unwrap()["books"].clone();
+        assert_eq!(books.as_array().unwrap().len(), 2);
+        assert_eq!(books[1]["id"], "book-0-2");
+        assert_eq!(books[1]["dataPath"], "books/book-1.json");
+    }
+
+    #[test]
+    fn books_are_stored_in_the_format_of_their_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let json_path = dir.path().join("books/book-0.json");
+        let json_bytes = fs::read(&json_path).unwrap();
+        let mut workspace = read_json_file(Path::new(&path)).unwrap();
+        let mut table = book_json("table");
+        table["sheets"][0]["rows"] = json!({ "1": { "A": { "value": "x" }, "B": { "value": 1 } } });
+        for (data_path, book) in [
+            ("books/packed.msgpack", Some(book_json("packed"))),
+            ("books/zipped.json.gz", Some(book_json("zipped"))),
+            ("books/table.csv", Some(table)),
+            ("books/sheet.xlsx", None),
+        ] {
+            let id = data_path.split(['/', '.']).nth(1).unwrap();
+            workspace["books"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!({ "id": id, "name": id, "dataPath": data_path }));
+            match book {
+                Some(book) => {
+                    write_json_file(&dir.path().join(data_path), &book, FileEncoding::default())
+                        .unwrap()
+                }
+                None => fs::write(dir.path().join(data_path), "PK").unwrap(),
+            }
+        }
+        write_json_file(Path::new(&path), &workspace, FileEncoding::default()).unwrap();
+        let packed_path = dir.path().join("books/packed.msgpack");
+        assert_eq!(fs::read(&packed_path).unwrap()[0] & 0xf0, 0x80);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("books/table.csv")).unwrap(),
+            "x,1\n"
+        );
+
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        let data: Vec<&Value> = snapshot.books.iter().map(|book| &book.data).collect();
+        assert_eq!(
+            data[..3],
+            [
+                &book_json("book-0"),
+                &book_json("packed"),
+                &book_json("zipped")
+            ]
+        );
+        assert_eq!(data[3]["sheets"][0]["rows"]["1"]["B"]["value"], 1);
+        assert_eq!(snapshot.failed.len(), 1);
+        assert!(matches!(
+            snapshot.failed[0].error,
+            WorkspaceError::UnknownBookFormat { .. }
+        ));
+
+        // Nothing changed, so nothing is written, whatever the format.
+        let unchanged = save_snapshot(
+            load_workspace_snapshot(path.clone(), None).unwrap(),
+            None,
+            None,
+        );
+        assert!(unchanged.unwrap().written.is_empty());
+
+        snapshot.books[1].data["sheets"][0]["rows"] = json!({ "2": { "C": { "value": 3.5 } } });
+        let saved = save_snapshot(snapshot, None, None).unwrap();
+        assert_eq!(saved.written, [packed_path.to_string_lossy()]);
+        assert_eq!(
+            read_json_file(&packed_path).unwrap()["sheets"][0]["rows"]["2"]["C"]["value"],
+            3.5
+        );
+        // JSON books are written exactly as before.
+        assert_eq!(fs::read(&json_path).unwrap(), json_bytes);
+        // workspace.json stays readable text.
+        assert!(read_json_file(Path::new(&path)).unwrap()["books"].is_array());
+        assert!(fs::read_to_string(&path).unwrap().starts_with('{'));
+    }
+
+    #[test]
+    fn metrics_time_each_file_only_when_asked_for() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let options = LoadOptions {
+            metrics: true,
+            ..Default::default()
+        };
+        let snapshot = load_workspace_snapshot(path.clone(), Some(options)).unwrap();
+        let metrics = snapshot.metrics.clone().unwrap();
+        assert_eq!(metrics.operation, Operation::Load);
+        assert_eq!(metrics.files.len(), 3);
+        assert!(metrics.files.iter().all(|file| file.parse_ms.is_some()));
+        assert_eq!(
+            metrics.bytes,
+            metrics.files.iter().map(|file| file.bytes).sum::<u64>()
+        );
+        assert!(metrics.slowest_book.unwrap().contains("books"));
+        assert!(load_workspace_snapshot(path, None)
+            .unwrap()
+            .metrics
+            .is_none());
+
+        let mut changed = snapshot;
+        changed.books[0].data["sheets"][0]["name"] = json!("Renamed");
+        let options = SaveOptions {
+            metrics: true,
+            ..Default::default()
+        };
+        let saved = save_snapshot(changed, None, Some(options)).unwrap();
+        let metrics = saved.metrics.unwrap();
+        assert_eq!(metrics.operation, Operation::Save);
+        assert!(!metrics.files.is_empty());
+        assert!(metrics
+            .files
+            .iter()
+            .all(|file| file.serialize_ms.is_some() && file.bytes > 0));
+    }
+
+    #[test]
+    fn flagged_books_are_encrypted_with_the_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let book_path = dir.path().join("books/book-1.json");
+
+        let flagged = || {
+            let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+            snapshot.workspace.data["books"][1]["encrypted"] = json!(true);
+            snapshot
+        };
+        assert!(matches!(
+            save_snapshot(flagged(), None, None),
+            Err(WorkspaceError::PassphraseRequired { .. })
+        ));
+
+        let options = SaveOptions {
+            passphrase: Some("secret".into()),
+            ..Default::default()
+        };
+        let result = save_snapshot(flagged(), None, Some(options)).unwrap();
+        assert_eq!(result.written.len(), 2);
+        assert!(is_encrypted_file(&book_path));
+        assert!(!is_encrypted_file(&dir.path().join("workspace.json")));
+
+        let load = |passphrase: &str| {
+            let options = LoadOptions {
+                passphrase: Some(passphrase.into()),
+                ..Default::default()
+            };
+            load_workspace_snapshot(path.clone(), Some(options)).unwrap()
+        };
+        assert_eq!(load("secret").books[1].data["book"]["id"], "book-1");
+        assert!(matches!(
+            load("wrong").failed[0].error,
+            WorkspaceError::WrongPassphrase { .. }
+        ));
+    }
+
+    #[test]
+    fn only_saves_of_every_book_clear_the_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let book_path = dir.path().join("books/book-0.json");
+        let mut unversioned = read_json_file(&book_path).unwrap();
+        unversioned
+            .as_object_mut()
+            .unwrap()
+            .shift_remove("schemaVersion");
+        write_json_file(&book_path, &unversioned, FileEncoding::default()).unwrap();
+        append_journal_entry(path.clone(), json!({ "cell": "A1" })).unwrap();
+        let recorded = || recover_from_journal(path.clone()).unwrap().entries.len();
+
+        let options = LoadOptions {
+            save_migrated: true,
+            ..Default::default()
+        };
+        let mut snapshot = load_workspace_snapshot(path.clone(), Some(options)).unwrap();
+        assert!(snapshot.warnings.is_empty());
+        assert_eq!(recorded(), 1);
+
+        let mut partial = load_workspace_snapshot(path.clone(), None).unwrap();
+        partial.books.truncate(1);
+        partial.books[0].data["book"]["name"] = json!("Edited");
+        save_snapshot_clearing_journal(partial, None, None).unwrap();
+        assert_eq!(recorded(), 1);
+
+        snapshot.books[1].data["book"]["name"] = json!("Edited");
+        save_snapshot_clearing_journal(snapshot, Some(true), None).unwrap();
+        assert_eq!(recorded(), 0);
+    }
+
+    #[test]
+    fn load_can_write_migrated_files_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let book_path = dir.path().join("books/book-0.json");
+        let mut unversioned = read_json_file(&book_path).unwrap();
+        unversioned
+            .as_object_mut()
+            .unwrap()
+            .shift_remove("schemaVersion");
+        write_json_file(&book_path, &unversioned, FileEncoding::default()).unwrap();
+
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        assert_eq!(snapshot.books[0].data["schemaVersion"], "1.0.0");
+        assert!(snapshot.books[0].is_dirty());
+        assert!(!snapshot.books[1].is_dirty());
+        assert!(read_json_file(&book_path)
+            .unwrap()
+            .get("schemaVersion")
+            .is_none());
+
+        let options = LoadOptions {
+            save_migrated: true,
+            ..Default::default()
+        };
+        let snapshot = load_workspace_snapshot(path, Some(options)).unwrap();
+        assert!(snapshot.warnings.is_empty());
+        assert!(!snapshot.books[0].is_dirty());
+        assert_eq!(
+            read_json_file(&book_path).unwrap()["schemaVersion"],
+            "1.0.0"
+        );
+        assert_eq!(
+            snapshot.books[0].modified,
+            modified_millis(&book_path).unwrap()
+        );
+    }
+
+    #[test]
+    fn touch_last_opened_rewrites_only_that_field_unless_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let workspace_path = PathBuf::from(&path);
+        let mut data = read_json_file(&workspace_path).unwrap();
+        data["workspace"] = json!({
+            "id": "ws",
+            "lastOpened": "2000-01-01T00:00:00Z",
+            "name": "Workspace"
+        });
+        write_json_file(&workspace_path, &data, FileEncoding::default()).unwrap();
+        let touch = || LoadOptions {
+            touch_last_opened: true,
+            ..Default::default()
+        };
+
+        let before = fs::read(&workspace_path).unwrap();
+        load_workspace_snapshot(path.clone(), None).unwrap();
+        assert_eq!(fs::read(&workspace_path).unwrap(), before);
+
+        let snapshot = load_workspace_snapshot(path.clone(), Some(touch())).unwrap();
+        assert!(snapshot.warnings.is_empty());
+        assert!(!snapshot.workspace.is_dirty());
+        let saved = read_json_file(&workspace_path).unwrap();
+        let meta = saved["workspace"].as_object().unwrap();
+        assert_eq!(
+            meta.keys().collect::<Vec<_>>(),
+            ["id", "lastOpened", "name"]
+        );
+        assert_ne!(meta["lastOpened"], "2000-01-01T00:00:00Z");
+        assert_eq!(snapshot.workspace.data, saved);
+        assert_eq!(
+            snapshot.workspace.modified,
+            modified_millis(&workspace_path).unwrap()
+        );
+
+        set_workspace_readonly(path.clone(), true).unwrap();
+        let before = fs::read(&workspace_path).unwrap();
+        load_workspace_snapshot(path, Some(touch())).unwrap();
+        assert_eq!(fs::read(&workspace_path).unwrap(), before);
+    }
+
+    #[test]
+    fn oversized_files_are_refused_unless_the_limit_is_lifted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let book_size = fs::metadata(dir.path().join("books/book-0.json"))
+            .unwrap()
+            .len();
+        let limited = |limits: Value| LoadOptions {
+            size_limits: serde_json::from_value(limits).unwrap(),
+            ..Default::default()
+        };
+
+        let snapshot = load_workspace_snapshot(
+            path.clone(),
+            Some(limited(json!({ "book": book_size - 1 }))),
+        )
+        .unwrap();
+        assert_eq!(snapshot.failed.len(), 2);
+        assert!(matches!(
+            snapshot.failed[0].error,
+            WorkspaceError::FileTooLarge { size, limit, .. }
+                if size == book_size && limit == book_size - 1
+        ));
+
+        assert!(matches!(
+            load_workspace_snapshot(path.clone(), Some(limited(json!({ "workspace": 10 })))),
+            Err(WorkspaceError::FileTooLarge { .. })
+        ));
+        let unlimited = limited(json!({ "workspace": null, "book": null }));
+        assert_eq!(unlimited.size_limits.book, None);
+        let snapshot = load_workspace_snapshot(path.clone(), Some(unlimited)).unwrap();
+        assert_eq!(snapshot.books.len(), 2);
+        assert_eq!(SizeLimits::default(), limited(json!({})).size_limits);
+
+        assert!(matches!(
+            load_workspace_snapshot(path.clone(), Some(limited(json!({ "bookCount": 1 })))),
+            Err(WorkspaceError::TooManyBooks {
+                count: 2,
+                limit: 1,
+                ..
+            })
+        ));
+        let snapshot =
+            load_workspace_snapshot(path, Some(limited(json!({ "bookCount": 2 })))).unwrap();
+        assert_eq!(snapshot.books.len(), 2);
+    }
+
+    #[test]
+    fn payloads_report_file_sizes_that_saves_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        for file in std::iter::once(&snapshot.workspace).chain(&snapshot.books) {
+            let on_disk = fs::metadata(&file.file_path).unwrap().len();
+            assert_eq!(file.size_bytes, Some(on_disk));
+            assert!(file.modified.is_some());
+        }
+
+        // As sent back by the frontend, sizes included.
+        let sent = serde_json::to_value(&snapshot).unwrap();
+        assert!(sent["books"][0]["sizeBytes"].is_u64());
+        let mut snapshot: WorkspaceSnapshotPayload = serde_json::from_value(sent).unwrap();
+        snapshot.books[0].data["book"]["name"] = json!("Renamed");
+        let book_path = snapshot.books[0].file_path.clone();
+        save_snapshot(snapshot, None, None).unwrap();
+        assert!(!fs::read_to_string(&book_path)
+            .unwrap()
+            .contains("sizeBytes"));
+    }
+
+    #[test]
+    fn save_skips_files_without_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 3, &[]);
+
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        snapshot.books[1].data["book"]["name"] = json!("Renamed");
+        let changed = snapshot.books[1].file_path.clone();
+
+        let result = save_snapshot(snapshot, None, None).unwrap();
+        assert_eq!(result.written, vec![changed.clone()]);
+        assert!(result.hashes.contains_key(&changed));
+    }
+
+    #[test]
+    fn simultaneous_saves_of_one_workspace_take_turns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        let names: Vec<String> = (0..8).map(|index| format!("Name {}", index)).collect();
+
+        std::thread::scope(|scope| {
+            for name in &names {
+                let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+                for book in &mut snapshot.books {
+                    book.data["book"]["name"] = json!(name);
+                }
+                scope.spawn(move || save_snapshot(snapshot, Some(true), None).unwrap());
+            }
+        });
+
+        let saved: Vec<Value> = snapshot
+            .books
+            .iter()
+            .map(|book| read_json_file(Path::new(&book.file_path)).unwrap())
+            .collect();
+        assert!(names.contains(&saved[0]["book"]["name"].as_str().unwrap().to_string()));
+        // Both books come from the same save, not from two interleaved ones.
+        assert_eq!(saved[0]["book"]["name"], saved[1]["book"]["name"]);
+        let leftovers: Vec<_> = fs::read_dir(dir.path().join("books"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| !name.to_string_lossy().ends_with(".json"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+    }
+
+    #[test]
+    fn save_refuses_books_missing_from_workspace_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        snapshot.books[0].data["book"]["name"] = json!("Renamed");
+        snapshot.workspace.data["books"]
+            .as_array_mut()
+            .unwrap()
+            .pop();
+        let dropped = snapshot.books[1].file_path.clone();
+        let before = fs::read_to_string(&snapshot.books[0].file_path).unwrap();
+
+        let err = save_snapshot(snapshot, None, None).unwrap_err();
+        assert!(matches!(
+            &err,
+            WorkspaceError::InconsistentSnapshot { unreferenced, missing, .. }
+                if *unreferenced == [dropped.clone()] && missing.is_empty()
+        ));
+        let snapshot = load_workspace_snapshot(path, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(&snapshot.books[0].file_path).unwrap(),
+            before
+        );
+    }
+
+    #[test]
+    fn save_writes_each_file_once_and_refuses_links_out_of_the_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 1, &[]);
+        let load = || {
+            let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+            snapshot.books[0].data["book"]["name"] = json!("Renamed");
+            let mut alias = snapshot.books[0].clone();
+            alias.file_path = dir
+                .path()
+                .join("books/./book-0.json")
+                .to_string_lossy()
+                .into_owned();
+            snapshot.books.push(alias);
+            snapshot
+        };
+
+        let result = save_snapshot(load(), None, None).unwrap();
+        assert_eq!(result.written.len(), 1);
+        assert_eq!(result.merged.len(), 1);
+        assert_eq!(result.merged[0].written_as, result.written[0]);
+
+        let mut snapshot = load();
+        snapshot.books[1].data["book"]["name"] = json!("Other");
+        assert!(matches!(
+            save_snapshot(snapshot, None, None),
+            Err(WorkspaceError::DuplicateDataPath { indices, .. }) if indices == vec![0, 1]
+        ));
+
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir().unwrap();
+            let target = outside.path().join("book.json");
+            fs::write(&target, "{}").unwrap();
+            let link = dir.path().join("books/link.json");
+            std::os::unix::fs::symlink(&target, &link).unwrap();
+            let mut snapshot = load();
+            snapshot.books[1].file_path = link.to_string_lossy().into_owned();
+            assert!(matches!(
+                save_snapshot(snapshot, None, None),
+                Err(WorkspaceError::PathOutsideWorkspace { .. })
+            ));
+            assert_eq!(fs::read_to_string(&target).unwrap(), "{}");
+        }
+    }
+
+    #[test]
+    fn save_reports_each_file_and_skips_identical_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        snapshot.books[0].data["book"]["name"] = json!("Renamed");
+        snapshot.books[1].hash = Some("stale".into());
+        let mut added = snapshot.books[1].clone();
+        added.file_path = dir
+            .path()
+            .join("books/new.json")
+            .to_string_lossy()
+            .into_owned();
+        snapshot.books.push(added);
+        let untouched = snapshot.books[1].file_path.clone();
+        let before = fs::metadata(&untouched).unwrap().modified().unwrap();
+
+        // The added book is not listed in `workspace.json`.
+        let options = SaveOptions {
+            consistency_check: ConsistencyCheck::Warn,
+            ..Default::default()
+        };
+        let result = save_snapshot(snapshot, Some(true), Some(options)).unwrap();
+        assert_eq!(result.warnings.len(), 1);
+        let report: Vec<_> = result
+            .files
+            .iter()
+            .map(|file| (file.created, file.changed, file.bytes_written > 0))
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                (false, true, true),
+                (false, false, false),
+                (true, true, true)
+            ]
+        );
+        assert_eq!(result.written.len(), 2);
+        assert!(!result.written.contains(&untouched));
+        assert!(result.hashes.contains_key(&untouched));
+        assert_eq!(
+            fs::metadata(&untouched).unwrap().modified().unwrap(),
+            before
+        );
+        assert_eq!(
+            result.files[0].bytes_written,
+            fs::metadata(&result.files[0].path).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn preserve_mode_keeps_each_files_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path(), 2, &[]);
+        let crlf_book = dir.path().join("books/book-0.json");
+        let crlf = fs::read_to_string(&crlf_book)
+            .unwrap()
+            .replace('\n', "\r\n");
+        fs::write(&crlf_book, crlf.trim_end()).unwrap();
+
+        let mut snapshot = load_workspace_snapshot(path, None).unwrap();
+        for book in &mut snapshot.books {
+            book.data["book"]["name"] = json!("Renamed");
+        }
+        let options = SaveOptions {
+            write: WriteOptions {
+                line_ending: LineEndingMode::Preserve,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = save_snapshot(snapshot, None, Some(options)).unwrap();
+
+        let written = fs::read_to_string(&crlf_book).unwrap();
+        assert!(written.contains("\r\n") && !written.ends_with('\n'));
+        let plain = fs::read_to_string(dir.path().join("books/book-1.json")).unwrap();
+        assert!(!plain.contains('\r') && plain.ends_with("}\n"));
+        let style = result.text_styles[crlf_book.to_str().unwrap()];
+        assert_eq!(style.line_ending, LineEnding::Crlf);
+    }
+}
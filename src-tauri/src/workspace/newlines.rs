@@ -0,0 +1,89 @@
+//! Unifies the line breaks inside string values, e.g. multi-line cell text
+//! typed on different platforms. Keys are never touched.
+
+use super::io::LineEnding;
+use serde_json::Value;
+
+/// Whether `text` has any line break other than `ending`.
+fn needs_change(text: &str, ending: LineEnding) -> bool {
+    let bytes = text.as_bytes();
+    match ending {
+        LineEnding::Lf => bytes.contains(&b'\r'),
+        LineEnding::Crlf => bytes.iter().enumerate().any(|(index, &byte)| match byte {
+            b'\r' => bytes.get(index + 1) != Some(&b'\n'),
+            b'\n' => index == 0 || bytes[index - 1] != b'\r',
+            _ => false,
+        }),
+    }
+}
+
+fn convert(text: &str, ending: LineEnding) -> String {
+    let separator = match ending {
+        LineEnding::Lf => "\n",
+        LineEnding::Crlf => "\r\n",
+    };
+    let mut converted = String::with_capacity(text.len() + text.len() / 16);
+    let mut rest = text;
+    while let Some(at) = rest.find(['\r', '\n']) {
+        converted.push_str(&rest[..at]);
+        converted.push_str(separator);
+        let width = if rest[at..].starts_with("\r\n") { 2 } else { 1 };
+        rest = &rest[at + width..];
+    }
+    converted.push_str(rest);
+    converted
+}
+
+/// Rewrites every CRLF, LF and lone CR in the string values of `value` as
+/// `ending` and returns how many strings changed. Strings already using
+/// `ending` are not copied, and the walk keeps its own stack so deeply
+/// nested documents cannot overflow the thread's.
+pub fn normalize(value: &mut Value, ending: LineEnding) -> usize {
+    let mut changed = 0;
+    let mut pending = vec![value];
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::String(text) if needs_change(text, ending) => {
+                *text = convert(text, ending);
+                changed += 1;
+            }
+            Value::Array(items) => pending.extend(items.iter_mut()),
+            Value::Object(entries) => pending.extend(entries.values_mut()),
+            _ => {}
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn converts_string_values_but_not_keys() {
+        let mut book = json!({
+            "line\r\nkey": "a\r\nb\rc\nd",
+            "rows": [{ "A": { "value": "x\r\n" } }, 1, null],
+            "plain": "no breaks"
+        });
+        assert_eq!(normalize(&mut book, LineEnding::Lf), 2);
+        assert_eq!(book["line\r\nkey"], "a\nb\nc\nd");
+        assert_eq!(book["rows"][0]["A"]["value"], "x\n");
+        assert_eq!(normalize(&mut book, LineEnding::Lf), 0);
+
+        assert_eq!(normalize(&mut book, LineEnding::Crlf), 2);
+        assert_eq!(book["line\r\nkey"], "a\r\nb\r\nc\r\nd");
+        assert_eq!(normalize(&mut book, LineEnding::Crlf), 0);
+    }
+
+    #[test]
+    fn spots_every_foreign_break() {
+        assert!(!needs_change("a\r\nb", LineEnding::Crlf));
+        assert!(needs_change("\nb", LineEnding::Crlf));
+        assert!(needs_change("a\r", LineEnding::Crlf));
+        assert!(needs_change("a\r\n\n", LineEnding::Crlf));
+        assert!(!needs_change("a\nb", LineEnding::Lf));
+        assert_eq!(convert("\r\r\n\n", LineEnding::Lf), "\n\n\n");
+    }
+}
@@ -0,0 +1,256 @@
+//! Repairs of the `books` of `workspace.json` that hand edits and merges
+//! tend to leave behind: `null` entries, ids used twice, `\` in `dataPath`
+//! and missing fields. `normalize_workspace` runs them on request and the
+//! `normalize` save option before every save; either way each change is
+//! listed, so that none goes unnoticed.
+
+use super::books::now_rfc3339;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    /// A `null` (or otherwise not an object) entry was dropped.
+    Removed,
+    /// The id was already used by an earlier entry and got a suffix.
+    RenamedDuplicate,
+    /// `\` separators were replaced by `/`.
+    Separators,
+    /// A missing field was filled with a default.
+    Filled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizeChange {
+    /// The field changed, e.g. `books[2].id`. Indices are those of the
+    /// workspace as it was passed in, before any entry was removed.
+    pub field: String,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// The first of `base`, `base-2`, `base-3`, ... not in `taken`, which it
+/// is added to.
+fn unique_id(base: &str, taken: &mut HashSet<String>) -> String {
+    let id = (1..)
+        .map(|attempt| match attempt {
+            1 => base.to_string(),
+            _ => format!("{}-{}", base, attempt),
+        })
+        .find(|id| !taken.contains(id))
+        .expect("some suffix is always free");
+    taken.insert(id.clone());
+    id
+}
+
+fn record(
+    changes: &mut Vec<NormalizeChange>,
+    index: usize,
+    name: &str,
+    kind: ChangeKind,
+    before: Option<Value>,
+    after: &Value,
+) {
+    changes.push(NormalizeChange {
+        field: format!("books[{}].{}", index, name),
+        kind,
+        before,
+        after: Some(after.clone()),
+    });
+}
+
+/// Sets the field `name` of `entry` to `value` when it is missing or null.
+fn fill(
+    changes: &mut Vec<NormalizeChange>,
+    entry: &mut Map<String, Value>,
+    index: usize,
+    name: &str,
+    value: Value,
+) {
+    if entry.get(name).is_none_or(Value::is_null) {
+        record(changes, index, name, ChangeKind::Filled, None, &value);
+        entry.insert(name.into(), value);
+    }
+}
+
+/// Normalizes `workspace` in place, stamping filled timestamps with `now`,
+/// and returns what changed in the order applied.
+pub(super) fn normalize(workspace: &mut Value, now: &str) -> Vec<NormalizeChange> {
+    let mut changes = Vec::new();
+    let Some(object) = workspace.as_object_mut() else {
+        return changes;
+    };
+    if object.get("books").is_none_or(Value::is_null) {
+        object.insert("books".into(), json!([]));
+        changes.push(NormalizeChange {
+            field: "books".into(),
+            kind: ChangeKind::Filled,
+            before: None,
+            after: Some(json!([])),
+        });
+    }
+    let Some(books) = object.get_mut("books").and_then(Value::as_array_mut) else {
+        return changes;
+    };
+
+    let mut taken: HashSet<String> = books
+        .iter()
+        .filter_map(|book_ref| book_ref["id"].as_str().map(str::to_string))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut kept = Vec::with_capacity(books.len());
+    for (index, book_ref) in std::mem::take(books).into_iter().enumerate() {
+        let Value::Object(mut entry) = book_ref else {
+            changes.push(NormalizeChange {
+                field: format!("books[{}]", index),
+                kind: ChangeKind::Removed,
+                before: Some(book_ref),
+                after: None,
+            });
+            continue;
+        };
+        match entry.get("id").and_then(Value::as_str).map(str::to_string) {
+            Some(id) if !seen.insert(id.clone()) => {
+                let renamed = unique_id(&id, &mut taken);
+                seen.insert(renamed.clone());
+                let renamed = Value::String(renamed);
+                let before = Some(id.into());
+                record(
+                    &mut changes,
+                    index,
+                    "id",
+                    ChangeKind::RenamedDuplicate,
+                    before,
+                    &renamed,
+                );
+                entry.insert("id".into(), renamed);
+            }
+            Some(_) => {}
+            None => {
+                let id = unique_id(&format!("book-{}", index + 1), &mut taken);
+                seen.insert(id.clone());
+                fill(&mut changes, &mut entry, index, "id", Value::String(id));
+            }
+        }
+        let id = entry["id"].as_str().unwrap_or_default().to_string();
+        if let Some(data_path) = entry.get("dataPath").and_then(Value::as_str) {
+            if data_path.contains('\\') {
+                let unified = Value::String(data_path.replace('\\', "/"));
+                let before = Some(data_path.into());
+                record(
+                    &mut changes,
+                    index,
+                    "dataPath",
+                    ChangeKind::Separators,
+                    before,
+                    &unified,
+                );
+                entry.insert("dataPath".into(), unified);
+            }
+        }
+        fill(
+            &mut changes,
+            &mut entry,
+            index,
+            "dataPath",
+            json!(format!("books/{}.json", id)),
+        );
+        let name = entry["dataPath"]
+            .as_str()
+            .and_then(|data_path| Path::new(data_path).file_stem())
+            .map_or(id, |stem| stem.to_string_lossy().into_owned());
+        fill(&mut changes, &mut entry, index, "name", Value::String(name));
+        fill(&mut changes, &mut entry, index, "order", json!(kept.len()));
+        fill(&mut changes, &mut entry, index, "createdAt", json!(now));
+        fill(&mut changes, &mut entry, index, "updatedAt", json!(now));
+        kept.push(Value::Object(entry));
+    }
+    *books = kept;
+    changes
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizedWorkspace {
+    pub workspace: Value,
+    pub changes: Vec<NormalizeChange>,
+}
+
+/// Returns the `workspace.json` data normalized, with the list of changes,
+/// without saving anything.
+#[tauri::command]
+pub fn normalize_workspace(workspace: Value) -> NormalizedWorkspace {
+    let mut workspace = workspace;
+    let changes = normalize(&mut workspace, &now_rfc3339());
+    NormalizedWorkspace { workspace, changes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs_the_book_list_and_logs_each_change() {
+        let full = |id: &str, data_path: &str| {
+            json!({
+                "id": id,
+                "name": "Book",
+                "dataPath": data_path,
+                "order": 0,
+                "createdAt": "2024-01-01T00:00:00Z",
+                "updatedAt": "2024-01-01T00:00:00Z"
+            })
+        };
+        let mut workspace = json!({
+            "schemaVersion": "1.0.0",
+            "books": [
+                full("a", "books/a.json"),
+                null,
+                full("a", "books\\sub\\b.json"),
+                { "dataPath": "books/c.json" },
+                full("a-2", "books/d.json")
+            ]
+        });
+        let changes = normalize(&mut workspace, "2025-01-01T00:00:00Z");
+        let log: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|change| (change.field.as_str(), change.kind))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                ("books[1]", ChangeKind::Removed),
+                ("books[2].id", ChangeKind::RenamedDuplicate),
+                ("books[2].dataPath", ChangeKind::Separators),
+                ("books[3].id", ChangeKind::Filled),
+                ("books[3].name", ChangeKind::Filled),
+                ("books[3].order", ChangeKind::Filled),
+                ("books[3].createdAt", ChangeKind::Filled),
+                ("books[3].updatedAt", ChangeKind::Filled),
+            ]
+        );
+        let books = workspace["books"].as_array().unwrap();
+        let ids: Vec<&str> = books
+            .iter()
+            .map(|book| book["id"].as_str().unwrap())
+            .collect();
+        // `a-2` is taken by a later entry, so the duplicate becomes `a-3`.
+        assert_eq!(ids, ["a", "a-3", "book-4", "a-2"]);
+        assert_eq!(books[1]["dataPath"], "books/sub/b.json");
+        assert_eq!(books[2]["name"], "c");
+        assert_eq!(books[2]["order"], 2);
+        assert_eq!(changes[0].before, Some(Value::Null));
+
+        assert!(normalize(&mut workspace, "2025-01-01T00:00:00Z").is_empty());
+        let mut empty = json!({});
+        assert_eq!(normalize(&mut empty, "")[0].kind, ChangeKind::Filled);
+        assert_eq!(empty["books"], json!([]));
+    }
+}
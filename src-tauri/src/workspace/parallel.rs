@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// Upper bound on worker threads so very wide machines don't open hundreds of
+/// files at once.
+const MAX_WORKERS: usize = 8;
+
+/// Upper bound on books read or written at the same time by all loads and
+/// saves together, which may run side by side (several windows,
+/// `search_all_workspaces`) and each use a pool of [`MAX_WORKERS`]. Keeps
+/// well below the default limit of open files of macOS (256) so loads
+/// don't fail with `Too many open files`.
+const MAX_OPEN_FILES: usize = 32;
+
+static OPEN_FILES: Semaphore = Semaphore::new(MAX_OPEN_FILES);
+
+/// Counting semaphore; [`Semaphore::acquire`] blocks while every permit is
+/// held.
+struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+struct Permit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    const fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let available = self.available.lock().unwrap_or_else(|err| err.into_inner());
+        let mut available = self
+            .released
+            .wait_while(available, |available| *available == 0)
+            .unwrap_or_else(|err| err.into_inner());
+        *available -= 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self
+            .0
+            .available
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Runs `f`, which opens the files of one book and closes them before
+/// returning, once fewer than [`MAX_OPEN_FILES`] other calls are running.
+/// `f` must not call this itself: with every permit held by callers waiting
+/// for a second one, all of them would wait forever.
+pub fn with_open_file<T>(f: impl FnOnce() -> T) -> T {
+    let _permit = OPEN_FILES.acquire();
+    f()
+}
+
+fn worker_count(len: usize) -> usize {
+    let cores = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1);
+    cores.min(MAX_WORKERS).min(len).max(1)
+}
+
+/// Maps `items` on a small pool of scoped threads and returns the results in
+/// the original order.
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &T) -> R + Sync,
+{
+    let workers = worker_count(items.len());
+    if workers <= 1 {
+        return items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| f(index, item))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let slots: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(index, item);
+                slots.lock().unwrap_or_else(|err| err.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    slots
+        .into_inner()
+        .unwrap_or_else(|err| err.into_inner())
+        .into_iter()
+        .map(|slot| slot.expect("every index is processed exactly once"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_map_preserves_order() {
+        let items: Vec<usize> = (0..100).collect();
+        let results = parallel_map(&items, |index, value| {
+            assert_eq!(index, *value);
+            value * 2
+        });
+        assert_eq!(results, (0..100).map(|value| value * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn semaphore_caps_concurrent_holders() {
+        let semaphore = Semaphore::new(2);
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(std::time::Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(*semaphore.available.lock().unwrap(), 2);
+    }
+}
@@ -0,0 +1,469 @@
+//! Patch files. A book saved with `patches` keeps its last full snapshot,
+//! and `<book file>.patch.jsonl` next to it gets one line per save with the
+//! cells changed since: `{ savedAt, base, ops }`, where `base` is the content
+//! hash of the full snapshot the ops apply to and `ops` are JSON Patch style
+//! `add` and `remove` operations on paths that name the sheet by id, such as
+//! `/sheets/sheet-1/rows/3/B`. A save that changes anything but cells, or
+//! would grow the patch file past `compactBytes`, writes the book in full
+//! instead and empties the patch file. Plain loads read only the full
+//! snapshots; `load_with_patches` applies the patches on top.
+
+use super::books::now_rfc3339;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{content_hash, is_encrypted_file, modified_millis, RetryPolicy};
+use super::transaction::Transaction;
+use super::{
+    load_book, load_workspace_snapshot, FilePayload, LoadOptions, SaveResult, SavedFile, TextStyle,
+    WorkspaceSnapshotPayload,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const PATCH_SUFFIX: &str = ".patch.jsonl";
+
+const DEFAULT_COMPACT_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PatchOptions {
+    /// Save the changed cells of books as patches instead of rewriting the
+    /// books. Encrypted books are always written in full.
+    pub enabled: bool,
+    /// Size a book's patch file may reach before the book is written in
+    /// full again; 1 MiB when omitted.
+    pub compact_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PatchLine {
+    saved_at: String,
+    base: String,
+    ops: Vec<PatchOp>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+}
+
+pub fn patch_path_for(book_path: &Path) -> PathBuf {
+    let mut path = OsString::from(book_path.as_os_str());
+    path.push(PATCH_SUFFIX);
+    PathBuf::from(path)
+}
+
+pub fn is_patch_file(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(PATCH_SUFFIX)
+}
+
+/// The contents of the patch file at `path`, empty when there is none.
+fn read_patch_file(path: &Path) -> WorkspaceResult<Vec<u8>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(WorkspaceError::io("read", path, err)),
+    }
+}
+
+fn invalid(path: &Path, line: usize, message: impl Into<String>) -> WorkspaceError {
+    WorkspaceError::InvalidPatch {
+        path: path.display().to_string(),
+        line,
+        message: message.into(),
+    }
+}
+
+/// Applies every line of `contents` to `book`, whose full snapshot has the
+/// content hash `base`. Lines are numbered from 1 in errors.
+fn apply_patch_file(
+    book: &mut Value,
+    base: &str,
+    contents: &[u8],
+    path: &Path,
+) -> WorkspaceResult<()> {
+    let lines = contents
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty());
+    for (index, line) in lines {
+        let line_number = index + 1;
+        let line: PatchLine = serde_json::from_slice(line)
+            .map_err(|err| invalid(path, line_number, err.to_string()))?;
+        if line.base != base {
+            return Err(invalid(
+                path,
+                line_number,
+                "was saved against another version of the book",
+            ));
+        }
+        for op in &line.ops {
+            apply_op(book, op).map_err(|message| invalid(path, line_number, message))?;
+        }
+    }
+    Ok(())
+}
+
+fn escape_segment(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn cell_path(sheet_id: &str, row: &str, col: &str) -> String {
+    format!(
+        "/sheets/{}/rows/{}/{}",
+        escape_segment(sheet_id),
+        escape_segment(row),
+        escape_segment(col)
+    )
+}
+
+fn apply_op(book: &mut Value, op: &PatchOp) -> Result<(), String> {
+    let path = match op {
+        PatchOp::Add { path, .. } | PatchOp::Remove { path } => path,
+    };
+    let segments: Vec<String> = path
+        .strip_prefix('/')
+        .unwrap_or_default()
+        .split('/')
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect();
+    let [sheets, sheet_id, rows, row, col] = segments.as_slice() else {
+        return Err(format!("{} is not a cell path", path));
+    };
+    if sheets != "sheets" || rows != "rows" {
+        return Err(format!("{} is not a cell path", path));
+    }
+    let sheet = book["sheets"]
+        .as_array_mut()
+        .and_then(|sheets| sheets.iter_mut().find(|sheet| sheet["id"] == *sheet_id))
+        .ok_or_else(|| format!("no sheet {}", sheet_id))?;
+    if !sheet["rows"].is_object() {
+        sheet["rows"] = Value::Object(Map::new());
+    }
+    let rows = sheet["rows"].as_object_mut().expect("made an object above");
+    match op {
+        PatchOp::Add { value, .. } => {
+            let cells = rows
+                .entry(row.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            cells
+                .as_object_mut()
+                .ok_or_else(|| format!("row {} of sheet {} is not an object", row, sheet_id))?
+                .insert(col.clone(), value.clone());
+        }
+        PatchOp::Remove { .. } => {
+            let cells = rows.get_mut(row.as_str()).and_then(Value::as_object_mut);
+            if cells.and_then(|cells| cells.remove(col.as_str())).is_none() {
+                return Err(format!("no cell {}{} in sheet {}", col, row, sheet_id));
+            }
+            if rows[row.as_str()].as_object().is_some_and(Map::is_empty) {
+                rows.remove(row.as_str());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The cell operations turning `previous` into `next`, or `None` when they
+/// differ in more than cells (sheets added or renamed, `gridSize`, ...).
+fn cell_ops(previous: &Value, next: &Value) -> Option<Vec<PatchOp>> {
+    let empty = Map::new();
+    let previous_sheets = previous["sheets"].as_array()?;
+    let mut ops = Vec::new();
+    for sheet in next["sheets"].as_array()? {
+        let id = sheet["id"].as_str()?;
+        let before = previous_sheets.iter().find(|sheet| sheet["id"] == id)?;
+        let before = before["rows"].as_object().unwrap_or(&empty);
+        let after = sheet["rows"].as_object().unwrap_or(&empty);
+        for (row, cells) in after {
+            let old_cells = before.get(row).and_then(Value::as_object).unwrap_or(&empty);
+            let cells = cells.as_object()?;
+            for (col, cell) in cells {
+                if old_cells.get(col) != Some(cell) {
+                    ops.push(PatchOp::Add {
+                        path: cell_path(id, row, col),
+                        value: cell.clone(),
+                    });
+                }
+            }
+            let removed = old_cells.keys().filter(|col| !cells.contains_key(*col));
+            ops.extend(removed.map(|col| PatchOp::Remove {
+                path: cell_path(id, row, col),
+            }));
+        }
+        for (row, cells) in before.iter().filter(|(row, _)| !after.contains_key(*row)) {
+            let cols = cells.as_object().into_iter().flat_map(Map::keys);
+            ops.extend(cols.map(|col| PatchOp::Remove {
+                path: cell_path(id, row, col),
+            }));
+        }
+    }
+    // Whatever the ops cannot express, such as a changed sheet name, shows
+    // up as a difference here.
+    let mut patched = previous.clone();
+    for op in &ops {
+        apply_op(&mut patched, op).ok()?;
+    }
+    (patched == *next).then_some(ops)
+}
+
+/// What a save with patches writes for one book.
+pub(super) enum PatchPlan {
+    /// The new contents of the patch file.
+    Append(Vec<u8>),
+    /// The state on disk already matches the book.
+    Unchanged,
+    Full,
+}
+
+/// Decides how `book` is saved. Anything unexpected about the files on
+/// disk, such as a patch file that does not apply, makes the save write the
+/// book in full, which is always correct.
+pub(super) fn plan(
+    book: &FilePayload,
+    passphrase: Option<&str>,
+    options: PatchOptions,
+) -> PatchPlan {
+    let path = Path::new(&book.file_path);
+    if !path.exists() || is_encrypted_file(path) {
+        return PatchPlan::Full;
+    }
+    let planned = (|| {
+        let full = load_book(path, passphrase, None).ok()?;
+        let base = full.hash.clone()?;
+        // A full snapshot from an older schema is upgraded on load, and the
+        // ops would apply to the upgraded form.
+        if content_hash(&full.data) != base {
+            return None;
+        }
+        let patch_path = patch_path_for(path);
+        let mut contents = read_patch_file(&patch_path).ok()?;
+        let mut previous = full.data;
+        apply_patch_file(&mut previous, &base, &contents, &patch_path).ok()?;
+        let ops = cell_ops(&previous, &book.data)?;
+        if ops.is_empty() {
+            return Some(PatchPlan::Unchanged);
+        }
+        let line = PatchLine {
+            saved_at: now_rfc3339(),
+            base,
+            ops,
+        };
+        if !contents.is_empty() && !contents.ends_with(b"\n") {
+            contents.push(b'\n');
+        }
+        contents.extend(serde_json::to_vec(&line).ok()?);
+        contents.push(b'\n');
+        let limit = options.compact_bytes.unwrap_or(DEFAULT_COMPACT_BYTES);
+        (contents.len() as u64 <= limit).then_some(PatchPlan::Append(contents))
+    })();
+    planned.unwrap_or(PatchPlan::Full)
+}
+
+/// Stages the patch file `contents` of `book`, reporting it like a written
+/// file under the patch file's path.
+pub(super) fn stage_patch(
+    book: &FilePayload,
+    contents: &[u8],
+    style: TextStyle,
+    policy: RetryPolicy,
+    result: &mut SaveResult,
+    transaction: &mut Transaction,
+) -> WorkspaceResult<()> {
+    let patch_path = patch_path_for(Path::new(&book.file_path));
+    let created = !patch_path.exists();
+    let bytes_written = transaction.stage_bytes(&patch_path, contents, policy)?;
+    result.files.push(SavedFile {
+        path: patch_path.to_string_lossy().into_owned(),
+        bytes_written,
+        created,
+        changed: true,
+    });
+    result.patched.push(book.file_path.clone());
+    result.text_styles.insert(book.file_path.clone(), style);
+    result
+        .hashes
+        .insert(book.file_path.clone(), content_hash(&book.data));
+    Ok(())
+}
+
+/// Reports `book` as skipped when its full snapshot and patches already
+/// hold its contents.
+pub(super) fn record_unchanged(
+    book: &FilePayload,
+    style: TextStyle,
+    result: &mut SaveResult,
+) -> WorkspaceResult<()> {
+    result.skipped.push(book.file_path.clone());
+    if let Some(modified) = modified_millis(Path::new(&book.file_path))? {
+        result.modified.insert(book.file_path.clone(), modified);
+    }
+    result.files.push(SavedFile {
+        path: book.file_path.clone(),
+        bytes_written: 0,
+        created: false,
+        changed: false,
+    });
+    result.text_styles.insert(book.file_path.clone(), style);
+    result
+        .hashes
+        .insert(book.file_path.clone(), content_hash(&book.data));
+    Ok(())
+}
+
+/// Empties the patch file of a book being written in full, as part of the
+/// same transaction; its patches are in the new full snapshot.
+pub(super) fn stage_clear(
+    book_path: &Path,
+    policy: RetryPolicy,
+    transaction: &mut Transaction,
+) -> WorkspaceResult<()> {
+    let patch_path = patch_path_for(book_path);
+    if fs::metadata(&patch_path).is_ok_and(|stat| stat.len() > 0) {
+        transaction.stage_bytes(&patch_path, &[], policy)?;
+    }
+    Ok(())
+}
+
+/// `load_workspace_snapshot` with each book's patch file applied. A book
+/// whose patches do not apply comes back as its full snapshot, with the
+/// `invalidPatch` error in `warnings` and without a `hash`, so the next
+/// save writes it in full and empties the broken patch file.
+#[tauri::command(async)]
+pub fn load_with_patches(
+    workspace_path: String,
+    options: Option<LoadOptions>,
+) -> WorkspaceResult<WorkspaceSnapshotPayload> {
+    let mut snapshot = load_workspace_snapshot(workspace_path, options)?;
+    for book in &mut snapshot.books {
+        let patch_path = patch_path_for(Path::new(&book.file_path));
+        let applied = read_patch_file(&patch_path).and_then(|contents| {
+            if contents.is_empty() {
+                return Ok(None);
+            }
+            let Some(base) = &book.hash else {
+                return Err(invalid(&patch_path, 1, "the book was not read as UTF-8"));
+            };
+            let mut data = book.data.clone();
+            apply_patch_file(&mut data, base, &contents, &patch_path)?;
+            Ok(Some(data))
+        });
+        match applied {
+            Ok(Some(data)) => {
+                book.hash = Some(content_hash(&data));
+                book.data = data;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                book.hash = None;
+                snapshot.warnings.push(err);
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use crate::workspace::{save_snapshot, SaveOptions};
+    use serde_json::json;
+
+    #[test]
+    fn saves_changed_cells_as_patches_and_loads_them_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let workspace = json!({
+            "schemaVersion": "1.0.0",
+            "books": [{ "id": "book-1", "name": "One", "dataPath": "books/one.json" }]
+        });
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+        let book_path = dir.path().join("books/one.json");
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "One" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": 100, "cols": 26 },
+                "rows": { "1": { "A": { "value": "a", "type": "string" } } }
+            }]
+        });
+        write_json_file(&book_path, &book, FileEncoding::default()).unwrap();
+        let full = fs::read(&book_path).unwrap();
+        let patch_path = patch_path_for(&book_path);
+        let load =
+            || load_with_patches(workspace_path.to_string_lossy().into_owned(), None).unwrap();
+        let save = |snapshot, compact_bytes| {
+            let options = SaveOptions {
+                patches: PatchOptions {
+                    enabled: true,
+                    compact_bytes,
+                },
+                ..Default::default()
+            };
+            save_snapshot(snapshot, None, Some(options)).unwrap()
+        };
+
+        let mut snapshot = load();
+        snapshot.books[0].data["sheets"][0]["rows"]["2"] =
+            json!({ "B": { "value": 2, "type": "number" } });
+        let result = save(snapshot, None);
+        assert_eq!(result.patched, [book_path.to_string_lossy()]);
+        assert_eq!(result.written, [patch_path.to_string_lossy()]);
+        assert_eq!(fs::read(&book_path).unwrap(), full);
+
+        let mut snapshot = load();
+        let rows = &mut snapshot.books[0].data["sheets"][0]["rows"];
+        assert_eq!(rows["2"]["B"]["value"], 2);
+        rows.as_object_mut().unwrap().remove("1");
+        let expected = snapshot.books[0].data.clone();
+        save(snapshot, None);
+        let lines = fs::read_to_string(&patch_path).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        assert!(lines.contains(r#"{"op":"remove","path":"/sheets/sheet-1/rows/1/A"}"#));
+        let snapshot = load();
+        assert_eq!(snapshot.books[0].data, expected);
+        assert!(snapshot.warnings.is_empty());
+
+        // A renamed sheet is not a cell change: the book is written in full
+        // and the patch file emptied with it.
+        let mut snapshot = load();
+        snapshot.books[0].data["sheets"][0]["name"] = json!("Renamed");
+        let expected = snapshot.books[0].data.clone();
+        let result = save(snapshot, None);
+        assert!(result.patched.is_empty());
+        assert_eq!(fs::read(&patch_path).unwrap(), b"");
+        assert_eq!(load().books[0].data, expected);
+
+        // Past `compactBytes` the patches are folded into the book.
+        let mut snapshot = load();
+        snapshot.books[0].data["sheets"][0]["rows"]["3"] =
+            json!({ "C": { "value": "c", "type": "string" } });
+        assert!(save(snapshot, Some(10)).patched.is_empty());
+        assert_eq!(fs::read(&patch_path).unwrap(), b"");
+
+        let on_disk = load().books[0].data.clone();
+        fs::write(
+            &patch_path,
+            "{\"savedAt\":\"\",\"base\":\"other\",\"ops\":[]}\n",
+        )
+        .unwrap();
+        let snapshot = load();
+        assert_eq!(snapshot.books[0].data, on_disk);
+        assert_eq!(snapshot.books[0].hash, None);
+        assert!(matches!(
+            &snapshot.warnings[..],
+            [WorkspaceError::InvalidPatch { line: 1, .. }]
+        ));
+        save(snapshot, None);
+        assert_eq!(fs::read(&patch_path).unwrap(), b"");
+    }
+}
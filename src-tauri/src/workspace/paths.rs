@@ -0,0 +1,492 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use serde_json::Value;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub fn workspace_dir_of(workspace_path: &Path) -> PathBuf {
+    workspace_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The directory of `workspace_path` as an absolute path with symbolic
+/// links resolved; a bare `workspace.json` is in the current directory.
+/// The `\\?\` prefix `fs::canonicalize` puts before drive paths on Windows
+/// is dropped so the frontend can join `dataPath`s to it.
+pub fn real_workspace_dir(workspace_path: &Path) -> String {
+    let dir = canonicalize_lenient(workspace_path)
+        .and_then(|real| real.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| workspace_dir_of(workspace_path));
+    without_verbatim_prefix(&dir.to_string_lossy()).to_string()
+}
+
+/// `path` resolved like [`real_workspace_dir`], so the two compare; as
+/// given when not even an ancestor exists.
+pub fn real_path(path: &Path) -> String {
+    let real = canonicalize_lenient(path).unwrap_or_else(|| path.to_path_buf());
+    without_verbatim_prefix(&real.to_string_lossy()).to_string()
+}
+
+fn without_verbatim_prefix(path: &str) -> &str {
+    match path.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest,
+        _ => path,
+    }
+}
+
+/// Replaces a leading `~` component with the home directory: `$HOME`, or
+/// the profile folder (`%USERPROFILE%`) on Windows, as `dirs::home_dir`
+/// finds them. `~user` is not expanded, and nothing is when the home
+/// directory is unknown.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) if rest.as_os_str().is_empty() => home,
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Absolute form of `path` without requiring it to exist; an empty path is
+/// the current directory. `..` is kept for [`normalize_lexically`].
+pub fn absolute(path: &Path) -> PathBuf {
+    let path = match path.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => path,
+    };
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Resolves `.` and `..` without touching the filesystem. Returns `None` when
+/// a `..` would climb above the start of the path.
+pub fn normalize_lexically(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return None;
+                }
+                normalized.pop();
+                depth -= 1;
+            }
+            Component::Normal(part) => {
+                normalized.push(part);
+                depth += 1;
+            }
+            Component::Prefix(_) | Component::RootDir => normalized.push(component),
+        }
+    }
+    Some(normalized)
+}
+
+/// `Path::is_absolute` only knows the host platform's rules, so Windows drive
+/// letters and UNC paths are checked by hand to reject them everywhere.
+pub fn is_absolute_like(data_path: &str) -> bool {
+    let bytes = data_path.as_bytes();
+    let has_drive_letter = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    data_path.starts_with('/') || has_drive_letter || Path::new(data_path).has_root()
+}
+
+pub fn resolve_data_path(
+    workspace_dir: &Path,
+    data_path: &str,
+    index: usize,
+) -> WorkspaceResult<PathBuf> {
+    let escapes = || WorkspaceError::PathOutsideWorkspace {
+        field: format!("books[{}].dataPath", index),
+        path: data_path.to_string(),
+    };
+    let unified = data_path.replace('\\', "/");
+    // `~/...` is checked once expanded: it must still end up inside the
+    // workspace directory.
+    if Path::new(&unified).starts_with("~") {
+        let inside = normalize_lexically(workspace_dir).and_then(|dir| {
+            normalize_lexically(&expand_home(Path::new(&unified)))
+                .filter(|path| path.starts_with(&dir) && *path != dir)
+        });
+        return inside.ok_or_else(escapes);
+    }
+    if is_absolute_like(&unified) {
+        return Err(escapes());
+    }
+    let relative = normalize_lexically(Path::new(&unified)).ok_or_else(escapes)?;
+    Ok(workspace_dir.join(relative))
+}
+
+/// `books[]` entries whose `dataPath`s name the same file, one
+/// `duplicateDataPath` error per shared file in the order first listed.
+/// Saving such a workspace would let one book overwrite the other. With
+/// `case_insensitive`, `Foo.json` and `foo.json` also collide, as they do on
+/// the default macOS and Windows file systems. Paths that do not resolve are
+/// skipped; loading reports those on its own.
+pub fn duplicate_data_paths<'a, I>(data_paths: I, case_insensitive: bool) -> Vec<WorkspaceError>
+where
+    I: IntoIterator<Item = Option<&'a str>>,
+{
+    let mut groups: Vec<(String, String, Vec<usize>)> = Vec::new();
+    for (index, data_path) in data_paths.into_iter().enumerate() {
+        let Some(data_path) = data_path else {
+            continue;
+        };
+        let Ok(resolved) = resolve_data_path(Path::new(""), data_path, index) else {
+            continue;
+        };
+        let mut key = resolved.to_string_lossy().into_owned();
+        if case_insensitive {
+            key = key.to_lowercase();
+        }
+        match groups.iter_mut().find(|(existing, _, _)| *existing == key) {
+            Some((_, _, indices)) => indices.push(index),
+            None => groups.push((key, data_path.to_string(), vec![index])),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, _, indices)| indices.len() > 1)
+        .map(|(_, path, indices)| WorkspaceError::DuplicateDataPath { path, indices })
+        .collect()
+}
+
+/// Resolves symbolic links, `.` and `..` through the filesystem. A path that
+/// does not exist yet resolves its nearest existing ancestor and appends the
+/// rest. Returns `None` when not even an ancestor can be resolved.
+pub fn canonicalize_lenient(path: &Path) -> Option<PathBuf> {
+    if let Ok(real) = fs::canonicalize(path) {
+        return Some(real);
+    }
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    canonicalize_lenient(parent).map(|dir| dir.join(name))
+}
+
+/// Fails when `path`, already known to lie lexically inside one of
+/// `real_roots` (resolved book roots), leaves all of them once links are
+/// followed. `fs::canonicalize` follows symbolic links on every platform
+/// and junctions and other reparse points on Windows, so any of them along
+/// the way is covered. A dangling link resolves to itself and passes; it
+/// fails later as a missing file.
+pub fn ensure_links_within(real_roots: &[PathBuf], path: &Path) -> WorkspaceResult<()> {
+    match canonicalize_lenient(path) {
+        Some(real) if !real_roots.iter().any(|root| real.starts_with(root)) => {
+            Err(WorkspaceError::SymlinkEscapesWorkspace {
+                path: path.display().to_string(),
+                target: real.display().to_string(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Where the book files of a workspace may live: its directory, plus the
+/// directories listed in `allowedExternalRoots` of `workspace.json` for
+/// books shared between workspaces (`../shared/common.json`). Roots are
+/// taken from the workspace directory or `~`; since `workspace.json` comes
+/// with the workspace, absolute roots are ignored, and so are roots at or
+/// above the home directory or the workspace directory, which would open
+/// all of it. Loads and saves both go through this, so whatever one
+/// accepts the other does too.
+#[derive(Debug, Clone)]
+pub struct BookRoots {
+    dir: PathBuf,
+    /// Lexically normalized; the workspace directory first.
+    roots: Vec<PathBuf>,
+}
+
+impl BookRoots {
+    pub fn new(workspace_dir: &Path, workspace_data: &Value) -> Self {
+        // A relative directory such as `../ws` would climb above its own
+        // start, leaving no roots at all.
+        let workspace_dir = absolute(workspace_dir);
+        let workspace_dir = &normalize_lexically(&workspace_dir).unwrap_or(workspace_dir);
+        let home = dirs::home_dir().and_then(|home| normalize_lexically(&home));
+        let external = workspace_data["allowedExternalRoots"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(|root| root.replace('\\', "/"))
+            .filter(|root| !root.is_empty() && !is_absolute_like(root))
+            .filter_map(|root| {
+                normalize_lexically(&workspace_dir.join(expand_home(Path::new(&root))))
+            })
+            .filter(|root| {
+                !workspace_dir.starts_with(root)
+                    && !home.as_ref().is_some_and(|home| home.starts_with(root))
+            });
+        let roots = normalize_lexically(workspace_dir)
+            .into_iter()
+            .chain(external)
+            .collect();
+        Self {
+            dir: workspace_dir.clone(),
+            roots,
+        }
+    }
+
+    fn allows(&self, path: &Path) -> bool {
+        normalize_lexically(path).is_some_and(|path| {
+            self.roots
+                .iter()
+                .any(|root| path.starts_with(root) && path != *root)
+        })
+    }
+
+    /// Like `resolve_data_path`, except that a `dataPath` climbing out of
+    /// the workspace directory, or a `~/...` one outside it, is accepted
+    /// when it ends up below one of the external roots. Absolute
+    /// `dataPath`s are refused all the same.
+    pub fn resolve(&self, data_path: &str, index: usize) -> WorkspaceResult<PathBuf> {
+        let resolved = resolve_data_path(&self.dir, data_path, index);
+        if resolved.is_ok() || self.roots.len() < 2 {
+            return resolved;
+        }
+        let unified = data_path.replace('\\', "/");
+        let expanded = expand_home(Path::new(&unified));
+        if expanded == Path::new(&unified) && is_absolute_like(&unified) {
+            return resolved;
+        }
+        match normalize_lexically(&self.dir.join(expanded)) {
+            Some(path) if self.allows(&path) => Ok(path),
+            _ => resolved,
+        }
+    }
+
+    /// Fails with `pathOutsideWorkspace` for a `filePath` outside every root.
+    pub fn ensure_allows(&self, path: &Path, index: usize) -> WorkspaceResult<()> {
+        if self.allows(path) {
+            Ok(())
+        } else {
+            Err(WorkspaceError::PathOutsideWorkspace {
+                field: format!("books[{}].filePath", index),
+                path: path.display().to_string(),
+            })
+        }
+    }
+
+    /// The roots with links resolved, for checks against resolved paths.
+    /// Roots that cannot be resolved at all are left out.
+    pub fn resolved(&self) -> Vec<PathBuf> {
+        self.roots
+            .iter()
+            .filter_map(|root| canonicalize_lenient(root))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_data_path_rejects_escaping_paths() {
+        let dir = Path::new("/workspace");
+        for data_path in [
+            "../../../../etc/passwd",
+            "books/../../secret.json",
+            "..\\..\\secret.json",
+            "/etc/passwd",
+            "C:\\Windows\\win.ini",
+            "c:/data.json",
+        ] {
+            let err = resolve_data_path(dir, data_path, 3).unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with("books[3].dataPath escapes workspace directory"));
+        }
+
+        assert_eq!(
+            resolve_data_path(dir, "./books/../books/a.json", 0).unwrap(),
+            Path::new("/workspace/books/a.json")
+        );
+    }
+
+    #[test]
+    fn home_is_expanded_before_paths_are_checked() {
+        let Some(home) = dirs::home_dir() else {
+            return;
+        };
+        assert_eq!(expand_home(Path::new("~")), home);
+        assert_eq!(
+            expand_home(Path::new("~/Documents/a.json")),
+            home.join("Documents/a.json")
+        );
+        for untouched in ["~user/a.json", "books/~/a.json", "~a.json"] {
+            assert_eq!(expand_home(Path::new(untouched)), Path::new(untouched));
+        }
+
+        let dir = home.join("workspace");
+        assert_eq!(
+            resolve_data_path(&dir, "~/workspace/books/a.json", 0).unwrap(),
+            dir.join("books/a.json")
+        );
+        assert!(resolve_data_path(&dir, "~/other/a.json", 0).is_err());
+        assert!(resolve_data_path(&dir, "~/workspace/../../etc/passwd", 0).is_err());
+        let roots = BookRoots::new(
+            &dir,
+            &serde_json::json!({ "allowedExternalRoots": ["~/shared"] }),
+        );
+        assert_eq!(
+            roots.resolve("~\\shared\\common.json", 1).unwrap(),
+            home.join("shared/common.json")
+        );
+        assert!(roots.resolve("~/elsewhere.json", 1).is_err());
+    }
+
+    #[test]
+    fn duplicate_data_paths_are_grouped() {
+        let paths = [
+            Some("books/a.json"),
+            Some("books/B.json"),
+            Some("./books/../books/a.json"),
+            None,
+            Some("books/b.json"),
+            Some("../outside.json"),
+        ];
+        let indices = |case_insensitive| -> Vec<Vec<usize>> {
+            duplicate_data_paths(paths, case_insensitive)
+                .into_iter()
+                .map(|err| match err {
+                    WorkspaceError::DuplicateDataPath { indices, .. } => indices,
+                    other => panic!("unexpected {:?}", other),
+                })
+                .collect()
+        };
+        assert_eq!(indices(false), vec![vec![0, 2]]);
+        assert_eq!(indices(true), vec![vec![0, 2], vec![1, 4]]);
+    }
+
+    #[test]
+    fn ensure_allows_checks_saved_paths() {
+        let roots = BookRoots::new(Path::new("/workspace"), &Value::Null);
+        assert!(roots
+            .ensure_allows(Path::new("/workspace/books/a.json"), 0)
+            .is_ok());
+        assert!(roots
+            .ensure_allows(Path::new("/workspace/../etc/passwd"), 1)
+            .is_err());
+        assert!(roots.ensure_allows(Path::new("/other/a.json"), 2).is_err());
+    }
+
+    #[test]
+    fn external_roots_admit_only_paths_below_them() {
+        let workspace =
+            serde_json::json!({ "allowedExternalRoots": ["../shared", "../../data/common"] });
+        let roots = BookRoots::new(Path::new("/projects/a"), &workspace);
+        let resolve = |data_path| roots.resolve(data_path, 0).ok();
+        assert_eq!(
+            resolve("../shared/common.json"),
+            Some(PathBuf::from("/projects/shared/common.json"))
+        );
+        assert_eq!(
+            resolve("../../data/common/x.json"),
+            Some(PathBuf::from("/data/common/x.json"))
+        );
+        assert_eq!(
+            resolve("books/a.json"),
+            Some(PathBuf::from("/projects/a/books/a.json"))
+        );
+        for refused in [
+            "../other/x.json",
+            "../shared",
+            "../shared/../secret.json",
+            "/data/common/x.json",
+        ] {
+            assert_eq!(resolve(refused), None, "{}", refused);
+        }
+        assert!(roots
+            .ensure_allows(Path::new("/projects/shared/b.json"), 0)
+            .is_ok());
+
+        let strict = BookRoots::new(Path::new("/projects/a"), &Value::Null);
+        assert!(strict.resolve("../shared/common.json", 0).is_err());
+    }
+
+    #[test]
+    fn external_roots_cannot_be_absolute_or_above_the_workspace() {
+        let workspace = serde_json::json!({
+            "allowedExternalRoots": ["/", "/data/common", "C:\\", "../../..", "../.."]
+        });
+        let roots = BookRoots::new(Path::new("/projects/a"), &workspace);
+        for refused in ["../../etc/passwd", "../../data/common/x.json"] {
+            assert!(roots.resolve(refused, 0).is_err(), "{}", refused);
+        }
+        assert!(roots.ensure_allows(Path::new("/etc/passwd"), 0).is_err());
+        assert!(roots
+            .ensure_allows(Path::new("/projects/a/books/a.json"), 0)
+            .is_ok());
+
+        let workspace = serde_json::json!({ "allowedExternalRoots": ["..", "../..", "."] });
+        let roots = BookRoots::new(Path::new("/projects/a"), &workspace);
+        assert!(roots.resolve("../b/x.json", 0).is_err());
+        assert!(roots
+            .ensure_allows(Path::new("/projects/b/x.json"), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn external_roots_cannot_open_the_home_directory() {
+        let Some(home) = dirs::home_dir() else {
+            return;
+        };
+        let workspace = serde_json::json!({ "allowedExternalRoots": ["~", "~/..", "~/shared"] });
+        let roots = BookRoots::new(&home.join("projects/a"), &workspace);
+        assert!(roots.resolve("~/.ssh/id_rsa", 0).is_err());
+        assert!(roots.resolve("../../.config/x.json", 0).is_err());
+        assert_eq!(
+            roots.resolve("~/shared/x.json", 0).unwrap(),
+            home.join("shared/x.json")
+        );
+    }
+
+    #[test]
+    fn relative_workspace_dirs_climbing_up_keep_their_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        // `../../<tempdir>/ws`, relative to the current directory.
+        let mut relative: PathBuf = cwd.components().skip(1).map(|_| "..").collect();
+        relative.extend(dir.path().components().skip(1));
+        let workspace_path = relative.join("ws/workspace.json");
+        assert!(workspace_path.starts_with(".."));
+
+        let roots = BookRoots::new(&workspace_dir_of(&workspace_path), &Value::Null);
+        let book = roots.resolve("books/a.json", 0).unwrap();
+        assert_eq!(book, dir.path().join("ws/books/a.json"));
+        assert!(roots.ensure_allows(&book, 0).is_ok());
+        assert!(roots.resolve("../x.json", 0).is_err());
+    }
+
+    #[test]
+    fn canonicalize_lenient_resolves_missing_tails() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(dir.path().join("books")).unwrap();
+        fs::write(dir.path().join("books/a.json"), "{}").unwrap();
+
+        let spelled = dir.path().join("./books/../books/a.json");
+        assert_eq!(
+            canonicalize_lenient(&spelled),
+            Some(real.join("books/a.json"))
+        );
+        assert_eq!(
+            canonicalize_lenient(&dir.path().join("books/new/b.json")),
+            Some(real.join("books/new/b.json"))
+        );
+
+        let workspace = dir.path().join("./books/../workspace.json");
+        assert_eq!(real_workspace_dir(&workspace), real.to_string_lossy());
+        assert_eq!(without_verbatim_prefix(r"\\?\C:\ws"), r"C:\ws");
+        assert_eq!(
+            without_verbatim_prefix(r"\\?\UNC\server\ws"),
+            r"\\?\UNC\server\ws"
+        );
+    }
+}
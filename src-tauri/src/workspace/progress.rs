@@ -0,0 +1,124 @@
+use super::error::WorkspaceResult;
+use super::metrics::METRICS_EVENT;
+use super::{load_snapshot, LoadOptions, WorkspaceSnapshotPayload};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter};
+
+pub const LOAD_PROGRESS_EVENT: &str = "workspace-load-progress";
+/// Books per progress event; the last book always gets one.
+const BOOKS_PER_EVENT: usize = 10;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadProgress {
+    /// Books attempted so far, including ones that failed to load.
+    pub loaded: usize,
+    pub total: usize,
+    /// The most recently attempted book.
+    pub current_path: Option<String>,
+    /// Set on the final event, which is sent whether or not the load
+    /// succeeded.
+    pub done: bool,
+}
+
+/// Counts finished books and reports every `BOOKS_PER_EVENT`th. Reports are
+/// made under the lock so they arrive in order even though books load in
+/// parallel.
+pub struct ProgressReporter<F: Fn(&LoadProgress) + Sync> {
+    state: Mutex<LoadProgress>,
+    report: F,
+}
+
+impl<F: Fn(&LoadProgress) + Sync> ProgressReporter<F> {
+    pub fn new(report: F) -> Self {
+        Self {
+            state: Mutex::new(LoadProgress::default()),
+            report,
+        }
+    }
+
+    pub fn start(&self, total: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.total = total;
+        (self.report)(&state);
+    }
+
+    pub fn book_done(&self, path: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.loaded += 1;
+        state.current_path = Some(path.to_string());
+        if state.loaded.is_multiple_of(BOOKS_PER_EVENT) || state.loaded == state.total {
+            (self.report)(&state);
+        }
+    }
+
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.done = true;
+        (self.report)(&state);
+    }
+}
+
+/// Same result as `load_workspace_snapshot`, but emits
+/// `workspace-load-progress` while books are read, and `workspace-metrics`
+/// at the end of loads with the `metrics` option. Runs off the main thread
+/// so the events reach the window during the load.
+#[tauri::command(async)]
+pub fn load_workspace_snapshot_with_progress(
+    app: AppHandle,
+    path: String,
+    options: Option<LoadOptions>,
+) -> WorkspaceResult<WorkspaceSnapshotPayload> {
+    let reporter = ProgressReporter::new(|progress: &LoadProgress| {
+        let _ = app.emit(LOAD_PROGRESS_EVENT, progress.clone());
+    });
+    let result = load_snapshot(
+        Path::new(&path),
+        options.unwrap_or_default(),
+        |total| reporter.start(total),
+        |path| reporter.book_done(path),
+    );
+    reporter.finish();
+    if let Some(metrics) = result
+        .as_ref()
+        .ok()
+        .and_then(|snapshot| snapshot.metrics.as_ref())
+    {
+        let _ = app.emit(METRICS_EVENT, metrics.clone());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_in_batches_and_always_finishes() {
+        let events = Mutex::new(Vec::new());
+        let reporter = ProgressReporter::new(|progress: &LoadProgress| {
+            events
+                .lock()
+                .unwrap()
+                .push((progress.loaded, progress.done));
+        });
+        reporter.start(23);
+        for index in 0..23 {
+            reporter.book_done(&format!("books/{}.json", index));
+        }
+        reporter.finish();
+
+        assert_eq!(
+            events.into_inner().unwrap(),
+            vec![
+                (0, false),
+                (10, false),
+                (20, false),
+                (23, false),
+                (23, true)
+            ]
+        );
+    }
+}
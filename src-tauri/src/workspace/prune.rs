@@ -0,0 +1,206 @@
+//! `prune_orphan_books`: removes book files no `workspace.json` entry refers
+//! to, the same files `diagnose_workspace` reports as orphans.
+
+use super::books::split_book_file_name;
+use super::diagnose::orphan_files;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_json_file, read_workspace_json};
+use super::paths::{resolve_data_path, workspace_dir_of};
+use super::readonly::ensure_writable;
+use super::schema::validate_workspace;
+use super::trash::move_to_trash;
+use super::watcher;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrunedOrphan {
+    /// Relative to the workspace directory, with `/` separators.
+    pub data_path: String,
+    /// Pass to `restore_from_trash` to bring the file back as a book.
+    pub trash_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneFailure {
+    pub data_path: String,
+    pub error: WorkspaceError,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneOrphansResult {
+    pub dry_run: bool,
+    /// Every orphan found; in a dry run nothing else happened to them.
+    pub orphans: Vec<String>,
+    pub pruned: Vec<PrunedOrphan>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<PruneFailure>,
+}
+
+/// The trash entry of an orphan, made up so that `restore_from_trash` can
+/// list the file as a book again under the id and name it carries.
+fn trash_entry(path: &Path, data_path: &str) -> Value {
+    let book = read_json_file(path).ok();
+    let text = |field: &str| {
+        book.as_ref()
+            .and_then(|book| book["book"][field].as_str())
+            .map(str::to_string)
+    };
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = text("name").unwrap_or_else(|| split_book_file_name(&file_name).0.to_string());
+    let id = text("id").unwrap_or_else(|| format!("book-{}", uuid::Uuid::new_v4()));
+    json!({ "id": id, "name": name, "dataPath": data_path })
+}
+
+/// Deletes the orphaned book files of a workspace or, with `to_trash`,
+/// moves each into its own `.sheet-up-trash/` entry. With `dry_run` only
+/// lists them. `workspace.json` must be valid, since every path it does not
+/// name would otherwise count as an orphan; internal files (backups, trash,
+/// manifest, journal, lock) are never touched.
+#[tauri::command]
+pub fn prune_orphan_books(
+    workspace_path: String,
+    to_trash: bool,
+    dry_run: bool,
+) -> WorkspaceResult<PruneOrphansResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace = read_workspace_json(&workspace_path)?;
+    validate_workspace(&workspace)?;
+    if !dry_run {
+        ensure_writable(&workspace_path, &workspace)?;
+    }
+    let workspace_dir = workspace_dir_of(&workspace_path);
+
+    let referenced = workspace["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(index, book_ref)| {
+            resolve_data_path(&workspace_dir, book_ref["dataPath"].as_str()?, index).ok()
+        });
+    let orphans = orphan_files(&workspace_dir, referenced);
+    let mut result = PruneOrphansResult {
+        dry_run,
+        orphans: orphans
+            .iter()
+            .map(|(relative, _)| relative.clone())
+            .collect(),
+        pruned: Vec::new(),
+        failed: Vec::new(),
+    };
+    if dry_run {
+        return Ok(result);
+    }
+
+    for (data_path, path) in orphans {
+        let removed = if to_trash {
+            let entry = trash_entry(&path, &data_path);
+            move_to_trash(&workspace_dir, &entry, Some(&path)).map(Some)
+        } else {
+            watcher::self_remove(&path);
+            fs::remove_file(&path)
+                .map(|()| None)
+                .map_err(|err| WorkspaceError::io("delete", &path, err))
+        };
+        match removed {
+            Ok(trash_id) => result.pruned.push(PrunedOrphan {
+                data_path,
+                trash_id,
+            }),
+            Err(error) => result.failed.push(PruneFailure { data_path, error }),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::backup::BACKUP_DIR;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use crate::workspace::manifest::MANIFEST_FILE_NAME;
+    use crate::workspace::restore_from_trash;
+
+    fn setup() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = FileEncoding::default();
+        for name in ["books/kept.json", "books/orphan.json", "extra/old.json.gz"] {
+            let id = split_book_file_name(name.rsplit('/').next().unwrap()).0;
+            let book = json!({ "book": { "id": id, "name": id }, "sheets": [] });
+            write_json_file(&dir.path().join(name), &book, plain).unwrap();
+        }
+        write_json_file(
+            &dir.path().join(BACKUP_DIR).join("a.json"),
+            &json!({}),
+            plain,
+        )
+        .unwrap();
+        write_json_file(&dir.path().join(MANIFEST_FILE_NAME), &json!({}), plain).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a book").unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({
+                "schemaVersion": "1.0.0",
+                "books": [{ "id": "kept", "name": "kept", "dataPath": "books/kept.json" }]
+            }),
+            plain,
+        )
+        .unwrap();
+        (dir, workspace_path.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn dry_run_only_lists_and_delete_spares_internal_files() {
+        let (dir, workspace_path) = setup();
+        let listed = prune_orphan_books(workspace_path.clone(), false, true).unwrap();
+        assert_eq!(listed.orphans, ["books/orphan.json", "extra/old.json.gz"]);
+        assert!(listed.pruned.is_empty());
+        assert!(dir.path().join("books/orphan.json").exists());
+
+        let pruned = prune_orphan_books(workspace_path, false, false).unwrap();
+        assert_eq!(pruned.pruned.len(), 2);
+        assert!(pruned.failed.is_empty());
+        assert!(!dir.path().join("books/orphan.json").exists());
+        assert!(!dir.path().join("extra/old.json.gz").exists());
+        for kept in [
+            "workspace.json",
+            "books/kept.json",
+            MANIFEST_FILE_NAME,
+            "notes.txt",
+        ] {
+            assert!(dir.path().join(kept).exists(), "{} was removed", kept);
+        }
+        assert!(dir.path().join(BACKUP_DIR).join("a.json").exists());
+    }
+
+    #[test]
+    fn trashed_orphans_can_be_restored_as_books() {
+        let (dir, workspace_path) = setup();
+        let pruned = prune_orphan_books(workspace_path.clone(), true, false).unwrap();
+        let orphan = pruned
+            .pruned
+            .iter()
+            .find(|orphan| orphan.data_path == "books/orphan.json")
+            .unwrap();
+        assert!(!dir.path().join("books/orphan.json").exists());
+        // A second run does not mistake the trashed copies for orphans.
+        let again = prune_orphan_books(workspace_path.clone(), true, true).unwrap();
+        assert!(again.orphans.is_empty());
+
+        let restored =
+            restore_from_trash(workspace_path, orphan.trash_id.clone().unwrap()).unwrap();
+        assert_eq!(restored.book_id, "orphan");
+        assert_eq!(restored.data_path, "books/orphan.json");
+        assert!(dir.path().join("books/orphan.json").exists());
+    }
+}
@@ -0,0 +1,141 @@
+//! The `readOnly` flag of `workspace.json`. While it is set, snapshot saves
+//! and every other command that writes the workspace or its books (creating,
+//! deleting, renaming and reordering books, CSV imports, relocating,
+//! restoring from the trash, pruning) are refused; `set_workspace_readonly`
+//! is the way to toggle it.
+
+use super::books::now_rfc3339;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_workspace_json, FileEncoding};
+use super::schema::validate_workspace;
+use super::write_tracked;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+pub(super) fn is_read_only(workspace: &Value) -> bool {
+    workspace.get("readOnly").and_then(Value::as_bool) == Some(true)
+}
+
+/// Fails with `readOnlyWorkspace` when the `workspace.json` on disk is
+/// read-only, or when the snapshot would make it so: a save must not be a
+/// way around `set_workspace_readonly`. A workspace not yet on disk is only
+/// checked through the snapshot. Commands that edit the file on disk pass
+/// what they read as `data`.
+pub(super) fn ensure_writable(workspace_path: &Path, data: &Value) -> WorkspaceResult<()> {
+    let on_disk = match read_workspace_json(workspace_path) {
+        Ok(current) => is_read_only(&current),
+        Err(WorkspaceError::NotFound { .. }) => false,
+        Err(err) => return Err(err),
+    };
+    if on_disk || is_read_only(data) {
+        return Err(WorkspaceError::ReadOnlyWorkspace {
+            path: workspace_path.display().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Sets or clears `readOnly` in `workspace.json`. Clearing removes the key
+/// rather than writing `false`. Snapshots loaded before the change still
+/// hold the old file and should be reloaded before saving.
+#[tauri::command]
+pub fn set_workspace_readonly(workspace_path: String, read_only: bool) -> WorkspaceResult<()> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let mut workspace = read_workspace_json(&workspace_path)?;
+    validate_workspace(&workspace)?;
+    if is_read_only(&workspace) == read_only {
+        return Ok(());
+    }
+
+    let root = workspace
+        .as_object_mut()
+        .expect("validated workspace is an object");
+    if read_only {
+        root.insert("readOnly".into(), Value::Bool(true));
+    } else {
+        root.shift_remove("readOnly");
+    }
+    if let Some(meta) = workspace
+        .get_mut("workspace")
+        .and_then(Value::as_object_mut)
+    {
+        meta.insert("updatedAt".into(), Value::String(now_rfc3339()));
+    }
+    write_tracked(&workspace_path, &workspace, FileEncoding::default())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+    use crate::workspace::{create_book, load_workspace_snapshot, reorder_books, save_snapshot};
+    use serde_json::json;
+
+    #[test]
+    fn saves_are_refused_until_the_flag_is_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("workspace.json");
+        let path_str = path.to_string_lossy().into_owned();
+        write_json_file(
+            &path,
+            &json!({ "schemaVersion": "1.0.0", "workspace": {}, "books": [], "readOnly": true }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+
+        let mut snapshot = load_workspace_snapshot(path_str.clone(), None).unwrap();
+        assert!(snapshot.read_only);
+        snapshot.workspace.data["readOnly"] = json!(false);
+        assert!(matches!(
+            save_snapshot(snapshot, Some(true), None),
+            Err(WorkspaceError::ReadOnlyWorkspace { .. })
+        ));
+
+        set_workspace_readonly(path_str.clone(), false).unwrap();
+        let saved = read_json_file(&path).unwrap();
+        assert!(saved.get("readOnly").is_none());
+        assert!(saved["workspace"]["updatedAt"].is_string());
+        let snapshot = load_workspace_snapshot(path_str.clone(), None).unwrap();
+        assert!(!snapshot.read_only);
+        save_snapshot(snapshot, None, None).unwrap();
+
+        let mut snapshot = load_workspace_snapshot(path_str.clone(), None).unwrap();
+
+        snapshot.workspace.data["readOnly"] = json!(true);
+        assert!(matches!(
+            save_snapshot(snapshot, Some(true), None),
+            Err(WorkspaceError::ReadOnlyWorkspace { .. })
+        ));
+        set_workspace_readonly(path_str, true).unwrap();
+        assert_eq!(read_json_file(&path).unwrap()["readOnly"], json!(true));
+    }
+
+    #[test]
+    fn book_commands_are_refused_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("workspace.json");
+        let path_str = path.to_string_lossy().into_owned();
+        write_json_file(
+            &path,
+            &json!({ "schemaVersion": "1.0.0", "workspace": {}, "books": [], "readOnly": true }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        assert!(matches!(
+            create_book(path_str.clone(), "Book".into()),
+            Err(WorkspaceError::ReadOnlyWorkspace { .. })
+        ));
+        assert!(matches!(
+            reorder_books(path_str.clone(), Vec::new()),
+            Err(WorkspaceError::ReadOnlyWorkspace { .. })
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert!(!dir.path().join("books").exists());
+
+        set_workspace_readonly(path_str.clone(), false).unwrap();
+        create_book(path_str, "Book".into()).unwrap();
+    }
+}
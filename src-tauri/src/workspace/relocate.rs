@@ -0,0 +1,208 @@
+use super::books::now_rfc3339;
+use super::error::WorkspaceResult;
+use super::io::{read_workspace_json, FileEncoding};
+use super::paths::{absolute, is_absolute_like, resolve_data_path, workspace_dir_of};
+use super::readonly::ensure_writable;
+use super::schema::validate_workspace;
+use super::write_tracked;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelocatedBook {
+    pub book_id: String,
+    pub old_data_path: String,
+    pub new_data_path: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnresolvedBook {
+    pub book_id: String,
+    pub data_path: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelocateResult {
+    pub rewritten: Vec<RelocatedBook>,
+    /// Books whose file could not be found; their `dataPath` is left as is.
+    pub unresolved: Vec<UnresolvedBook>,
+}
+
+/// Path segments of `path` with `.` and `..` applied and any root or drive
+/// letter dropped. Both separators are accepted whatever the host uses.
+fn segments(path: &str) -> Vec<String> {
+    let mut segments: Vec<String> = Vec::new();
+    for (index, part) in path.split(['/', '\\']).enumerate() {
+        let is_drive = index == 0 && part.len() == 2 && part.ends_with(':');
+        match part {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ if is_drive => {}
+            _ => segments.push(part.to_string()),
+        }
+    }
+    segments
+}
+
+fn same_segment(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// Where an absolute `data_path` lives relative to `workspace_dir`: under
+/// the directory itself, or otherwise the longest tail of the path that
+/// names an existing file in it (the workspace was moved or copied with its
+/// books).
+fn relative_data_path(workspace_dir: &Path, data_path: &str) -> Option<String> {
+    let parts = segments(data_path);
+    let dir_parts = segments(&workspace_dir.to_string_lossy());
+    let inside = parts.len() > dir_parts.len()
+        && parts
+            .iter()
+            .zip(&dir_parts)
+            .all(|(part, dir_part)| same_segment(part, dir_part));
+    if inside {
+        return Some(parts[dir_parts.len()..].join("/"));
+    }
+    (1..parts.len())
+        .map(|start| parts[start..].join("/"))
+        .find(|tail| workspace_dir.join(tail).is_file())
+}
+
+/// Rewrites absolute `dataPath`s as `/`-separated paths relative to the
+/// workspace's current directory, e.g. after the folder was moved. Relative
+/// `dataPath`s are kept. Books whose file cannot be found are reported in
+/// `unresolved` and never removed. `workspace.json` is only written when
+/// something changed.
+#[tauri::command]
+pub fn relocate_workspace(workspace_path: String) -> WorkspaceResult<RelocateResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace_dir = absolute(&workspace_dir_of(&workspace_path));
+    let mut workspace = read_workspace_json(&workspace_path)?;
+    ensure_writable(&workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+
+    let mut result = RelocateResult::default();
+    for (index, book_ref) in workspace["books"]
+        .as_array_mut()
+        .expect("validated workspace has a books array")
+        .iter_mut()
+        .enumerate()
+    {
+        let book_id = book_ref["id"].as_str().unwrap_or_default().to_string();
+        let data_path = book_ref["dataPath"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let relative = if is_absolute_like(&data_path) {
+            relative_data_path(&workspace_dir, &data_path)
+        } else {
+            Some(data_path.clone())
+        };
+        let found = relative.as_deref().is_some_and(|relative| {
+            resolve_data_path(&workspace_dir, relative, index).is_ok_and(|path| path.is_file())
+        });
+        if !found {
+            result.unresolved.push(UnresolvedBook {
+                book_id: book_id.clone(),
+                data_path: data_path.clone(),
+            });
+        }
+        match relative {
+            Some(relative) if relative != data_path => {
+                book_ref["dataPath"] = json!(relative);
+                result.rewritten.push(RelocatedBook {
+                    book_id,
+                    old_data_path: data_path,
+                    new_data_path: relative,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if !result.rewritten.is_empty() {
+        if let Some(meta) = workspace
+            .get_mut("workspace")
+            .and_then(Value::as_object_mut)
+        {
+            meta.insert("updatedAt".into(), Value::String(now_rfc3339()));
+        }
+        write_tracked(&workspace_path, &workspace, FileEncoding::default())?;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+    use std::fs;
+
+    #[test]
+    fn absolute_data_paths_become_relative() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        fs::create_dir_all(dir.path().join("books")).unwrap();
+        for name in ["a.json", "b.json", "c.json", "e.json"] {
+            fs::write(dir.path().join("books").join(name), "{}").unwrap();
+        }
+        let inside = dir.path().join("books/b.json");
+        let refs: Vec<Value> = [
+            "books/a.json",
+            inside.to_str().unwrap(),
+            "/old/place/ws/books/c.json",
+            "/nowhere/d.json",
+            "C:\\old\\ws\\books\\e.json",
+            "books/missing.json",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(index, data_path)| {
+            json!({ "id": format!("book-{}", index), "name": "Book", "dataPath": data_path })
+        })
+        .collect();
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": refs }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+
+        let result = relocate_workspace(workspace_path.to_string_lossy().into_owned()).unwrap();
+        let rewritten: Vec<(&str, &str)> = result
+            .rewritten
+            .iter()
+            .map(|book| (book.book_id.as_str(), book.new_data_path.as_str()))
+            .collect();
+        assert_eq!(
+            rewritten,
+            vec![
+                ("book-1", "books/b.json"),
+                ("book-2", "books/c.json"),
+                ("book-4", "books/e.json")
+            ]
+        );
+        let unresolved: Vec<&str> = result
+            .unresolved
+            .iter()
+            .map(|book| book.data_path.as_str())
+            .collect();
+        assert_eq!(unresolved, vec!["/nowhere/d.json", "books/missing.json"]);
+
+        let saved = read_json_file(&workspace_path).unwrap();
+        assert_eq!(saved["books"][1]["dataPath"], "books/b.json");
+        assert_eq!(saved["books"][3]["dataPath"], "/nowhere/d.json");
+        let again = relocate_workspace(workspace_path.to_string_lossy().into_owned()).unwrap();
+        assert!(again.rewritten.is_empty());
+    }
+}
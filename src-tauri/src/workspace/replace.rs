@@ -0,0 +1,351 @@
+use super::backup::BackupOptions;
+use super::cells::{column_index, row_index};
+use super::error::WorkspaceResult;
+use super::io::{LineEndingMode, SizeLimits, WriteOptions};
+use super::parallel::parallel_map;
+use super::paths::workspace_dir_of;
+use super::search::{build_matcher, cell_text, SearchFailure};
+use super::{
+    book_file_path, load_book, load_workspace_file, save_snapshot, FilePayload, SaveOptions,
+    WorkspaceSnapshotPayload,
+};
+use regex::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplaceOptions {
+    pub case_insensitive: bool,
+    /// Only replace cells whose whole text is the query.
+    pub whole_cell: bool,
+    /// Treat the query as a regular expression; the replacement may then
+    /// refer to capture groups as `$1` or `${name}`.
+    pub regex: bool,
+    /// Leave number and boolean cells alone, so a match inside `2024` does
+    /// not turn the cell into text. On by default.
+    pub strings_only: bool,
+    /// Compute the result without writing any file.
+    pub dry_run: bool,
+    /// How many changed cells to include in `preview`; 100 when omitted.
+    pub max_preview: Option<usize>,
+    /// Decrypts encrypted books and re-encrypts them on save.
+    pub passphrase: Option<String>,
+    pub backup: BackupOptions,
+}
+
+impl Default for ReplaceOptions {
+    fn default() -> Self {
+        Self {
+            case_insensitive: false,
+            whole_cell: false,
+            regex: false,
+            strings_only: true,
+            dry_run: false,
+            max_preview: None,
+            passphrase: None,
+            backup: BackupOptions::default(),
+        }
+    }
+}
+
+const DEFAULT_MAX_PREVIEW: usize = 100;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplacedCell {
+    pub book_id: String,
+    pub sheet_id: String,
+    /// A1-style address such as `"B3"`.
+    pub cell: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplacedBook {
+    pub book_id: String,
+    pub file_path: String,
+    pub replacements: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceResult {
+    /// Matches replaced, over all cells.
+    pub replacements: usize,
+    /// Books with at least one changed cell, in workspace order.
+    pub books: Vec<ReplacedBook>,
+    /// The first changed cells in book, sheet, row, column order.
+    pub preview: Vec<ReplacedCell>,
+    /// Files written; empty for a dry run.
+    pub written: Vec<String>,
+    /// Books that could not be loaded and were left untouched.
+    pub failed: Vec<SearchFailure>,
+}
+
+struct Replacer<'a> {
+    matcher: &'a Regex,
+    replacement: &'a str,
+    options: &'a ReplaceOptions,
+    preview_limit: usize,
+}
+
+impl Replacer<'_> {
+    fn apply<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        if self.options.regex {
+            self.matcher.replace_all(text, self.replacement)
+        } else {
+            self.matcher.replace_all(text, NoExpand(self.replacement))
+        }
+    }
+
+    /// Replaces in place and returns the number of matches replaced.
+    fn book(&self, book_id: &str, book: &mut Value, preview: &mut Vec<ReplacedCell>) -> usize {
+        let mut replacements = 0;
+        for sheet in book["sheets"].as_array_mut().into_iter().flatten() {
+            let sheet_id = sheet["id"].as_str().unwrap_or_default().to_string();
+            let Some(rows) = sheet["rows"].as_object_mut() else {
+                continue;
+            };
+            let mut row_keys: Vec<String> = rows.keys().cloned().collect();
+            row_keys.sort_by_key(|key| (row_index(key).unwrap_or(u32::MAX), key.clone()));
+            for row in row_keys {
+                let Some(cells) = rows[&row].as_object_mut() else {
+                    continue;
+                };
+                let mut columns: Vec<String> = cells.keys().cloned().collect();
+                columns.sort_by_key(|key| (column_index(key).unwrap_or(u32::MAX), key.clone()));
+                for column in columns {
+                    let cell = &mut cells[&column];
+                    if self.options.strings_only && !cell["value"].is_string() {
+                        continue;
+                    }
+                    let Some(old) = cell_text(cell) else {
+                        continue;
+                    };
+                    let new = self.apply(&old);
+                    if new == old {
+                        continue;
+                    }
+                    let new = new.into_owned();
+                    replacements += self.matcher.find_iter(&old).count();
+                    if preview.len() < self.preview_limit {
+                        preview.push(ReplacedCell {
+                            book_id: book_id.to_string(),
+                            sheet_id: sheet_id.clone(),
+                            cell: format!("{}{}", column, row),
+                            old,
+                            new: new.clone(),
+                        });
+                    }
+                    cell["value"] = json!(new);
+                    if cell.get("type").is_some() {
+                        cell["type"] = json!("string");
+                    }
+                }
+            }
+        }
+        replacements
+    }
+}
+
+/// Replaces `query` in the cell values of every book. All books are changed
+/// in memory first and then saved together through
+/// `save_workspace_snapshot`, keeping each file's line endings. If writing
+/// fails part way the error is `partialSave` listing the books already
+/// written; those are not rolled back.
+#[tauri::command(async)]
+pub fn replace_in_workspace(
+    workspace_path: String,
+    query: String,
+    replacement: String,
+    options: Option<ReplaceOptions>,
+) -> WorkspaceResult<ReplaceResult> {
+    let options = options.unwrap_or_default();
+    let matcher = build_matcher(
+        &query,
+        options.regex,
+        options.case_insensitive,
+        options.whole_cell,
+    )?;
+    let replacer = Replacer {
+        matcher: &matcher,
+        replacement: &replacement,
+        options: &options,
+        preview_limit: options.max_preview.unwrap_or(DEFAULT_MAX_PREVIEW),
+    };
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace = load_workspace_file(&workspace_path, SizeLimits::default().workspace)?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let refs = workspace.data["books"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let passphrase = options.passphrase.as_deref();
+
+    let per_book = parallel_map(&refs, |index, book_ref| -> WorkspaceResult<_> {
+        let path = book_file_path(&workspace_dir, book_ref, index)?;
+        let mut book = load_book(&path, passphrase, SizeLimits::default().book)?;
+        let book_id = book_ref["id"].as_str().unwrap_or_default();
+        let mut preview = Vec::new();
+        let replacements = replacer.book(book_id, &mut book.data, &mut preview);
+        // Unchanged books are dropped here rather than kept until the save.
+        Ok((replacements > 0).then_some((book, replacements, preview)))
+    });
+
+    let mut result = ReplaceResult::default();
+    let mut changed: Vec<FilePayload> = Vec::new();
+    for (index, (book_ref, outcome)) in refs.iter().zip(per_book).enumerate() {
+        let book_id = book_ref["id"].as_str().map(str::to_string);
+        match outcome {
+            Ok(None) => {}
+            Ok(Some((book, replacements, preview))) => {
+                result.replacements += replacements;
+                result.books.push(ReplacedBook {
+                    book_id: book_id.unwrap_or_default(),
+                    file_path: book.file_path.clone(),
+                    replacements,
+                });
+                let room = replacer.preview_limit - result.preview.len();
+                result.preview.extend(preview.into_iter().take(room));
+                changed.push(book);
+            }
+            Err(error) => result.failed.push(SearchFailure {
+                index,
+                book_id,
+                error,
+            }),
+        }
+    }
+
+    if options.dry_run || changed.is_empty() {
+        return Ok(result);
+    }
+    let snapshot = WorkspaceSnapshotPayload {
+        workspace,
+        books: changed,
+        failed: Vec::new(),
+        warnings: Vec::new(),
+        created: Vec::new(),
+        large_cells: Vec::new(),
+        read_only: false,
+        metrics: None,
+        workspace_dir: String::new(),
+    };
+    let save_options = SaveOptions {
+        backup: options.backup.clone(),
+        passphrase: options.passphrase.clone(),
+        write: WriteOptions {
+            line_ending: LineEndingMode::Preserve,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    result.written = save_snapshot(snapshot, None, Some(save_options))?.written;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file, FileEncoding};
+    use std::path::Path;
+
+    fn write_workspace(dir: &Path) -> String {
+        let refs = json!([
+            { "id": "book-1", "name": "One", "dataPath": "one.json" },
+            { "id": "book-2", "name": "Two", "dataPath": "two.json" }
+        ]);
+        let workspace_path = dir.join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "books": refs }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let text = |value: &str| json!({ "value": value, "type": "string" });
+        for (id, rows) in [
+            (
+                "one",
+                json!({
+                    "1": { "A": text("2024-03-01"), "B": { "value": 2024, "type": "number" } },
+                    "2": { "A": text("2023-12-31") }
+                }),
+            ),
+            ("two", json!({ "1": { "A": text("no dates here") } })),
+        ] {
+            let book = json!({
+                "schemaVersion": "1.0.0",
+                "book": { "id": id, "name": id },
+                "sheets": [{
+                    "id": "sheet-1",
+                    "name": "Sheet",
+                    "gridSize": { "rows": 100, "cols": 26 },
+                    "rows": rows
+                }]
+            });
+            write_json_file(
+                &dir.join(format!("{}.json", id)),
+                &book,
+                FileEncoding::default(),
+            )
+            .unwrap();
+        }
+        workspace_path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn replaces_with_captures_and_saves_changed_books_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path());
+        let one = dir.path().join("one.json");
+        let options = |dry_run| ReplaceOptions {
+            regex: true,
+            dry_run,
+            ..Default::default()
+        };
+        let replace = |dry_run| {
+            replace_in_workspace(
+                path.clone(),
+                r"(\d{4})-(\d{2})-(\d{2})".into(),
+                "$3/$2/$1".into(),
+                Some(options(dry_run)),
+            )
+            .unwrap()
+        };
+
+        let preview = replace(true);
+        assert_eq!(preview.replacements, 2);
+        assert!(preview.written.is_empty());
+        assert_eq!(preview.preview[0].new, "01/03/2024");
+        assert_eq!(
+            read_json_file(&one).unwrap()["sheets"][0]["rows"]["1"]["A"]["value"],
+            "2024-03-01"
+        );
+
+        let result = replace(false);
+        assert_eq!(result.books.len(), 1);
+        assert_eq!(result.books[0].book_id, "book-1");
+        assert_eq!(result.written, vec![one.to_string_lossy().into_owned()]);
+        let rows = &read_json_file(&one).unwrap()["sheets"][0]["rows"];
+        assert_eq!(rows["1"]["A"]["value"], "01/03/2024");
+        assert_eq!(rows["2"]["A"]["value"], "31/12/2023");
+        assert_eq!(rows["1"]["B"]["value"], 2024);
+
+        // Plain queries insert the replacement literally and can reach
+        // number cells when asked to.
+        let literal = ReplaceOptions {
+            strings_only: false,
+            ..Default::default()
+        };
+        let result = replace_in_workspace(path, "2024".into(), "$0".into(), Some(literal)).unwrap();
+        assert_eq!(result.replacements, 2);
+        let rows = &read_json_file(&one).unwrap()["sheets"][0]["rows"];
+        assert_eq!(rows["1"]["A"]["value"], "01/03/$0");
+        assert_eq!(rows["1"]["B"], json!({ "value": "$0", "type": "string" }));
+    }
+}
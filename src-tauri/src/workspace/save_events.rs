@@ -0,0 +1,133 @@
+//! `save_workspace_snapshot`, which announces each save with
+//! `workspace-save-started` and its outcome with `workspace-save-finished`
+//! or `workspace-save-failed`, so an autosave indicator can follow the
+//! backend without waiting on the command.
+
+use super::error::WorkspaceResult;
+use super::metrics::METRICS_EVENT;
+use super::{save_snapshot_clearing_journal, SaveOptions, SaveResult, WorkspaceSnapshotPayload};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Instant;
+use tauri::{AppHandle, Emitter};
+
+pub const SAVE_STARTED_EVENT: &str = "workspace-save-started";
+pub const SAVE_FINISHED_EVENT: &str = "workspace-save-finished";
+pub const SAVE_FAILED_EVENT: &str = "workspace-save-failed";
+
+/// Payload of `workspace-save-finished`. Every event also carries
+/// `workspacePath`, so windows showing other workspaces can ignore it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveFinished<'a> {
+    pub workspace_path: &'a str,
+    /// Files actually written, as in `written` of the result.
+    pub paths: &'a [String],
+    /// Bytes now on disk in those files.
+    pub total_bytes: u64,
+    pub duration_ms: u64,
+}
+
+fn save_reporting(
+    snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+    emit: impl Fn(&str, Value),
+) -> WorkspaceResult<SaveResult> {
+    let workspace_path = snapshot.workspace.file_path.clone();
+    emit(
+        SAVE_STARTED_EVENT,
+        json!({ "workspacePath": workspace_path }),
+    );
+    let started = Instant::now();
+    let saved = save_snapshot_clearing_journal(snapshot, force, options);
+    match &saved {
+        Ok(result) => {
+            let finished = SaveFinished {
+                workspace_path: &workspace_path,
+                paths: &result.written,
+                total_bytes: result.files.iter().map(|file| file.bytes_written).sum(),
+                duration_ms: started.elapsed().as_millis() as u64,
+            };
+            if let Ok(payload) = serde_json::to_value(finished) {
+                emit(SAVE_FINISHED_EVENT, payload);
+            }
+            if let Some(payload) = result
+                .metrics
+                .as_ref()
+                .and_then(|m| serde_json::to_value(m).ok())
+            {
+                emit(METRICS_EVENT, payload);
+            }
+        }
+        Err(error) => emit(
+            SAVE_FAILED_EVENT,
+            json!({ "workspacePath": workspace_path, "error": error }),
+        ),
+    }
+    saved
+}
+
+/// Saves a snapshot and returns what was written; the events only mirror
+/// that result.
+#[tauri::command]
+pub fn save_workspace_snapshot(
+    app: AppHandle,
+    snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+) -> WorkspaceResult<SaveResult> {
+    save_reporting(snapshot, force, options, |event, payload| {
+        let _ = app.emit(event, payload);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use crate::workspace::load_workspace_snapshot;
+    use std::sync::Mutex;
+
+    #[test]
+    fn reports_start_and_outcome_of_each_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("workspace.json");
+        let workspace = json!({ "schemaVersion": "1.0.0", "books": [] });
+        write_json_file(&path, &workspace, FileEncoding::default()).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let events = Mutex::new(Vec::new());
+        let emit = |event: &str, payload: Value| {
+            events.lock().unwrap().push((event.to_string(), payload));
+        };
+
+        let mut snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        snapshot.workspace.data["workspace"] = json!({ "name": "Renamed" });
+        let result = save_reporting(snapshot, None, None, emit).unwrap();
+        let invalid = SaveOptions {
+            compression_level: Some(10),
+            ..Default::default()
+        };
+        let snapshot = load_workspace_snapshot(path.clone(), None).unwrap();
+        assert!(save_reporting(snapshot, None, Some(invalid), emit).is_err());
+
+        let events = events.into_inner().unwrap();
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                SAVE_STARTED_EVENT,
+                SAVE_FINISHED_EVENT,
+                SAVE_STARTED_EVENT,
+                SAVE_FAILED_EVENT
+            ]
+        );
+        assert!(events
+            .iter()
+            .all(|(_, payload)| payload["workspacePath"] == path));
+        let finished = &events[1].1;
+        assert_eq!(finished["paths"], json!(result.written));
+        assert_eq!(finished["totalBytes"], result.files[0].bytes_written);
+        assert_eq!(events[3].1["error"]["code"], "invalidOption");
+    }
+}
@@ -0,0 +1,87 @@
+//! In-process exclusion for saves: two saves of one workspace never run at
+//! the same time, saves of different workspaces still do. The first save
+//! holds the workspace until it returns; the second waits for it up to a
+//! timeout and then gives up with `saveTimeout`.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::paths::{canonicalize_lenient, normalize_lexically};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, LazyLock, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How long a save waits for another save of the same workspace when
+/// `lockTimeoutMs` is not given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Workspaces with a save in progress, keyed by resolved workspace path.
+static SAVING: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(Default::default);
+/// Signalled whenever a save gives its workspace back.
+static RELEASED: Condvar = Condvar::new();
+
+fn saving() -> MutexGuard<'static, HashSet<PathBuf>> {
+    SAVING.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Entitles its holder to save one workspace; gives it back when dropped.
+pub(super) struct SaveGuard {
+    key: PathBuf,
+}
+
+impl Drop for SaveGuard {
+    fn drop(&mut self) {
+        saving().remove(&self.key);
+        RELEASED.notify_all();
+    }
+}
+
+/// Waits until no other save of `workspace_path` is running, for at most
+/// `timeout`. Paths naming the same file through links or `..` count as
+/// the same workspace.
+pub(super) fn acquire(workspace_path: &Path, timeout: Duration) -> WorkspaceResult<SaveGuard> {
+    let key = canonicalize_lenient(workspace_path)
+        .or_else(|| normalize_lexically(workspace_path))
+        .unwrap_or_else(|| workspace_path.to_path_buf());
+    let started = Instant::now();
+    let mut saving = saving();
+    while saving.contains(&key) {
+        let waited = started.elapsed();
+        if waited >= timeout {
+            return Err(WorkspaceError::SaveTimeout {
+                path: workspace_path.display().to_string(),
+                waited_ms: waited.as_millis() as u64,
+            });
+        }
+        saving = RELEASED
+            .wait_timeout(saving, timeout - waited)
+            .unwrap_or_else(|err| err.into_inner())
+            .0;
+    }
+    saving.insert(key.clone());
+    Ok(SaveGuard { key })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_same_workspace_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a/workspace.json");
+        let second = dir.path().join("b/workspace.json");
+
+        let held = acquire(&first, DEFAULT_TIMEOUT).unwrap();
+        let alias = dir.path().join("a/../a/workspace.json");
+        assert!(matches!(
+            acquire(&alias, Duration::from_millis(20)),
+            Err(WorkspaceError::SaveTimeout { waited_ms, .. }) if waited_ms >= 20
+        ));
+        assert!(acquire(&second, Duration::ZERO).is_ok());
+
+        let waiter = std::thread::spawn(move || acquire(&first, DEFAULT_TIMEOUT).is_ok());
+        std::thread::sleep(Duration::from_millis(20));
+        drop(held);
+        assert!(waiter.join().unwrap());
+    }
+}
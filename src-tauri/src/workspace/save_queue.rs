@@ -0,0 +1,280 @@
+//! Background queue that runs snapshot saves one at a time on a worker
+//! thread, so an autosave and a manual save landing together never write
+//! the same files at once. A save waiting for its turn is replaced by a
+//! newer save of the same workspace. Outcomes are reported through
+//! `workspace-save-completed`.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::{save_snapshot, SaveOptions, SaveResult, WorkspaceSnapshotPayload};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard};
+use std::thread;
+use tauri::{AppHandle, Emitter};
+
+pub const SAVE_COMPLETED_EVENT: &str = "workspace-save-completed";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnqueuedSave {
+    pub save_id: String,
+    /// The save took the place of an earlier one for the same workspace
+    /// that had not started; both ids are reported when it completes.
+    pub coalesced: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveCompleted {
+    /// Every `enqueue_save` this save stood for, oldest first.
+    pub save_ids: Vec<String>,
+    pub workspace_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<SaveResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<WorkspaceError>,
+}
+
+type Notify = Arc<dyn Fn(&SaveCompleted) + Send + Sync>;
+
+struct Job {
+    save_ids: Vec<String>,
+    snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<Job>,
+    /// A save is being written or its outcome reported.
+    running: bool,
+    worker_started: bool,
+}
+
+static QUEUE: LazyLock<Mutex<Queue>> = LazyLock::new(Default::default);
+/// Signalled whenever a job is added or one finishes.
+static CHANGED: Condvar = Condvar::new();
+
+fn queue() -> MutexGuard<'static, Queue> {
+    QUEUE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn wait(guard: MutexGuard<'static, Queue>) -> MutexGuard<'static, Queue> {
+    CHANGED.wait(guard).unwrap_or_else(|err| err.into_inner())
+}
+
+/// Moves the disk state a save left behind into a later snapshot of the
+/// same workspace. Without this the later save would take the first one's
+/// writes for someone else's and fail with `conflict`.
+fn carry_over(snapshot: &mut WorkspaceSnapshotPayload, result: &SaveResult) {
+    for file in std::iter::once(&mut snapshot.workspace).chain(&mut snapshot.books) {
+        if let Some(hash) = result.hashes.get(&file.file_path) {
+            file.hash = Some(hash.clone());
+            file.modified = result.modified.get(&file.file_path).copied();
+            file.text_style = result.text_styles.get(&file.file_path).copied();
+        }
+    }
+}
+
+fn run_worker() {
+    loop {
+        let job = {
+            let mut queue = queue();
+            loop {
+                if let Some(job) = queue.pending.pop_front() {
+                    queue.running = true;
+                    break job;
+                }
+                queue = wait(queue);
+            }
+        };
+        let workspace_path = job.snapshot.workspace.file_path.clone();
+        let saved = panic::catch_unwind(AssertUnwindSafe(|| {
+            save_snapshot(job.snapshot, job.force, job.options)
+        }))
+        .unwrap_or_else(|_| {
+            Err(WorkspaceError::io(
+                "save",
+                Path::new(&workspace_path),
+                io::Error::other("the save panicked"),
+            ))
+        });
+        if let Ok(result) = &saved {
+            let mut queue = queue();
+            for pending in queue
+                .pending
+                .iter_mut()
+                .filter(|pending| pending.snapshot.workspace.file_path == workspace_path)
+            {
+                carry_over(&mut pending.snapshot, result);
+            }
+        }
+
+        let (result, error) = match saved {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        (job.notify)(&SaveCompleted {
+            save_ids: job.save_ids,
+            workspace_path,
+            result,
+            error,
+        });
+        queue().running = false;
+        CHANGED.notify_all();
+    }
+}
+
+fn enqueue(
+    snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+    notify: Notify,
+) -> WorkspaceResult<EnqueuedSave> {
+    let save_id = uuid::Uuid::new_v4().to_string();
+    let mut queue = queue();
+    if !queue.worker_started {
+        thread::Builder::new()
+            .name("save-queue".into())
+            .spawn(run_worker)
+            .map_err(|err| {
+                WorkspaceError::io(
+                    "start the save queue for",
+                    Path::new(&snapshot.workspace.file_path),
+                    err,
+                )
+            })?;
+        queue.worker_started = true;
+    }
+
+    let waiting = queue
+        .pending
+        .iter_mut()
+        .find(|job| job.snapshot.workspace.file_path == snapshot.workspace.file_path);
+    let coalesced = waiting.is_some();
+    match waiting {
+        Some(job) => {
+            job.save_ids.push(save_id.clone());
+            job.snapshot = snapshot;
+            job.force = force;
+            job.options = options;
+            job.notify = notify;
+        }
+        None => queue.pending.push_back(Job {
+            save_ids: vec![save_id.clone()],
+            snapshot,
+            force,
+            options,
+            notify,
+        }),
+    }
+    CHANGED.notify_all();
+    Ok(EnqueuedSave { save_id, coalesced })
+}
+
+/// Queues the same save as `save_workspace_snapshot` and returns at once.
+/// Its outcome arrives as `workspace-save-completed` carrying `saveId`.
+#[tauri::command]
+pub fn enqueue_save(
+    app: AppHandle,
+    snapshot: WorkspaceSnapshotPayload,
+    force: Option<bool>,
+    options: Option<SaveOptions>,
+) -> WorkspaceResult<EnqueuedSave> {
+    let notify: Notify = Arc::new(move |completed: &SaveCompleted| {
+        if let Ok(payload) = serde_json::to_value(completed) {
+            let _ = app.emit(SAVE_COMPLETED_EVENT, payload);
+        }
+    });
+    enqueue(snapshot, force, options, notify)
+}
+
+/// Blocks until every queued save has been written and reported. Called
+/// when the app exits so that no save is lost.
+#[tauri::command(async)]
+pub fn flush_save_queue() {
+    let mut queue = queue();
+    while queue.running || !queue.pending.is_empty() {
+        queue = wait(queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file, FileEncoding};
+    use crate::workspace::load_workspace_snapshot;
+    use serde_json::json;
+    use std::sync::mpsc;
+
+    #[test]
+    fn saves_run_in_order_and_waiting_ones_coalesce() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "workspace": {}, "books": [] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let load = || {
+            load_workspace_snapshot(workspace_path.to_string_lossy().into_owned(), None).unwrap()
+        };
+        let renamed = |name: &str| {
+            let mut snapshot = load();
+            snapshot.workspace.data["workspace"]["name"] = json!(name);
+            snapshot
+        };
+
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        let (release, hold) = mpsc::channel::<()>();
+        let hold = Mutex::new(hold);
+        let record = {
+            let completed = completed.clone();
+            move |event: &SaveCompleted| {
+                completed.lock().unwrap().push((
+                    event.save_ids.clone(),
+                    event.error.as_ref().map(ToString::to_string),
+                ));
+            }
+        };
+        let record = Arc::new(record);
+        // The first save holds the worker until released, so the next two
+        // are still waiting when they arrive.
+        let first_record = record.clone();
+        let blocking: Notify = Arc::new(move |event: &SaveCompleted| {
+            first_record(event);
+            let _ = hold.lock().unwrap().recv();
+        });
+
+        let snapshots = [renamed("first"), renamed("second"), renamed("third")];
+        let [first_snapshot, second_snapshot, third_snapshot] = snapshots;
+        let first = enqueue(first_snapshot, None, None, blocking).unwrap();
+        while !queue().running {
+            thread::yield_now();
+        }
+        let second = enqueue(second_snapshot, None, None, record.clone()).unwrap();
+        let third = enqueue(third_snapshot, None, None, record.clone()).unwrap();
+        assert!(!first.coalesced && !second.coalesced && third.coalesced);
+        release.send(()).unwrap();
+        flush_save_queue();
+
+        let completed = completed.lock().unwrap();
+        assert_eq!(
+            *completed,
+            [
+                (vec![first.save_id], None),
+                (vec![second.save_id, third.save_id], None)
+            ]
+        );
+        assert_eq!(
+            read_json_file(&workspace_path).unwrap()["workspace"]["name"],
+            "third"
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use super::cells::{column_index, row_index};
+use super::error::{SchemaIssue, WorkspaceError, WorkspaceResult};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    String,
+    Number,
+    Array,
+    Object,
+    Boolean,
+    /// A string or `null`.
+    OptionalString,
+}
+
+impl FieldKind {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            Self::String => value.is_string(),
+            Self::Number => value.is_number(),
+            Self::Array => value.is_array(),
+            Self::Object => value.is_object(),
+            Self::Boolean => value.is_boolean(),
+            Self::OptionalString => value.is_string() || value.is_null(),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Number => "a number",
+            Self::Array => "an array",
+            Self::Object => "an object",
+            Self::Boolean => "a boolean",
+            Self::OptionalString => "a string or null",
+        }
+    }
+}
+
+struct FieldRule {
+    name: &'static str,
+    kind: FieldKind,
+    required: bool,
+}
+
+const fn required(name: &'static str, kind: FieldKind) -> FieldRule {
+    FieldRule {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+const fn optional(name: &'static str, kind: FieldKind) -> FieldRule {
+    FieldRule {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+// Only the fields the backend relies on are checked. Unknown fields are
+// accepted so files written by newer versions still open (forward
+// compatibility); `src/schemas/*.schema.json` remain the full definition.
+const WORKSPACE_RULES: &[FieldRule] = &[
+    optional("schemaVersion", FieldKind::String),
+    optional("workspace", FieldKind::Object),
+    optional("folders", FieldKind::Array),
+    required("books", FieldKind::Array),
+    optional("readOnly", FieldKind::Boolean),
+    optional("allowedExternalRoots", FieldKind::Array),
+];
+
+const BOOK_REFERENCE_RULES: &[FieldRule] = &[
+    required("id", FieldKind::String),
+    required("name", FieldKind::String),
+    required("dataPath", FieldKind::String),
+    optional("order", FieldKind::Number),
+    optional("folderId", FieldKind::OptionalString),
+    optional("activeSheetId", FieldKind::String),
+];
+
+const BOOK_RULES: &[FieldRule] = &[
+    optional("schemaVersion", FieldKind::String),
+    required("book", FieldKind::Object),
+    required("sheets", FieldKind::Array),
+];
+
+const SHEET_RULES: &[FieldRule] = &[
+    required("id", FieldKind::String),
+    required("name", FieldKind::String),
+    required("gridSize", FieldKind::Object),
+    required("rows", FieldKind::Object),
+];
+
+/// Large books with many broken cells would otherwise produce an issue per
+/// cell; the first few are enough to locate the problem.
+const MAX_ISSUES: usize = 50;
+
+fn check_fields(value: &Value, prefix: &str, rules: &[FieldRule], issues: &mut Vec<SchemaIssue>) {
+    let Some(object) = value.as_object() else {
+        issues.push(SchemaIssue::new(prefix, "must be an object"));
+        return;
+    };
+    for rule in rules {
+        let field = if prefix.is_empty() {
+            rule.name.to_string()
+        } else {
+            format!("{}.{}", prefix, rule.name)
+        };
+        match object.get(rule.name) {
+            None if rule.required => issues.push(SchemaIssue::new(field, "is missing")),
+            None => {}
+            Some(value) if !rule.kind.matches(value) => issues.push(SchemaIssue::new(
+                field,
+                format!("must be {}", rule.kind.describe()),
+            )),
+            Some(_) => {}
+        }
+    }
+}
+
+/// Checks the structure of `workspace.json` and lists every problem found.
+pub fn workspace_issues(value: &Value) -> Vec<SchemaIssue> {
+    let mut issues = Vec::new();
+    check_fields(value, "", WORKSPACE_RULES, &mut issues);
+    if let Some(books) = value.get("books").and_then(Value::as_array) {
+        for (index, book) in books.iter().enumerate() {
+            check_fields(
+                book,
+                &format!("books[{}]", index),
+                BOOK_REFERENCE_RULES,
+                &mut issues,
+            );
+        }
+    }
+    issues
+}
+
+fn grid_dimension(grid_size: Option<&Value>, name: &str) -> Option<u64> {
+    grid_size?.get(name)?.as_u64()
+}
+
+fn check_rows(
+    rows: &Map<String, Value>,
+    prefix: &str,
+    limits: (Option<u64>, Option<u64>),
+    issues: &mut Vec<SchemaIssue>,
+) {
+    let (max_rows, max_cols) = limits;
+    for (row_key, row) in rows {
+        if issues.len() >= MAX_ISSUES {
+            return;
+        }
+        let row_field = format!("{}.rows[\"{}\"]", prefix, row_key);
+        match row_index(row_key) {
+            None => {
+                issues.push(SchemaIssue::new(&row_field, "is not a valid row number"));
+                continue;
+            }
+            Some(row) if max_rows.is_some_and(|max| u64::from(row) > max) => {
+                issues.push(SchemaIssue::new(&row_field, "exceeds gridSize.rows"));
+            }
+            Some(_) => {}
+        }
+        let Some(cells) = row.as_object() else {
+            issues.push(SchemaIssue::new(&row_field, "must be an object"));
+            continue;
+        };
+        for (column_key, cell) in cells {
+            let cell_field = format!("{}[\"{}\"]", row_field, column_key);
+            match column_index(column_key) {
+                None => issues.push(SchemaIssue::new(&cell_field, "is not a valid column label")),
+                Some(column) if max_cols.is_some_and(|max| u64::from(column) > max) => {
+                    issues.push(SchemaIssue::new(&cell_field, "exceeds gridSize.cols"))
+                }
+                Some(_) if !cell.is_object() => {
+                    issues.push(SchemaIssue::new(&cell_field, "must be an object"))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Checks a book file: sheet fields, `gridSize` and that every cell sits at
+/// a valid address inside the declared grid. Runs in one pass over the
+/// cells.
+pub fn book_issues(value: &Value) -> Vec<SchemaIssue> {
+    let mut issues = Vec::new();
+    check_fields(value, "", BOOK_RULES, &mut issues);
+    let Some(sheets) = value.get("sheets").and_then(Value::as_array) else {
+        return issues;
+    };
+    for (index, sheet) in sheets.iter().enumerate() {
+        let prefix = format!("sheets[{}]", index);
+        let before = issues.len();
+        check_fields(sheet, &prefix, SHEET_RULES, &mut issues);
+        if issues.len() > before {
+            continue;
+        }
+        let grid_size = sheet.get("gridSize");
+        let limits = (
+            grid_dimension(grid_size, "rows"),
+            grid_dimension(grid_size, "cols"),
+        );
+        for (name, dimension) in [("rows", limits.0), ("cols", limits.1)] {
+            if dimension.is_none() {
+                issues.push(SchemaIssue::new(
+                    format!("{}.gridSize.{}", prefix, name),
+                    "must be a non-negative integer",
+                ));
+            }
+        }
+        if let Some(rows) = sheet.get("rows").and_then(Value::as_object) {
+            check_rows(rows, &prefix, limits, &mut issues);
+        }
+        if issues.len() >= MAX_ISSUES {
+            break;
+        }
+    }
+    issues
+}
+
+pub fn validate_book(value: &Value) -> WorkspaceResult<()> {
+    let issues = book_issues(value);
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(WorkspaceError::schema("book file", issues))
+    }
+}
+
+pub fn validate_workspace(value: &Value) -> WorkspaceResult<()> {
+    let issues = workspace_issues(value);
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(WorkspaceError::schema("workspace.json", issues))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validate_workspace_lists_book_fields() {
+        let value = json!({
+            "books": [
+                { "id": "book-1", "name": "A", "dataPath": "books/a.json", "extra": true },
+                { "id": 2, "dataPath": "books/b.json", "folderId": null },
+                "oops"
+            ]
+        });
+
+        let fields: Vec<String> = workspace_issues(&value)
+            .into_iter()
+            .map(|issue| format!("{} {}", issue.field, issue.message))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "books[1].id must be a string",
+                "books[1].name is missing",
+                "books[2] must be an object",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_workspace_requires_books_array() {
+        let err = validate_workspace(&json!({ "books": {} })).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "workspace.json has an invalid structure: books must be an array"
+        );
+    }
+
+    fn sample_book() -> Value {
+        json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-001", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-001",
+                "name": "Sheet",
+                "gridSize": { "rows": 10, "cols": 3 },
+                "rows": {
+                    "1": { "A": { "value": "x", "type": "string" } },
+                    "2": { "C": { "value": 1, "type": "number" } }
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn validate_book_accepts_cells_inside_the_grid() {
+        assert!(validate_book(&sample_book()).is_ok());
+    }
+
+    #[test]
+    fn validate_book_reports_bad_addresses() {
+        let mut book = sample_book();
+        let rows = book["sheets"][0]["rows"].as_object_mut().unwrap();
+        rows.insert("11".into(), json!({ "A": { "value": 1 } }));
+        rows.insert("r0".into(), json!({}));
+        rows.insert("3".into(), json!({ "D": { "value": 1 }, "b": {}, "A": 5 }));
+
+        let fields: Vec<String> = book_issues(&book)
+            .into_iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        assert_eq!(
+            fields,
+            // Issues follow the order of keys in the file.
+            vec![
+                "sheets[0].rows[\"11\"] exceeds gridSize.rows",
+                "sheets[0].rows[\"r0\"] is not a valid row number",
+                "sheets[0].rows[\"3\"][\"D\"] exceeds gridSize.cols",
+                "sheets[0].rows[\"3\"][\"b\"] is not a valid column label",
+                "sheets[0].rows[\"3\"][\"A\"] must be an object",
+            ]
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use super::cells::{column_index, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::SizeLimits;
+use super::parallel::parallel_map;
+use super::paths::workspace_dir_of;
+use super::{book_file_path, load_book, load_workspace_file};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+pub(super) const DEFAULT_MAX_RESULTS: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    pub case_insensitive: bool,
+    /// Only match cells whose whole text is the query.
+    pub whole_cell: bool,
+    /// Treat the query as a regular expression (`regex` crate syntax).
+    pub regex: bool,
+    /// Stop after this many hits and set `truncated`; 1000 when omitted.
+    pub max_results: Option<usize>,
+    /// Decrypts encrypted books.
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub book_id: String,
+    pub sheet_id: String,
+    /// A1-style address such as `"B3"`.
+    pub cell: String,
+    /// The cell's value as text.
+    pub value: String,
+    /// `[start, end)` of each match in UTF-16 code units, so they can be
+    /// used with JavaScript string indices directly.
+    pub match_ranges: Vec<[usize; 2]>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchFailure {
+    pub index: usize,
+    pub book_id: Option<String>,
+    pub error: WorkspaceError,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    /// Hits in book, sheet, row, column order.
+    pub hits: Vec<SearchHit>,
+    /// Set when `maxResults` cut the list short.
+    pub truncated: bool,
+    /// Books that could not be searched because they failed to load.
+    pub failed: Vec<SearchFailure>,
+}
+
+/// Compiles `query` into the regex both search and replace run on. Plain
+/// queries are escaped so they match literally.
+pub(super) fn build_matcher(
+    query: &str,
+    regex: bool,
+    case_insensitive: bool,
+    whole_cell: bool,
+) -> WorkspaceResult<Regex> {
+    if query.is_empty() {
+        return Err(WorkspaceError::InvalidOption {
+            name: "query",
+            message: "must not be empty".into(),
+        });
+    }
+    let pattern = if regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    let pattern = if whole_cell {
+        format!("^(?:{})$", pattern)
+    } else {
+        pattern
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|err| WorkspaceError::InvalidOption {
+            name: "query",
+            message: err.to_string(),
+        })
+}
+
+pub(super) fn cell_text(cell: &Value) -> Option<String> {
+    match cell.get("value")? {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+fn utf16_offset(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].encode_utf16().count()
+}
+
+/// Keys of `map` sorted by `index`, unparsable keys last.
+pub(super) fn sorted_entries(
+    map: &Map<String, Value>,
+    index: fn(&str) -> Option<u32>,
+) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(key, _)| (index(key).unwrap_or(u32::MAX), key.as_str()));
+    entries
+}
+
+/// Hits in one book, at most `limit` of them.
+fn search_book(book_id: &str, book: &Value, matcher: &Regex, limit: usize) -> Vec<SearchHit> {
+    let mut hits = Vec::new();
+    for sheet in book["sheets"].as_array().into_iter().flatten() {
+        let sheet_id = sheet["id"].as_str().unwrap_or_default();
+        let Some(rows) = sheet["rows"].as_object() else {
+            continue;
+        };
+        for (row, cells) in sorted_entries(rows, row_index) {
+            let Some(cells) = cells.as_object() else {
+                continue;
+            };
+            for (column, cell) in sorted_entries(cells, column_index) {
+                let Some(value) = cell_text(cell) else {
+                    continue;
+                };
+                let match_ranges: Vec<[usize; 2]> = matcher
+                    .find_iter(&value)
+                    .filter(|found| !found.is_empty())
+                    .map(|found| {
+                        [
+                            utf16_offset(&value, found.start()),
+                            utf16_offset(&value, found.end()),
+                        ]
+                    })
+                    .collect();
+                if match_ranges.is_empty() {
+                    continue;
+                }
+                if hits.len() == limit {
+                    return hits;
+                }
+                hits.push(SearchHit {
+                    book_id: book_id.to_string(),
+                    sheet_id: sheet_id.to_string(),
+                    cell: format!("{}{}", column, row),
+                    value,
+                    match_ranges,
+                });
+            }
+        }
+    }
+    hits
+}
+
+/// Loads the book `book_ref` points at and returns its hits, at most
+/// `limit` of them. The book is dropped before returning.
+pub(super) fn search_book_ref(
+    workspace_dir: &Path,
+    index: usize,
+    book_ref: &Value,
+    matcher: &Regex,
+    limit: usize,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<Vec<SearchHit>> {
+    let path = book_file_path(workspace_dir, book_ref, index)?;
+    let book = load_book(&path, passphrase, SizeLimits::default().book)?.data;
+    let book_id = book_ref["id"].as_str().unwrap_or_default();
+    Ok(search_book(book_id, &book, matcher, limit))
+}
+
+/// Searches the cell values of every book for `query`. Books are loaded in
+/// parallel and each is dropped once searched, so only the hits are kept.
+#[tauri::command(async)]
+pub fn search_workspace(
+    workspace_path: String,
+    query: String,
+    options: Option<SearchOptions>,
+) -> WorkspaceResult<SearchResult> {
+    let options = options.unwrap_or_default();
+    let matcher = build_matcher(
+        &query,
+        options.regex,
+        options.case_insensitive,
+        options.whole_cell,
+    )?;
+    let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace = load_workspace_file(&workspace_path, SizeLimits::default().workspace)?.data;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let refs = workspace["books"].as_array().cloned().unwrap_or_default();
+    let passphrase = options.passphrase.as_deref();
+
+    // One more than the limit per book tells us whether anything was cut.
+    let per_book = parallel_map(&refs, |index, book_ref| {
+        let limit = max_results.saturating_add(1);
+        search_book_ref(&workspace_dir, index, book_ref, &matcher, limit, passphrase)
+    });
+
+    let mut result = SearchResult::default();
+    for (index, (book_ref, hits)) in refs.iter().zip(per_book).enumerate() {
+        match hits {
+            Ok(hits) => result.hits.extend(hits),
+            Err(error) => result.failed.push(SearchFailure {
+                index,
+                book_id: book_ref["id"].as_str().map(str::to_string),
+                error,
+            }),
+        }
+        if result.hits.len() > max_results {
+            result.hits.truncate(max_results);
+            result.truncated = true;
+            break;
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+
+    fn write_workspace(dir: &Path) -> String {
+        let refs = json!([
+            { "id": "book-1", "name": "One", "dataPath": "one.json" },
+            { "id": "book-2", "name": "Two", "dataPath": "two.json" },
+            { "id": "book-3", "name": "Missing", "dataPath": "missing.json" }
+        ]);
+        let workspace_path = dir.join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "books": refs }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let text = |value: &str| json!({ "value": value, "type": "string" });
+        for (id, file, rows) in [
+            (
+                "book-1",
+                "one.json",
+                json!({
+                    "10": { "A": text("Tokyo tower") },
+                    "2": { "B": text("東京とTokyo"), "A": { "value": 42, "type": "number" } }
+                }),
+            ),
+            ("book-2", "two.json", json!({ "1": { "C": text("tokyo") } })),
+        ] {
+            let book = json!({
+                "schemaVersion": "1.0.0",
+                "book": { "id": id, "name": id },
+                "sheets": [{
+                    "id": "sheet-1",
+                    "name": "Sheet",
+                    "gridSize": { "rows": 100, "cols": 26 },
+                    "rows": rows
+                }]
+            });
+            write_json_file(&dir.join(file), &book, FileEncoding::default()).unwrap();
+        }
+        workspace_path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn finds_cells_across_books() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_workspace(dir.path());
+        let search = |query: &str, options: SearchOptions| {
+            search_workspace(path.clone(), query.into(), Some(options)).unwrap()
+        };
+
+        let result = search("Tokyo", SearchOptions::default());
+        let cells: Vec<(&str, &str, &[[usize; 2]])> = result
+            .hits
+            .iter()
+            .map(|hit| {
+                (
+                    hit.book_id.as_str(),
+                    hit.cell.as_str(),
+                    hit.match_ranges.as_slice(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                ("book-1", "B2", &[[3, 8]][..]),
+                ("book-1", "A10", &[[0, 5]][..])
+            ]
+        );
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].book_id.as_deref(), Some("book-3"));
+
+        let ignoring_case = SearchOptions {
+            case_insensitive: true,
+            whole_cell: true,
+            ..Default::default()
+        };
+        let hits = search("tokyo", ignoring_case).hits;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].book_id, "book-2");
+
+        let regex = SearchOptions {
+            regex: true,
+            max_results: Some(1),
+            ..Default::default()
+        };
+        let result = search(r"\d+|tower", regex);
+        assert_eq!(result.hits[0].cell, "A2");
+        assert_eq!(result.hits[0].value, "42");
+        assert!(result.truncated);
+
+        let invalid = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            search_workspace(path.clone(), "(".into(), Some(invalid)),
+            Err(WorkspaceError::InvalidOption { name: "query", .. })
+        ));
+    }
+}
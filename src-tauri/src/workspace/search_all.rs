@@ -0,0 +1,283 @@
+//! `search_all_workspaces`: `search_workspace` over every workspace below a
+//! directory, found the way `scan_workspace_index` finds them. The books of
+//! all the workspaces are searched as one parallel batch, so a few large
+//! workspaces and many small ones keep the workers equally busy.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::index::workspace_files;
+use super::io::SizeLimits;
+use super::load_workspace_file;
+use super::parallel::parallel_map;
+use super::paths::workspace_dir_of;
+use super::progress::{LoadProgress, ProgressReporter};
+use super::search::{
+    build_matcher, search_book_ref, SearchHit, SearchOptions, DEFAULT_MAX_RESULTS,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+
+/// Emitted as books are searched, with the `LoadProgress` payload of loads:
+/// `loaded` counts the books searched out of `total` across all workspaces.
+pub const SEARCH_PROGRESS_EVENT: &str = "workspace-search-progress";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSearchHit {
+    pub workspace_path: String,
+    #[serde(flatten)]
+    pub hit: SearchHit,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSearchFailure {
+    pub workspace_path: String,
+    /// Index of the book in `workspace.json`; `None` when `workspace.json`
+    /// itself could not be loaded.
+    pub index: Option<usize>,
+    pub book_id: Option<String>,
+    pub error: WorkspaceError,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllWorkspacesSearchResult {
+    /// Hits by workspace path, then in the order of `search_workspace`.
+    pub hits: Vec<WorkspaceSearchHit>,
+    /// Set when `maxResults` cut the list short.
+    pub truncated: bool,
+    /// Workspaces and books that could not be searched.
+    pub failed: Vec<WorkspaceSearchFailure>,
+    /// Directories that could not be listed while looking for workspaces.
+    pub skipped: Vec<String>,
+    /// Workspaces found, including those in `failed`.
+    pub workspaces: usize,
+}
+
+struct Target<'a> {
+    workspace: usize,
+    workspace_dir: &'a Path,
+    index: usize,
+    book_ref: &'a Value,
+}
+
+/// Searches every workspace below `root_dir`; `search_all_workspaces` is
+/// this plus its progress events.
+pub(super) fn search_all(
+    root_dir: &Path,
+    query: &str,
+    options: SearchOptions,
+    on_start: impl FnOnce(usize),
+    on_book_done: impl Fn(&str) + Sync,
+) -> WorkspaceResult<AllWorkspacesSearchResult> {
+    let matcher = build_matcher(
+        query,
+        options.regex,
+        options.case_insensitive,
+        options.whole_cell,
+    )?;
+    let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+    fs::read_dir(root_dir).map_err(|err| WorkspaceError::io("read", root_dir, err))?;
+    let mut result = AllWorkspacesSearchResult::default();
+    let mut paths: Vec<PathBuf> = Vec::new();
+    workspace_files(root_dir, 0, &mut paths, &mut result.skipped);
+    paths.sort();
+    result.workspaces = paths.len();
+
+    let mut workspaces: Vec<(String, PathBuf, Vec<Value>)> = Vec::new();
+    for path in &paths {
+        let workspace_path = path.to_string_lossy().into_owned();
+        match load_workspace_file(path, SizeLimits::default().workspace) {
+            Ok(workspace) => {
+                let refs = workspace.data["books"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                workspaces.push((workspace_path, workspace_dir_of(path), refs));
+            }
+            Err(error) => result.failed.push(WorkspaceSearchFailure {
+                workspace_path,
+                index: None,
+                book_id: None,
+                error,
+            }),
+        }
+    }
+    let targets: Vec<Target> = workspaces
+        .iter()
+        .enumerate()
+        .flat_map(|(workspace, (_, workspace_dir, refs))| {
+            refs.iter()
+                .enumerate()
+                .map(move |(index, book_ref)| Target {
+                    workspace,
+                    workspace_dir,
+                    index,
+                    book_ref,
+                })
+        })
+        .collect();
+
+    on_start(targets.len());
+    let passphrase = options.passphrase.as_deref();
+    // One more than the limit per book tells us whether anything was cut.
+    let per_book = parallel_map(&targets, |_, target| {
+        let limit = max_results.saturating_add(1);
+        let hits = search_book_ref(
+            target.workspace_dir,
+            target.index,
+            target.book_ref,
+            &matcher,
+            limit,
+            passphrase,
+        );
+        on_book_done(&workspaces[target.workspace].0);
+        hits
+    });
+
+    for (target, hits) in targets.iter().zip(per_book) {
+        let workspace_path = &workspaces[target.workspace].0;
+        match hits {
+            Ok(hits) => result
+                .hits
+                .extend(hits.into_iter().map(|hit| WorkspaceSearchHit {
+                    workspace_path: workspace_path.clone(),
+                    hit,
+                })),
+            Err(error) => result.failed.push(WorkspaceSearchFailure {
+                workspace_path: workspace_path.clone(),
+                index: Some(target.index),
+                book_id: target.book_ref["id"].as_str().map(str::to_string),
+                error,
+            }),
+        }
+        if result.hits.len() > max_results {
+            result.hits.truncate(max_results);
+            result.truncated = true;
+            break;
+        }
+    }
+    Ok(result)
+}
+
+/// Searches the cell values of every book of every workspace below
+/// `root_dir` for `query`, with the options of `search_workspace`, emitting
+/// `workspace-search-progress` as books are done. Workspaces and books that
+/// cannot be read end up in `failed` without stopping the search.
+#[tauri::command(async)]
+pub fn search_all_workspaces(
+    app: AppHandle,
+    root_dir: String,
+    query: String,
+    options: Option<SearchOptions>,
+) -> WorkspaceResult<AllWorkspacesSearchResult> {
+    let reporter = ProgressReporter::new(|progress: &LoadProgress| {
+        let _ = app.emit(SEARCH_PROGRESS_EVENT, progress.clone());
+    });
+    let result = search_all(
+        Path::new(&root_dir),
+        &query,
+        options.unwrap_or_default(),
+        |total| reporter.start(total),
+        |path| reporter.book_done(path),
+    );
+    reporter.finish();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn write_workspace(dir: &Path, cells: &[&str]) {
+        let mut refs = Vec::new();
+        for (index, text) in cells.iter().enumerate() {
+            let data_path = format!("books/{}.json", index);
+            refs.push(
+                json!({ "id": format!("book-{}", index), "name": "Book", "dataPath": data_path }),
+            );
+            let book = json!({
+                "schemaVersion": "1.0.0",
+                "book": { "id": index.to_string(), "name": "Book" },
+                "sheets": [{
+                    "id": "sheet-1",
+                    "name": "Sheet",
+                    "gridSize": { "rows": 10, "cols": 10 },
+                    "rows": { "1": { "A": { "value": text, "type": "string" } } }
+                }]
+            });
+            write_json_file(&dir.join(&data_path), &book, FileEncoding::default()).unwrap();
+        }
+        refs.push(json!({ "id": "gone", "name": "Gone", "dataPath": "books/gone.json" }));
+        write_json_file(
+            &dir.join("workspace.json"),
+            &json!({ "schemaVersion": "1.0.0", "books": refs }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn searches_every_workspace_and_keeps_going_past_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        write_workspace(&dir.path().join("a"), &["apple pie", "banana"]);
+        write_workspace(&dir.path().join("nested/b"), &["pineapple"]);
+        fs::create_dir_all(dir.path().join("c")).unwrap();
+        fs::write(dir.path().join("c/workspace.json"), "{ broken").unwrap();
+
+        let started = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let result = search_all(
+            dir.path(),
+            "apple",
+            SearchOptions::default(),
+            |total| started.store(total, Ordering::Relaxed),
+            |_| {
+                done.fetch_add(1, Ordering::Relaxed);
+            },
+        )
+        .unwrap();
+        assert_eq!(result.workspaces, 3);
+        assert_eq!((started.into_inner(), done.into_inner()), (5, 5));
+        let hits: Vec<(&str, &str)> = result
+            .hits
+            .iter()
+            .map(|hit| (hit.hit.book_id.as_str(), hit.hit.value.as_str()))
+            .collect();
+        assert_eq!(hits, [("book-0", "apple pie"), ("book-0", "pineapple")]);
+        assert!(result.hits[1].workspace_path.ends_with("workspace.json"));
+        assert!(!result.truncated);
+        // The broken workspace and the missing book of each readable one.
+        let failed: Vec<(Option<usize>, Option<&str>)> = result
+            .failed
+            .iter()
+            .map(|failure| (failure.index, failure.book_id.as_deref()))
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                (None, None),
+                (Some(2), Some("gone")),
+                (Some(1), Some("gone"))
+            ]
+        );
+        assert!(matches!(
+            result.failed[0].error,
+            WorkspaceError::ParseError { .. }
+        ));
+
+        let limited = SearchOptions {
+            max_results: Some(1),
+            ..Default::default()
+        };
+        let result = search_all(dir.path(), "apple", limited, |_| {}, |_| {}).unwrap();
+        assert_eq!(result.hits.len(), 1);
+        assert!(result.truncated);
+    }
+}
@@ -0,0 +1,246 @@
+//! Sorting rows on save: a book whose `workspace.json` entry has
+//! `sortBy: { column, order, empty, sheetId, headerRows }` gets the rows of
+//! that sheet reordered by that column before it is written, so that it
+//! stays sorted however it was edited. Each row moves as a whole. Like the
+//! structural edits, formulas are not rewritten for the rows they refer to
+//! having moved.
+
+use super::allowed_book_file_path;
+use super::cells::{column_index, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::paths::{normalize_lexically, BookRoots};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Where rows with nothing in the sort column go, whatever the order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EmptyPlacement {
+    First,
+    #[default]
+    Last,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortBy {
+    /// Column letters, e.g. `"B"`.
+    pub column: String,
+    #[serde(default)]
+    pub order: SortOrder,
+    #[serde(default)]
+    pub empty: EmptyPlacement,
+    /// The sheet to sort; the first sheet of the book when omitted.
+    #[serde(default)]
+    pub sheet_id: Option<String>,
+    /// Rows at the top that stay where they are, e.g. 1 for a header row.
+    #[serde(default)]
+    pub header_rows: u32,
+}
+
+/// The `sortBy` of every book entry that has one, keyed like
+/// `encrypted_book_paths`. A `sortBy` that cannot be used fails the save
+/// rather than leaving the book unsorted unnoticed.
+pub(super) fn sort_specs(
+    roots: &BookRoots,
+    workspace_data: &Value,
+) -> WorkspaceResult<HashMap<PathBuf, SortBy>> {
+    let mut specs = HashMap::new();
+    let refs = workspace_data["books"].as_array().into_iter().flatten();
+    for (index, book_ref) in refs.enumerate() {
+        let Some(sort_by) = book_ref.get("sortBy").filter(|sort_by| !sort_by.is_null()) else {
+            continue;
+        };
+        let invalid = |message: String| {
+            WorkspaceError::invalid_schema(format!("books[{}].sortBy {}", index, message))
+        };
+        let spec = SortBy::deserialize(sort_by).map_err(|err| invalid(err.to_string()))?;
+        if column_index(&spec.column).is_none() {
+            return Err(invalid(format!("has an invalid column {:?}", spec.column)));
+        }
+        let Ok(path) = allowed_book_file_path(roots, book_ref, index) else {
+            continue;
+        };
+        if let Some(path) = normalize_lexically(&path) {
+            specs.insert(path, spec);
+        }
+    }
+    Ok(specs)
+}
+
+/// Sort key of a cell: numbers before strings before booleans, as
+/// spreadsheets order mixed columns. Strings compare by code point, which
+/// is the same on every machine whatever its locale.
+enum Key<'a> {
+    Number(f64),
+    Text(&'a str),
+    Boolean(bool),
+}
+
+impl<'a> Key<'a> {
+    fn of(cell: Option<&'a Value>) -> Option<Self> {
+        match cell?.get("value")? {
+            Value::Number(number) => number.as_f64().map(Self::Number),
+            Value::String(text) if text.is_empty() => None,
+            Value::String(text) => Some(Self::Text(text)),
+            Value::Bool(flag) => Some(Self::Boolean(*flag)),
+            _ => None,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Number(_) => 0,
+            Self::Text(_) => 1,
+            Self::Boolean(_) => 2,
+        }
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.total_cmp(b),
+            (Self::Text(a), Self::Text(b)) => a.cmp(b),
+            (Self::Boolean(a), Self::Boolean(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+/// Sorts the rows of the sheet `spec` names in `book` and returns how many
+/// rows moved. Rows below the header are packed from the first row after
+/// it in sorted order, so rows with no cells at all end up after the rest;
+/// rows with equal keys keep their order. A book without that sheet is left
+/// as it is.
+pub(super) fn sort_book(book: &mut Value, spec: &SortBy) -> usize {
+    let sheets = book["sheets"].as_array_mut().into_iter().flatten();
+    let mut sheets = sheets.filter(|sheet| {
+        spec.sheet_id
+            .as_ref()
+            .is_none_or(|id| sheet["id"] == id.as_str())
+    });
+    let Some(rows) = sheets
+        .next()
+        .and_then(|sheet| sheet["rows"].as_object_mut())
+    else {
+        return 0;
+    };
+    let mut header = Map::new();
+    let mut body: Vec<(u32, Value)> = Vec::new();
+    for (key, cells) in std::mem::take(rows) {
+        match row_index(&key) {
+            Some(row) if row > spec.header_rows => body.push((row, cells)),
+            _ => {
+                header.insert(key, cells);
+            }
+        }
+    }
+    body.sort_by_key(|(row, _)| *row);
+    body.sort_by(|(_, a), (_, b)| {
+        let (a, b) = (Key::of(a.get(&spec.column)), Key::of(b.get(&spec.column)));
+        match (a, b) {
+            (Some(a), Some(b)) if spec.order == SortOrder::Desc => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) if spec.empty == EmptyPlacement::First => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) if spec.empty == EmptyPlacement::First => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+        }
+    });
+    let mut moved = 0;
+    *rows = header;
+    for (offset, (row, cells)) in body.into_iter().enumerate() {
+        let new_row = spec.header_rows + 1 + offset as u32;
+        if new_row != row {
+            moved += 1;
+        }
+        rows.insert(new_row.to_string(), cells);
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn moves_whole_rows_by_the_sort_column() {
+        let row = |key: Value, note: &str| json!({ "B": { "value": key }, "C": { "value": note } });
+        let mut book = json!({
+            "sheets": [{
+                "id": "sheet-1",
+                "rows": {
+                    "1": row(json!("amount"), "header"),
+                    "2": row(json!(10), "ten"),
+                    "3": row(json!("x"), "text"),
+                    "5": row(json!(9.5), "nine and a half"),
+                    "6": { "C": { "value": "no amount" } },
+                    "7": row(json!(100), "hundred"),
+                    "8": row(json!(true), "flag")
+                }
+            }]
+        });
+        let spec: SortBy =
+            serde_json::from_value(json!({ "column": "B", "headerRows": 1 })).unwrap();
+        let notes = |book: &Value| -> Vec<String> {
+            let rows = book["sheets"][0]["rows"].as_object().unwrap();
+            (1..=rows.len())
+                .map(|row| {
+                    rows[&row.to_string()]["C"]["value"]
+                        .as_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(sort_book(&mut book, &spec), 6);
+        assert_eq!(
+            notes(&book),
+            [
+                "header",
+                "nine and a half",
+                "ten",
+                "hundred",
+                "text",
+                "flag",
+                "no amount"
+            ]
+        );
+        assert_eq!(sort_book(&mut book, &spec), 0);
+
+        let spec: SortBy = serde_json::from_value(json!({
+            "column": "B",
+            "order": "desc",
+            "empty": "first",
+            "headerRows": 1
+        }))
+        .unwrap();
+        sort_book(&mut book, &spec);
+        assert_eq!(
+            notes(&book),
+            [
+                "header",
+                "no amount",
+                "flag",
+                "text",
+                "hundred",
+                "ten",
+                "nine and a half"
+            ]
+        );
+        assert_eq!(book["sheets"][0]["rows"]["3"]["B"]["value"], true);
+    }
+}
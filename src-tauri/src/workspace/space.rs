@@ -0,0 +1,105 @@
+//! Refuses saves that would obviously run out of disk space part way,
+//! leaving some books written and others not.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{serialized_size, TextStyle};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Room kept free beyond the estimate for file system overhead.
+const SLACK_BYTES: u64 = 64 * 1024;
+
+/// A file a save is about to write.
+pub(super) struct PlannedWrite<'a> {
+    pub path: &'a Path,
+    pub data: &'a Value,
+    pub style: TextStyle,
+    /// The current file is copied to a backup first.
+    pub backup: bool,
+}
+
+/// Upper bound on the space the writes need while they run. Every new file
+/// is counted in full as if nothing were freed in between (each is written
+/// next to the old one before replacing it), compression is ignored, and
+/// backups add the size of the file they copy. A tenth more on top.
+pub(super) fn estimate_required(writes: &[PlannedWrite]) -> u64 {
+    let bytes: u64 = writes
+        .iter()
+        .map(|write| {
+            let backup = if write.backup {
+                fs::metadata(write.path).map_or(0, |stat| stat.len())
+            } else {
+                0
+            };
+            serialized_size(write.data, write.style) + backup
+        })
+        .sum();
+    bytes + bytes / 10 + SLACK_BYTES
+}
+
+/// Fails with `insufficientDiskSpace` when the volume holding `dir` has less
+/// free space than the writes need. Volumes that cannot report their free
+/// space are not checked.
+pub(super) fn ensure_disk_space(dir: &Path, writes: &[PlannedWrite]) -> WorkspaceResult<()> {
+    check(dir, writes, |dir| fs2::available_space(dir))
+}
+
+fn check(
+    dir: &Path,
+    writes: &[PlannedWrite],
+    available_space: impl FnOnce(&Path) -> io::Result<u64>,
+) -> WorkspaceResult<()> {
+    if writes.is_empty() {
+        return Ok(());
+    }
+    let Ok(available) = available_space(dir) else {
+        return Ok(());
+    };
+    let required = estimate_required(writes);
+    if required > available {
+        return Err(WorkspaceError::InsufficientDiskSpace {
+            path: dir.display().to_string(),
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+
+    #[test]
+    fn estimate_covers_the_written_files_and_full_disks_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let data =
+            json!({ "sheets": [{ "rows": { "1": { "A": { "value": "x".repeat(5000) } } } }] });
+        write_json_file(&path, &data, FileEncoding::default()).unwrap();
+        let writes = [PlannedWrite {
+            path: &path,
+            data: &data,
+            style: TextStyle::default(),
+            backup: true,
+        }];
+
+        let written = fs::metadata(&path).unwrap().len();
+        assert_eq!(serialized_size(&data, TextStyle::default()), written);
+        let required = estimate_required(&writes);
+        assert!(required > 2 * written);
+
+        assert!(check(dir.path(), &writes, |_| Ok(required)).is_ok());
+        assert!(matches!(
+            check(dir.path(), &writes, |_| Ok(written)),
+            Err(WorkspaceError::InsufficientDiskSpace { available, .. }) if available == written
+        ));
+        let unsupported = || Err(io::Error::from(io::ErrorKind::Unsupported));
+        assert!(check(dir.path(), &writes, |_| unsupported()).is_ok());
+        assert!(check(dir.path(), &[], |_| Ok(0)).is_ok());
+    }
+}
@@ -0,0 +1,462 @@
+//! Exports a whole workspace into an SQLite database for analysis tools.
+//!
+//! Each book becomes one table holding the rows of all its sheets, tagged
+//! with `_sheet` and `_row`, and one column per column letter. A book whose
+//! sheets or rows differ in width gets as many columns as its widest row;
+//! cells that are not there are `NULL`. `_sheet_up_books` maps every table
+//! back to its book.
+
+use super::cells::{column_index, column_label, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{replace_file, temp_path_for, SizeLimits};
+use super::paths::workspace_dir_of;
+use super::{book_file_path, load_book, load_workspace_file};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Table listing which book each table came from.
+pub const BOOKS_TABLE: &str = "_sheet_up_books";
+
+/// SQLite's default `SQLITE_MAX_COLUMN`.
+const MAX_COLUMNS: usize = 2000;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqliteTable {
+    pub table_name: String,
+    pub book_id: String,
+    pub book_name: String,
+    /// Data rows inserted; rows without any cell are left out.
+    pub rows: usize,
+    /// Cell columns, not counting `_sheet` and `_row`.
+    pub columns: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqliteExportResult {
+    pub db_path: String,
+    pub tables: Vec<SqliteTable>,
+}
+
+/// Type declared for a column, narrowed down from the values in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Empty,
+    Integer,
+    Real,
+    Text,
+    /// Values of several kinds; the column is declared without a type so
+    /// SQLite keeps each value as it was inserted.
+    Mixed,
+}
+
+impl ColumnType {
+    fn of(value: &SqlValue) -> Self {
+        match value {
+            SqlValue::Null => Self::Empty,
+            SqlValue::Integer(_) => Self::Integer,
+            SqlValue::Real(_) => Self::Real,
+            SqlValue::Text(_) | SqlValue::Blob(_) => Self::Text,
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Empty, other) | (other, Self::Empty) => other,
+            (left, right) if left == right => left,
+            (Self::Integer, Self::Real) | (Self::Real, Self::Integer) => Self::Real,
+            _ => Self::Mixed,
+        }
+    }
+
+    fn declared(self) -> &'static str {
+        match self {
+            Self::Integer => " INTEGER",
+            Self::Real => " REAL",
+            Self::Text => " TEXT",
+            Self::Empty | Self::Mixed => "",
+        }
+    }
+}
+
+/// Booleans become 0 and 1, as SQLite has no boolean type. Empty strings
+/// are `NULL`, as the export to CSV treats them.
+fn sql_value(cell: &Value) -> SqlValue {
+    match cell.get("value") {
+        Some(Value::Bool(flag)) => SqlValue::Integer(i64::from(*flag)),
+        Some(Value::Number(number)) => match number.as_i64() {
+            Some(integer) => SqlValue::Integer(integer),
+            None => SqlValue::Real(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        Some(Value::String(text)) if !text.is_empty() => SqlValue::Text(text.clone()),
+        _ => SqlValue::Null,
+    }
+}
+
+/// Non-empty rows of every sheet: sheet name, 1-based row number and
+/// cells, in sheet order and then by row number.
+fn book_rows(book: &Value) -> Vec<(&str, u32, &Map<String, Value>)> {
+    let mut rows = Vec::new();
+    for sheet in book["sheets"].as_array().into_iter().flatten() {
+        let name = sheet["name"].as_str().unwrap_or_default();
+        let mut sheet_rows: Vec<_> = sheet["rows"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, cells)| Some((name, row_index(key)?, cells.as_object()?)))
+            .filter(|(_, _, cells)| !cells.is_empty())
+            .collect();
+        sheet_rows.sort_by_key(|&(_, row, _)| row);
+        rows.extend(sheet_rows);
+    }
+    rows
+}
+
+/// Values of one row laid out over `columns` columns.
+fn row_values(cells: &Map<String, Value>, columns: usize) -> Vec<SqlValue> {
+    let mut values = vec![SqlValue::Null; columns];
+    for (label, cell) in cells {
+        if let Some(column) = column_index(label).filter(|&column| column as usize <= columns) {
+            values[column as usize - 1] = sql_value(cell);
+        }
+    }
+    values
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Table name for a book: letters, digits and `_` are kept (including
+/// non-ASCII letters), anything else becomes `_`. Names that would start
+/// with a digit or fall in SQLite's reserved `sqlite_` namespace get a `_`
+/// in front. Taken names, compared case-insensitively like SQLite does, get
+/// `_2`, `_3`, … appended.
+fn table_name(book_name: &str, taken: &mut HashSet<String>) -> String {
+    let mut base: String = book_name
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect();
+    if base.is_empty() {
+        base = "book".into();
+    }
+    if base.starts_with(|ch: char| ch.is_ascii_digit())
+        || base.to_lowercase().starts_with("sqlite_")
+    {
+        base.insert(0, '_');
+    }
+    let mut name = base.clone();
+    let mut suffix = 1;
+    while !taken.insert(name.to_lowercase()) {
+        suffix += 1;
+        name = format!("{}_{}", base, suffix);
+    }
+    name
+}
+
+/// Writes every book of the workspace into a new SQLite database at
+/// `db_path`, replacing any file there once the export has succeeded. All
+/// rows go in through one transaction and a prepared statement per book.
+/// Books sharing a `dataPath` are exported once. Encrypted books need
+/// `passphrase`.
+#[tauri::command(async)]
+pub fn export_workspace_to_sqlite(
+    workspace_path: String,
+    db_path: String,
+    passphrase: Option<String>,
+) -> WorkspaceResult<SqliteExportResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let db_path = PathBuf::from(db_path);
+    let workspace = load_workspace_file(&workspace_path, SizeLimits::default().workspace)?.data;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+
+    if let Some(parent) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|err| WorkspaceError::io("create directory", parent, err))?;
+    }
+    let temp_path = temp_path_for(&db_path);
+    let written = write_database(
+        &temp_path,
+        &db_path,
+        &workspace,
+        &workspace_dir,
+        passphrase.as_deref(),
+    );
+    let tables = match written {
+        Ok(tables) => tables,
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+    };
+    if let Err(err) = replace_file(&temp_path, &db_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(WorkspaceError::io("write", &db_path, err));
+    }
+    Ok(SqliteExportResult {
+        db_path: db_path.to_string_lossy().into_owned(),
+        tables,
+    })
+}
+
+fn write_database(
+    temp_path: &Path,
+    db_path: &Path,
+    workspace: &Value,
+    workspace_dir: &Path,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<Vec<SqliteTable>> {
+    let db_error = |err: rusqlite::Error| WorkspaceError::Database {
+        path: db_path.display().to_string(),
+        message: err.to_string(),
+    };
+    let mut connection = Connection::open(temp_path).map_err(db_error)?;
+    let transaction = connection.transaction().map_err(db_error)?;
+    transaction
+        .execute_batch(&format!(
+            "CREATE TABLE {} (table_name TEXT PRIMARY KEY, book_id TEXT, book_name TEXT, \
+             data_path TEXT, columns INTEGER)",
+            quote_identifier(BOOKS_TABLE)
+        ))
+        .map_err(db_error)?;
+
+    let mut taken = HashSet::from([BOOKS_TABLE.to_lowercase()]);
+    let mut exported_paths = HashSet::new();
+    let mut tables = Vec::new();
+    for (index, book_ref) in workspace["books"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let data_path = book_ref["dataPath"].as_str().unwrap_or_default();
+        if !exported_paths.insert(data_path) {
+            continue;
+        }
+        let book = book_file_path(workspace_dir, book_ref, index)
+            .and_then(|path| load_book(&path, passphrase, SizeLimits::default().book))
+            .map_err(|err| WorkspaceError::BookLoad {
+                index,
+                source: Box::new(err),
+            })?;
+        let book_name = book_ref["name"].as_str().unwrap_or_default();
+        let rows = book_rows(&book.data);
+
+        // Width and column types come first so the table can be created
+        // before any row is inserted.
+        let columns = rows
+            .iter()
+            .flat_map(|(_, _, cells)| cells.keys())
+            .filter_map(|label| column_index(label))
+            .max()
+            .unwrap_or(0) as usize;
+        if columns + 2 > MAX_COLUMNS {
+            return Err(WorkspaceError::Database {
+                path: db_path.display().to_string(),
+                message: format!(
+                    "books[{}] has {} columns, more than SQLite allows in a table",
+                    index, columns
+                ),
+            });
+        }
+        let mut types = vec![ColumnType::Empty; columns];
+        for (_, _, cells) in &rows {
+            for (column, value) in row_values(cells, columns).iter().enumerate() {
+                types[column] = types[column].merge(ColumnType::of(value));
+            }
+        }
+
+        let name = table_name(book_name, &mut taken);
+        let definitions: Vec<String> = (1..=columns as u32)
+            .zip(&types)
+            .map(|(column, kind)| {
+                format!(
+                    "{}{}",
+                    quote_identifier(&column_label(column)),
+                    kind.declared()
+                )
+            })
+            .collect();
+        transaction
+            .execute_batch(&format!(
+                "CREATE TABLE {} (\"_sheet\" TEXT NOT NULL, \"_row\" INTEGER NOT NULL{}{})",
+                quote_identifier(&name),
+                if columns > 0 { ", " } else { "" },
+                definitions.join(", ")
+            ))
+            .map_err(db_error)?;
+
+        let placeholders = vec!["?"; columns + 2].join(", ");
+        let mut insert = transaction
+            .prepare(&format!(
+                "INSERT INTO {} VALUES ({})",
+                quote_identifier(&name),
+                placeholders
+            ))
+            .map_err(db_error)?;
+        for (sheet, row, cells) in &rows {
+            let values = [
+                SqlValue::Text(sheet.to_string()),
+                SqlValue::Integer(i64::from(*row)),
+            ]
+            .into_iter()
+            .chain(row_values(cells, columns));
+            insert.execute(params_from_iter(values)).map_err(db_error)?;
+        }
+        drop(insert);
+
+        transaction
+            .execute(
+                &format!(
+                    "INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5)",
+                    quote_identifier(BOOKS_TABLE)
+                ),
+                params![
+                    name,
+                    book_ref["id"].as_str().unwrap_or_default(),
+                    book_name,
+                    data_path,
+                    columns as i64
+                ],
+            )
+            .map_err(db_error)?;
+        tables.push(SqliteTable {
+            table_name: name,
+            book_id: book_ref["id"].as_str().unwrap_or_default().to_string(),
+            book_name: book_name.to_string(),
+            rows: rows.len(),
+            columns,
+        });
+    }
+    transaction.commit().map_err(db_error)?;
+    connection.close().map_err(|(_, err)| db_error(err))?;
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+
+    fn book(id: &str, rows: Value) -> Value {
+        json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": id, "name": id },
+            "sheets": [
+                { "id": "s1", "name": "Sheet1", "gridSize": { "rows": 10, "cols": 10 }, "rows": rows },
+                {
+                    "id": "s2",
+                    "name": "Sheet2",
+                    "gridSize": { "rows": 10, "cols": 10 },
+                    "rows": { "1": { "D": { "value": "wide" } } }
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn table_names_are_sanitized_and_kept_apart() {
+        let mut taken = HashSet::from([BOOKS_TABLE.to_string()]);
+        assert_eq!(table_name("売上 2024/Q1", &mut taken), "売上_2024_Q1");
+        assert_eq!(table_name("売上-2024-Q1", &mut taken), "売上_2024_Q1_2");
+        assert_eq!(table_name("2024", &mut taken), "_2024");
+        assert_eq!(table_name("SQLite_stats", &mut taken), "_SQLite_stats");
+        assert_eq!(table_name("", &mut taken), "book");
+        assert_eq!(table_name("Book", &mut taken), "Book_2");
+        assert_eq!(table_name(BOOKS_TABLE, &mut taken), "_sheet_up_books_2");
+    }
+
+    #[test]
+    fn exports_books_as_typed_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let rows = json!({
+            "2": { "A": { "value": "pen" }, "B": { "value": 1.5 }, "C": { "value": true } },
+            "1": { "A": { "value": "item" }, "B": { "value": 3 }, "C": { "value": false } },
+            "3": {}
+        });
+        write_json_file(
+            &dir.path().join("books/a.json"),
+            &book("a", rows),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        write_json_file(
+            &dir.path().join("books/b.json"),
+            &book("b", json!({})),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "books": [
+                { "id": "a", "name": "Sales/2024", "dataPath": "books/a.json" },
+                { "id": "b", "name": "Sales 2024", "dataPath": "books/b.json" },
+                { "id": "c", "name": "Copy", "dataPath": "books/a.json" }
+            ] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let db_path = dir.path().join("out/export.sqlite");
+        fs::create_dir_all(db_path.parent().unwrap()).unwrap();
+        fs::write(&db_path, "old").unwrap();
+
+        let result = export_workspace_to_sqlite(
+            workspace_path.to_string_lossy().into_owned(),
+            db_path.to_string_lossy().into_owned(),
+            None,
+        )
+        .unwrap();
+        let names: Vec<_> = result
+            .tables
+            .iter()
+            .map(|t| t.table_name.as_str())
+            .collect();
+        assert_eq!(names, ["Sales_2024", "Sales_2024_2"]);
+        assert_eq!((result.tables[0].rows, result.tables[0].columns), (3, 4));
+
+        let connection = Connection::open(&db_path).unwrap();
+        let mut query = connection
+            .prepare(
+                "SELECT _sheet || ':' || _row || ':' || quote(A) || ':' || typeof(B) || ':' \
+                 || quote(C) || ':' || quote(D) FROM Sales_2024 ORDER BY _sheet, _row",
+            )
+            .unwrap();
+        let rows: Vec<String> = query
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "Sheet1:1:'item':real:0:NULL",
+                "Sheet1:2:'pen':real:1:NULL",
+                "Sheet2:1:NULL:null:NULL:'wide'"
+            ]
+        );
+        let declared: String = connection
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'Sales_2024'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(declared.contains("\"B\" REAL") && declared.contains("\"C\" INTEGER"));
+        let book_id: String = connection
+            .query_row(
+                "SELECT book_id FROM _sheet_up_books WHERE table_name = 'Sales_2024_2'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(book_id, "b");
+    }
+}
@@ -0,0 +1,311 @@
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::intern;
+use super::io::{read_json_file_as, SizeLimits};
+use super::load_workspace_file;
+use super::metadata::book_metadata;
+use super::parallel::parallel_map;
+use super::paths::{workspace_dir_of, BookRoots};
+use super::search::SearchFailure;
+use serde::de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargestBook {
+    pub book_id: Option<String>,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceStats {
+    /// Entries in `workspace.json`.
+    pub books: usize,
+    /// Cells over every readable book; books in `failed` are not counted.
+    pub total_cells: u64,
+    /// Cells holding a value other than `null` or `""`.
+    pub non_empty_cells: u64,
+    /// Size on disk of `workspace.json` and every existing book file.
+    pub total_size_bytes: u64,
+    pub largest_book: Option<LargestBook>,
+    /// Newest modification time (epoch millis) of those files.
+    pub last_modified: Option<u64>,
+    /// Books whose cells could not be counted.
+    pub failed: Vec<SearchFailure>,
+}
+
+/// Cell counts of a book, taken while it is parsed so that its sheets never
+/// sit in memory as a whole; only one cell at a time is materialized.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct CellCounts {
+    cells: u64,
+    non_empty: u64,
+}
+
+/// The part of a book the parser is in: `{ sheets: [{ rows: { "1": { "A":
+/// cell } } }] }`. Everything outside this path is skipped unparsed.
+#[derive(Clone, Copy)]
+enum Level {
+    Book,
+    Sheets,
+    Sheet,
+    Rows,
+    Row,
+}
+
+struct Counter<'a> {
+    counts: &'a mut CellCounts,
+    level: Level,
+}
+
+impl Counter<'_> {
+    fn at(&mut self, level: Level) -> Counter<'_> {
+        Counter {
+            counts: self.counts,
+            level,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Counter<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        match self.level {
+            Level::Sheets => deserializer.deserialize_seq(self),
+            _ => deserializer.deserialize_map(self),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Counter<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self.level {
+            Level::Book => "a book object",
+            Level::Sheets => "an array of sheets",
+            Level::Sheet => "a sheet object",
+            Level::Rows => "an object of rows",
+            Level::Row => "an object of cells",
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(self.at(Level::Sheet))?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match (self.level, key.as_str()) {
+                (Level::Book, "sheets") => map.next_value_seed(self.at(Level::Sheets))?,
+                (Level::Sheet, "rows") => map.next_value_seed(self.at(Level::Rows))?,
+                (Level::Rows, _) => map.next_value_seed(self.at(Level::Row))?,
+                (Level::Row, _) => {
+                    let cell: Value = map.next_value()?;
+                    self.counts.cells += 1;
+                    if is_non_empty(&cell) {
+                        self.counts.non_empty += 1;
+                    }
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for CellCounts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut counts = CellCounts::default();
+        Counter {
+            counts: &mut counts,
+            level: Level::Book,
+        }
+        .deserialize(deserializer)?;
+        Ok(counts)
+    }
+}
+
+fn is_non_empty(cell: &Value) -> bool {
+    // Empty strings are never interned.
+    if cell.get(intern::REF_KEY).is_some() {
+        return true;
+    }
+    match cell.get("value") {
+        None | Some(Value::Null) => false,
+        Some(Value::String(text)) => !text.is_empty(),
+        Some(_) => true,
+    }
+}
+
+struct BookStats {
+    size_bytes: Option<u64>,
+    modified: Option<u64>,
+    counts: WorkspaceResult<CellCounts>,
+}
+
+fn book_stats(
+    roots: &BookRoots,
+    index: usize,
+    book_ref: &Value,
+    passphrase: Option<&str>,
+) -> BookStats {
+    let metadata = book_metadata(roots, index, book_ref);
+    let counts = match (metadata.error, &metadata.file_path) {
+        (Some(err), _) => Err(err),
+        (None, Some(path)) => read_json_file_as(Path::new(path), passphrase),
+        (None, None) => Err(WorkspaceError::invalid_schema(format!(
+            "books[{}].dataPath is missing or invalid",
+            index
+        ))),
+    };
+    BookStats {
+        size_bytes: metadata.size_bytes,
+        modified: metadata.modified,
+        counts,
+    }
+}
+
+/// Sizes up a workspace: book and cell counts, bytes on disk, and the latest
+/// change. Books stream through a counting parser in parallel rather than
+/// being loaded. Books that cannot be read still count towards the sizes
+/// and are listed in `failed`. Encrypted books need `passphrase`.
+#[tauri::command(async)]
+pub fn workspace_stats(
+    workspace_path: String,
+    passphrase: Option<String>,
+) -> WorkspaceResult<WorkspaceStats> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace = load_workspace_file(&workspace_path, SizeLimits::default().workspace)?;
+    let roots = BookRoots::new(&workspace_dir_of(&workspace_path), &workspace.data);
+    let refs = workspace.data["books"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let per_book = parallel_map(refs, |index, book_ref| {
+        book_stats(&roots, index, book_ref, passphrase.as_deref())
+    });
+
+    let mut stats = WorkspaceStats {
+        books: refs.len(),
+        total_size_bytes: std::fs::metadata(&workspace_path).map_or(0, |stat| stat.len()),
+        last_modified: workspace.modified,
+        ..Default::default()
+    };
+    for (index, (book_ref, book)) in refs.iter().zip(per_book).enumerate() {
+        let book_id = book_ref["id"].as_str().map(str::to_string);
+        if let Some(size_bytes) = book.size_bytes {
+            stats.total_size_bytes += size_bytes;
+            if stats
+                .largest_book
+                .as_ref()
+                .is_none_or(|largest| size_bytes > largest.size_bytes)
+            {
+                stats.largest_book = Some(LargestBook {
+                    book_id: book_id.clone(),
+                    size_bytes,
+                });
+            }
+        }
+        stats.last_modified = stats.last_modified.max(book.modified);
+        match book.counts {
+            Ok(counts) => {
+                stats.total_cells += counts.cells;
+                stats.non_empty_cells += counts.non_empty;
+            }
+            Err(error) => stats.failed.push(SearchFailure {
+                index,
+                book_id,
+                error,
+            }),
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{write_json_file, FileEncoding};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn counts_cells_while_parsing() {
+        let book = json!({
+            "book": { "id": "book-1", "rows": { "1": { "A": {} } } },
+            "sheets": [
+                {
+                    "id": "sheet-1",
+                    "rows": {
+                        "1": { "A": { "value": "x" }, "B": { "value": "" } },
+                        "2": { "C": { "value": 0 }, "D": { "value": null, "style": {} } }
+                    }
+                },
+                { "id": "sheet-2", "rows": { "9": { "A": { "value": false } } } }
+            ]
+        });
+        let counts: CellCounts = serde_json::from_value(book).unwrap();
+        assert_eq!(
+            counts,
+            CellCounts {
+                cells: 5,
+                non_empty: 3
+            }
+        );
+        assert!(serde_json::from_value::<CellCounts>(json!({ "sheets": {} })).is_err());
+    }
+
+    #[test]
+    fn sums_books_and_keeps_sizes_of_broken_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let refs = json!([
+            { "id": "book-1", "name": "One", "dataPath": "one.json.gz" },
+            { "id": "book-2", "name": "Two", "dataPath": "two.json" },
+            { "id": "book-3", "name": "Gone", "dataPath": "gone.json" }
+        ]);
+        write_json_file(
+            &workspace_path,
+            &json!({ "schemaVersion": "1.0.0", "books": refs }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        let rows = json!({ "1": { "A": { "value": 1 }, "B": {} } });
+        write_json_file(
+            &dir.path().join("one.json.gz"),
+            &json!({ "sheets": [{ "id": "sheet-1", "rows": rows }] }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        fs::write(dir.path().join("two.json"), "{ \"sheets\": [ broken").unwrap();
+
+        let stats = workspace_stats(workspace_path.to_string_lossy().into_owned(), None).unwrap();
+        assert_eq!(stats.books, 3);
+        assert_eq!((stats.total_cells, stats.non_empty_cells), (2, 1));
+        let sizes: u64 = ["workspace.json", "one.json.gz", "two.json"]
+            .iter()
+            .map(|name| fs::metadata(dir.path().join(name)).unwrap().len())
+            .sum();
+        assert_eq!(stats.total_size_bytes, sizes);
+        assert!(stats.last_modified.is_some());
+        let failed: Vec<_> = stats.failed.iter().map(|failure| failure.index).collect();
+        assert_eq!(failed, vec![1, 2]);
+        assert!(matches!(
+            stats.failed[0].error,
+            WorkspaceError::ParseError { .. }
+        ));
+        assert!(matches!(
+            stats.failed[1].error,
+            WorkspaceError::NotFound { .. }
+        ));
+    }
+}
@@ -0,0 +1,427 @@
+//! `insert_rows` / `delete_rows` / `insert_cols` / `delete_cols`: structural
+//! edits of one sheet that move every cell after the edited position, done
+//! here so the frontend does not have to rewrite each cell itself.
+
+use super::cells::{column_index, column_label, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_encrypted_file, FileEncoding, SizeLimits};
+use super::{load_book, write_tracked};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StructureEditOptions {
+    /// The sheet to edit; the first sheet of the book when omitted.
+    pub sheet_id: Option<String>,
+    /// Decrypts an encrypted book and re-encrypts it when writing.
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructureEditResult {
+    pub sheet_id: String,
+    /// `gridSize` of the sheet after the edit.
+    pub rows: u64,
+    pub cols: u64,
+    /// Cells now at another address.
+    pub moved: usize,
+    /// Cells that were in the deleted rows or columns.
+    pub removed: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    Rows,
+    Cols,
+}
+
+impl Axis {
+    fn grid_key(self) -> &'static str {
+        match self {
+            Self::Rows => "rows",
+            Self::Cols => "cols",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Insert,
+    Delete,
+}
+
+/// Where an edit of `count` lines at `at` moves line `index`, or `None`
+/// when the line is deleted.
+fn shifted(index: u32, change: Change, at: u32, count: u32) -> Option<u32> {
+    match change {
+        _ if index < at => Some(index),
+        Change::Insert => Some(index + count),
+        Change::Delete if index < at + count => None,
+        Change::Delete => Some(index - count),
+    }
+}
+
+/// How a line key is read and written, and how many cells its entry holds.
+struct Lines<P, F, C> {
+    parse: P,
+    format: F,
+    cells: C,
+}
+
+/// Rebuilds `entries` under the keys `shift` gives, dropping those it
+/// maps to `None`, and returns how many cells moved and were removed. Keys
+/// that are not line keys are kept as they are. The result is in line
+/// order, and since `shift` never maps two lines to one, no entry can
+/// overwrite another.
+fn shift_keys<P, F, C>(
+    entries: &mut Map<String, Value>,
+    lines: &Lines<P, F, C>,
+    shift: impl Fn(u32) -> Option<u32>,
+) -> (usize, usize)
+where
+    P: Fn(&str) -> Option<u32>,
+    F: Fn(u32) -> String,
+    C: Fn(&Value) -> usize,
+{
+    let (mut moved, mut removed) = (0, 0);
+    let mut kept: Vec<(u32, Value)> = Vec::with_capacity(entries.len());
+    let mut others = Map::new();
+    for (key, value) in std::mem::take(entries) {
+        let Some(index) = (lines.parse)(&key) else {
+            others.insert(key, value);
+            continue;
+        };
+        let cells = (lines.cells)(&value);
+        match shift(index) {
+            Some(new_index) => {
+                if new_index != index {
+                    moved += cells;
+                }
+                kept.push((new_index, value));
+            }
+            None => removed += cells,
+        }
+    }
+    kept.sort_by_key(|(index, _)| *index);
+    for (index, value) in kept {
+        let previous = entries.insert((lines.format)(index), value);
+        debug_assert!(previous.is_none(), "two lines shifted onto {}", index);
+    }
+    entries.extend(others);
+    (moved, removed)
+}
+
+fn edit_sheet(
+    sheet: &mut Value,
+    axis: Axis,
+    change: Change,
+    at: u32,
+    count: u32,
+) -> WorkspaceResult<StructureEditResult> {
+    let size = sheet["gridSize"][axis.grid_key()].as_u64().unwrap_or(1);
+    let invalid =
+        |name: &'static str, message: String| WorkspaceError::InvalidOption { name, message };
+    if count == 0 {
+        return Err(invalid("count", "must be at least 1".into()));
+    }
+    let new_size = match change {
+        Change::Insert if at == 0 || u64::from(at) > size + 1 => {
+            return Err(invalid("at", format!("must be between 1 and {}", size + 1)))
+        }
+        Change::Insert => size + u64::from(count),
+        Change::Delete if at == 0 || u64::from(at) + u64::from(count) - 1 > size => {
+            return Err(invalid(
+                "count",
+                format!(
+                    "must stay within the {} {} of the sheet",
+                    size,
+                    axis.grid_key()
+                ),
+            ))
+        }
+        // A sheet keeps at least one row and column, empty once everything
+        // is deleted.
+        Change::Delete => (size - u64::from(count)).max(1),
+    };
+    if new_size > u64::from(u32::MAX) {
+        return Err(invalid(
+            "count",
+            format!("would grow the sheet past {}", u32::MAX),
+        ));
+    }
+
+    if !sheet["rows"].is_object() {
+        sheet["rows"] = json!({});
+    }
+    let rows = sheet["rows"]
+        .as_object_mut()
+        .expect("rows was just made an object");
+    let shift = |index| shifted(index, change, at, count);
+    let (moved, removed) = match axis {
+        Axis::Rows => {
+            let lines = Lines {
+                parse: row_index,
+                format: |index: u32| index.to_string(),
+                cells: |row: &Value| row.as_object().map_or(0, Map::len),
+            };
+            shift_keys(rows, &lines, shift)
+        }
+        Axis::Cols => {
+            let lines = Lines {
+                parse: column_index,
+                format: column_label,
+                cells: |_: &Value| 1,
+            };
+            let mut totals = (0, 0);
+            for cells in rows.values_mut().filter_map(Value::as_object_mut) {
+                let (moved, removed) = shift_keys(cells, &lines, shift);
+                totals = (totals.0 + moved, totals.1 + removed);
+            }
+            // Rows whose only cells were deleted are not kept empty.
+            if change == Change::Delete {
+                rows.retain(|_, cells| cells.as_object().is_none_or(|cells| !cells.is_empty()));
+            }
+            totals
+        }
+    };
+    if let Some(grid_size) = sheet["gridSize"].as_object_mut() {
+        grid_size.insert(axis.grid_key().into(), json!(new_size));
+    }
+    Ok(StructureEditResult {
+        sheet_id: sheet["id"].as_str().unwrap_or_default().to_string(),
+        rows: sheet["gridSize"]["rows"].as_u64().unwrap_or(1),
+        cols: sheet["gridSize"]["cols"].as_u64().unwrap_or(1),
+        moved,
+        removed,
+    })
+}
+
+fn edit_book(
+    book_file_path: &str,
+    axis: Axis,
+    change: Change,
+    (at, count): (u32, u32),
+    options: Option<StructureEditOptions>,
+) -> WorkspaceResult<StructureEditResult> {
+    let options = options.unwrap_or_default();
+    let path = Path::new(book_file_path);
+    let passphrase = options.passphrase.as_deref();
+    let mut book = load_book(path, passphrase, SizeLimits::default().book)?;
+    let sheet = book.data["sheets"]
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .find(|sheet| {
+            options
+                .sheet_id
+                .as_ref()
+                .is_none_or(|id| sheet["id"] == id.as_str())
+        })
+        .ok_or_else(|| WorkspaceError::SheetNotFound {
+            path: book_file_path.to_string(),
+            sheet_id: options.sheet_id.clone().unwrap_or_default(),
+        })?;
+    let result = edit_sheet(sheet, axis, change, at, count)?;
+    let encoding = FileEncoding {
+        passphrase: passphrase.filter(|_| is_encrypted_file(path)),
+        style: book.text_style.unwrap_or_default(),
+        ..Default::default()
+    };
+    write_tracked(path, &book.data, encoding)?;
+    Ok(result)
+}
+
+/// Inserts `count` empty rows before row `at` (1-based; one past the last
+/// row appends) and moves the rows below down.
+#[tauri::command]
+pub fn insert_rows(
+    book_file_path: String,
+    at: u32,
+    count: u32,
+    options: Option<StructureEditOptions>,
+) -> WorkspaceResult<StructureEditResult> {
+    edit_book(
+        &book_file_path,
+        Axis::Rows,
+        Change::Insert,
+        (at, count),
+        options,
+    )
+}
+
+/// Deletes `count` rows from row `at` on, with their cells, and moves the
+/// rows below up. Deleting every row leaves a single empty one.
+#[tauri::command]
+pub fn delete_rows(
+    book_file_path: String,
+    at: u32,
+    count: u32,
+    options: Option<StructureEditOptions>,
+) -> WorkspaceResult<StructureEditResult> {
+    edit_book(
+        &book_file_path,
+        Axis::Rows,
+        Change::Delete,
+        (at, count),
+        options,
+    )
+}
+
+/// Inserts `count` empty columns before column `at` (1 is `A`) and moves the
+/// columns to its right along.
+#[tauri::command]
+pub fn insert_cols(
+    book_file_path: String,
+    at: u32,
+    count: u32,
+    options: Option<StructureEditOptions>,
+) -> WorkspaceResult<StructureEditResult> {
+    edit_book(
+        &book_file_path,
+        Axis::Cols,
+        Change::Insert,
+        (at, count),
+        options,
+    )
+}
+
+/// Deletes `count` columns from column `at` on, with their cells, and moves
+/// the columns to their right back. Deleting every column leaves a single
+/// empty one.
+#[tauri::command]
+pub fn delete_cols(
+    book_file_path: String,
+    at: u32,
+    count: u32,
+    options: Option<StructureEditOptions>,
+) -> WorkspaceResult<StructureEditResult> {
+    edit_book(
+        &book_file_path,
+        Axis::Cols,
+        Change::Delete,
+        (at, count),
+        options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+
+    fn text(value: &str) -> Value {
+        json!({ "value": value, "type": "string" })
+    }
+
+    /// A 3x3 sheet with the cell at row r, column c holding `"<c><r>"`.
+    fn write_book(dir: &Path) -> String {
+        let path = dir.join("book.json");
+        let mut rows = Map::new();
+        for row in 1..=3 {
+            let cells: Map<String, Value> = (1..=3)
+                .map(|col| {
+                    let label = column_label(col);
+                    let address = format!("{}{}", label, row);
+                    (label, text(&address))
+                })
+                .collect();
+            rows.insert(row.to_string(), Value::Object(cells));
+        }
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet",
+                "gridSize": { "rows": 3, "cols": 3 },
+                "rows": rows
+            }]
+        });
+        write_json_file(&path, &book, FileEncoding::default()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn sheet(path: &str) -> Value {
+        read_json_file(Path::new(path)).unwrap()["sheets"][0].clone()
+    }
+
+    #[test]
+    fn row_edits_shift_cells_at_either_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_book(dir.path());
+
+        let result = insert_rows(path.clone(), 1, 2, None).unwrap();
+        assert_eq!((result.rows, result.moved, result.removed), (5, 9, 0));
+        let rows = &sheet(&path)["rows"];
+        assert!(rows.get("1").is_none());
+        assert_eq!(rows["3"]["A"]["value"], "A1");
+        assert_eq!(rows["5"]["C"]["value"], "C3");
+
+        // Appending moves nothing.
+        let result = insert_rows(path.clone(), 6, 1, None).unwrap();
+        assert_eq!((result.rows, result.moved), (6, 0));
+        assert!(matches!(
+            insert_rows(path.clone(), 8, 1, None),
+            Err(WorkspaceError::InvalidOption { name: "at", .. })
+        ));
+
+        let result = delete_rows(path.clone(), 1, 3, None).unwrap();
+        assert_eq!((result.rows, result.moved, result.removed), (3, 6, 3));
+        let keys: Vec<String> = sheet(&path)["rows"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(keys, ["1", "2"]);
+        assert_eq!(sheet(&path)["rows"]["2"]["B"]["value"], "B3");
+
+        assert!(matches!(
+            delete_rows(path.clone(), 2, 3, None),
+            Err(WorkspaceError::InvalidOption { name: "count", .. })
+        ));
+        let result = delete_rows(path.clone(), 1, 3, None).unwrap();
+        assert_eq!((result.rows, result.removed), (1, 6));
+        assert_eq!(sheet(&path)["rows"], json!({}));
+    }
+
+    #[test]
+    fn column_edits_relabel_cells_in_every_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_book(dir.path());
+
+        let result = insert_cols(path.clone(), 2, 1, None).unwrap();
+        assert_eq!((result.cols, result.moved), (4, 6));
+        let rows = &sheet(&path)["rows"];
+        assert_eq!(rows["1"]["A"]["value"], "A1");
+        assert!(rows["1"].get("B").is_none());
+        assert_eq!(rows["3"]["D"]["value"], "C3");
+
+        let result = delete_cols(path.clone(), 4, 1, None).unwrap();
+        assert_eq!((result.cols, result.moved, result.removed), (3, 0, 3));
+        let result = delete_cols(path.clone(), 1, 2, None).unwrap();
+        assert_eq!((result.cols, result.moved, result.removed), (1, 3, 3));
+        let rows = &sheet(&path)["rows"];
+        assert_eq!(rows["2"], json!({ "A": text("B2") }));
+
+        // Deleting the last column empties the sheet but keeps one column.
+        let result = delete_cols(path.clone(), 1, 1, None).unwrap();
+        assert_eq!((result.cols, result.removed), (1, 3));
+        assert_eq!(sheet(&path)["rows"], json!({}));
+        assert!(matches!(
+            insert_cols(
+                path,
+                1,
+                1,
+                Some(StructureEditOptions {
+                    sheet_id: Some("missing".into()),
+                    ..Default::default()
+                })
+            ),
+            Err(WorkspaceError::SheetNotFound { .. })
+        ));
+    }
+}
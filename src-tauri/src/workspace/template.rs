@@ -0,0 +1,292 @@
+//! `create_workspace_from_template`: a new workspace made from a copy of a
+//! template workspace, with fresh ids and its placeholders filled in.
+
+use super::books::now_rfc3339;
+use super::duplicate::{files_to_copy, move_into_place, staging_dir_for};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{
+    is_encrypted_file, read_workspace_json, write_json_file, FileEncoding, SizeLimits,
+};
+use super::paths::workspace_dir_of;
+use super::schema::validate_workspace;
+use super::{load_book, WORKSPACE_FILE_NAME};
+use chrono::Local;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateWorkspaceResult {
+    pub workspace_path: String,
+    /// Book files written, not counting `workspace.json`.
+    pub book_files: usize,
+    /// Placeholders replaced across cell values and book names.
+    pub replaced: usize,
+}
+
+/// The values `{{name}}` and `{{date}}` stand for. Other `{{...}}` text is
+/// left as it is.
+struct Placeholders {
+    pairs: [(&'static str, String); 2],
+}
+
+impl Placeholders {
+    fn new(name: &str) -> Self {
+        Self {
+            pairs: [
+                ("{{name}}", name.to_string()),
+                ("{{date}}", Local::now().format("%Y-%m-%d").to_string()),
+            ],
+        }
+    }
+
+    /// Fills in `text` when it holds a placeholder, counting each one.
+    fn fill(&self, text: &mut Value, replaced: &mut usize) {
+        let Some(original) = text.as_str() else {
+            return;
+        };
+        let mut filled = original.to_string();
+        for (placeholder, value) in &self.pairs {
+            let found = filled.matches(placeholder).count();
+            if found > 0 {
+                *replaced += found;
+                filled = filled.replace(placeholder, value);
+            }
+        }
+        if filled != original {
+            *text = json!(filled);
+        }
+    }
+}
+
+/// Fills in the placeholders of every string cell and of the book name, and
+/// gives the book `book_id` with fresh timestamps.
+fn fill_book(book: &mut Value, book_id: &str, placeholders: &Placeholders, now: &str) -> usize {
+    let mut replaced = 0;
+    if let Some(meta) = book["book"].as_object_mut() {
+        meta.insert("id".into(), json!(book_id));
+        meta.insert("createdAt".into(), json!(now));
+        meta.insert("updatedAt".into(), json!(now));
+        if let Some(name) = meta.get_mut("name") {
+            placeholders.fill(name, &mut replaced);
+        }
+    }
+    for sheet in book["sheets"].as_array_mut().into_iter().flatten() {
+        for row in sheet["rows"]
+            .as_object_mut()
+            .into_iter()
+            .flat_map(|rows| rows.values_mut())
+        {
+            for cell in row
+                .as_object_mut()
+                .into_iter()
+                .flat_map(|row| row.values_mut())
+            {
+                if let Some(value) = cell.get_mut("value") {
+                    placeholders.fill(value, &mut replaced);
+                }
+            }
+        }
+    }
+    replaced
+}
+
+/// Creates a workspace in `dest_dir` from the template at `template_path`.
+/// The workspace and its books get new ids, `{{name}}` and `{{date}}` in
+/// cell values and book names are replaced by `name` and today's date, and
+/// a `readOnly` template yields an editable copy. Like duplication, the
+/// files are staged first and nothing is written if any target already
+/// exists. `passphrase` opens encrypted template books, which stay
+/// encrypted in the copy.
+#[tauri::command]
+pub fn create_workspace_from_template(
+    template_path: String,
+    dest_dir: String,
+    name: String,
+    passphrase: Option<String>,
+) -> WorkspaceResult<TemplateWorkspaceResult> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(WorkspaceError::InvalidOption {
+            name: "name",
+            message: "must not be empty".into(),
+        });
+    }
+    let template_path = PathBuf::from(template_path);
+    let template_dir = workspace_dir_of(&template_path);
+    let dest_dir = PathBuf::from(dest_dir);
+    let mut workspace = read_workspace_json(&template_path)?;
+    validate_workspace(&workspace)?;
+
+    // The manifest is not carried over: its hashes are those of the
+    // template's books, and the next save writes a new one.
+    let books = files_to_copy(&workspace)?;
+    let workspace_file = Path::new(WORKSPACE_FILE_NAME);
+    for relative in books.iter().map(PathBuf::as_path).chain([workspace_file]) {
+        let target = dest_dir.join(relative);
+        if target.exists() {
+            return Err(WorkspaceError::AlreadyExists {
+                path: target.display().to_string(),
+            });
+        }
+    }
+
+    let placeholders = Placeholders::new(&name);
+    let now = now_rfc3339();
+    let mut replaced = 0;
+    let mut new_ids: HashMap<String, String> = HashMap::new();
+    let mut new_id = |old: &str| {
+        new_ids
+            .entry(old.to_string())
+            .or_insert_with(|| format!("book-{}", uuid::Uuid::new_v4()))
+            .clone()
+    };
+    for book_ref in workspace["books"].as_array_mut().into_iter().flatten() {
+        let id = new_id(book_ref["id"].as_str().unwrap_or_default());
+        book_ref["id"] = json!(id);
+        if let Some(book_name) = book_ref.get_mut("name") {
+            placeholders.fill(book_name, &mut replaced);
+        }
+    }
+    if let Some(object) = workspace.as_object_mut() {
+        object.remove("readOnly");
+    }
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert(
+            "id".into(),
+            json!(format!("workspace-{}", uuid::Uuid::new_v4())),
+        );
+        meta.insert("name".into(), json!(name));
+        meta.insert("createdAt".into(), json!(now));
+        meta.insert("updatedAt".into(), json!(now));
+        if let Some(settings) = meta.get_mut("settings").and_then(Value::as_object_mut) {
+            settings.remove("recentBookIds");
+        }
+    }
+
+    let staging = staging_dir_for(&dest_dir);
+    let staged = (|| {
+        for relative in &books {
+            let source = template_dir.join(relative);
+            let encrypted = is_encrypted_file(&source);
+            let mut book = load_book(&source, passphrase.as_deref(), SizeLimits::default().book)?;
+            let id = new_id(book.data["book"]["id"].as_str().unwrap_or_default());
+            replaced += fill_book(&mut book.data, &id, &placeholders, &now);
+            let target = staging.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|err| WorkspaceError::io("create", parent, err))?;
+            }
+            write_json_file(
+                &target,
+                &book.data,
+                FileEncoding {
+                    passphrase: passphrase.as_deref().filter(|_| encrypted),
+                    style: book.text_style.unwrap_or_default(),
+                    ..Default::default()
+                },
+            )?;
+        }
+        write_json_file(
+            &staging.join(workspace_file),
+            &workspace,
+            FileEncoding::default(),
+        )?;
+        let mut files = books.clone();
+        files.push(workspace_file.to_path_buf());
+        move_into_place(&staging, &dest_dir, &files)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    staged?;
+
+    Ok(TemplateWorkspaceResult {
+        workspace_path: dest_dir.join(workspace_file).to_string_lossy().into_owned(),
+        book_files: books.len(),
+        replaced,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::read_json_file;
+
+    #[test]
+    fn fills_placeholders_and_renews_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let template_path = dir.path().join("template/workspace.json");
+        let workspace = json!({
+            "schemaVersion": "1.0.0",
+            "readOnly": true,
+            "workspace": {
+                "id": "workspace-template",
+                "name": "Template",
+                "settings": { "recentBookIds": ["book-1"] }
+            },
+            "books": [{ "id": "book-1", "name": "{{name}} 見積.json", "dataPath": "books/a.json" }]
+        });
+        write_json_file(&template_path, &workspace, FileEncoding::default()).unwrap();
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "{{name}} 見積" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": 3, "cols": 3 },
+                "rows": {
+                    "1": {
+                        "A": { "value": "{{name}} / {{date}}", "type": "string" },
+                        "B": { "value": "{{unknown}}", "type": "string" },
+                        "C": { "value": 42, "type": "number" }
+                    }
+                }
+            }]
+        });
+        let book_path = dir.path().join("template/books/a.json");
+        write_json_file(&book_path, &book, FileEncoding::default()).unwrap();
+        let template = template_path.to_string_lossy().into_owned();
+        let dest = dir.path().join("new");
+
+        let result = create_workspace_from_template(
+            template.clone(),
+            dest.to_string_lossy().into_owned(),
+            " 山田 ".into(),
+            None,
+        )
+        .unwrap();
+        assert_eq!((result.book_files, result.replaced), (1, 4));
+        let created = read_json_file(Path::new(&result.workspace_path)).unwrap();
+        assert!(created.get("readOnly").is_none());
+        assert_eq!(created["workspace"]["name"], "山田");
+        assert_ne!(created["workspace"]["id"], "workspace-template");
+        assert_eq!(created["workspace"]["settings"], json!({}));
+        let entry = &created["books"][0];
+        assert_eq!(entry["name"], "山田 見積.json");
+        assert_ne!(entry["id"], "book-1");
+
+        let copied = read_json_file(&dest.join("books/a.json")).unwrap();
+        assert_eq!(copied["book"]["id"], entry["id"]);
+        assert_eq!(copied["book"]["name"], "山田 見積");
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let cells = &copied["sheets"][0]["rows"]["1"];
+        assert_eq!(cells["A"]["value"], format!("山田 / {}", today));
+        assert_eq!(cells["B"]["value"], "{{unknown}}");
+        assert_eq!(cells["C"]["value"], 42);
+        assert_eq!(read_json_file(&book_path).unwrap(), book);
+
+        // Another workspace in the same place would overwrite this one.
+        let err = create_workspace_from_template(
+            template,
+            dest.to_string_lossy().into_owned(),
+            "別".into(),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, WorkspaceError::AlreadyExists { path } if path.ends_with("a.json")));
+        let unchanged = read_json_file(Path::new(&result.workspace_path)).unwrap();
+        assert_eq!(unchanged["workspace"]["name"], "山田");
+    }
+}
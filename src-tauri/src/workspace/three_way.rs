@@ -0,0 +1,374 @@
+//! Three-way merge of books changed on disk since they were loaded. With the
+//! book data as loaded (the base), changes made only on disk and changes made
+//! only in the snapshot are combined; only cells both sides changed to
+//! different values are conflicts. Sheets are matched by `id`, and other
+//! fields of the book and its sheets merge the same way as cells.
+
+use super::io::SizeLimits;
+use super::{load_book, modified_millis, FilePayload, WorkspaceResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// What a save does with books merged with their changed files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ThreeWayMode {
+    /// Save the merged books unless a cell conflicts, in which case nothing
+    /// is written and the error is `mergeConflict`.
+    #[default]
+    Auto,
+    /// Write nothing and return the merges in `threeWay`, so they can be
+    /// shown for confirmation and saved as the snapshot's new data.
+    Confirm,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ThreeWayOptions {
+    /// Book data as loaded, keyed by `filePath`. A changed book without an
+    /// entry here still fails with `conflict`.
+    pub base: BTreeMap<String, Value>,
+    pub mode: ThreeWayMode,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreeWayConflict {
+    /// Sheet of the conflict; absent for fields of the book itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sheet_id: Option<String>,
+    /// Address like `B3` when a cell conflicts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cell: Option<String>,
+    /// Key path of a conflicting field other than a cell, e.g. `book.name`,
+    /// or `name` within a sheet. Absent when a whole sheet conflicts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The three versions; `null` where the value does not exist.
+    pub base: Option<Value>,
+    pub disk: Option<Value>,
+    pub ours: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookMerge {
+    pub file_path: String,
+    /// The merged book. Conflicting cells keep the snapshot's value.
+    pub data: Value,
+    /// Modification time of the file the merge was made against; the
+    /// snapshot's `modified` once the merge is accepted.
+    pub modified: Option<u64>,
+    pub conflicts: Vec<ThreeWayConflict>,
+}
+
+/// Merges every dirty book whose file changed since it was loaded and that
+/// has a base, replacing its data with the merge and its `modified` with
+/// that of the file, so the save that follows no longer sees a conflict.
+pub(super) fn merge_with_disk(
+    books: &mut [FilePayload],
+    options: &ThreeWayOptions,
+    passphrase: Option<&str>,
+) -> WorkspaceResult<Vec<BookMerge>> {
+    let mut merges = Vec::new();
+    for book in books.iter_mut().filter(|book| book.is_dirty()) {
+        let Some(expected) = book.modified else {
+            continue;
+        };
+        let path = Path::new(&book.file_path);
+        if modified_millis(path)?.is_none_or(|modified| modified == expected) {
+            continue;
+        }
+        let Some(base) = options.base.get(&book.file_path) else {
+            continue;
+        };
+        let disk = load_book(path, passphrase, SizeLimits::default().book)?;
+        let (data, conflicts) = merge_book(base, &disk.data, &book.data);
+        book.data = data.clone();
+        book.modified = disk.modified;
+        merges.push(BookMerge {
+            file_path: book.file_path.clone(),
+            data,
+            modified: disk.modified,
+            conflicts,
+        });
+    }
+    Ok(merges)
+}
+
+/// Merges the changes from `base` to `disk` and from `base` to `ours`.
+fn merge_book(base: &Value, disk: &Value, ours: &Value) -> (Value, Vec<ThreeWayConflict>) {
+    let mut merger = Merger::default();
+    let merged = merger.merge(Some(base), Some(disk), Some(ours), &At::root());
+    (merged.unwrap_or_else(|| ours.clone()), merger.conflicts)
+}
+
+/// Where in the book a value being merged sits.
+#[derive(Clone)]
+enum At {
+    Field {
+        sheet_id: Option<String>,
+        path: String,
+    },
+    Rows {
+        sheet_id: String,
+    },
+    Row {
+        sheet_id: String,
+        row: String,
+    },
+    Cell {
+        sheet_id: String,
+        cell: String,
+    },
+}
+
+impl At {
+    fn root() -> Self {
+        Self::Field {
+            sheet_id: None,
+            path: String::new(),
+        }
+    }
+
+    fn key(&self, key: &str) -> Self {
+        match self {
+            Self::Field {
+                sheet_id: Some(sheet_id),
+                path,
+            } if path.is_empty() && key == "rows" => Self::Rows {
+                sheet_id: sheet_id.clone(),
+            },
+            Self::Field { sheet_id, path } => Self::Field {
+                sheet_id: sheet_id.clone(),
+                path: if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                },
+            },
+            Self::Rows { sheet_id } => Self::Row {
+                sheet_id: sheet_id.clone(),
+                row: key.to_string(),
+            },
+            Self::Row { sheet_id, row } => Self::Cell {
+                sheet_id: sheet_id.clone(),
+                cell: format!("{}{}", key, row),
+            },
+            // Cells are merged whole, so nothing below them is reached.
+            Self::Cell { .. } => self.clone(),
+        }
+    }
+
+    /// The element with `id` of an array; the top-level `sheets` holds the
+    /// sheets.
+    fn element(&self, id: &str) -> Self {
+        match self {
+            Self::Field {
+                sheet_id: None,
+                path,
+            } if path == "sheets" => Self::Field {
+                sheet_id: Some(id.to_string()),
+                path: String::new(),
+            },
+            Self::Field { sheet_id, path } => Self::Field {
+                sheet_id: sheet_id.clone(),
+                path: format!("{}[{}]", path, id),
+            },
+            other => other.clone(),
+        }
+    }
+
+    fn is_cell(&self) -> bool {
+        matches!(self, Self::Cell { .. })
+    }
+
+    fn is_updated_at(&self) -> bool {
+        matches!(self, Self::Field { path, .. } if path == "updatedAt" || path.ends_with(".updatedAt"))
+    }
+}
+
+#[derive(Default)]
+struct Merger {
+    conflicts: Vec<ThreeWayConflict>,
+}
+
+impl Merger {
+    /// The merged value, `None` when the merge removes it.
+    fn merge(
+        &mut self,
+        base: Option<&Value>,
+        disk: Option<&Value>,
+        ours: Option<&Value>,
+        at: &At,
+    ) -> Option<Value> {
+        if disk == ours || disk == base {
+            return ours.cloned();
+        }
+        if ours == base {
+            return disk.cloned();
+        }
+        match (base, disk, ours) {
+            (_, Some(Value::Object(disk)), Some(Value::Object(ours)))
+                if !at.is_cell() && base.is_none_or(Value::is_object) =>
+            {
+                let empty = Map::new();
+                let base = base.and_then(Value::as_object).unwrap_or(&empty);
+                let mut merged = Map::new();
+                let disk_only = disk.keys().filter(|key| !ours.contains_key(*key));
+                for key in ours.keys().chain(disk_only) {
+                    let value =
+                        self.merge(base.get(key), disk.get(key), ours.get(key), &at.key(key));
+                    if let Some(value) = value {
+                        merged.insert(key.clone(), value);
+                    }
+                }
+                return Some(Value::Object(merged));
+            }
+            (_, Some(Value::Array(disk)), Some(Value::Array(ours))) => {
+                let base = match base {
+                    Some(Value::Array(base)) => by_id(base),
+                    Some(_) => None,
+                    None => Some(HashMap::new()),
+                };
+                if let (Some(base), Some(disk_ids), Some(ours_ids)) =
+                    (base, by_id(disk), by_id(ours))
+                {
+                    let disk_only = disk
+                        .iter()
+                        .filter_map(element_id)
+                        .filter(|id| !ours_ids.contains_key(id));
+                    let ids: Vec<&str> = ours
+                        .iter()
+                        .filter_map(element_id)
+                        .chain(disk_only)
+                        .collect();
+                    let merged = ids
+                        .into_iter()
+                        .filter_map(|id| {
+                            self.merge(
+                                base.get(id).copied(),
+                                disk_ids.get(id).copied(),
+                                ours_ids.get(id).copied(),
+                                &at.element(id),
+                            )
+                        })
+                        .collect();
+                    return Some(Value::Array(merged));
+                }
+            }
+            // Both sides stamp their edits; the later stamp is kept.
+            (_, Some(Value::String(disk)), Some(Value::String(ours))) if at.is_updated_at() => {
+                return Some(Value::String(disk.max(ours).clone()));
+            }
+            _ => {}
+        }
+        self.conflicts.push(conflict(at, base, disk, ours));
+        ours.cloned()
+    }
+}
+
+fn element_id(element: &Value) -> Option<&str> {
+    element.get("id").and_then(Value::as_str)
+}
+
+/// The elements of `array` by their `id`; `None` unless every element has a
+/// distinct string `id`.
+fn by_id(array: &[Value]) -> Option<HashMap<&str, &Value>> {
+    let mut elements = HashMap::new();
+    for element in array {
+        if elements.insert(element_id(element)?, element).is_some() {
+            return None;
+        }
+    }
+    Some(elements)
+}
+
+fn conflict(
+    at: &At,
+    base: Option<&Value>,
+    disk: Option<&Value>,
+    ours: Option<&Value>,
+) -> ThreeWayConflict {
+    let (sheet_id, cell, field) = match at {
+        At::Field { sheet_id, path } => (
+            sheet_id.clone(),
+            None,
+            Some(path.clone()).filter(|path| !path.is_empty()),
+        ),
+        At::Rows { sheet_id } => (Some(sheet_id.clone()), None, Some("rows".into())),
+        At::Row { sheet_id, row } => (Some(sheet_id.clone()), None, Some(format!("rows.{}", row))),
+        At::Cell { sheet_id, cell } => (Some(sheet_id.clone()), Some(cell.clone()), None),
+    };
+    ThreeWayConflict {
+        sheet_id,
+        cell,
+        field,
+        base: base.cloned(),
+        disk: disk.cloned(),
+        ours: ours.cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn book(name: &str, updated_at: &str, rows: Value) -> Value {
+        json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": name, "updatedAt": updated_at },
+            "sheets": [{ "id": "sheet-1", "name": "Sheet1", "rows": rows }]
+        })
+    }
+
+    #[test]
+    fn merges_one_sided_changes_and_lists_cells_changed_on_both_sides() {
+        let cell = |value: &str| json!({ "value": value, "type": "string" });
+        let base = book(
+            "Book",
+            "2026-01-01T00:00:00Z",
+            json!({ "1": { "A": cell("a"), "B": cell("b"), "C": cell("c"), "D": cell("d") } }),
+        );
+        let mut disk = book(
+            "Renamed",
+            "2026-01-03T00:00:00Z",
+            json!({ "1": { "A": cell("a"), "B": cell("disk"), "C": cell("disk"), "D": cell("d") } }),
+        );
+        disk["sheets"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "id": "sheet-2", "name": "Added", "rows": {} }));
+        let ours = book(
+            "Book",
+            "2026-01-02T00:00:00Z",
+            json!({
+                "1": { "A": cell("ours"), "B": cell("b"), "C": cell("ours") },
+                "2": { "A": cell("new") }
+            }),
+        );
+
+        let (merged, conflicts) = merge_book(&base, &disk, &ours);
+        assert_eq!(merged["book"]["name"], "Renamed");
+        assert_eq!(merged["book"]["updatedAt"], "2026-01-03T00:00:00Z");
+        let rows = &merged["sheets"][0]["rows"];
+        assert_eq!(rows["1"]["A"]["value"], "ours");
+        assert_eq!(rows["1"]["B"]["value"], "disk");
+        assert_eq!(rows["1"]["C"]["value"], "ours");
+        assert!(rows["1"].get("D").is_none());
+        assert_eq!(rows["2"]["A"]["value"], "new");
+        assert_eq!(merged["sheets"][1]["name"], "Added");
+
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.sheet_id.as_deref(), Some("sheet-1"));
+        assert_eq!(conflict.cell.as_deref(), Some("C1"));
+        assert_eq!(conflict.field, None);
+        assert_eq!(conflict.base, Some(cell("c")));
+        assert_eq!(conflict.disk, Some(cell("disk")));
+        assert_eq!(conflict.ours, Some(cell("ours")));
+    }
+}
@@ -0,0 +1,192 @@
+//! All-or-nothing writes of several files, used by snapshot saves so that a
+//! failure part way never leaves some books new and others old. Every file
+//! is first written in full to a temp file next to it; only once all of
+//! them are staged are they renamed into place, and a rename that fails
+//! puts back the files already renamed. A crash between two renames can
+//! still leave a mix, as with any update of several files.
+
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{
+    replace_file, stage_bytes, stage_json_file, temp_path_for, FileEncoding, RetryPolicy,
+    StagedFile,
+};
+use super::parallel::with_open_file;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Default)]
+pub(super) struct Transaction {
+    staged: Vec<StagedFile>,
+}
+
+impl Transaction {
+    /// Stages `value` for `path` and returns the bytes it will take; `path`
+    /// itself is untouched until [`commit`](Self::commit).
+    pub fn stage(
+        &mut self,
+        path: &Path,
+        value: &Value,
+        encoding: FileEncoding,
+    ) -> WorkspaceResult<u64> {
+        let staged = with_open_file(|| stage_json_file(path, value, encoding))?;
+        let size = staged.size();
+        self.staged.push(staged);
+        Ok(size)
+    }
+
+    /// [`stage`](Self::stage) for contents that are already bytes.
+    pub fn stage_bytes(
+        &mut self,
+        path: &Path,
+        bytes: &[u8],
+        policy: RetryPolicy,
+    ) -> WorkspaceResult<u64> {
+        self.staged
+            .push(with_open_file(|| stage_bytes(path, bytes, policy))?);
+        Ok(bytes.len() as u64)
+    }
+
+    /// Moves the files staged by `other` to the end of this transaction.
+    pub fn append(&mut self, mut other: Transaction) {
+        self.staged.append(&mut other.staged);
+    }
+
+    /// Destinations of the staged files, in the order they are committed.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.staged.iter().map(StagedFile::dest)
+    }
+
+    /// Renames every staged file into place in the order staged, keeping
+    /// each previous file aside until all are in place. When one cannot be
+    /// renamed, the files already renamed get their previous contents back
+    /// (or are removed if they did not exist before), the rest are
+    /// discarded, and its error is returned. If putting a file back fails
+    /// too, the error is `partialSave` listing the files left new.
+    pub fn commit(self) -> WorkspaceResult<()> {
+        let mut done: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+        let mut staged = self.staged.into_iter();
+        let failed = staged.by_ref().find_map(|file| {
+            let dest = file.dest().to_path_buf();
+            let committed = keep_original(&dest).and_then(|original| match file.commit() {
+                Ok(()) => {
+                    done.push((dest.clone(), original));
+                    Ok(())
+                }
+                Err(err) => {
+                    if let Some(original) = original {
+                        let _ = fs::remove_file(original);
+                    }
+                    Err(err)
+                }
+            });
+            committed
+                .err()
+                .map(|err| WorkspaceError::io("write", &dest, err))
+        });
+        // Removes the temp files of whatever was not committed.
+        drop(staged);
+
+        let Some(err) = failed else {
+            for original in done.into_iter().filter_map(|(_, original)| original) {
+                let _ = fs::remove_file(original);
+            }
+            return Ok(());
+        };
+        let mut left_new = Vec::new();
+        for (dest, original) in done.into_iter().rev() {
+            let restored = match original {
+                Some(original) => replace_file(&original, &dest),
+                None => fs::remove_file(&dest),
+            };
+            if restored.is_err() {
+                left_new.push(dest.display().to_string());
+            }
+        }
+        if left_new.is_empty() {
+            Err(err)
+        } else {
+            Err(WorkspaceError::PartialSave {
+                source: Box::new(err),
+                saved: left_new,
+            })
+        }
+    }
+}
+
+/// Keeps the current file at `path` under a temp name next to it, as a
+/// hard link where the file system has them so that nothing is copied.
+/// `None` when there is no file yet.
+fn keep_original(path: &Path) -> io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let original = temp_path_for(path);
+    if fs::hard_link(path, &original).is_err() {
+        if let Err(err) = fs::copy(path, &original) {
+            let _ = fs::remove_file(&original);
+            return Err(err);
+        }
+    }
+    Ok(Some(original))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn a_failed_rename_puts_back_the_files_already_renamed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, c) = (
+            dir.path().join("a.json"),
+            dir.path().join("b.json"),
+            dir.path().join("c.json"),
+        );
+        fs::write(&a, "old a\n").unwrap();
+        // A directory in the way of the last file makes its rename fail
+        // after the other two went through.
+        fs::create_dir(&c).unwrap();
+
+        let mut transaction = Transaction::default();
+        for path in [&a, &b, &c] {
+            let size = transaction
+                .stage(path, &json!({ "new": true }), FileEncoding::default())
+                .unwrap();
+            assert!(size > 0);
+        }
+        assert_eq!(entries(dir.path()).len(), 5);
+        assert!(transaction.commit().is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old a\n");
+        assert!(!b.exists());
+        assert!(c.is_dir());
+        assert_eq!(entries(dir.path()), ["a.json", "c.json"]);
+
+        let mut transaction = Transaction::default();
+        for path in [&a, &b] {
+            transaction
+                .stage(path, &json!({ "new": true }), FileEncoding::default())
+                .unwrap();
+        }
+        let paths: Vec<&Path> = transaction.paths().collect();
+        assert_eq!(paths, [a.as_path(), b.as_path()]);
+        transaction.commit().unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "{\n  \"new\": true\n}\n");
+        assert_eq!(
+            fs::read_to_string(&a).unwrap(),
+            fs::read_to_string(&b).unwrap()
+        );
+        assert_eq!(entries(dir.path()), ["a.json", "b.json", "c.json"]);
+    }
+}
@@ -0,0 +1,345 @@
+//! `transform_book_cells`: bulk clean-up of the text cells of one book,
+//! such as trimming or case and width conversion.
+
+use super::cells::{column_index, row_index};
+use super::csv_export::CellRange;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_encrypted_file, FileEncoding, SizeLimits};
+use super::{load_book, write_tracked};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CellTransform {
+    /// Strips leading and trailing whitespace, including full-width spaces.
+    Trim,
+    Uppercase,
+    Lowercase,
+    /// Full-width ASCII letters, digits, symbols and the ideographic space
+    /// to their half-width forms. Katakana is left as it is.
+    HalfWidth,
+    /// The inverse of `halfWidth`.
+    FullWidth,
+}
+
+/// Offset between a printable ASCII character and its full-width form.
+const FULL_WIDTH_OFFSET: u32 = 0xFF01 - 0x21;
+
+impl CellTransform {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::Trim => text.trim().to_string(),
+            Self::Uppercase => text.to_uppercase(),
+            Self::Lowercase => text.to_lowercase(),
+            Self::HalfWidth => text
+                .chars()
+                .map(|c| match c {
+                    '\u{3000}' => ' ',
+                    '\u{FF01}'..='\u{FF5E}' => {
+                        char::from_u32(c as u32 - FULL_WIDTH_OFFSET).unwrap_or(c)
+                    }
+                    _ => c,
+                })
+                .collect(),
+            Self::FullWidth => text
+                .chars()
+                .map(|c| match c {
+                    ' ' => '\u{3000}',
+                    '!'..='~' => char::from_u32(c as u32 + FULL_WIDTH_OFFSET).unwrap_or(c),
+                    _ => c,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TransformOptions {
+    /// Only this sheet; every sheet when omitted.
+    pub sheet_id: Option<String>,
+    /// Only these columns, by letter (`["A", "C"]`).
+    pub columns: Option<Vec<String>>,
+    /// Only the cells inside this rectangle.
+    pub range: Option<CellRange>,
+    /// Compute the result without writing the file.
+    pub dry_run: bool,
+    /// How many changed cells to include in `preview`; 100 when omitted.
+    pub max_preview: Option<usize>,
+    /// Decrypts an encrypted book and re-encrypts it when writing.
+    pub passphrase: Option<String>,
+}
+
+const DEFAULT_MAX_PREVIEW: usize = 100;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformedCell {
+    pub sheet_id: String,
+    /// A1-style address such as `"B3"`.
+    pub cell: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformResult {
+    /// Cells whose value the transforms changed.
+    pub changed: usize,
+    /// The first changed cells in sheet, row, column order.
+    pub preview: Vec<TransformedCell>,
+    /// The book file was rewritten; never for a dry run or without changes.
+    pub written: bool,
+}
+
+/// Which cells of which sheets the options select.
+struct Selection {
+    sheet_id: Option<String>,
+    columns: Option<HashSet<u32>>,
+    range: Option<(RangeInclusive<u32>, RangeInclusive<u32>)>,
+}
+
+impl Selection {
+    fn new(options: &TransformOptions) -> WorkspaceResult<Self> {
+        let columns = options
+            .columns
+            .as_ref()
+            .map(|labels| {
+                labels
+                    .iter()
+                    .map(|label| {
+                        column_index(&label.trim().to_ascii_uppercase()).ok_or_else(|| {
+                            WorkspaceError::InvalidOption {
+                                name: "columns",
+                                message: format!("{:?} is not a column like \"A\"", label),
+                            }
+                        })
+                    })
+                    .collect::<WorkspaceResult<HashSet<u32>>>()
+            })
+            .transpose()?;
+        Ok(Self {
+            sheet_id: options.sheet_id.clone(),
+            columns,
+            range: options.range.as_ref().map(CellRange::bounds).transpose()?,
+        })
+    }
+
+    fn includes(&self, row: u32, column: u32) -> bool {
+        self.columns
+            .as_ref()
+            .is_none_or(|columns| columns.contains(&column))
+            && self
+                .range
+                .as_ref()
+                .is_none_or(|(rows, columns)| rows.contains(&row) && columns.contains(&column))
+    }
+}
+
+/// Applies `transforms` in order to the selected string cells of `book` in
+/// place, collecting up to `preview_limit` changed cells.
+fn transform_book(
+    book: &mut Value,
+    transforms: &[CellTransform],
+    selection: &Selection,
+    preview_limit: usize,
+    result: &mut TransformResult,
+) {
+    let sheets = book["sheets"].as_array_mut().into_iter().flatten();
+    let sheets = sheets.filter(|sheet| {
+        selection
+            .sheet_id
+            .as_ref()
+            .is_none_or(|id| sheet["id"] == id.as_str())
+    });
+    for sheet in sheets {
+        let sheet_id = sheet["id"].as_str().unwrap_or_default().to_string();
+        let Some(rows) = sheet["rows"].as_object_mut() else {
+            continue;
+        };
+        let mut row_keys: Vec<(u32, String)> = rows
+            .keys()
+            .filter_map(|key| Some((row_index(key)?, key.clone())))
+            .collect();
+        row_keys.sort();
+        for (row, row_key) in row_keys {
+            let Some(cells) = rows[&row_key].as_object_mut() else {
+                continue;
+            };
+            let mut columns: Vec<(u32, String)> = cells
+                .keys()
+                .filter_map(|key| Some((column_index(key)?, key.clone())))
+                .filter(|(column, _)| selection.includes(row, *column))
+                .collect();
+            columns.sort();
+            for (_, column_key) in columns {
+                let cell = &mut cells[&column_key];
+                // Numbers and booleans are never touched.
+                let Some(old) = cell["value"].as_str() else {
+                    continue;
+                };
+                let new = transforms
+                    .iter()
+                    .fold(old.to_string(), |text, transform| transform.apply(&text));
+                if new == old {
+                    continue;
+                }
+                result.changed += 1;
+                if result.preview.len() < preview_limit {
+                    result.preview.push(TransformedCell {
+                        sheet_id: sheet_id.clone(),
+                        cell: format!("{}{}", column_key, row_key),
+                        old: old.to_string(),
+                        new: new.clone(),
+                    });
+                }
+                cell["value"] = json!(new);
+            }
+        }
+    }
+}
+
+/// Applies `transforms` one after another to the string cells of a book
+/// file and rewrites it, keeping its line endings and encryption. Number
+/// and boolean cells are left as they are. With `dryRun` only reports what
+/// would change.
+#[tauri::command]
+pub fn transform_book_cells(
+    book_file_path: String,
+    transforms: Vec<CellTransform>,
+    options: Option<TransformOptions>,
+) -> WorkspaceResult<TransformResult> {
+    let options = options.unwrap_or_default();
+    let selection = Selection::new(&options)?;
+    let path = Path::new(&book_file_path);
+    let passphrase = options.passphrase.as_deref();
+    let mut book = load_book(path, passphrase, SizeLimits::default().book)?;
+
+    let mut result = TransformResult::default();
+    let preview_limit = options.max_preview.unwrap_or(DEFAULT_MAX_PREVIEW);
+    transform_book(
+        &mut book.data,
+        &transforms,
+        &selection,
+        preview_limit,
+        &mut result,
+    );
+    if options.dry_run || result.changed == 0 {
+        return Ok(result);
+    }
+    let encoding = FileEncoding {
+        passphrase: passphrase.filter(|_| is_encrypted_file(path)),
+        style: book.text_style.unwrap_or_default(),
+        ..Default::default()
+    };
+    write_tracked(path, &book.data, encoding)?;
+    result.written = true;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+
+    fn write_book(dir: &Path) -> String {
+        let path = dir.join("book.json");
+        let text = |value: &str| json!({ "value": value, "type": "string" });
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet",
+                "gridSize": { "rows": 10, "cols": 5 },
+                "rows": {
+                    "1": { "A": text("  ａｂｃ１２３　"), "B": text(" keep ") },
+                    "2": {
+                        "A": text("Done"),
+                        "C": { "value": 42, "type": "number" },
+                        "D": { "value": true, "type": "boolean" }
+                    }
+                }
+            }]
+        });
+        write_json_file(&path, &book, FileEncoding::default()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn transforms_apply_in_order_to_string_cells() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_book(dir.path());
+        let transforms = vec![
+            CellTransform::HalfWidth,
+            CellTransform::Trim,
+            CellTransform::Uppercase,
+        ];
+        let only_a = |dry_run| TransformOptions {
+            columns: Some(vec!["a".into()]),
+            dry_run,
+            ..Default::default()
+        };
+
+        let preview =
+            transform_book_cells(path.clone(), transforms.clone(), Some(only_a(true))).unwrap();
+        assert_eq!(preview.changed, 2);
+        assert!(!preview.written);
+        assert_eq!(preview.preview[0].cell, "A1");
+        assert_eq!(preview.preview[0].new, "ABC123");
+        let before = read_json_file(Path::new(&path)).unwrap();
+        assert_eq!(
+            before["sheets"][0]["rows"]["1"]["A"]["value"],
+            "  ａｂｃ１２３　"
+        );
+
+        let result = transform_book_cells(path.clone(), transforms, Some(only_a(false))).unwrap();
+        assert!(result.written);
+        let rows = &read_json_file(Path::new(&path)).unwrap()["sheets"][0]["rows"];
+        assert_eq!(rows["1"]["A"]["value"], "ABC123");
+        assert_eq!(rows["2"]["A"]["value"], "DONE");
+        assert_eq!(rows["1"]["B"]["value"], " keep ");
+        assert_eq!(rows["2"]["C"]["value"], 42);
+        assert_eq!(rows["2"]["D"]["value"], true);
+    }
+
+    #[test]
+    fn range_limits_cells_and_width_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_book(dir.path());
+        let options = TransformOptions {
+            range: Some(CellRange {
+                start: "B1".into(),
+                end: "D2".into(),
+            }),
+            ..Default::default()
+        };
+        let result =
+            transform_book_cells(path.clone(), vec![CellTransform::Trim], Some(options)).unwrap();
+        assert_eq!(result.changed, 1);
+        assert_eq!(result.preview[0].cell, "B1");
+
+        let full = CellTransform::FullWidth.apply("Ab 1!");
+        assert_eq!(full, "Ａｂ　１！");
+        assert_eq!(CellTransform::HalfWidth.apply(&full), "Ab 1!");
+        assert!(matches!(
+            transform_book_cells(
+                path,
+                vec![],
+                Some(TransformOptions {
+                    columns: Some(vec!["1".into()]),
+                    ..Default::default()
+                })
+            ),
+            Err(WorkspaceError::InvalidOption {
+                name: "columns",
+                ..
+            })
+        ));
+    }
+}
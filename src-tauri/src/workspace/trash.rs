@@ -0,0 +1,296 @@
+//! Books deleted with `to_trash` are kept in
+//! `<workspace>/.sheet-up-trash/<trashId>/`: `entry.json` holds the removed
+//! workspace entry, and the book file, if it was moved, sits next to it under
+//! its original file name.
+
+use super::books::{split_book_file_name, unique_data_path};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{read_json_file, read_workspace_json, write_json_file, FileEncoding};
+use super::paths::{normalize_lexically, resolve_data_path, workspace_dir_of};
+use super::readonly::ensure_writable;
+use super::schema::validate_workspace;
+use super::{rename_tracked, write_tracked};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const TRASH_DIR: &str = ".sheet-up-trash";
+const ENTRY_FILE_NAME: &str = "entry.json";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+    pub trash_id: String,
+    pub book_id: Option<String>,
+    pub name: Option<String>,
+    pub data_path: Option<String>,
+    pub deleted_at: Option<String>,
+    /// `false` when only the workspace entry was trashed because the file was
+    /// shared with another book.
+    pub has_file: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreFromTrashResult {
+    pub book_id: String,
+    /// Differs from the original when that path has been taken since.
+    pub data_path: String,
+    pub file_path: String,
+}
+
+fn entry_dir(workspace_dir: &Path, trash_id: &str) -> WorkspaceResult<PathBuf> {
+    let dir = workspace_dir.join(TRASH_DIR).join(trash_id);
+    // Ids are plain directory names; anything path-like cannot name an entry.
+    if trash_id.is_empty() || trash_id.contains(['/', '\\', '.']) || !dir.is_dir() {
+        return Err(WorkspaceError::TrashEntryNotFound {
+            path: workspace_dir.join(TRASH_DIR).display().to_string(),
+            trash_id: trash_id.to_string(),
+        });
+    }
+    Ok(dir)
+}
+
+/// Records `book_ref` in a new trash entry and moves `file` into it. Returns
+/// the entry's id.
+pub(super) fn move_to_trash(
+    workspace_dir: &Path,
+    book_ref: &Value,
+    file: Option<&Path>,
+) -> WorkspaceResult<String> {
+    let trash_id = uuid::Uuid::new_v4().to_string();
+    let dir = workspace_dir.join(TRASH_DIR).join(&trash_id);
+    fs::create_dir_all(&dir).map_err(|err| WorkspaceError::io("create", &dir, err))?;
+
+    let file_name = file
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().into_owned());
+    let entry = json!({
+        "deletedAt": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "book": book_ref,
+        "fileName": file_name
+    });
+    let stored = write_json_file(&dir.join(ENTRY_FILE_NAME), &entry, FileEncoding::default())
+        .and_then(|()| match (file, &file_name) {
+            (Some(file), Some(name)) => rename_tracked(file, &dir.join(name)),
+            _ => Ok(()),
+        });
+    if let Err(err) = stored {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(err);
+    }
+    Ok(trash_id)
+}
+
+/// Moves the trashed file back to `destination` and drops the entry.
+pub(super) fn take_from_trash(
+    workspace_dir: &Path,
+    trash_id: &str,
+    destination: &Path,
+) -> WorkspaceResult<()> {
+    let dir = entry_dir(workspace_dir, trash_id)?;
+    let entry = read_json_file(&dir.join(ENTRY_FILE_NAME))?;
+    if let Some(name) = entry["fileName"].as_str() {
+        rename_tracked(&dir.join(name), destination)?;
+    }
+    fs::remove_dir_all(&dir).map_err(|err| WorkspaceError::io("delete", &dir, err))
+}
+
+#[tauri::command]
+pub fn list_trash(workspace_path: String) -> WorkspaceResult<Vec<TrashEntry>> {
+    let trash_dir = workspace_dir_of(Path::new(&workspace_path)).join(TRASH_DIR);
+    let dirs = match fs::read_dir(&trash_dir) {
+        Ok(dirs) => dirs,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(WorkspaceError::io("read", &trash_dir, err)),
+    };
+    let text = |value: &Value| value.as_str().map(str::to_string);
+    let mut entries: Vec<TrashEntry> = dirs
+        .filter_map(Result::ok)
+        .filter_map(|dir| {
+            let entry = read_json_file(&dir.path().join(ENTRY_FILE_NAME)).ok()?;
+            Some(TrashEntry {
+                trash_id: dir.file_name().to_string_lossy().into_owned(),
+                book_id: text(&entry["book"]["id"]),
+                name: text(&entry["book"]["name"]),
+                data_path: text(&entry["book"]["dataPath"]),
+                deleted_at: text(&entry["deletedAt"]),
+                has_file: entry["fileName"].is_string(),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    Ok(entries)
+}
+
+/// Puts a trashed book back at the end of its folder (or the root, if the
+/// folder is gone). The file returns to its original `dataPath` unless that
+/// is taken, in which case it gets a fresh name under `books/`.
+#[tauri::command]
+pub fn restore_from_trash(
+    workspace_path: String,
+    trash_id: String,
+) -> WorkspaceResult<RestoreFromTrashResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let mut workspace = read_workspace_json(&workspace_path)?;
+    ensure_writable(&workspace_path, &workspace)?;
+    validate_workspace(&workspace)?;
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let dir = entry_dir(&workspace_dir, &trash_id)?;
+    let entry = read_json_file(&dir.join(ENTRY_FILE_NAME))?;
+
+    let mut book_ref = entry["book"].clone();
+    let (Some(book_id), Some(original)) = (
+        book_ref["id"].as_str().map(str::to_string),
+        book_ref["dataPath"].as_str().map(str::to_string),
+    ) else {
+        return Err(WorkspaceError::invalid_schema(format!(
+            "Trash entry {} has no book id or dataPath",
+            trash_id
+        )));
+    };
+    let books = workspace["books"]
+        .as_array()
+        .expect("validated workspace has a books array");
+    if books.iter().any(|book| book["id"] == book_id.as_str()) {
+        return Err(WorkspaceError::DuplicateBookId {
+            path: workspace_path.display().to_string(),
+            book_id,
+        });
+    }
+
+    let index = books.len();
+    let data_path = match entry["fileName"].as_str() {
+        Some(file_name) => {
+            let referenced = books.iter().enumerate().any(|(other, book)| {
+                book["dataPath"].as_str().is_some_and(|path| {
+                    let resolve = |path, index| {
+                        resolve_data_path(&workspace_dir, path, index)
+                            .ok()
+                            .and_then(|path| normalize_lexically(&path))
+                    };
+                    resolve(path, other) == resolve(&original, index)
+                })
+            });
+            let free = resolve_data_path(&workspace_dir, &original, index)
+                .is_ok_and(|path| !path.exists());
+            if free && !referenced {
+                original
+            } else {
+                let (stem, extension) = split_book_file_name(file_name);
+                unique_data_path(&workspace_dir, &workspace, stem, extension)
+            }
+        }
+        None => original,
+    };
+    let file_path = resolve_data_path(&workspace_dir, &data_path, index)?;
+
+    let folder_id = book_ref["folderId"].clone();
+    let folder_exists = workspace["folders"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|folder| folder["id"] == folder_id);
+    let folder_id = if folder_exists {
+        folder_id
+    } else {
+        Value::Null
+    };
+    let order = books
+        .iter()
+        .filter(|book| book["folderId"] == folder_id)
+        .count();
+    book_ref["folderId"] = folder_id;
+    book_ref["order"] = json!(order);
+    book_ref["dataPath"] = json!(data_path);
+    workspace["books"]
+        .as_array_mut()
+        .expect("validated workspace has a books array")
+        .push(book_ref);
+    if let Some(meta) = workspace["workspace"].as_object_mut() {
+        meta.insert(
+            "updatedAt".into(),
+            json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+    }
+
+    let has_file = entry["fileName"].is_string();
+    if has_file {
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| WorkspaceError::io("create", parent, err))?;
+        }
+        let name = entry["fileName"].as_str().unwrap_or_default();
+        rename_tracked(&dir.join(name), &file_path)?;
+        if let Err(err) = write_tracked(&workspace_path, &workspace, FileEncoding::default()) {
+            let _ = rename_tracked(&file_path, &dir.join(name));
+            return Err(err);
+        }
+    } else {
+        write_tracked(&workspace_path, &workspace, FileEncoding::default())?;
+    }
+    fs::remove_dir_all(&dir).map_err(|err| WorkspaceError::io("delete", &dir, err))?;
+
+    Ok(RestoreFromTrashResult {
+        book_id,
+        data_path,
+        file_path: file_path.to_string_lossy().into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::books::delete_book;
+
+    #[test]
+    fn trashed_books_can_be_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join("workspace.json");
+        let workspace = json!({
+            "workspace": {},
+            "folders": [{ "id": "folder-1" }],
+            "books": [
+                { "id": "book-1", "name": "a.json", "folderId": "folder-1", "order": 0,
+                  "dataPath": "books/a.json" },
+                { "id": "book-2", "name": "b.json", "folderId": "folder-1", "order": 1,
+                  "dataPath": "books/b.json" }
+            ]
+        });
+        write_json_file(&workspace_path, &workspace, FileEncoding::default()).unwrap();
+        let book_a = dir.path().join("books/a.json");
+        write_json_file(&book_a, &json!({ "a": 1 }), FileEncoding::default()).unwrap();
+        let path = workspace_path.to_string_lossy().into_owned();
+
+        let deleted = delete_book(path.clone(), "book-1".into(), true).unwrap();
+        let trash_id = deleted.trash_id.unwrap();
+        assert!(deleted.file_removed && !book_a.exists());
+        let listed = list_trash(path.clone()).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].book_id.as_deref(), Some("book-1"));
+        assert!(listed[0].has_file);
+
+        // Something else took the old path in the meantime.
+        fs::write(&book_a, "{}").unwrap();
+        let restored = restore_from_trash(path.clone(), trash_id.clone()).unwrap();
+        assert_eq!(restored.data_path, "books/a (2).json");
+        assert_eq!(
+            read_json_file(Path::new(&restored.file_path)).unwrap(),
+            json!({ "a": 1 })
+        );
+        let workspace = read_json_file(&workspace_path).unwrap();
+        assert_eq!(workspace["books"][1]["id"], "book-1");
+        assert_eq!(workspace["books"][1]["order"], 1);
+        assert!(list_trash(path.clone()).unwrap().is_empty());
+
+        assert!(matches!(
+            restore_from_trash(path.clone(), trash_id),
+            Err(WorkspaceError::TrashEntryNotFound { .. })
+        ));
+        assert!(matches!(
+            restore_from_trash(path, "..".into()),
+            Err(WorkspaceError::TrashEntryNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+//! `trim_empty_book`: drops empty cells from a book and shrinks each sheet's
+//! `gridSize` to the last row and column holding data. No cell moves: the
+//! cells kept stay at their addresses, so empty rows and columns before or
+//! between data are kept too, and only those after the last data go.
+
+use super::cells::{column_index, row_index};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_encrypted_file, FileEncoding, SizeLimits};
+use super::{load_book, write_tracked};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TrimOptions {
+    /// The sheet to trim; every sheet when omitted.
+    pub sheet_id: Option<String>,
+    /// Count cells holding only whitespace as empty, and remove them.
+    pub whitespace_is_empty: bool,
+    /// Decrypts an encrypted book and re-encrypts it when writing.
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetTrim {
+    pub sheet_id: String,
+    /// `gridSize` of the sheet after trimming, at least 1 x 1.
+    pub rows: u64,
+    pub cols: u64,
+    /// `gridSize` before, or 0 where the sheet had none.
+    pub previous_rows: u64,
+    pub previous_cols: u64,
+    /// Empty cells removed.
+    pub removed: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrimResult {
+    pub sheets: Vec<SheetTrim>,
+    /// Empty cells removed across all sheets.
+    pub removed: usize,
+}
+
+/// A cell is empty when it has nothing but a `value` (and its `type`) and
+/// that value is missing, `null` or `""` - or only whitespace, with
+/// `whitespace_is_empty`. Cells with a formula, style or anything else are
+/// kept whatever their value.
+fn is_empty_cell(cell: &Value, whitespace_is_empty: bool) -> bool {
+    let Some(cell) = cell.as_object() else {
+        return cell.is_null();
+    };
+    if cell.keys().any(|key| key != "value" && key != "type") {
+        return false;
+    }
+    match cell.get("value") {
+        None | Some(Value::Null) => true,
+        Some(Value::String(text)) if whitespace_is_empty => text.trim().is_empty(),
+        Some(Value::String(text)) => text.is_empty(),
+        Some(_) => false,
+    }
+}
+
+fn trim_sheet(sheet: &mut Value, whitespace_is_empty: bool) -> SheetTrim {
+    let previous_rows = sheet["gridSize"]["rows"].as_u64().unwrap_or(0);
+    let previous_cols = sheet["gridSize"]["cols"].as_u64().unwrap_or(0);
+    let mut removed = 0;
+    let (mut last_row, mut last_col) = (1, 1);
+    if let Some(rows) = sheet["rows"].as_object_mut() {
+        for (row, cells) in rows.iter_mut() {
+            let Some(cells) = cells.as_object_mut() else {
+                continue;
+            };
+            let before = cells.len();
+            cells.retain(|_, cell| !is_empty_cell(cell, whitespace_is_empty));
+            removed += before - cells.len();
+            let cols = cells.keys().filter_map(|key| column_index(key));
+            if let Some(col) = cols.max() {
+                last_col = last_col.max(col);
+                if let Some(row) = row_index(row) {
+                    last_row = last_row.max(row);
+                }
+            }
+        }
+        rows.retain(|_, cells| cells.as_object().is_none_or(|cells| !cells.is_empty()));
+    }
+    let (rows, cols) = (u64::from(last_row), u64::from(last_col));
+    if let Some(grid_size) = sheet["gridSize"].as_object_mut() {
+        grid_size.insert("rows".into(), json!(rows));
+        grid_size.insert("cols".into(), json!(cols));
+    }
+    SheetTrim {
+        sheet_id: sheet["id"].as_str().unwrap_or_default().to_string(),
+        rows,
+        cols,
+        previous_rows,
+        previous_cols,
+        removed,
+    }
+}
+
+/// Removes the empty cells of the book at `book_file_path` (see
+/// [`is_empty_cell`]) and sets each sheet's `gridSize` to the smallest size
+/// from `A1` that holds every remaining cell. Cells are never moved or
+/// renumbered, so formulas and references to them stay valid.
+#[tauri::command]
+pub fn trim_empty_book(
+    book_file_path: String,
+    options: Option<TrimOptions>,
+) -> WorkspaceResult<TrimResult> {
+    let options = options.unwrap_or_default();
+    let path = Path::new(&book_file_path);
+    let passphrase = options.passphrase.as_deref();
+    let mut book = load_book(path, passphrase, SizeLimits::default().book)?;
+    let sheets: Vec<&mut Value> = book.data["sheets"]
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter(|sheet| {
+            options
+                .sheet_id
+                .as_ref()
+                .is_none_or(|id| sheet["id"] == id.as_str())
+        })
+        .collect();
+    if sheets.is_empty() {
+        if let Some(sheet_id) = options.sheet_id {
+            return Err(WorkspaceError::SheetNotFound {
+                path: book_file_path,
+                sheet_id,
+            });
+        }
+    }
+    let sheets: Vec<SheetTrim> = sheets
+        .into_iter()
+        .map(|sheet| trim_sheet(sheet, options.whitespace_is_empty))
+        .collect();
+    let encoding = FileEncoding {
+        passphrase: passphrase.filter(|_| is_encrypted_file(path)),
+        style: book.text_style.unwrap_or_default(),
+        ..Default::default()
+    };
+    write_tracked(path, &book.data, encoding)?;
+    Ok(TrimResult {
+        removed: sheets.iter().map(|sheet| sheet.removed).sum(),
+        sheets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file};
+
+    #[test]
+    fn drops_empty_cells_and_shrinks_the_grid_without_moving_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let cell = |value: Value| json!({ "value": value, "type": "string" });
+        let book = json!({
+            "schemaVersion": "1.0.0",
+            "book": { "id": "book-1", "name": "Book" },
+            "sheets": [{
+                "id": "sheet-1",
+                "name": "Sheet1",
+                "gridSize": { "rows": 1000, "cols": 50 },
+                "rows": {
+                    "2": { "B": cell(json!("data")), "Z": cell(json!("")) },
+                    "4": { "C": cell(json!("  ")) },
+                    "900": { "A": cell(Value::Null), "E": { "value": null, "style": { "bold": true } } }
+                }
+            }]
+        });
+        write_json_file(&path, &book, FileEncoding::default()).unwrap();
+        let path_string = path.to_string_lossy().into_owned();
+
+        let result = trim_empty_book(path_string.clone(), None).unwrap();
+        assert_eq!(result.removed, 2);
+        let sheet = &result.sheets[0];
+        assert_eq!((sheet.previous_rows, sheet.previous_cols), (1000, 50));
+        // The styled cell at E900 is not empty.
+        assert_eq!((sheet.rows, sheet.cols), (900, 5));
+        let written = read_json_file(&path).unwrap();
+        let rows = &written["sheets"][0]["rows"];
+        assert_eq!(rows["2"], json!({ "B": cell(json!("data")) }));
+        assert_eq!(rows["4"]["C"]["value"], "  ");
+        assert!(rows["900"].get("A").is_none());
+
+        let mut styled = written.clone();
+        styled["sheets"][0]["rows"]
+            .as_object_mut()
+            .unwrap()
+            .remove("900");
+        write_json_file(&path, &styled, FileEncoding::default()).unwrap();
+        let options = TrimOptions {
+            whitespace_is_empty: true,
+            ..Default::default()
+        };
+        let result = trim_empty_book(path_string.clone(), Some(options)).unwrap();
+        assert_eq!(result.removed, 1);
+        assert_eq!((result.sheets[0].rows, result.sheets[0].cols), (2, 2));
+        let written = read_json_file(&path).unwrap();
+        assert_eq!(
+            written["sheets"][0]["rows"],
+            json!({ "2": { "B": cell(json!("data")) } })
+        );
+
+        let options = TrimOptions {
+            sheet_id: Some("missing".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            trim_empty_book(path_string, Some(options)),
+            Err(WorkspaceError::SheetNotFound { .. })
+        ));
+    }
+}
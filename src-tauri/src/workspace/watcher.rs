@@ -0,0 +1,221 @@
+use super::backup::BACKUP_DIR;
+use super::cache;
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::formats::book_format;
+use super::io::modified_millis;
+use super::paths::workspace_dir_of;
+use super::trash::TRASH_DIR;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use tauri::{AppHandle, Emitter, State};
+
+pub const FILE_CHANGED_EVENT: &str = "workspace-file-changed";
+
+/// Last change this process made to a file. Watcher events for it are
+/// ignored until the file no longer looks the way we left it.
+enum SelfWrite {
+    Pending,
+    Written(u64),
+    Removed,
+}
+
+static SELF_WRITES: LazyLock<Mutex<HashMap<PathBuf, SelfWrite>>> = LazyLock::new(Default::default);
+
+/// Holds the active watcher; dropping it stops watching.
+#[derive(Default)]
+pub struct WatcherState(Mutex<Option<RecommendedWatcher>>);
+
+impl WatcherState {
+    fn replace(&self, watcher: Option<RecommendedWatcher>) {
+        *self.0.lock().unwrap_or_else(|err| err.into_inner()) = watcher;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChangedEvent {
+    pub kind: &'static str,
+    pub paths: Vec<String>,
+}
+
+/// Canonical form used as the `SELF_WRITES` key; works for files that do
+/// not exist yet as long as their directory does.
+fn identity(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn record(path: &Path, write: Option<SelfWrite>) {
+    let mut writes = SELF_WRITES.lock().unwrap_or_else(|err| err.into_inner());
+    match write {
+        Some(write) => writes.insert(identity(path), write),
+        None => writes.remove(&identity(path)),
+    };
+}
+
+pub fn begin_self_write(path: &Path) {
+    record(path, Some(SelfWrite::Pending));
+}
+
+/// `modified` is the file's mtime after a successful write, `None` if the
+/// write failed.
+pub fn end_self_write(path: &Path, modified: Option<u64>) {
+    record(path, modified.map(SelfWrite::Written));
+}
+
+pub fn self_remove(path: &Path) {
+    record(path, Some(SelfWrite::Removed));
+}
+
+fn is_self_write(path: &Path) -> bool {
+    let writes = SELF_WRITES.lock().unwrap_or_else(|err| err.into_inner());
+    match writes.get(&identity(path)) {
+        Some(SelfWrite::Pending) => true,
+        Some(SelfWrite::Written(modified)) => {
+            modified_millis(path).ok().flatten() == Some(*modified)
+        }
+        Some(SelfWrite::Removed) => !path.exists(),
+        None => false,
+    }
+}
+
+/// Workspace and book files are JSON or another registered book format;
+/// temp files, backups and the trash are noise.
+fn is_workspace_file(path: &Path) -> bool {
+    book_format(path).is_ok()
+        && !path.components().any(|component| {
+            [BACKUP_DIR, TRASH_DIR]
+                .iter()
+                .any(|dir| component == Component::Normal(dir.as_ref()))
+        })
+}
+
+fn changed_event(event: Event) -> Option<FileChangedEvent> {
+    let kind = match event.kind {
+        EventKind::Create(_) => "created",
+        EventKind::Modify(_) => "modified",
+        EventKind::Remove(_) => "removed",
+        _ => return None,
+    };
+    let paths: Vec<String> = event
+        .paths
+        .iter()
+        .filter(|path| is_workspace_file(path) && !is_self_write(path))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    (!paths.is_empty()).then_some(FileChangedEvent { kind, paths })
+}
+
+fn watch_error(path: &Path, err: notify::Error) -> WorkspaceError {
+    match err.kind {
+        notify::ErrorKind::Io(err) => WorkspaceError::io("watch", path, err),
+        notify::ErrorKind::PathNotFound => WorkspaceError::NotFound {
+            path: path.display().to_string(),
+        },
+        kind => WorkspaceError::Io {
+            action: "watch",
+            path: path.display().to_string(),
+            message: notify::Error::new(kind).to_string(),
+        },
+    }
+}
+
+/// Emits `workspace-file-changed` whenever a JSON file under the workspace
+/// directory is changed by something other than this app, and drops every
+/// changed file from the load cache. Replaces any previous watch.
+#[tauri::command]
+pub fn watch_workspace(
+    app: AppHandle,
+    state: State<'_, WatcherState>,
+    path: String,
+) -> WorkspaceResult<()> {
+    let workspace_dir = workspace_dir_of(Path::new(&path));
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        cache::forget(&event.paths);
+        if let Some(payload) = changed_event(event) {
+            let _ = app.emit(FILE_CHANGED_EVENT, payload);
+        }
+    })
+    .map_err(|err| watch_error(&workspace_dir, err))?;
+    watcher
+        .watch(&workspace_dir, RecursiveMode::Recursive)
+        .map_err(|err| watch_error(&workspace_dir, err))?;
+
+    state.replace(Some(watcher));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unwatch_workspace(state: State<'_, WatcherState>) {
+    state.replace(None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind};
+
+    fn event(kind: EventKind, path: &Path) -> Event {
+        Event::new(kind).add_path(path.to_path_buf())
+    }
+
+    #[test]
+    fn ignores_own_writes_until_changed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+
+        begin_self_write(&path);
+        fs::write(&path, "{}").unwrap();
+        assert!(changed_event(event(EventKind::Create(CreateKind::File), &path)).is_none());
+
+        let modified = modified_millis(&path).unwrap();
+        end_self_write(&path, modified);
+        assert!(changed_event(event(EventKind::Modify(ModifyKind::Any), &path)).is_none());
+
+        end_self_write(&path, Some(0));
+        let changed = changed_event(event(EventKind::Modify(ModifyKind::Any), &path)).unwrap();
+        assert_eq!(changed.kind, "modified");
+        assert_eq!(changed.paths, vec![path.to_string_lossy().into_owned()]);
+    }
+
+    #[test]
+    fn ignores_temp_and_backup_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            dir.path().join(".book.json.4242.1f3a9c0d.tmp"),
+            dir.path().join(BACKUP_DIR).join("book.json"),
+            dir.path().join(TRASH_DIR).join("id").join("book.json"),
+        ] {
+            assert!(changed_event(event(EventKind::Create(CreateKind::File), &path)).is_none());
+        }
+    }
+
+    #[test]
+    fn reports_books_of_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["book.json", "book.json.gz", "book.msgpack", "Book.CSV"] {
+            let path = dir.path().join(name);
+            assert!(
+                changed_event(event(EventKind::Create(CreateKind::File), &path)).is_some(),
+                "{}",
+                name
+            );
+        }
+        let notes = dir.path().join("notes.txt");
+        assert!(changed_event(event(EventKind::Create(CreateKind::File), &notes)).is_none());
+    }
+}
@@ -0,0 +1,418 @@
+//! `export_workspace_zip` / `import_workspace_zip`: the workspace as a zip
+//! archive holding `workspace.json`, the manifest and every book file at
+//! its path relative to the workspace directory. Files go in and come out
+//! byte for byte, so compressed and encrypted books stay that way.
+
+use super::duplicate::{files_to_copy, move_into_place, staging_dir_for};
+use super::error::{WorkspaceError, WorkspaceResult};
+use super::io::{is_compressed, is_encrypted_file, read_workspace_json, write_atomic, SizeLimits};
+use super::manifest::MANIFEST_FILE_NAME;
+use super::paths::workspace_dir_of;
+use super::schema::validate_workspace;
+use super::{book_file_path, WORKSPACE_FILE_NAME};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+fn zip_error(path: &Path, err: ZipError) -> WorkspaceError {
+    match err {
+        ZipError::Io(err) => WorkspaceError::io("read", path, err),
+        other => WorkspaceError::InvalidArchive {
+            path: path.display().to_string(),
+            message: other.to_string(),
+        },
+    }
+}
+
+/// `relative` as a zip entry name, which always uses `/`.
+fn entry_name(relative: &Path) -> String {
+    let parts: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.join("/")
+}
+
+/// The relative path an entry name stands for, or `None` for one that is
+/// absolute, has a drive prefix or backslash, or climbs with `..`, whether
+/// or not it would end up inside the destination.
+fn entry_path(name: &str) -> Option<PathBuf> {
+    if name.starts_with('/') || name.contains(['\\', '\0']) {
+        return None;
+    }
+    let mut path = PathBuf::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            _ if part.contains(':') => return None,
+            _ => path.push(part),
+        }
+    }
+    // Whatever the platform makes of it, it must be plain names only.
+    let plain = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (plain && !path.as_os_str().is_empty()).then_some(path)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportZipResult {
+    pub zip_path: String,
+    /// Files stored, including `workspace.json` and the manifest.
+    pub files: usize,
+}
+
+/// Copies `source` into the current entry of `zip`. A failure to read the
+/// source is kept in `failure`, so it is not mistaken for one writing the
+/// archive.
+fn copy_into<W: Write + io::Seek>(
+    source: &Path,
+    zip: &mut ZipWriter<W>,
+    failure: &mut Option<WorkspaceError>,
+) -> io::Result<()> {
+    let mut fail = |err: io::Error| {
+        let message = err.to_string();
+        *failure = Some(WorkspaceError::io("read", source, err));
+        io::Error::other(message)
+    };
+    let mut reader = File::open(source).map_err(&mut fail)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).map_err(&mut fail)?;
+        if read == 0 {
+            return Ok(());
+        }
+        zip.write_all(&buffer[..read])?;
+    }
+}
+
+/// Packs `workspace.json`, the checksum manifest and every book file of the
+/// workspace into a zip archive at `zip_path`, keeping their paths relative
+/// to the workspace directory. Files are streamed through one at a time,
+/// and the archive only appears once it is complete. Books already gzip
+/// compressed or encrypted are stored as they are.
+#[tauri::command]
+pub fn export_workspace_zip(
+    workspace_path: String,
+    zip_path: String,
+) -> WorkspaceResult<ExportZipResult> {
+    let workspace_path = PathBuf::from(workspace_path);
+    let workspace_dir = workspace_dir_of(&workspace_path);
+    let zip_path = PathBuf::from(zip_path);
+    let workspace = read_workspace_json(&workspace_path)?;
+    validate_workspace(&workspace)?;
+
+    let mut files = vec![PathBuf::from(WORKSPACE_FILE_NAME)];
+    if workspace_dir.join(MANIFEST_FILE_NAME).is_file() {
+        files.push(PathBuf::from(MANIFEST_FILE_NAME));
+    }
+    files.extend(files_to_copy(&workspace)?);
+
+    let mut failure = None;
+    let written = write_atomic(&zip_path, |file| {
+        let mut zip = ZipWriter::new(file);
+        for relative in &files {
+            let source = workspace_dir.join(relative);
+            let packed = is_compressed(&source) || is_encrypted_file(&source);
+            let size = fs::metadata(&source).map_or(0, |metadata| metadata.len());
+            let options = SimpleFileOptions::default()
+                .compression_method(if packed {
+                    CompressionMethod::Stored
+                } else {
+                    CompressionMethod::Deflated
+                })
+                .large_file(size >= u64::from(u32::MAX));
+            zip.start_file(entry_name(relative), options)?;
+            copy_into(&source, &mut zip, &mut failure)?;
+        }
+        zip.finish()?;
+        Ok(())
+    });
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+    written.map_err(|err| WorkspaceError::io("write", &zip_path, err))?;
+    Ok(ExportZipResult {
+        zip_path: zip_path.to_string_lossy().into_owned(),
+        files: files.len(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportZipResult {
+    pub workspace_path: String,
+    /// Files extracted, including `workspace.json` and the manifest.
+    pub files: usize,
+}
+
+/// The files of an archive by entry index, checked before anything is
+/// extracted. Directory entries are skipped.
+fn archive_files(
+    zip_path: &Path,
+    archive: &mut ZipArchive<File>,
+) -> WorkspaceResult<Vec<(usize, PathBuf)>> {
+    let limits = SizeLimits::default();
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|err| zip_error(zip_path, err))?;
+        let unsafe_entry = || WorkspaceError::UnsafeArchiveEntry {
+            path: zip_path.display().to_string(),
+            entry: entry.name().to_string(),
+        };
+        let relative = entry_path(entry.name()).ok_or_else(unsafe_entry)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let limit = if relative == Path::new(WORKSPACE_FILE_NAME) {
+            limits.workspace
+        } else {
+            limits.book
+        };
+        if let Some(limit) = limit.filter(|limit| entry.size() > *limit) {
+            return Err(WorkspaceError::FileTooLarge {
+                path: format!("{}:{}", zip_path.display(), entry.name()),
+                size: entry.size(),
+                limit,
+            });
+        }
+        if !seen.insert(relative.clone()) {
+            return Err(WorkspaceError::InvalidArchive {
+                path: zip_path.display().to_string(),
+                message: format!("{} is stored more than once", entry.name()),
+            });
+        }
+        files.push((index, relative));
+    }
+    if !seen.contains(Path::new(WORKSPACE_FILE_NAME)) {
+        return Err(WorkspaceError::InvalidArchive {
+            path: zip_path.display().to_string(),
+            message: format!("no {} at the top level", WORKSPACE_FILE_NAME),
+        });
+    }
+    Ok(files)
+}
+
+/// Extracts an archive from `export_workspace_zip` into `dest_dir`.
+/// Entries that are absolute or climb out with `..` are refused before
+/// anything is written, as are archives without a valid `workspace.json`
+/// or missing a book it lists. Existing files are an `alreadyExists` error
+/// unless `overwrite` is set. Entries are streamed into a staging directory
+/// and moved in once all are extracted.
+#[tauri::command]
+pub fn import_workspace_zip(
+    zip_path: String,
+    dest_dir: String,
+    overwrite: Option<bool>,
+) -> WorkspaceResult<ImportZipResult> {
+    let zip_path = PathBuf::from(zip_path);
+    let dest_dir = PathBuf::from(dest_dir);
+    let file = File::open(&zip_path).map_err(|err| WorkspaceError::io("read", &zip_path, err))?;
+    let mut archive = ZipArchive::new(file).map_err(|err| zip_error(&zip_path, err))?;
+    let entries = archive_files(&zip_path, &mut archive)?;
+    let files: Vec<PathBuf> = entries
+        .iter()
+        .map(|(_, relative)| relative.clone())
+        .collect();
+    if !overwrite.unwrap_or(false) {
+        if let Some(existing) = files
+            .iter()
+            .map(|relative| dest_dir.join(relative))
+            .find(|target| target.exists())
+        {
+            return Err(WorkspaceError::AlreadyExists {
+                path: existing.display().to_string(),
+            });
+        }
+    }
+
+    let staging = staging_dir_for(&dest_dir);
+    let staged = (|| {
+        for (index, relative) in &entries {
+            let target = staging.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|err| WorkspaceError::io("create", parent, err))?;
+            }
+            let mut entry = archive
+                .by_index(*index)
+                .map_err(|err| zip_error(&zip_path, err))?;
+            let mut out =
+                File::create(&target).map_err(|err| WorkspaceError::io("write", &target, err))?;
+            // The recorded size was checked against the limits; an entry
+            // inflating past it is not trusted any further.
+            let recorded = entry.size();
+            let copied = io::copy(&mut (&mut entry).take(recorded + 1), &mut out)
+                .map_err(|err| WorkspaceError::io("extract", &target, err))?;
+            if copied > recorded {
+                return Err(WorkspaceError::InvalidArchive {
+                    path: zip_path.display().to_string(),
+                    message: format!("{} is larger than recorded", entry.name()),
+                });
+            }
+        }
+        let workspace = read_workspace_json(&staging.join(WORKSPACE_FILE_NAME))?;
+        validate_workspace(&workspace)?;
+        for (index, book_ref) in workspace["books"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let relative = book_file_path(Path::new(""), book_ref, index)?;
+            if !files.contains(&relative) {
+                return Err(WorkspaceError::InvalidArchive {
+                    path: zip_path.display().to_string(),
+                    message: format!("books[{}] file {} is missing", index, entry_name(&relative)),
+                });
+            }
+        }
+        move_into_place(&staging, &dest_dir, &files)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    staged?;
+
+    Ok(ImportZipResult {
+        workspace_path: dest_dir
+            .join(WORKSPACE_FILE_NAME)
+            .to_string_lossy()
+            .into_owned(),
+        files: files.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::io::{read_json_file, write_json_file, FileEncoding};
+    use crate::workspace::load_workspace_snapshot;
+    use serde_json::json;
+
+    fn write_workspace(dir: &Path) -> String {
+        let workspace_path = dir.join("workspace.json");
+        write_json_file(
+            &workspace_path,
+            &json!({
+                "schemaVersion": "1.0.0",
+                "books": [
+                    { "id": "book-1", "name": "One", "dataPath": "books/one.json" },
+                    { "id": "book-2", "name": "Two", "dataPath": "books/nested/two.json.gz" }
+                ]
+            }),
+            FileEncoding::default(),
+        )
+        .unwrap();
+        for (id, file) in [("book-1", "one.json"), ("book-2", "nested/two.json.gz")] {
+            let book =
+                json!({ "schemaVersion": "1.0.0", "book": { "id": id, "name": id }, "sheets": [] });
+            write_json_file(
+                &dir.join("books").join(file),
+                &book,
+                FileEncoding::default(),
+            )
+            .unwrap();
+        }
+        workspace_path.to_string_lossy().into_owned()
+    }
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trips_a_workspace_with_its_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = write_workspace(&dir.path().join("source"));
+        let zip_path = dir.path().join("out.zip");
+        let zip = zip_path.to_string_lossy().into_owned();
+        let exported = export_workspace_zip(workspace_path, zip.clone()).unwrap();
+        assert_eq!(exported.files, 3);
+
+        let dest = dir.path().join("restored");
+        let dest_dir = dest.to_string_lossy().into_owned();
+        let imported = import_workspace_zip(zip.clone(), dest_dir.clone(), None).unwrap();
+        assert_eq!(imported.files, 3);
+        let nested = read_json_file(&dest.join("books/nested/two.json.gz")).unwrap();
+        assert_eq!(nested["book"]["id"], "book-2");
+        let snapshot = load_workspace_snapshot(imported.workspace_path, None).unwrap();
+        assert_eq!(snapshot.books.len(), 2);
+        assert!(snapshot.failed.is_empty());
+
+        assert!(matches!(
+            import_workspace_zip(zip.clone(), dest_dir.clone(), None),
+            Err(WorkspaceError::AlreadyExists { .. })
+        ));
+        import_workspace_zip(zip, dest_dir, Some(true)).unwrap();
+    }
+
+    #[test]
+    fn refuses_entries_escaping_the_destination() {
+        for name in [
+            "../evil.json",
+            "/etc/evil.json",
+            "books/../../evil.json",
+            "C:/evil.json",
+            "books\\..\\evil.json",
+        ] {
+            assert_eq!(entry_path(name), None, "{}", name);
+        }
+        assert_eq!(
+            entry_path("./books//a.json"),
+            Some(PathBuf::from("books/a.json"))
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("evil.zip");
+        write_zip(
+            &zip_path,
+            &[
+                ("workspace.json", r#"{"schemaVersion":"1.0.0","books":[]}"#),
+                ("../evil.json", "{}"),
+            ],
+        );
+        let dest = dir.path().join("dest");
+        let result = import_workspace_zip(
+            zip_path.to_string_lossy().into_owned(),
+            dest.to_string_lossy().into_owned(),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(WorkspaceError::UnsafeArchiveEntry { entry, .. }) if entry == "../evil.json"
+        ));
+        assert!(!dir.path().join("evil.json").exists());
+        assert!(!dest.exists());
+
+        write_zip(
+            &zip_path,
+            &[(
+                "workspace.json",
+                r#"{"schemaVersion":"1.0.0","books":[{"id":"a","name":"A","dataPath":"books/a.json"}]}"#,
+            )],
+        );
+        assert!(matches!(
+            import_workspace_zip(
+                zip_path.to_string_lossy().into_owned(),
+                dest.to_string_lossy().into_owned(),
+                None,
+            ),
+            Err(WorkspaceError::InvalidArchive { .. })
+        ));
+        assert!(!dest.join("workspace.json").exists());
+    }
+}
@@ -61,6 +61,19 @@ function App() {
       const loaded = await openWorkspaceFromDialog();
       if (loaded) {
         loadWorkspace(loaded);
+        const failedBooks = loaded.failedBooks ?? [];
+        if (failedBooks.length > 0) {
+          await showErrorDialog(
+            '一部のブックを読み込めませんでした',
+            failedBooks
+              .map((failure) => `${failure.dataPath ?? `books[${failure.index}]`}: ${failure.message}`)
+              .join('\n')
+          );
+        }
+        const loadWarnings = loaded.loadWarnings ?? [];
+        if (loadWarnings.length > 0) {
+          await showErrorDialog('ブックファイルが破損している可能性があります', loadWarnings.join('\n'));
+        }
       }
     } catch (error) {
       await showErrorDialog('ワークスペースの読み込みに失敗しました', toErrorMessage(error));
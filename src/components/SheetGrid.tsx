@@ -10,6 +10,8 @@ import {
   type MouseEvent
 } from 'react';
 import type { SheetData } from '../types/schema';
+import { isTauri } from '../lib/env';
+import { parseClipboardTable } from '../lib/tauri/workspaceBridge';
 
 interface SheetGridProps {
   sheet: SheetData | null;
@@ -29,6 +31,18 @@ const toColumnLabel = (index: number): string => {
   return label;
 };
 
+/**
+ * backend の parse_clipboard_table と同じく、末尾の改行 1 つは行にせず、途中の空行は空の行として残す。
+ * 短い行は '' で埋めて最長の行の列数に揃える
+ */
+const splitClipboardText = (text: string): string[][] => {
+  const body = text.replace(/(\r\n|\n|\r)$/, '');
+  if (body.length === 0) return [];
+  const rows = body.split(/\r\n|\n|\r/).map((line) => line.split('\t'));
+  const cols = rows.reduce((max, row) => Math.max(max, row.length), 0);
+  return rows.map((row) => [...row, ...Array<string>(cols - row.length).fill('')]);
+};
+
 const fromColumnLabel = (label: string): number | null => {
   if (!label) {
     return null;
@@ -453,7 +467,7 @@ const SheetGrid: FC<SheetGridProps> = ({ sheet, onCommitCell, onCommitCells }) =
   );
 
   const handlePaste = useCallback(
-    (event: ClipboardEvent<HTMLDivElement>) => {
+    async (event: ClipboardEvent<HTMLDivElement>) => {
       if (!sheet || editingCell || !selectedCell || !onCommitCells) return;
       const clipboardText = event.clipboardData.getData('text/plain');
       if (!clipboardText) return;
@@ -463,18 +477,24 @@ const SheetGrid: FC<SheetGridProps> = ({ sheet, onCommitCell, onCommitCells }) =
       const startPos = getCellPosition(selectedCell.rowKey, selectedCell.columnKey);
       if (!startPos) return;
 
-      const rows = clipboardText
-        .replace(/\r/g, '')
-        .split('\n')
-        .filter((line) => line.length > 0);
+      // Tauri 上ではクオート内の改行・タブも backend で正しく分割する。失敗したら JS で分割する
+      let rows: string[][];
+      try {
+        rows = isTauri
+          ? (await parseClipboardTable(clipboardText, { inferTypes: false })).cells.map((row) =>
+              row.map(String)
+            )
+          : splitClipboardText(clipboardText);
+      } catch (error) {
+        rows = splitClipboardText(clipboardText);
+      }
 
       if (rows.length === 0) return;
 
       const updates: Array<{ rowKey: string; columnKey: string; value: string }> = [];
       let lastCell: CellPosition = selectedCell;
 
-      rows.forEach((line, rowOffset) => {
-        const columns = line.split('\t');
+      rows.forEach((columns, rowOffset) => {
         columns.forEach((value, colOffset) => {
           const targetRowIndex = startPos.rowIndex + rowOffset;
           const targetColIndex = startPos.colIndex + colOffset;
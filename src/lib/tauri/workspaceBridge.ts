@@ -1,21 +1,346 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { open, message as showSystemMessage } from '@tauri-apps/plugin-dialog';
 import { join } from '@tauri-apps/api/path';
-import type { LoadedFile, WorkspaceSnapshot } from '../../types/workspaceSnapshot';
+import type {
+  LargeCell,
+  LoadedFile,
+  WorkspaceMetrics,
+  WorkspaceSnapshot
+} from '../../types/workspaceSnapshot';
 import type { WorkspaceFile, BookFile } from '../../types/schema';
 import { validateBookFile, validateWorkspaceFile } from '../schemaValidator';
 import { isTauri } from '../env';
 
+export interface TextStyle {
+  lineEnding: 'lf' | 'crlf';
+  finalNewline: boolean;
+  /** UTF-8 の BOM 付きで読み込まれたか。`preserve` モードの保存でのみ再現される */
+  bom?: boolean;
+  /** インデント無しの 1 行で書かれていたか */
+  minified?: boolean;
+}
+
 interface FilePayloadDto {
   filePath: unknown;
   data: unknown;
+  modified?: number;
+  hash?: string;
+  textStyle?: TextStyle;
+  sizeBytes?: number;
+  dataPath?: string;
+  resolvedPath?: string;
+}
+
+interface BookLoadFailureDto {
+  index: number;
+  dataPath: string | null;
+  error: WorkspaceErrorDto;
 }
 
 interface WorkspaceSnapshotDto {
   workspace: FilePayloadDto;
   books: FilePayloadDto[];
+  failed?: BookLoadFailureDto[];
+  warnings?: WorkspaceErrorDto[];
+  created?: string[];
+  largeCells?: LargeCell[];
+  readOnly?: boolean;
+  metrics?: WorkspaceMetrics;
+  workspaceDir?: string;
+}
+
+export interface WorkspaceErrorDto {
+  code: string;
+  message: string;
+  [key: string]: unknown;
+}
+
+/**
+ * 読み込み時の JSON パースエラー（`code: 'parseError'`）。line / column は 1 始まりで、
+ * column は UTF-8 のバイト数。圧縮・暗号化・文字コード変換されたファイルでは復号後のテキスト上の位置。
+ */
+export interface ParseErrorDto extends WorkspaceErrorDto {
+  code: 'parseError';
+  path: string;
+  line: number;
+  column: number;
+  /** 壊れていたのが workspace.json か book か */
+  fileKind: 'workspace' | 'book' | 'other';
+}
+
+export const isParseError = (error: WorkspaceErrorDto): error is ParseErrorDto =>
+  error.code === 'parseError';
+
+export class WorkspaceCommandError extends Error {
+  readonly code: string;
+  readonly details: WorkspaceErrorDto;
+
+  constructor(details: WorkspaceErrorDto) {
+    super(details.message);
+    this.name = 'WorkspaceCommandError';
+    this.code = details.code;
+    this.details = details;
+  }
+}
+
+const isWorkspaceErrorDto = (value: unknown): value is WorkspaceErrorDto =>
+  typeof value === 'object' &&
+  value !== null &&
+  typeof (value as WorkspaceErrorDto).code === 'string' &&
+  typeof (value as WorkspaceErrorDto).message === 'string';
+
+const invokeCommand = async <T>(command: string, args?: Record<string, unknown>): Promise<T> => {
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    throw isWorkspaceErrorDto(error) ? new WorkspaceCommandError(error) : error;
+  }
+};
+
+export interface SaveResultDto {
+  /** 実際に書き込まれたファイル（変更のないファイルはスキップされる） */
+  written: string[];
+  /**
+   * 書き込む予定だったが、ディスク上の内容が同じだったため書き込まなかったファイル（mtime は変わらない）。
+   * インデント・最終行の改行など整形だけの差は同じ内容とみなし、改行コードと BOM の差は変更として扱う
+   */
+  skipped: string[];
+  modified: Record<string, number>;
+  hashes: Record<string, string>;
+  textStyles: Record<string, TextStyle>;
+  warnings?: string[];
+  /**
+   * シンボリックリンクや `./` などの表記違いで同じファイルを指していたため、
+   * 書き込みを writtenAs 側の1回にまとめた book
+   */
+  merged?: { filePath: string; writtenAs: string }[];
+  /** 書き込んだ book のうち largeCells の閾値を超えたセル */
+  largeCells?: LargeCell[];
+  /**
+   * 書き込み対象になったファイルごとの結果。ディスク上の内容と同一だったファイルは
+   * 書き込まず changed: false（bytesWritten: 0）になり、written には含まれない
+   */
+  files: SavedFileDto[];
+  /** patches 指定時、full で書き直さずパッチファイルへの追記で保存した book（written にはパッチファイルが入る） */
+  patched?: string[];
+  /** threeWay 指定時、読み込み後にディスク上で変更されていたため3-wayマージした book */
+  threeWay?: BookMergeDto[];
+  /** workspace.json の sortBy に従って行を並べ替えてから書き込んだ book（ディスク上の内容は保存した payload と異なる） */
+  sorted?: string[];
+  /** metrics オプション指定時の保存の所要時間 */
+  metrics?: WorkspaceMetrics;
+  /** normalize オプションで workspace.json に加えた変更（ディスク上の内容は保存した payload と異なる） */
+  normalized?: NormalizeChange[];
+}
+
+export interface NormalizeChange {
+  /** 変更した箇所（例: `books[2].id`）。添字は正規化前の books のもの */
+  field: string;
+  /** removed: null 等の要素を除去 / renamedDuplicate: 重複 id に連番 / separators: `\` を `/` に統一 / filled: 欠落フィールドを補完 */
+  kind: 'removed' | 'renamedDuplicate' | 'separators' | 'filled';
+  before?: unknown;
+  after?: unknown;
+}
+
+/**
+ * workspace.json の books から null 要素を除き、重複 id に `-2` などの連番を付け、dataPath の区切りを `/` に揃え、
+ * 欠落した必須フィールドを補完したデータを返す。保存はしない
+ */
+export const normalizeWorkspaceBooks = async (
+  workspace: WorkspaceFile
+): Promise<{ workspace: WorkspaceFile; changes: NormalizeChange[] }> =>
+  invokeCommand('normalize_workspace', { workspace });
+
+export interface ClipboardTableOptions {
+  /** auto（既定）はクオート外にタブがあればタブ、全行のカンマ数が揃っていればカンマ、それ以外は 1 列とみなす */
+  delimiter?: 'auto' | 'tab' | 'comma';
+  /** 数値として読める値を number にする（既定 true）。false なら全セルが文字列 */
+  inferTypes?: boolean;
+}
+
+export interface ClipboardTable {
+  rows: number;
+  cols: number;
+  delimiter: '\t' | ',';
+  /** rows 行 × cols 列。列数の足りない行は空文字で埋まる */
+  cells: Array<Array<string | number>>;
+}
+
+/**
+ * Excel / Google Sheets からコピーした TSV・CSV テキストをセル配列に変換する。
+ * クオート内の改行・タブ・`""` を解釈し、末尾の改行 1 つは行として数えない
+ */
+export const parseClipboardTable = async (
+  text: string,
+  options?: ClipboardTableOptions
+): Promise<ClipboardTable> => invokeCommand('parse_clipboard_table', { text, options });
+
+export interface BookMergeDto {
+  filePath: string;
+  /** マージ後の book。衝突したセルはスナップショット側の値のまま */
+  data: unknown;
+  /** マージの基にしたディスク上のファイルの mtime */
+  modified?: number;
+  conflicts: ThreeWayConflictDto[];
+}
+
+/** base から両側で別の値に変更された箇所。存在しない側は null */
+export interface ThreeWayConflictDto {
+  /** book 自体のフィールドの衝突では無し */
+  sheetId?: string;
+  /** セルの衝突のときのアドレス（例: `B3`） */
+  cell?: string;
+  /** セル以外のフィールドの衝突のときのキーパス（例: `book.name`）。シート全体の衝突では無し */
+  field?: string;
+  base: unknown;
+  disk: unknown;
+  ours: unknown;
+}
+
+export interface SavedFileDto {
+  path: string;
+  bytesWritten: number;
+  /** 保存前にファイルが存在しなかった */
+  created: boolean;
+  changed: boolean;
+}
+
+export interface SaveWorkspaceOptions {
+  force?: boolean;
+  /** 上書き前のファイルを `.sheet-up-backups/` へ退避する */
+  backup?: {
+    enabled?: boolean;
+    generations?: number;
+  };
+  /** dataPath が `.json.gz` のブックに使う gzip 圧縮レベル（0〜9、既定 6） */
+  compressionLevel?: number;
+  /** workspace.json で `encrypted: true` のブックを暗号化するパスフレーズ */
+  passphrase?: string;
+  /**
+   * 改行コードと末尾改行。既定は LF + 末尾改行あり（BOM 無し UTF-8）。
+   * `preserve` は読み込み時のファイルのスタイル（BOM の有無を含む）を維持する
+   */
+  write?: {
+    lineEnding?: 'lf' | 'crlf' | 'preserve';
+    /** minify した場合にも適用される */
+    finalNewline?: boolean;
+    /**
+     * false でインデント無しの最小化 JSON を書く。workspace.json と book で個別に指定でき、
+     * 既定は true（`preserve` では読み込み時のファイルに合わせる）
+     */
+    pretty?: {
+      workspace?: boolean;
+      books?: boolean;
+    };
+    /**
+     * 全オブジェクトのキーを階層を問わずアルファベット（コードポイント）順で書き、同じデータからは常に同じ
+     * ファイルを出力する。配列の順序は変えない。既定の「読み込み時・payload のキー順を維持」より優先され、
+     * `preserve` でもキー順は維持しない。JSON ファイルのみが対象
+     */
+    sortKeys?: boolean;
+  };
+  /**
+   * 大文字小文字だけが異なる dataPath も同じファイルとみなす。
+   * 複数の book が同じファイルを指す場合、保存は `duplicateDataPath` エラーになる
+   */
+  caseInsensitivePaths?: boolean;
+  /**
+   * クラウド同期ソフトがファイルを一時的にロックしている場合などの書き込み失敗を再試行する。
+   * 既定は最大 5 回、初回待機 50ms（以降は倍々）。全て失敗すると `retriesExhausted` エラーになる
+   */
+  retry?: {
+    maxAttempts?: number;
+    initialDelayMs?: number;
+  };
+  /**
+   * 同じ文字列が多くのセルに現れる book を、文字列テーブルと参照インデックスの形式（`_interned`）で保存する。
+   * 10% 以上小さくならない book は通常の形式で保存される。読み込み時は自動で展開される
+   */
+  internStrings?: boolean;
+  /**
+   * books payload と workspace の `books[].dataPath` が食い違うときの扱い。
+   * `strict`（既定）は `inconsistentSnapshot` で保存を拒否し、`warn` は保存したうえで warnings に載せる
+   */
+  consistencyCheck?: 'strict' | 'warn';
+  /** book の文字列値の改行をこのコードに揃えて保存する。揃っていない book は編集が無くても書き直す */
+  cellLineBreaks?: 'lf' | 'crlf';
+  /** 同じワークスペースの保存が実行中のとき待つ時間（ミリ秒、既定 30 秒）。超えると `saveTimeout` になる */
+  lockTimeoutMs?: number;
+  /** 書き込む book の大きすぎるセル値の検出。検出結果は largeCells に入り、保存は止まらない */
+  largeCells?: LargeCellOptions;
+  /**
+   * 読み込み後にディスク上で変更された book を、`conflict` で失敗させずに読み込み時の内容（base）を基に
+   * 3-wayマージする。片側だけの変更は自動で取り込み、両側が同じセルを別の値に変えた場合だけ衝突として返す
+   */
+  threeWay?: ThreeWayOptions;
+  /**
+   * 変化したセルだけを book の隣の `<dataPath>.patch.jsonl` に追記し、book 本体は書き直さない。
+   * セル以外（シート名・シートの追加など）が変わった book、暗号化された book、パッチが compactBytes
+   * （既定 1 MiB）を超える book は full で書き直してパッチファイルを空にする。読み込みは loadWithPatches を使う
+   */
+  patches?: {
+    enabled?: boolean;
+    compactBytes?: number;
+  };
+  /** ファイルごとの所要時間を計測して戻り値の metrics に入れ、`workspace-metrics` イベントでも通知する */
+  metrics?: boolean;
+  /** 保存前に workspace.json の books を normalizeWorkspaceBooks と同じ規則で正規化する。変更内容は normalized に入る */
+  normalize?: boolean;
+}
+
+export interface ThreeWayOptions {
+  /** 読み込み時の book の data（filePath がキー）。含まれない book は従来どおり `conflict` になる */
+  base: Record<string, unknown>;
+  /**
+   * `auto`（既定）は衝突が無ければマージ結果を保存し、衝突があれば何も書かずに `mergeConflict`
+   * （conflicts 付き）で失敗する。`confirm` は何も書かずに結果を threeWay で返すので、確認後に
+   * acceptBookMerge してからマージ後の data で保存し直す
+   */
+  mode?: 'auto' | 'confirm';
+}
+
+export interface LargeCellOptions {
+  /** この UTF-8 バイト長を超える値を検出する（既定 1 MiB） */
+  thresholdBytes?: number;
+  /** 検出した値を閾値の長さに切り詰める。超過分は保存すると失われる */
+  truncate?: boolean;
 }
 
+// ロード時／保存時の mtime をパス単位で保持し、保存時に外部変更の検出へ使う
+const fileStamps = new Map<string, number>();
+// 最後にディスクと一致していた内容のハッシュ。一致するファイルは保存時に書き込まれない
+const fileHashes = new Map<string, string>();
+// 読み込み時に検出した改行スタイル。`preserve` モードの保存で再現する
+const fileTextStyles = new Map<string, TextStyle>();
+
+const rememberStamp = (payload: FilePayloadDto): void => {
+  if (typeof payload.filePath !== 'string') {
+    return;
+  }
+  if (typeof payload.modified === 'number') {
+    fileStamps.set(payload.filePath, payload.modified);
+  }
+  // UTF-8 以外から変換して読み込んだ book には hash が無く、次回の保存で書き直される
+  if (typeof payload.hash === 'string') {
+    fileHashes.set(payload.filePath, payload.hash);
+  } else {
+    fileHashes.delete(payload.filePath);
+  }
+  if (payload.textStyle) {
+    fileTextStyles.set(payload.filePath, payload.textStyle);
+  }
+};
+
+const withStamp = <TData>(
+  file: LoadedFile<TData>
+): LoadedFile<TData> & { modified?: number; hash?: string; textStyle?: TextStyle } => ({
+  ...file,
+  modified: fileStamps.get(file.filePath),
+  hash: fileHashes.get(file.filePath),
+  textStyle: fileTextStyles.get(file.filePath)
+});
+
 const ensureString = (value: unknown, label: string): string => {
   if (typeof value !== 'string' || value.length === 0) {
     throw new Error(`${label} が不正です。`);
@@ -31,7 +356,9 @@ const normalizeWorkspace = (payload: FilePayloadDto): LoadedFile<WorkspaceFile>
   }
   return {
     filePath,
-    data: payload.data as WorkspaceFile
+    data: payload.data as WorkspaceFile,
+    modified: payload.modified,
+    sizeBytes: payload.sizeBytes
   };
 };
 
@@ -43,13 +370,28 @@ const normalizeBook = (payload: FilePayloadDto): LoadedFile<BookFile> => {
   }
   return {
     filePath,
-    data: payload.data as BookFile
+    data: payload.data as BookFile,
+    modified: payload.modified,
+    sizeBytes: payload.sizeBytes,
+    dataPath: payload.dataPath,
+    resolvedPath: payload.resolvedPath
   };
 };
 
 const normalizeSnapshot = (snapshot: WorkspaceSnapshotDto): WorkspaceSnapshot => ({
   workspace: normalizeWorkspace(snapshot.workspace),
-  books: snapshot.books.map(normalizeBook)
+  books: snapshot.books.map(normalizeBook),
+  failedBooks: (snapshot.failed ?? []).map((failure) => ({
+    index: failure.index,
+    dataPath: failure.dataPath,
+    message: failure.error.message
+  })),
+  loadWarnings: (snapshot.warnings ?? []).map((warning) => warning.message),
+  createdBooks: snapshot.created ?? [],
+  largeCells: snapshot.largeCells ?? [],
+  readOnly: snapshot.readOnly ?? false,
+  metrics: snapshot.metrics,
+  workspaceDir: snapshot.workspaceDir
 });
 
 export const selectWorkspaceDirectory = async (): Promise<string | null> => {
@@ -73,23 +415,1404 @@ export const resolveWorkspaceFilePath = async (path: string): Promise<string> =>
   return join(path, 'workspace.json');
 };
 
-export const loadWorkspaceSnapshot = async (workspacePath: string): Promise<WorkspaceSnapshot> => {
-  const dto = await invoke<WorkspaceSnapshotDto>('load_workspace_snapshot', {
+export interface LoadWorkspaceOptions {
+  /** すべてのブックが読み込めなかった場合はワークスペース全体をエラーにする */
+  failIfAllBooksFail?: boolean;
+  /**
+   * 暗号化されたブックの復号に使うパスフレーズ。未指定・誤りの場合、該当ブックは
+   * failedBooks に `passphraseRequired` / `wrongPassphrase` として報告される
+   */
+  passphrase?: string;
+  /** issueLoadId で発行した ID。cancelLoad で読み込みを中断できるようになる */
+  loadId?: string;
+  /**
+   * 古い schemaVersion から移行したファイルを読み込み時にすぐ書き戻す。
+   * 指定しない場合、移行済みの内容は次回の保存で書き込まれる。
+   * アプリより新しいメジャーバージョンのファイルは `versionTooNew` エラーになる
+   */
+  saveMigrated?: boolean;
+  /**
+   * 複数の book が同じ dataPath を指す場合に `duplicateDataPath` エラーで読み込みを中止する。
+   * 指定しない場合は warnings に含まれる
+   */
+  failOnDuplicateDataPaths?: boolean;
+  /** 大文字小文字だけが異なる dataPath も同じファイルとみなす（macOS / Windows 向け） */
+  caseInsensitivePaths?: boolean;
+  /**
+   * ファイルが存在しないブックを空の状態で新規作成してから読み込む。
+   * 作成したファイルは戻り値の createdBooks で確認できる。指定しない場合は failedBooks に含まれる
+   */
+  createMissing?: boolean;
+  /**
+   * ディスク上のサイズ（バイト）の上限。超えたファイルは読み込まずに `fileTooLarge` になる
+   * （book は failedBooks に含まれる）。既定は workspace.json 16 MiB、book 512 MiB。
+   * bookCount は workspace.json の books の要素数の上限（既定 10000）で、超えるとどの book も開かずに
+   * `tooManyBooks` になる。null を指定するとその上限を無効にする
+   */
+  sizeLimits?: { workspace?: number | null; book?: number | null; bookCount?: number | null };
+  /**
+   * 読み込みに成功したら workspace.json の `workspace.lastOpened` を現在時刻に更新して書き戻す。
+   * 他のフィールドは変わらないがコメントは残らない。読み取り専用のワークスペースでは更新しない
+   */
+  touchLastOpened?: boolean;
+  /**
+   * dataPath の途中のシンボリックリンク・ジャンクションが workspace ディレクトリの外を指していても読み込む。
+   * 既定では外を指す book は `symlinkEscapesWorkspace` として failed に入る
+   */
+  followOutsideLinks?: boolean;
+  /**
+   * book の文字列値に含まれる CRLF・CR を LF に揃えて読み込む（キーはそのまま）。
+   * 変換された book は次回の保存で書き直される
+   */
+  normalizeLineBreaks?: boolean;
+  /**
+   * 大きすぎるセル値の検出。検出結果は戻り値の largeCells に入り、読み込みは止まらない。
+   * truncate で切り詰めた book は次回の保存で書き直される
+   */
+  largeCells?: LargeCellOptions;
+  /**
+   * book を読むエンコーディング。省略時は BOM と内容から判定し、UTF-8 でなければ Shift_JIS か EUC-JP として
+   * 変換する（判定できなければ `invalidEncoding`）。UTF-8 以外から読み込んだ book は次回の保存で UTF-8 で書き直される
+   */
+  encoding?: TextEncoding;
+  /**
+   * ファイルごとの所要時間（I/O とパース）を計測して戻り値の metrics に入れる。
+   * loadWorkspaceSnapshotWithProgress では `workspace-metrics` イベントでも通知する
+   */
+  metrics?: boolean;
+  /**
+   * 各 book に workspace.json のエントリの dataPath（書かれたまま）と、それをシンボリックリンクまで解決した
+   * 絶対パス resolvedPath を付ける。同名の book や並び替え後でも book とエントリを確実に突き合わせられる
+   */
+  resolvedPaths?: boolean;
+}
+
+export type TextEncoding = 'utf8' | 'utf16Le' | 'utf16Be' | 'shiftJis' | 'eucJp';
+
+/** 読み込みを中断可能にするための ID を発行する。1 つの ID は 1 回の読み込みにのみ使える */
+export const issueLoadId = async (): Promise<string> => invokeCommand<string>('issue_load_id');
+
+/**
+ * loadId を指定した読み込みの中断を要求する。中断された読み込みは `cancelled` エラーになる。
+ * 該当する読み込みがない（完了済みなど）場合は false
+ */
+export const cancelLoad = async (loadId: string): Promise<boolean> =>
+  invokeCommand<boolean>('cancel_load', { loadId });
+
+/**
+ * workspace.json は `//`・`/* */` コメントと末尾カンマを許容して読み込む。
+ * 保存時は通常の JSON として書き出すため、コメントは残らない
+ */
+export const loadWorkspaceSnapshot = async (
+  workspacePath: string,
+  options?: LoadWorkspaceOptions
+): Promise<WorkspaceSnapshot> => {
+  const dto = await invokeCommand<WorkspaceSnapshotDto>('load_workspace_snapshot', {
+    path: workspacePath,
+    options
+  });
+  rememberStamp(dto.workspace);
+  dto.books.forEach(rememberStamp);
+  return normalizeSnapshot(dto);
+};
+
+/**
+ * loadWorkspaceSnapshot の結果に、各 book の `.patch.jsonl`（patches オプションで保存した差分）を順に適用して
+ * 最新の状態を返す。パッチを適用できなかった book は full snapshot のまま `invalidPatch` を warnings に載せ、
+ * 次回の保存で full に書き直される（パッチファイルは空になる）
+ */
+export const loadWithPatches = async (
+  workspacePath: string,
+  options?: LoadWorkspaceOptions
+): Promise<WorkspaceSnapshot> => {
+  const dto = await invokeCommand<WorkspaceSnapshotDto>('load_with_patches', {
+    workspacePath,
+    options
+  });
+  rememberStamp(dto.workspace);
+  dto.books.forEach(rememberStamp);
+  return normalizeSnapshot(dto);
+};
+
+/**
+ * オプションなしの loadWorkspaceSnapshot と同じ結果を返す。前回のキャッシュ付き読み込みから
+ * 更新日時とサイズが変わっていないファイルは backend のメモリ上のキャッシュから返される
+ */
+export const loadWorkspaceSnapshotCached = async (
+  workspacePath: string
+): Promise<WorkspaceSnapshot> => {
+  const dto = await invokeCommand<WorkspaceSnapshotDto>('load_workspace_snapshot_cached', {
     path: workspacePath
   });
+  rememberStamp(dto.workspace);
+  dto.books.forEach(rememberStamp);
   return normalizeSnapshot(dto);
 };
 
+/** ワークスペースのキャッシュを破棄し、次回の loadWorkspaceSnapshotCached で全ファイルを読み直させる */
+export const invalidateCache = (workspacePath: string): Promise<void> =>
+  invokeCommand<void>('invalidate_cache', { path: workspacePath });
+
+export interface WorkspaceLoadProgressEvent {
+  /** 読み込みを試みたブック数（失敗したものを含む） */
+  loaded: number;
+  total: number;
+  currentPath: string | null;
+  /** 最後の通知で true。読み込みが失敗した場合も送られる */
+  done: boolean;
+}
+
+/**
+ * loadWorkspaceSnapshot と同じ結果を返しつつ、ブックの読み込み状況を
+ * `workspace-load-progress` イベントで通知する。通知は数ブックごとにまとめて送られる
+ */
+export const loadWorkspaceSnapshotWithProgress = async (
+  workspacePath: string,
+  options?: LoadWorkspaceOptions
+): Promise<WorkspaceSnapshot> => {
+  const dto = await invokeCommand<WorkspaceSnapshotDto>('load_workspace_snapshot_with_progress', {
+    path: workspacePath,
+    options
+  });
+  rememberStamp(dto.workspace);
+  dto.books.forEach(rememberStamp);
+  return normalizeSnapshot(dto);
+};
+
+export const onWorkspaceLoadProgress = (
+  handler: (event: WorkspaceLoadProgressEvent) => void
+): Promise<UnlistenFn> =>
+  listen<WorkspaceLoadProgressEvent>('workspace-load-progress', (event) =>
+    handler(event.payload)
+  );
+
+export interface BookMetadata {
+  index: number;
+  id: string | null;
+  name: string | null;
+  dataPath: string | null;
+  filePath: string | null;
+  /** ファイルが存在しない場合は null */
+  sizeBytes: number | null;
+  modified: number | null;
+  /** dataPath が不正、またはファイル情報を取得できなかった場合のみ */
+  error?: WorkspaceErrorDto;
+}
+
+export interface WorkspaceMetadata {
+  workspace: LoadedFile<WorkspaceFile>;
+  books: BookMetadata[];
+}
+
+/** workspace.json だけを読み込み、ブック本体は読まずにメタデータを返す */
+export const loadWorkspaceMetadata = async (workspacePath: string): Promise<WorkspaceMetadata> => {
+  const dto = await invokeCommand<{ workspace: FilePayloadDto; books: BookMetadata[] }>(
+    'load_workspace_metadata',
+    { path: workspacePath }
+  );
+  rememberStamp(dto.workspace);
+  return { workspace: normalizeWorkspace(dto.workspace), books: dto.books };
+};
+
+/** 指定したブックだけを読み込む。workspace.json に無い ID は `code: 'bookNotFound'` になる */
+export const loadSingleBook = async (
+  workspacePath: string,
+  bookId: string,
+  options?: Pick<
+    LoadWorkspaceOptions,
+    'passphrase' | 'sizeLimits' | 'followOutsideLinks' | 'normalizeLineBreaks' | 'encoding'
+  >
+): Promise<LoadedFile<BookFile>> => {
+  const dto = await invokeCommand<FilePayloadDto>('load_single_book', {
+    workspacePath,
+    bookId,
+    options
+  });
+  rememberStamp(dto);
+  return normalizeBook(dto);
+};
+
+/**
+ * ロード後に他のクライアントがファイルを更新していた場合は `code: 'conflict'` の
+ * WorkspaceCommandError になる。`force` を指定すると検出をスキップして上書きする。
+ * 保存先ボリュームの空き容量が明らかに足りない場合は、何も書き込まずに
+ * `code: 'insufficientDiskSpace'`（required / available はバイト数）になる。
+ * 同じファイルを指す book が内容の異なるまま複数あると `code: 'duplicateDataPath'`、
+ * シンボリックリンクの解決先がワークスペースディレクトリ外なら `code: 'pathOutsideWorkspace'` になる。
+ * 読み取り専用のワークスペースへの保存は `force` に関係なく `code: 'readOnlyWorkspace'` になる。
+ * 書き込み自体の失敗は、権限不足なら `code: 'permissionDenied'`、読み取り専用のボリュームなら
+ * `code: 'readOnlyFilesystem'`、空き容量やクォータ不足なら `code: 'storageFull'` になり、
+ * 別の場所への保存や空き容量の確保を案内できる。それ以外は `code: 'io'`。
+ */
+const saveArgs = (snapshot: WorkspaceSnapshot, options?: SaveWorkspaceOptions) => ({
+  snapshot: {
+    workspace: withStamp(snapshot.workspace),
+    books: snapshot.books.map(withStamp)
+  },
+  force: options?.force ?? false,
+  options: {
+    backup: options?.backup,
+    compressionLevel: options?.compressionLevel,
+    passphrase: options?.passphrase,
+    write: options?.write,
+    caseInsensitivePaths: options?.caseInsensitivePaths,
+    retry: options?.retry,
+    internStrings: options?.internStrings,
+    consistencyCheck: options?.consistencyCheck,
+    cellLineBreaks: options?.cellLineBreaks,
+    lockTimeoutMs: options?.lockTimeoutMs,
+    largeCells: options?.largeCells,
+    threeWay: options?.threeWay,
+    patches: options?.patches,
+    metrics: options?.metrics,
+    normalize: options?.normalize
+  }
+});
+
+const rememberSaveResult = (result: SaveResultDto) => {
+  Object.entries(result.modified).forEach(([filePath, modified]) => {
+    fileStamps.set(filePath, modified);
+  });
+  Object.entries(result.hashes).forEach(([filePath, hash]) => {
+    fileHashes.set(filePath, hash);
+  });
+  Object.entries(result.textStyles).forEach(([filePath, textStyle]) => {
+    fileTextStyles.set(filePath, textStyle);
+  });
+};
+
+/**
+ * 全ファイルを一時ファイルに書き切ってから一括で置き換える。途中で失敗した場合は置き換え済みのファイルも
+ * 元に戻すため、ディスク上は全ファイルが保存前か保存後のどちらかになる（復元にも失敗した場合のみ `partialSave`）
+ */
 export const saveWorkspaceSnapshot = async (
-  snapshot: WorkspaceSnapshot
-): Promise<void> => {
-  await invoke('save_workspace_snapshot', { snapshot });
+  snapshot: WorkspaceSnapshot,
+  options?: SaveWorkspaceOptions
+): Promise<SaveResultDto> => {
+  const result = await invokeCommand<SaveResultDto>(
+    'save_workspace_snapshot',
+    saveArgs(snapshot, options)
+  );
+  rememberSaveResult(result);
+  return result;
+};
+
+/** confirm モードで返ったマージを受け入れ、次の保存をマージの基にしたファイルに対する変更として扱う */
+export const acceptBookMerge = (merge: BookMergeDto) => {
+  if (merge.modified !== undefined) {
+    fileStamps.set(merge.filePath, merge.modified);
+  }
+};
+
+export const SAVE_STARTED_EVENT = 'workspace-save-started';
+export const SAVE_FINISHED_EVENT = 'workspace-save-finished';
+export const SAVE_FAILED_EVENT = 'workspace-save-failed';
+
+// どのイベントにも workspacePath が入るので、別のワークスペースを開いているウィンドウは無視できる
+export interface SaveStartedEvent {
+  workspacePath: string;
+}
+
+export interface SaveFinishedEvent {
+  workspacePath: string;
+  /** 実際に書き込まれたファイル（SaveResult の written と同じ） */
+  paths: string[];
+  /** 書き込まれたファイルのディスク上の合計バイト数 */
+  totalBytes: number;
+  durationMs: number;
+}
+
+export interface SaveFailedEvent {
+  workspacePath: string;
+  error: WorkspaceErrorDto;
+}
+
+/** saveWorkspaceSnapshot の開始を受け取る。保存の成否は onSaveFinished / onSaveFailed で届く */
+export const onSaveStarted = (
+  handler: (event: SaveStartedEvent) => void
+): Promise<UnlistenFn> =>
+  listen<SaveStartedEvent>(SAVE_STARTED_EVENT, (event) => handler(event.payload));
+
+export const onSaveFinished = (
+  handler: (event: SaveFinishedEvent) => void
+): Promise<UnlistenFn> =>
+  listen<SaveFinishedEvent>(SAVE_FINISHED_EVENT, (event) => handler(event.payload));
+
+export const onSaveFailed = (
+  handler: (event: SaveFailedEvent) => void
+): Promise<UnlistenFn> =>
+  listen<SaveFailedEvent>(SAVE_FAILED_EVENT, (event) => handler(event.payload));
+
+export interface EnqueuedSave {
+  saveId: string;
+  /** 未着手だった同じワークスペースの保存に合流した */
+  coalesced: boolean;
+}
+
+export interface SaveCompletedEvent {
+  /** この保存にまとめられた enqueueSave の saveId（古い順） */
+  saveIds: string[];
+  workspacePath: string;
+  result?: SaveResultDto;
+  error?: WorkspaceErrorDto;
+}
+
+// 完了した保存の mtime・ハッシュを記録するリスナー。最初の enqueueSave で登録する
+let saveCompletedListener: Promise<UnlistenFn> | null = null;
+
+export const onSaveCompleted = (
+  handler: (event: SaveCompletedEvent) => void
+): Promise<UnlistenFn> =>
+  listen<SaveCompletedEvent>('workspace-save-completed', (event) => handler(event.payload));
+
+/**
+ * 保存をバックエンドのキューへ積んで即座に返る。保存は 1 件ずつ順に行われ、
+ * 未着手の同じワークスペースの保存は最新のものに置き換えられる。
+ * 結果は onSaveCompleted で saveId ごとに受け取る。アプリ終了時には残りの保存を書き終えてから終わる
+ */
+export const enqueueSave = async (
+  snapshot: WorkspaceSnapshot,
+  options?: SaveWorkspaceOptions
+): Promise<EnqueuedSave> => {
+  saveCompletedListener ??= onSaveCompleted((event) => {
+    if (event.result) {
+      rememberSaveResult(event.result);
+    }
+  });
+  await saveCompletedListener;
+  return invokeCommand<EnqueuedSave>('enqueue_save', saveArgs(snapshot, options));
 };
 
+/** キューに積まれた保存がすべて終わるまで待つ */
+export const flushSaveQueue = async (): Promise<void> => invokeCommand<void>('flush_save_queue');
+
 export const deleteBookFile = async (path: string): Promise<void> => {
-  await invoke('delete_book_file', { path });
+  await invokeCommand('delete_book_file', { path });
+};
+
+export interface BackupInfo {
+  id: string;
+  path: string;
+  createdAt: string | null;
+  sizeBytes: number;
+  valid: boolean;
+}
+
+export interface RestoreBackupResult {
+  restoredId: string;
+  previousBackupId: string | null;
+}
+
+/** 新しい順に並んだバックアップ一覧を返す */
+export const listBackups = async (path: string): Promise<BackupInfo[]> =>
+  invokeCommand<BackupInfo[]>('list_backups', { path });
+
+export const restoreBackup = async (
+  path: string,
+  backupId: string
+): Promise<RestoreBackupResult> =>
+  invokeCommand<RestoreBackupResult>('restore_backup', { path, backupId });
+
+export interface CsvExportOptions {
+  /** 省略時は先頭のシート */
+  sheetId?: string;
+  delimiter?: string;
+  /** `necessary` は数値に見える文字列（`"007"` など）もクオートする */
+  quoteStyle?: 'necessary' | 'always' | 'nonNumeric' | 'never';
+  /** 値のないセルに書き出す文字列 */
+  emptyValue?: string;
+  /** `used` は値のある範囲まで、`grid` は gridSize まで出力する */
+  range?: 'used' | 'grid';
+}
+
+export interface CsvExportResult {
+  sheetId: string;
+  rows: number;
+  columns: number;
+}
+
+export const exportBookToCsv = async (
+  bookFilePath: string,
+  outputPath: string,
+  options?: CsvExportOptions
+): Promise<CsvExportResult> =>
+  invokeCommand<CsvExportResult>('export_book_to_csv', { bookFilePath, outputPath, options });
+
+/** 矩形のセル範囲。start と end はどちらが左上でもよい */
+export interface CellRange {
+  start: string;
+  end: string;
+}
+
+/**
+ * シートの一部（例: `{ start: 'A1', end: 'C10' }`）だけを CSV/TSV で書き出す。
+ * 範囲内の空セルやデータ範囲を超えた部分は emptyValue で埋める。options.range は無視される
+ */
+export const exportCellRange = async (
+  bookFilePath: string,
+  range: CellRange,
+  format: 'csv' | 'tsv',
+  outputPath: string,
+  options?: Omit<CsvExportOptions, 'range'>
+): Promise<CsvExportResult> =>
+  invokeCommand<CsvExportResult>('export_cell_range', {
+    bookFilePath,
+    range,
+    format,
+    outputPath,
+    options
+  });
+
+export type MarkdownAlign = 'none' | 'left' | 'center' | 'right';
+
+export interface MarkdownExportOptions {
+  /** 省略時は先頭のシート */
+  sheetId?: string;
+  /** 1行目をヘッダーにする（既定 true）。false の場合ヘッダーは列名（A, B, …） */
+  headerRow?: boolean;
+  /** 値のないセルに書き出す文字列 */
+  emptyValue?: string;
+  /** 列幅をそろえるためにスペースで埋める（既定 true） */
+  pad?: boolean;
+  /** 本文がすべて数値の列の寄せ方（既定 `right`） */
+  numberAlign?: MarkdownAlign;
+  /** それ以外の列の寄せ方（既定 `none`） */
+  textAlign?: MarkdownAlign;
+  /** `used` は値のある範囲まで、`grid` は gridSize まで出力する */
+  range?: 'used' | 'grid';
+}
+
+export interface MarkdownExportResult {
+  sheetId: string;
+  /** ヘッダーを除いた行数 */
+  rows: number;
+  columns: number;
+}
+
+/** シートを GitHub Flavored Markdown のテーブルとして書き出す。`|` は `\|`、改行は `<br>` になる */
+export const exportBookToMarkdown = async (
+  bookFilePath: string,
+  outputPath: string,
+  options?: MarkdownExportOptions
+): Promise<MarkdownExportResult> =>
+  invokeCommand<MarkdownExportResult>('export_book_to_markdown', {
+    bookFilePath,
+    outputPath,
+    options
+  });
+
+export interface CsvImportOptions {
+  /** 省略時はカンマ／タブ／セミコロンから自動推定する */
+  delimiter?: string;
+  encoding?: 'utf8' | 'shiftJis';
+  /** false の場合はすべての値を文字列として取り込む（既定は true） */
+  inferTypes?: boolean;
+  /** 省略時は CSV のファイル名 */
+  bookName?: string;
+}
+
+export interface CsvImportResult {
+  bookId: string;
+  sheetId: string;
+  filePath: string;
+  dataPath: string;
+  delimiter: string;
+  rows: number;
+  columns: number;
+}
+
+/**
+ * CSV を新しいブックとして書き出し、workspace.json の books に追記する。
+ * ディスク上の workspace.json が更新されるため、呼び出し後はワークスペースを再読み込みすること。
+ */
+export const importCsvAsBook = async (
+  csvPath: string,
+  workspacePath: string,
+  options?: CsvImportOptions
+): Promise<CsvImportResult> =>
+  invokeCommand<CsvImportResult>('import_csv_as_book', { csvPath, workspacePath, options });
+
+export interface CsvDirectoryImportOptions {
+  /** 全ファイルに共通の設定。bookName は無視され、ファイル名が book 名になる */
+  csv?: CsvImportOptions;
+  /** サブフォルダも探索する（`.` で始まるフォルダは除く） */
+  recursive?: boolean;
+  /** 既存 book と名前が衝突した場合。skip（既定）／rename（`名前 (2)` など）／overwrite（内容を置き換える） */
+  onConflict?: 'skip' | 'rename' | 'overwrite';
+}
+
+export interface CsvDirectoryImportResult {
+  imported: {
+    csvPath: string;
+    bookName: string;
+    /** 既存 book の内容を置き換えた場合 true */
+    overwritten: boolean;
+    book: CsvImportResult;
+  }[];
+  /** 名前の衝突でスキップした CSV のパス */
+  skipped: string[];
+  failed: { csvPath: string; error: WorkspaceErrorDto }[];
+}
+
+/**
+ * フォルダ内の `.csv` をそれぞれ book として取り込み、workspace.json に追記する。
+ * 変換に失敗したファイルは failed に入り、残りは続行される。完了後はワークスペースを再読み込みすること
+ */
+export const importCsvDirectory = async (
+  dirPath: string,
+  workspacePath: string,
+  options?: CsvDirectoryImportOptions
+): Promise<CsvDirectoryImportResult> =>
+  invokeCommand<CsvDirectoryImportResult>('import_csv_directory', {
+    dirPath,
+    workspacePath,
+    options
+  });
+
+export interface CreateBookResult {
+  bookId: string;
+  sheetId: string;
+  dataPath: string;
+  filePath: string;
+}
+
+/**
+ * 空のブックを books/ 配下に作成し、workspace.json の books に追記する。
+ * ファイル名に使えない文字や予約名は置き換えられ、重複時は " (2)" などの連番が付く。
+ */
+export const createBook = async (workspacePath: string, name: string): Promise<CreateBookResult> =>
+  invokeCommand<CreateBookResult>('create_book', { workspacePath, name });
+
+export interface RenameBookResult {
+  dataPath: string;
+  filePath: string;
+}
+
+/**
+ * ブック名とファイル名をあわせて変更する。ディレクトリと拡張子（.json / .json.gz）は維持される。
+ * 変更先のファイル名が既に使われている場合は何も変更せずエラーになる。
+ */
+export const renameBook = async (
+  workspacePath: string,
+  bookId: string,
+  newName: string
+): Promise<RenameBookResult> =>
+  invokeCommand<RenameBookResult>('rename_book', { workspacePath, bookId, newName });
+
+/**
+ * books 配列を orderedBookIds の順に並べ替え、フォルダごとの order を振り直す。
+ * すべてのブック ID をちょうど 1 回ずつ含める必要がある（過不足があるとエラー）。
+ */
+export const reorderBooks = async (
+  workspacePath: string,
+  orderedBookIds: string[]
+): Promise<void> => {
+  await invokeCommand('reorder_books', { workspacePath, orderedBookIds });
+};
+
+export interface DeleteBookResult {
+  /** restoreFromTrash に渡す ID。完全削除の場合は null */
+  trashId: string | null;
+  /** 他のブックが同じファイルを参照している場合などは false */
+  fileRemoved: boolean;
+}
+
+/**
+ * workspace.json からブックを取り除き、ファイルを削除する。
+ * toTrash が true の場合はファイルを .sheet-up-trash/ に退避し、後から復元できる。
+ */
+export const deleteBook = async (
+  workspacePath: string,
+  bookId: string,
+  toTrash: boolean
+): Promise<DeleteBookResult> =>
+  invokeCommand<DeleteBookResult>('delete_book', { workspacePath, bookId, toTrash });
+
+export interface TrashEntry {
+  trashId: string;
+  bookId: string | null;
+  name: string | null;
+  dataPath: string | null;
+  deletedAt: string | null;
+  hasFile: boolean;
+}
+
+export const listTrash = async (workspacePath: string): Promise<TrashEntry[]> =>
+  invokeCommand<TrashEntry[]>('list_trash', { workspacePath });
+
+export interface RestoreFromTrashResult {
+  bookId: string;
+  /** 元のパスが使用済みの場合は別名になる */
+  dataPath: string;
+  filePath: string;
+}
+
+export const restoreFromTrash = async (
+  workspacePath: string,
+  trashId: string
+): Promise<RestoreFromTrashResult> =>
+  invokeCommand<RestoreFromTrashResult>('restore_from_trash', { workspacePath, trashId });
+
+export interface DuplicateWorkspaceResult {
+  workspacePath: string;
+  bookFiles: number;
+}
+
+/**
+ * ワークスペースと全ブックを destDir にコピーする（名前を付けて保存）。
+ * コピー先に同名のファイルがある場合は何も書き込まずにエラーになる。元のワークスペースは変更されない。
+ */
+export const duplicateWorkspace = async (
+  sourcePath: string,
+  destDir: string,
+  newName?: string
+): Promise<DuplicateWorkspaceResult> =>
+  invokeCommand<DuplicateWorkspaceResult>('duplicate_workspace', { sourcePath, destDir, newName });
+
+export interface TemplateWorkspaceResult {
+  workspacePath: string;
+  bookFiles: number;
+  /** セル値と book 名で置換したプレースホルダの数 */
+  replaced: number;
+}
+
+/**
+ * テンプレートのワークスペースを destDir にコピーして新規ワークスペースを作る。
+ * ワークスペースと book の id は振り直され、セル値と book 名の `{{name}}` は name に、
+ * `{{date}}` は今日の日付（YYYY-MM-DD）に置換される。テンプレートが readOnly でもコピーは編集可能。
+ * コピー先に同名のファイルがある場合は何も書き込まずに `alreadyExists` エラーになる
+ */
+export const createWorkspaceFromTemplate = async (
+  templatePath: string,
+  destDir: string,
+  name: string,
+  passphrase?: string
+): Promise<TemplateWorkspaceResult> =>
+  invokeCommand<TemplateWorkspaceResult>('create_workspace_from_template', {
+    templatePath,
+    destDir,
+    name,
+    passphrase
+  });
+
+export interface DiffWorkspacesOptions {
+  /** 1 と 1.0 のように表現だけが異なる数値を同値とみなす（既定は true） */
+  numbersByValue?: boolean;
+  /** セル差分をこの件数で打ち切り、truncated を true にする */
+  maxCellChanges?: number;
+  passphrase?: string;
+}
+
+export interface WorkspaceDiff {
+  addedBooks: { id: string; name: string | null }[];
+  removedBooks: { id: string; name: string | null }[];
+  commonBooks: string[];
+  addedSheets: { bookId: string; sheetId: string }[];
+  removedSheets: { bookId: string; sheetId: string }[];
+  changedCells: {
+    bookId: string;
+    sheetId: string;
+    /** "B3" のような A1 形式のアドレス */
+    cell: string;
+    /** 片側にしか存在しないセルでは null */
+    old: unknown;
+    new: unknown;
+  }[];
+  truncated: boolean;
+  failed: { bookId: string; error: WorkspaceErrorDto }[];
+}
+
+/** ワークスペース A から B への差分（ブック・シートの増減と変更されたセル）を返す */
+export const diffWorkspaces = async (
+  pathA: string,
+  pathB: string,
+  options?: DiffWorkspacesOptions
+): Promise<WorkspaceDiff> =>
+  invokeCommand<WorkspaceDiff>('diff_workspaces', { pathA, pathB, options });
+
+export interface SearchWorkspaceOptions {
+  caseInsensitive?: boolean;
+  /** セルの値全体がクエリと一致するものだけを返す */
+  wholeCell?: boolean;
+  /** クエリを正規表現（Rust の regex クレートの構文）として扱う */
+  regex?: boolean;
+  /** ヒット数の上限（既定 1000）。超えた分は返さず truncated を立てる */
+  maxResults?: number;
+  passphrase?: string;
+}
+
+export interface SearchHit {
+  bookId: string;
+  sheetId: string;
+  /** `B3` のような A1 形式のアドレス */
+  cell: string;
+  value: string;
+  /** 一致した範囲 `[start, end)`。JavaScript の文字列インデックスでそのまま使える */
+  matchRanges: [number, number][];
+}
+
+export interface SearchWorkspaceResult {
+  hits: SearchHit[];
+  truncated: boolean;
+  failed: { index: number; bookId: string | null; error: WorkspaceErrorDto }[];
+}
+
+/** ワークスペース内の全ブックのセル値から文字列を検索する */
+export const searchWorkspace = async (
+  workspacePath: string,
+  query: string,
+  options?: SearchWorkspaceOptions
+): Promise<SearchWorkspaceResult> =>
+  invokeCommand<SearchWorkspaceResult>('search_workspace', { workspacePath, query, options });
+
+export interface AllWorkspacesSearchResult {
+  /** ワークスペースのパス順、その中は searchWorkspace と同じ順 */
+  hits: (SearchHit & { workspacePath: string })[];
+  truncated: boolean;
+  /** index が null のものは workspace.json 自体を読み込めなかった */
+  failed: {
+    workspacePath: string;
+    index: number | null;
+    bookId: string | null;
+    error: WorkspaceErrorDto;
+  }[];
+  /** 権限不足などで一覧できなかったディレクトリ */
+  skipped: string[];
+  /** 見つかったワークスペースの数（failed のものを含む） */
+  workspaces: number;
+}
+
+/**
+ * rootDir 配下の全ワークスペースの全ブックから文字列を検索する。オプションは searchWorkspace と同じで、
+ * maxResults は全体での上限。進捗は `workspace-search-progress` イベント（loaded は検索済みのブック数）で通知される
+ */
+export const searchAllWorkspaces = async (
+  rootDir: string,
+  query: string,
+  options?: SearchWorkspaceOptions
+): Promise<AllWorkspacesSearchResult> =>
+  invokeCommand<AllWorkspacesSearchResult>('search_all_workspaces', { rootDir, query, options });
+
+export const onWorkspaceSearchProgress = (
+  handler: (event: WorkspaceLoadProgressEvent) => void
+): Promise<UnlistenFn> =>
+  listen<WorkspaceLoadProgressEvent>('workspace-search-progress', (event) =>
+    handler(event.payload)
+  );
+
+/** metrics オプションを指定した読み込み・保存の完了時に所要時間を受け取る */
+export const onWorkspaceMetrics = (
+  handler: (metrics: WorkspaceMetrics) => void
+): Promise<UnlistenFn> =>
+  listen<WorkspaceMetrics>('workspace-metrics', (event) => handler(event.payload));
+
+export interface ReplaceInWorkspaceOptions {
+  caseInsensitive?: boolean;
+  wholeCell?: boolean;
+  /** 正規表現として扱う。置換文字列で `$1` や `${name}` のキャプチャ参照が使える */
+  regex?: boolean;
+  /** 数値・真偽値セルを対象外にする（既定 true）。false だと一致したセルは文字列になる */
+  stringsOnly?: boolean;
+  /** ファイルを書き込まず、件数とプレビューだけを返す */
+  dryRun?: boolean;
+  /** preview に含める変更セル数の上限（既定 100） */
+  maxPreview?: number;
+  passphrase?: string;
+  backup?: SaveWorkspaceOptions['backup'];
+}
+
+export interface ReplaceInWorkspaceResult {
+  replacements: number;
+  books: { bookId: string; filePath: string; replacements: number }[];
+  preview: { bookId: string; sheetId: string; cell: string; old: string; new: string }[];
+  /** 書き込んだファイル。dryRun では空 */
+  written: string[];
+  failed: { index: number; bookId: string | null; error: WorkspaceErrorDto }[];
+}
+
+/**
+ * 全ブックのセル値を一括置換し、変更のあったブックだけを保存する。
+ * すべての置換をメモリ上で適用してから保存するが、保存の途中で失敗した場合に
+ * 書き込み済みのブックは元に戻らない（`partialSave` エラーで一覧が返る）
+ */
+export const replaceInWorkspace = async (
+  workspacePath: string,
+  query: string,
+  replacement: string,
+  options?: ReplaceInWorkspaceOptions
+): Promise<ReplaceInWorkspaceResult> =>
+  invokeCommand<ReplaceInWorkspaceResult>('replace_in_workspace', {
+    workspacePath,
+    query,
+    replacement,
+    options
+  });
+
+export interface ExportBundleResult {
+  outputPath: string;
+  books: number;
+  compressed: boolean;
+}
+
+/**
+ * workspace.json と全ブックを 1 つの JSON（`{ version, workspace, books: [{ dataPath, data }] }`）に
+ * まとめて書き出す。outputPath が `.json.gz`（例: `.bundle.json.gz`）なら gzip 圧縮する。
+ * 壊れたブックがあると中断する。暗号化ブックは復号した状態で格納される
+ */
+export const exportBundle = async (
+  workspacePath: string,
+  outputPath: string,
+  passphrase?: string
+): Promise<ExportBundleResult> =>
+  invokeCommand<ExportBundleResult>('export_bundle', { workspacePath, outputPath, passphrase });
+
+export interface ImportBundleResult {
+  workspacePath: string;
+  books: number;
+  warnings?: string[];
+}
+
+/**
+ * exportBundle で作ったバンドルを destDir に展開し、通常のワークスペースとして復元する。
+ * 展開先に同名のファイルがある場合は `alreadyExists` エラー（overwrite で上書き）。
+ * 未対応の version は `unsupportedBundleVersion`、destDir の外を指す dataPath は拒否される
+ */
+export const importBundle = async (
+  bundlePath: string,
+  destDir: string,
+  overwrite?: boolean
+): Promise<ImportBundleResult> =>
+  invokeCommand<ImportBundleResult>('import_bundle', { bundlePath, destDir, overwrite });
+
+export interface ExportZipResult {
+  zipPath: string;
+  /** 格納したファイル数（workspace.json・マニフェストを含む） */
+  files: number;
+}
+
+/**
+ * workspace.json・マニフェスト・全 book を、ワークスペースからの相対パスのまま zip に格納する。
+ * ファイルは内容を変えずに格納される（圧縮・暗号化された book もそのまま）
+ */
+export const exportWorkspaceZip = async (
+  workspacePath: string,
+  zipPath: string
+): Promise<ExportZipResult> =>
+  invokeCommand<ExportZipResult>('export_workspace_zip', { workspacePath, zipPath });
+
+export interface ImportZipResult {
+  workspacePath: string;
+  files: number;
+}
+
+/**
+ * exportWorkspaceZip の zip を destDir に展開する。絶対パスや `..` を含むエントリがあると
+ * 何も書き込まずに `unsafeArchiveEntry`、workspace.json や book が欠けた zip は `invalidArchive` になる。
+ * 展開先に同名のファイルがある場合は `alreadyExists` エラー（overwrite で上書き）
+ */
+export const importWorkspaceZip = async (
+  zipPath: string,
+  destDir: string,
+  overwrite?: boolean
+): Promise<ImportZipResult> =>
+  invokeCommand<ImportZipResult>('import_workspace_zip', { zipPath, destDir, overwrite });
+
+export interface RelocateWorkspaceResult {
+  rewritten: { bookId: string; oldDataPath: string; newDataPath: string }[];
+  /** 実ファイルが見つからなかった book。dataPath はそのまま残る */
+  unresolved: { bookId: string; dataPath: string }[];
+}
+
+/**
+ * フォルダ移動後のワークスペースについて、絶対パスの dataPath を
+ * ワークスペースディレクトリからの相対パス（区切りは `/`）に書き換えて保存する。
+ * 相対パスの dataPath は変更しない。書き換えがなければ workspace.json は保存されない
+ */
+export const relocateWorkspace = async (workspacePath: string): Promise<RelocateWorkspaceResult> =>
+  invokeCommand<RelocateWorkspaceResult>('relocate_workspace', { workspacePath });
+
+export interface WorkspaceStats {
+  books: number;
+  /** 読み込めた book のセル数の合計。failed の book は含まない */
+  totalCells: number;
+  /** value が null・空文字以外のセル数 */
+  nonEmptyCells: number;
+  /** workspace.json と存在する book ファイルのディスク上のサイズ（バイト） */
+  totalSizeBytes: number;
+  largestBook: { bookId: string | null; sizeBytes: number } | null;
+  /** これらのファイルの最終更新日時（epoch ミリ秒） */
+  lastModified: number | null;
+  /** セル数を数えられなかった book。サイズは集計に含まれる */
+  failed: { index: number; bookId: string | null; error: WorkspaceErrorDto }[];
+}
+
+/** ワークスペースの規模（book 数・セル数・サイズ・最終更新日時）を集計する */
+export const workspaceStats = async (
+  workspacePath: string,
+  passphrase?: string
+): Promise<WorkspaceStats> =>
+  invokeCommand<WorkspaceStats>('workspace_stats', { workspacePath, passphrase });
+
+/**
+ * workspace.json の readOnly を切り替える。解除時はキーごと削除する。
+ * readOnly の間は保存に加え、ブックの作成・削除・名前変更・並べ替え、CSV 取り込み、再配置、ゴミ箱からの復元も
+ * `code: 'readOnlyWorkspace'` で拒否される。
+ * 切り替え前に読み込んだスナップショットは保存前に読み込み直すこと
+ */
+export const setWorkspaceReadOnly = async (workspacePath: string, readOnly: boolean): Promise<void> =>
+  invokeCommand<void>('set_workspace_readonly', { workspacePath, readOnly });
+
+export interface WorkspaceLock {
+  lockPath: string;
+  pid: number;
+  acquiredAt: string;
+  /** 異常終了したプロセスから引き継いだ場合、そのロックに記録されていた PID */
+  stalePid: number | null;
+}
+
+/**
+ * ワークスペースディレクトリの `.sheet-up.lock` を取得する。生存している別プロセスが
+ * 保持している場合は `code: 'workspaceLocked'`（pid / acquiredAt 付き）になる。
+ * 記録された PID のプロセスが存在しなければ stale lock として引き継ぐ
+ */
+export const acquireWorkspaceLock = async (workspacePath: string): Promise<WorkspaceLock> =>
+  invokeCommand<WorkspaceLock>('acquire_workspace_lock', { workspacePath });
+
+/** ワークスペースを閉じるときに呼ぶ。自プロセスが保持していないロックには何もしない */
+export const releaseWorkspaceLock = async (workspacePath: string): Promise<void> =>
+  invokeCommand<void>('release_workspace_lock', { workspacePath });
+
+export interface JournalEntry<TEntry = unknown> {
+  recordedAt: string;
+  entry: TEntry;
+}
+
+export interface JournalRecovery<TEntry = unknown> {
+  journalPath: string;
+  /** 前回の保存以降に記録された編集。復旧するものがなければ空 */
+  entries: JournalEntry<TEntry>[];
+  /** 読み取れずにジャーナル末尾から切り捨てたバイト数 */
+  discardedBytes: number;
+}
+
+/** 編集操作を `.sheet-up-journal.jsonl` に追記する。saveWorkspaceSnapshot で全ブックの保存が成功するとジャーナルは消える */
+export const appendJournalEntry = async (workspacePath: string, entry: unknown): Promise<void> =>
+  invokeCommand<void>('append_journal_entry', { workspacePath, entry });
+
+/**
+ * 起動時にジャーナルから復旧候補を読み出す。ファイルは書き換えないので、
+ * 適用するかどうかはユーザーに確認する。適用しない場合は discardJournal で破棄する
+ */
+export const recoverFromJournal = async <TEntry = unknown>(
+  workspacePath: string
+): Promise<JournalRecovery<TEntry>> =>
+  invokeCommand<JournalRecovery<TEntry>>('recover_from_journal', { workspacePath });
+
+export const discardJournal = async (workspacePath: string): Promise<void> =>
+  invokeCommand<void>('discard_journal', { workspacePath });
+
+export interface SqliteTable {
+  tableName: string;
+  bookId: string;
+  bookName: string;
+  /** 挿入した行数（セルの無い行は含まない） */
+  rows: number;
+  /** `_sheet`・`_row` を除くセル列の数 */
+  columns: number;
+}
+
+export interface SqliteExportResult {
+  dbPath: string;
+  tables: SqliteTable[];
+}
+
+/**
+ * ワークスペース全体を SQLite データベースへ書き出す。book ごとに 1 テーブル（全シートの行を
+ * `_sheet`・`_row` 付きで格納、列名は列記号）を作り、数値・文字列・真偽（0/1）を SQLite の型へ対応付ける。
+ * 列数は book 内で最も広い行に合わせ、無いセルは NULL。テーブル名は book 名をサニタイズし、
+ * 衝突時は `_2` などを付ける。対応表は `_sheet_up_books` テーブルに入る。既存の dbPath は成功時に置き換える
+ */
+export const exportWorkspaceToSqlite = async (
+  workspacePath: string,
+  dbPath: string,
+  passphrase?: string
+): Promise<SqliteExportResult> =>
+  invokeCommand<SqliteExportResult>('export_workspace_to_sqlite', {
+    workspacePath,
+    dbPath,
+    passphrase
+  });
+
+export interface BookDiagnosis {
+  index: number;
+  id: string | null;
+  dataPath: string | null;
+  filePath: string | null;
+  /** ファイルが無い、またはパスが不正なときは null */
+  sizeBytes: number | null;
+  /** 暗号化された book は存在とサイズだけを確認する */
+  encrypted: boolean;
+  error?: WorkspaceErrorDto;
+}
+
+export interface WorkspaceDiagnosis {
+  workspace: { sizeBytes: number; error?: WorkspaceErrorDto };
+  books: BookDiagnosis[];
+  /** 複数のエントリが同じ dataPath を指しているもの（`duplicateDataPath`） */
+  duplicates: WorkspaceErrorDto[];
+  /** どのエントリからも参照されていない .json / .json.gz（workspace ディレクトリからの相対パス） */
+  orphans: string[];
+  healthy: boolean;
+}
+
+/**
+ * workspace.json・各 book・ディレクトリ内の孤立ファイルをまとめて点検する。
+ * 何も書き込まないので、トラブル時にそのまま実行してよい
+ */
+export const diagnoseWorkspace = async (workspacePath: string): Promise<WorkspaceDiagnosis> =>
+  invokeCommand<WorkspaceDiagnosis>('diagnose_workspace', { workspacePath });
+
+export interface PruneOrphansResult {
+  dryRun: boolean;
+  /** 見つかった孤立ファイル（workspace ディレクトリからの相対パス） */
+  orphans: string[];
+  /** trashId はゴミ箱へ移したときだけ入り、restoreFromTrash で book として戻せる */
+  pruned: { dataPath: string; trashId: string | null }[];
+  failed?: { dataPath: string; error: WorkspaceErrorDto }[];
+}
+
+/**
+ * workspace.json から参照されていない book ファイルを削除（toTrash ならゴミ箱へ移動）する。
+ * dryRun では候補を返すだけで何も変更しない。バックアップ・マニフェストなどの内部ファイルは対象外
+ */
+export const pruneOrphanBooks = async (
+  workspacePath: string,
+  options: { toTrash: boolean; dryRun: boolean }
+): Promise<PruneOrphansResult> =>
+  invokeCommand<PruneOrphansResult>('prune_orphan_books', { workspacePath, ...options });
+
+/** 順に適用するセル変換。全角/半角は英数字・記号・スペースのみで、カタカナは対象外 */
+export type CellTransform = 'trim' | 'uppercase' | 'lowercase' | 'halfWidth' | 'fullWidth';
+
+export interface TransformOptions {
+  /** 省略時は全シート */
+  sheetId?: string;
+  /** 対象の列（例: `['A', 'C']`） */
+  columns?: string[];
+  /** 対象の矩形範囲 */
+  range?: CellRange;
+  /** true の場合はファイルを書き換えずに結果だけ返す */
+  dryRun?: boolean;
+  /** preview に含める変更セル数（既定 100） */
+  maxPreview?: number;
+  passphrase?: string;
+}
+
+export interface TransformedCell {
+  sheetId: string;
+  cell: string;
+  old: string;
+  new: string;
+}
+
+export interface TransformResult {
+  /** 値が変わったセル数 */
+  changed: number;
+  preview: TransformedCell[];
+  /** ファイルを書き換えたか（dryRun や変更なしの場合は false） */
+  written: boolean;
+}
+
+/** book の文字列セルに transforms を順に適用する。数値・真偽値セルはそのまま残る */
+export const transformBookCells = async (
+  bookFilePath: string,
+  transforms: CellTransform[],
+  options?: TransformOptions
+): Promise<TransformResult> =>
+  invokeCommand<TransformResult>('transform_book_cells', { bookFilePath, transforms, options });
+
+export interface FormulaOptions {
+  /** 対象のシート（既定は全シート） */
+  sheetId?: string;
+  /** 計算結果を各セルの computedValue として book に保存する（既定は結果を返すだけ） */
+  store?: boolean;
+  passphrase?: string;
+}
+
+export interface FormulaCell {
+  sheetId: string;
+  cell: string;
+  formula: string;
+  value: number | null;
+  /** `#ERROR!`（解釈できない数式）・`#VALUE!`・`#DIV/0!`・`#NUM!` */
+  error: string | null;
+}
+
+export interface FormulaResult {
+  cells: FormulaCell[];
+  written: boolean;
+}
+
+/**
+ * book の数式セル（formula フィールド、または type が formula で `=` で始まる value）を評価する。
+ * セル参照（A1・A1:B3）、四則演算と括弧、SUM / AVERAGE / MIN / MAX に対応する。
+ * 数式同士が循環参照している場合は `circularReference` エラー（cells に循環するセル）になる
+ */
+export const evaluateBookFormulas = async (
+  bookFilePath: string,
+  options?: FormulaOptions
+): Promise<FormulaResult> =>
+  invokeCommand<FormulaResult>('evaluate_book_formulas', { bookFilePath, options });
+
+export type SchemaKind = 'workspace' | 'book';
+
+export interface SchemaIssue {
+  /** 違反した値の JSON Pointer（ドキュメント全体は空文字） */
+  pointer: string;
+  /** 失敗したスキーマキーワードの JSON Pointer */
+  keyword: string;
+  message: string;
+  /** 型の不一致の場合に許される JSON の型 */
+  expected?: string[];
+}
+
+export interface SchemaValidation {
+  kind: SchemaKind;
+  valid: boolean;
+  /** 埋め込まれたスキーマが対象とする schemaVersion */
+  schemaVersion: string | null;
+  issues: SchemaIssue[];
+}
+
+/** ファイルを埋め込みの JSON Schema（既定は workspace）で検証し、すべての違反を返す */
+export const validateAgainstSchema = async (
+  path: string,
+  kind?: SchemaKind
+): Promise<SchemaValidation> =>
+  invokeCommand<SchemaValidation>('validate_workspace_against_schema', { path, kind });
+
+/** 両方の book に値があり内容が異なるセルの解決方法。nonEmptyWins は値のある側（両方ある場合は base）を採用する */
+export type MergeStrategy = 'preferBase' | 'preferIncoming' | 'nonEmptyWins';
+
+export interface MergeOptions {
+  /** マージ結果を新しい book ファイルとしてここへ書き出す。既存のファイルは上書きしない */
+  outputPath?: string;
+  /** base の book をバックアップしてから上書きする。outputPath とは併用できない。どちらも無い場合は書き込まない */
+  overwriteBase?: boolean;
+  /** outputPath に書き出す book の名前（既定は base の名前） */
+  name?: string;
+  /** どちらかが暗号化されている場合、結果も同じパスフレーズで暗号化される */
+  passphrase?: string;
+  /** conflicts に含める件数（既定 1000） */
+  maxConflicts?: number;
+}
+
+export interface MergeConflict {
+  sheetId: string;
+  cell: string;
+  base: unknown;
+  incoming: unknown;
+  resolved: unknown;
+}
+
+export interface MergeResult {
+  /** 衝突したセル数（conflicts に含まれない分も数える） */
+  conflictCount: number;
+  conflicts: MergeConflict[];
+  /** incoming にだけあったセル数 */
+  cellsAdded: number;
+  /** id・名前のどちらでも base と対応しなかったため追加したシート数 */
+  sheetsAdded: number;
+  written: string | null;
+  /** 上書き前の base のバックアップ。restoreBackup で元に戻せる */
+  backupId: string | null;
+}
+
+/** 2 つの book をセル単位でマージする。行・列の範囲は両方を合わせた範囲になる */
+export const mergeBooks = async (
+  baseBookPath: string,
+  incomingBookPath: string,
+  strategy: MergeStrategy,
+  options?: MergeOptions
+): Promise<MergeResult> =>
+  invokeCommand<MergeResult>('merge_books', {
+    baseBookPath,
+    incomingBookPath,
+    strategy,
+    options
+  });
+
+export interface StructureEditOptions {
+  /** 編集するシート（既定は book の先頭のシート） */
+  sheetId?: string;
+  passphrase?: string;
+}
+
+export interface StructureEditResult {
+  sheetId: string;
+  /** 編集後の gridSize */
+  rows: number;
+  cols: number;
+  /** アドレスが変わったセル数 */
+  moved: number;
+  /** 削除した行・列にあったセル数 */
+  removed: number;
+}
+
+/**
+ * at 行目（1 始まり）の前に count 行を挿入し、それ以降のセルを下へずらす。
+ * at に行数 + 1 を渡すと末尾に追加する
+ */
+export const insertRows = async (
+  bookFilePath: string,
+  at: number,
+  count: number,
+  options?: StructureEditOptions
+): Promise<StructureEditResult> =>
+  invokeCommand<StructureEditResult>('insert_rows', { bookFilePath, at, count, options });
+
+/** at 行目から count 行を削除し、それ以降のセルを上へずらす。範囲がシートを超える場合は `invalidOption` */
+export const deleteRows = async (
+  bookFilePath: string,
+  at: number,
+  count: number,
+  options?: StructureEditOptions
+): Promise<StructureEditResult> =>
+  invokeCommand<StructureEditResult>('delete_rows', { bookFilePath, at, count, options });
+
+/** at 列目（A 列が 1）の前に count 列を挿入し、それ以降のセルを右へずらす */
+export const insertCols = async (
+  bookFilePath: string,
+  at: number,
+  count: number,
+  options?: StructureEditOptions
+): Promise<StructureEditResult> =>
+  invokeCommand<StructureEditResult>('insert_cols', { bookFilePath, at, count, options });
+
+/** at 列目から count 列を削除し、それ以降のセルを左へずらす */
+export const deleteCols = async (
+  bookFilePath: string,
+  at: number,
+  count: number,
+  options?: StructureEditOptions
+): Promise<StructureEditResult> =>
+  invokeCommand<StructureEditResult>('delete_cols', { bookFilePath, at, count, options });
+
+export interface TrimOptions {
+  /** 省略時は全シート */
+  sheetId?: string;
+  /** 空白文字だけのセルも空とみなして削除する */
+  whitespaceIsEmpty?: boolean;
+  passphrase?: string;
+}
+
+export interface SheetTrim {
+  sheetId: string;
+  /** トリム後の gridSize（最小 1 x 1） */
+  rows: number;
+  cols: number;
+  previousRows: number;
+  previousCols: number;
+  /** 削除した空セルの数 */
+  removed: number;
+}
+
+/**
+ * 空セル（value が無い・null・空文字で、type 以外のプロパティを持たないセル）を削除し、各シートの gridSize を
+ * A1 から残ったセルを含む最小の大きさに縮める。セルは移動しない（座標はそのまま）ため、データより前や
+ * 間にある空行・空列は残り、最後のデータより後ろだけが削られる
+ */
+export const trimEmptyBook = async (
+  bookFilePath: string,
+  options?: TrimOptions
+): Promise<{ sheets: SheetTrim[]; removed: number }> =>
+  invokeCommand('trim_empty_book', { bookFilePath, options });
+
+export interface InspectTypesOptions {
+  /** 省略時は全シート */
+  sheetId?: string;
+  /** 列の最頻の型がこの割合（空以外のセル中、0 より大きく 1 以下）に届かないと mixed になる。既定 0.95 */
+  threshold?: number;
+  passphrase?: string;
+}
+
+export type CellKind = 'number' | 'string' | 'boolean' | 'empty';
+
+export interface ColumnTypes {
+  column: string;
+  counts: Record<CellKind, number>;
+  /** 空以外のセルで最も多い型。空セルだけの列は null */
+  kind: Exclude<CellKind, 'empty'> | null;
+  mixed: boolean;
+  /** kind と異なる型のセルの座標（最大 100 件） */
+  anomalies: string[];
+  /** anomalies に載らなかった分も含めた件数 */
+  anomalyCount: number;
+}
+
+export interface TypeReport {
+  sheets: { sheetId: string; columns: ColumnTypes[] }[];
+  /** mixed または anomalies のある列の数 */
+  flaggedColumns: number;
+}
+
+/** book の列ごとのセル値の型分布を集計し、列の型に合わないセルを報告する（読み取りのみ） */
+export const inspectBookTypes = async (
+  bookFilePath: string,
+  options?: InspectTypesOptions
+): Promise<TypeReport> => invokeCommand<TypeReport>('inspect_book_types', { bookFilePath, options });
+
+export interface WorkspaceSummary {
+  workspacePath: string;
+  id: string | null;
+  name: string | null;
+  bookCount: number;
+  /** workspace.json の更新日時（epoch ミリ秒） */
+  modified: number | null;
+  lastOpened: string | null;
+  /** workspace.json を読めなかった場合のみ */
+  error?: WorkspaceErrorDto;
+}
+
+export interface WorkspaceIndex {
+  rootDir: string;
+  /** workspacePath 順 */
+  workspaces: WorkspaceSummary[];
+  /** キャッシュを使わず読み直した workspace.json の数 */
+  rescanned: number;
+  /** 権限不足などで一覧できずに飛ばしたディレクトリ */
+  skipped: string[];
+}
+
+/**
+ * rootDir 配下のワークスペースを探し、workspace.json だけを読んで概要を返す（book 本体は読まない）。
+ * 結果はアプリ設定ディレクトリにキャッシュされ、次回からは更新日時が変わった workspace.json だけを読み直す
+ */
+export const scanWorkspaceIndex = async (rootDir: string): Promise<WorkspaceIndex> =>
+  invokeCommand<WorkspaceIndex>('scan_workspace_index', { rootDir });
+
+export interface WorkspaceFileChangedEvent {
+  kind: 'created' | 'modified' | 'removed';
+  paths: string[];
+}
+
+/**
+ * ワークスペースディレクトリ配下の JSON ファイルの外部変更を監視する。
+ * アプリ自身の保存による変更は通知されない。呼び出すたびに以前の監視は置き換わる。
+ */
+export const watchWorkspace = async (workspacePath: string): Promise<void> => {
+  await invokeCommand('watch_workspace', { path: workspacePath });
+};
+
+export const unwatchWorkspace = async (): Promise<void> => {
+  await invokeCommand('unwatch_workspace');
 };
 
+export const onWorkspaceFileChanged = (
+  handler: (event: WorkspaceFileChangedEvent) => void
+): Promise<UnlistenFn> =>
+  listen<WorkspaceFileChangedEvent>('workspace-file-changed', (event) => handler(event.payload));
+
 export const openWorkspaceFromDialog = async (): Promise<WorkspaceSnapshot | null> => {
   const directory = await selectWorkspaceDirectory();
   if (!directory) {
@@ -16,6 +16,8 @@ export interface WorkspaceMeta {
   name: string;
   createdAt: string; // ISO8601 timestamp
   updatedAt?: string;
+  /** 最後に touchLastOpened 付きで読み込まれた時刻（ISO8601） */
+  lastOpened?: string;
   settings?: WorkspaceSettings;
 }
 
@@ -27,13 +29,35 @@ export interface FolderMeta {
   metadata?: Record<string, unknown>;
 }
 
+export interface BookSortBy {
+  /** 列名（例: 'B'） */
+  column: string;
+  /** 既定 'asc'。数値 < 文字列 < 真偽値の順で、文字列はロケールに依存しないコードポイント順 */
+  order?: 'asc' | 'desc';
+  /** 並べ替え列が空の行の位置（order に関係なく）。既定 'last' */
+  empty?: 'first' | 'last';
+  /** 省略時は先頭のシート */
+  sheetId?: EntityId;
+  /** 並べ替えずに上に残すヘッダー行の数。既定 0 */
+  headerRows?: number;
+}
+
 export interface BookReference {
   id: EntityId;
   name: string;
   folderId?: EntityId | null;
   order: number;
+  /**
+   * 拡張子で形式が決まる: `.json` / `.json.gz` / `.msgpack`（ネイティブの整数・浮動小数点数で正確に表せない数値は文字列になる）、
+   * `.csv`（先頭シートのセル値のみ保存される）。
+   * それ以外の拡張子のブックは `unknownBookFormat` で読み込めない
+   */
   dataPath: string;
   thumbPath?: string;
+  /** true の場合、ブックファイルは保存時のパスフレーズで暗号化される */
+  encrypted?: boolean;
+  /** 指定すると保存時にこの列で行を並べ替えてから書き込む（行ごと移動する） */
+  sortBy?: BookSortBy;
   activeSheetId?: EntityId;
   createdAt: string;
   updatedAt: string;
@@ -45,6 +69,14 @@ export interface WorkspaceFile {
   workspace: WorkspaceMeta;
   folders: FolderMeta[];
   books: BookReference[];
+  /** true の間は保存が拒否される。解除は setWorkspaceReadOnly で行う */
+  readOnly?: boolean;
+  /**
+   * ワークスペース外で読み書きを許可するディレクトリ（workspace.json 基準の相対パスか `~/...`）。
+   * 絶対パスや、ホームディレクトリ・ワークスペースのディレクトリ自身かその上位を指すものは無視される。
+   * これらの配下を指す dataPath だけが `../` 等で外に出られる
+   */
+  allowedExternalRoots?: string[];
 }
 
 export interface GridSize {
@@ -59,6 +91,8 @@ export interface CellData {
   type: CellType;
   format?: string;
   formula?: string;
+  /** evaluateBookFormulas（store 指定時）が保存した数式の計算結果。失敗した場合は `#DIV/0!` などのエラーコード */
+  computedValue?: number | string;
   comment?: string;
   metadata?: Record<string, unknown>;
 }
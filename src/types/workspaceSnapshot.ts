@@ -3,9 +3,76 @@ import type { BookFile, WorkspaceFile } from './schema';
 export interface LoadedFile<TData> {
   filePath: string;
   data: TData;
+  /** 読み込み時のファイルの更新日時（epoch ミリ秒） */
+  modified?: number;
+  /** 読み込み時のファイルサイズ（バイト）。表示用で、保存時には無視される */
+  sizeBytes?: number;
+  /** resolvedPaths オプション指定時: workspace.json のエントリの dataPath（書かれたまま） */
+  dataPath?: string;
+  /** resolvedPaths オプション指定時: dataPath の絶対パス（シンボリックリンク解決済み。workspaceDir と比較できる） */
+  resolvedPath?: string;
+}
+
+export interface FailedBook {
+  index: number;
+  dataPath: string | null;
+  message: string;
+}
+
+/** 閾値を超える大きさの値を持つセル */
+export interface LargeCell {
+  bookId: string;
+  sheetId: string;
+  /** A1 形式のセル番地 */
+  cell: string;
+  /** 検出時の値の UTF-8 バイト長 */
+  size: number;
+  /** 閾値の長さに切り詰めた */
+  truncated: boolean;
+}
+
+/** 1 ファイル分の読み書きの所要時間（ミリ秒） */
+export interface FileTiming {
+  path: string;
+  /** 読み込んだ・書き込んだバイト数 */
+  bytes: number;
+  totalMs: number;
+  /** ファイルシステムの読み書き（保存時は fsync を含む） */
+  ioMs: number;
+  /** 読み込み時の、I/O を除いたパースの時間 */
+  parseMs?: number;
+  /** 保存時の、I/O を除いたシリアライズ（圧縮・暗号化を含む）の時間 */
+  serializeMs?: number;
+}
+
+/** metrics オプション指定時の読み込み・保存の所要時間 */
+export interface WorkspaceMetrics {
+  operation: 'load' | 'save';
+  workspacePath: string;
+  /** 検証・バックアップ・リネームなど files に含まれない処理も含めた全体の時間 */
+  totalMs: number;
+  bytes: number;
+  /** 完了した順。保存時に内容が同じでスキップしたファイル、キャッシュから返したファイルは含まない */
+  files: FileTiming[];
+  /** totalMs が最も大きかった book */
+  slowestBook: string | null;
 }
 
 export interface WorkspaceSnapshot {
   workspace: LoadedFile<WorkspaceFile>;
   books: LoadedFile<BookFile>[];
+  /** 読み込めなかったブック（ロード時のみ設定される） */
+  failedBooks?: FailedBook[];
+  /** 読み込みは続行したが注意が必要な問題（チェックサム不一致など） */
+  loadWarnings?: string[];
+  /** createMissing 指定時に空の状態で新規作成したブックのファイルパス */
+  createdBooks?: string[];
+  /** largeCells の閾値を超えたセル（ロード時のみ設定される） */
+  largeCells?: LargeCell[];
+  /** workspace.json の readOnly。true の間は保存が拒否される（ロード時のみ設定される） */
+  readOnly?: boolean;
+  /** metrics オプション指定時の読み込みの所要時間（ロード時のみ設定される） */
+  metrics?: WorkspaceMetrics;
+  /** workspace.json のあるディレクトリの絶対パス（シンボリックリンク解決済み。ロード時のみ設定される） */
+  workspaceDir?: string;
 }